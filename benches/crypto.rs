@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pasta_curves::{
+    group::{ff::Field, Group},
+    pallas,
+};
+use rand::rngs::OsRng;
+
+use darkfi::crypto::util::{
+    multiscalar_mul, pedersen_commitment_scalar, pedersen_commitment_sum, value_to_scalar,
+};
+
+/// Sum of `n` random points times random scalars, naively and as a
+/// multi-scalar multiplication.
+fn bench_multiscalar_mul(c: &mut Criterion) {
+    let mut group = c.benchmark_group("multiscalar_mul");
+    for n in [8, 64, 128] {
+        let scalars: Vec<_> = (0..n).map(|_| pallas::Scalar::random(&mut OsRng)).collect();
+        let points: Vec<_> = (0..n).map(|_| pallas::Point::random(&mut OsRng)).collect();
+
+        group.bench_with_input(BenchmarkId::new("naive", n), &n, |b, _| {
+            b.iter(|| {
+                scalars
+                    .iter()
+                    .zip(points.iter())
+                    .fold(pallas::Point::identity(), |acc, (s, p)| acc + p * s)
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("msm", n), &n, |b, _| {
+            b.iter(|| multiscalar_mul(&scalars, &points))
+        });
+    }
+    group.finish();
+}
+
+/// Sum of 64 value commitments, as the clear inputs of a transaction.
+fn bench_commitment_sum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("commitment_sum");
+    let values: Vec<_> = (0..64).map(value_to_scalar).collect();
+    let blinds: Vec<_> = (0..64).map(|_| pallas::Scalar::random(&mut OsRng)).collect();
+
+    group.bench_function("naive", |b| {
+        b.iter(|| {
+            values.iter().zip(blinds.iter()).fold(pallas::Point::identity(), |acc, (v, r)| {
+                acc + pedersen_commitment_scalar(*v, *r)
+            })
+        })
+    });
+    group.bench_function("msm", |b| b.iter(|| pedersen_commitment_sum(&values, &blinds)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_multiscalar_mul, bench_commitment_sum);
+criterion_main!(benches);
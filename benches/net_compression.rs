@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::RngCore;
+
+use darkfi::net::{
+    compression::{compress_packet, decompress_packet},
+    message::Packet,
+    Compression,
+};
+
+/// A raft log suffix of 500 tasks, as sent when the encryption is disabled.
+fn plaintext_tasks() -> Vec<u8> {
+    let mut tasks = vec![];
+    for i in 0..500 {
+        let task = format!(
+            "{{\"ref_id\":\"{:032x}\",\"id\":{},\"title\":\"task number {}\",\"desc\":\"\",\
+             \"assign\":[\"dark\"],\"project\":[\"tau\"],\"due\":null,\"rank\":{}.0,\
+             \"created_at\":{},\"events\":[],\"comments\":[]}}",
+            i * 7919,
+            i,
+            i,
+            i % 10,
+            1650000000 + i * 60
+        );
+        tasks.extend_from_slice(task.as_bytes());
+    }
+    tasks
+}
+
+fn packet(payload: Vec<u8>) -> Packet {
+    Packet { command: "netmsg".into(), payload }
+}
+
+/// Compressing and decompressing packets with payloads shaped like raft
+/// logs of plaintext tasks and of encrypted ones, which don't compress.
+fn bench_compression(c: &mut Criterion) {
+    let tasks = plaintext_tasks();
+    let mut encrypted = vec![0; tasks.len()];
+    rand::thread_rng().fill_bytes(&mut encrypted);
+
+    let mut group = c.benchmark_group("compression");
+    for (name, payload) in [("plaintext", tasks), ("encrypted", encrypted)] {
+        group.throughput(Throughput::Bytes(payload.len() as u64));
+        let compressed = compress_packet(packet(payload.clone()), Compression::Deflate).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("compress", name), &payload, |b, payload| {
+            b.iter(|| compress_packet(packet(payload.clone()), Compression::Deflate).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("decompress", name),
+            &compressed,
+            |b, compressed| {
+                b.iter(|| {
+                    let command = compressed.command.clone();
+                    decompress_packet(Packet { command, payload: compressed.payload.clone() })
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_compression);
+criterion_main!(benches);
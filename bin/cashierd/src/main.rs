@@ -582,7 +582,7 @@ impl Cashierd {
                 cashier_public = addr.public;
             } else {
                 let cashier_secret = SecretKey::random(&mut OsRng);
-                cashier_public = PublicKey::from_secret(cashier_secret);
+                cashier_public = PublicKey::from_secret(cashier_secret.clone());
 
                 self.cashier_wallet
                     .put_withdraw_keys(
@@ -90,7 +90,7 @@ impl MemoryState {
             // ... attempt to decrypt the note ...
             if let Ok(note) = ciphertext.decrypt(secret) {
                 // ... and return the decrypted note for this coin.
-                return Some((note, *secret))
+                return Some((note, secret.clone()))
             }
         }
         // We weren't able to decrypt the note with any of our keys.
@@ -17,6 +17,14 @@ pub enum RpcError {
     NotYetSynced = -32112,
     InvalidAddressParam = -32113,
     InvalidAmountParam = -32114,
+    InvalidTokenIdParam = -32115,
+    NothingToSweep = -32116,
+    InvalidTransaction = -32117,
+    WalletLocked = -32118,
+    WrongPassphrase = -32119,
+    InvalidIdempotencyKey = -32120,
+    TransferInProgress = -32121,
+    IdempotencyKeyReused = -32122,
 }
 
 fn to_tuple(e: RpcError) -> (i64, String) {
@@ -35,6 +43,14 @@ fn to_tuple(e: RpcError) -> (i64, String) {
         RpcError::NotYetSynced => "Blockchain not yet synced",
         RpcError::InvalidAddressParam => "Invalid address parameter",
         RpcError::InvalidAmountParam => "invalid amount parameter",
+        RpcError::InvalidTokenIdParam => "Invalid token ID parameter",
+        RpcError::NothingToSweep => "Not enough coins of the token to sweep",
+        RpcError::InvalidTransaction => "Invalid transaction",
+        RpcError::WalletLocked => "Wallet is locked",
+        RpcError::WrongPassphrase => "Wrong passphrase",
+        RpcError::InvalidIdempotencyKey => "Invalid idempotency key",
+        RpcError::TransferInProgress => "A transfer with this idempotency key is in progress",
+        RpcError::IdempotencyKeyReused => "Idempotency key already used for another transfer",
     };
 
     (e as i64, msg.to_string())
@@ -1,10 +1,14 @@
-use std::str::FromStr;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
 use async_executor::Executor;
 use async_std::sync::{Arc, Mutex};
 use async_trait::async_trait;
 use futures_lite::future;
 use log::{debug, error, info};
+use rand::{rngs::OsRng, RngCore};
 use serde_derive::Deserialize;
 use structopt::StructOpt;
 use structopt_toml::StructOptToml;
@@ -144,14 +148,40 @@ struct Args {
     #[structopt(short, parse(from_occurrences))]
     /// Increase verbosity (-vvv supported)
     verbose: u8,
+
+    #[structopt(long)]
+    /// Log to the given file as well (default is $DARKFI_LOG or /tmp/darkfi.log)
+    log_file: Option<String>,
 }
 
+/// Methods spending coins or changing the keys, refused while the wallet
+/// is locked.
+const LOCKED_METHODS: [&str; 8] = [
+    "tx.transfer",
+    "tx.build",
+    "wallet.keygen",
+    "wallet.export_keypair",
+    "wallet.import_keypair",
+    "wallet.set_default_address",
+    "wallet.set_default",
+    "wallet.sweep",
+];
+
 pub struct Darkfid {
     synced: Mutex<bool>, // AtomicBool is weird in Arc
+    // kept in the wallet as well, so it stays locked across restarts
+    wallet_locked: Mutex<bool>,
+    // only a salted argon2 hash of the wallet password is kept, to check
+    // the passphrase given to unlock the wallet
+    wallet_pass_hash: String,
     _consensus_p2p: Option<P2pPtr>,
     sync_p2p: Option<P2pPtr>,
     client: Arc<Client>,
     validator_state: ValidatorStatePtr,
+    // idempotency keys of the tx.transfer requests being handled
+    transfers_in_flight: Mutex<HashSet<String>>,
+    // transfers built with tx.build by tx ID, recorded once broadcast
+    built_transfers: Mutex<HashMap<String, rpc_tx::SentTransfer>>,
 }
 
 // JSON-RPC methods
@@ -169,12 +199,22 @@ impl RequestHandler for Darkfid {
 
         let params = req.params.as_array().unwrap();
 
+        if let Some(method) = req.method.as_str() {
+            if LOCKED_METHODS.contains(&method) && *self.wallet_locked.lock().await {
+                return server_error(RpcError::WalletLocked, req.id)
+            }
+        }
+
         match req.method.as_str() {
             Some("ping") => return self.pong(req.id, params).await,
             Some("clock") => return self.clock(req.id, params).await,
+            Some("system.version") => return self.version(req.id, params).await,
             Some("blockchain.get_slot") => return self.get_slot(req.id, params).await,
             Some("blockchain.merkle_roots") => return self.merkle_roots(req.id, params).await,
             Some("tx.transfer") => return self.transfer(req.id, params).await,
+            Some("tx.build") => return self.build(req.id, params).await,
+            Some("tx.broadcast") => return self.broadcast(req.id, params).await,
+            Some("tx.history") => return self.history(req.id, params).await,
             Some("wallet.keygen") => return self.keygen(req.id, params).await,
             Some("wallet.get_key") => return self.get_key(req.id, params).await,
             Some("wallet.export_keypair") => return self.export_keypair(req.id, params).await,
@@ -182,7 +222,13 @@ impl RequestHandler for Darkfid {
             Some("wallet.set_default_address") => {
                 return self.set_default_address(req.id, params).await
             }
+            Some("wallet.set_default") => return self.set_default(req.id, params).await,
             Some("wallet.get_balances") => return self.get_balances(req.id, params).await,
+            Some("wallet.get_transactions") => return self.get_transactions(req.id, params).await,
+            Some("wallet.sweep") => return self.sweep(req.id, params).await,
+            Some("wallet.lock") => return self.lock(req.id, params).await,
+            Some("wallet.unlock") => return self.unlock(req.id, params).await,
+            Some("wallet.is_locked") => return self.is_locked(req.id, params).await,
             Some(_) | None => return JsonError::new(MethodNotFound, None, req.id).into(),
         }
     }
@@ -193,17 +239,36 @@ impl Darkfid {
         validator_state: ValidatorStatePtr,
         consensus_p2p: Option<P2pPtr>,
         sync_p2p: Option<P2pPtr>,
+        wallet_pass: &str,
     ) -> Result<Self> {
         debug!("Waiting for validator state lock");
         let client = validator_state.read().await.client.clone();
         debug!("Released validator state lock");
 
+        // A wallet is only locked once explicitly locked
+        let wallet_locked = client.wallet.get_locked().await?.unwrap_or(false);
+
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let config = argon2::Config { variant: argon2::Variant::Argon2id, ..Default::default() };
+        let wallet_pass_hash = match argon2::hash_encoded(wallet_pass.as_bytes(), &salt, &config) {
+            Ok(hash) => hash,
+            Err(e) => {
+                error!("Failed hashing the wallet password: {}", e);
+                return Err(Error::ParseFailed("Failed hashing the wallet password"))
+            }
+        };
+
         Ok(Self {
             synced: Mutex::new(false),
+            wallet_locked: Mutex::new(wallet_locked),
+            wallet_pass_hash,
             _consensus_p2p: consensus_p2p,
             sync_p2p,
             client,
             validator_state,
+            transfers_in_flight: Mutex::new(HashSet::new()),
+            built_transfers: Mutex::new(HashMap::new()),
         })
     }
 }
@@ -393,7 +458,9 @@ async fn realmain(args: Args, ex: Arc<Executor<'_>>) -> Result<()> {
     };
 
     // Initialize program state
-    let darkfid = Darkfid::new(state.clone(), consensus_p2p.clone(), sync_p2p.clone()).await?;
+    let darkfid =
+        Darkfid::new(state.clone(), consensus_p2p.clone(), sync_p2p.clone(), &args.wallet_pass)
+            .await?;
     let darkfid = Arc::new(darkfid);
 
     // JSON-RPC server
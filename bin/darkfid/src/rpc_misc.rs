@@ -23,4 +23,12 @@ impl Darkfid {
     pub async fn clock(&self, id: Value, _params: &[Value]) -> JsonResult {
         JsonResponse::new(json!(Timestamp::current_time()), id).into()
     }
+
+    // RPCAPI:
+    // Returns the version of darkfid.
+    // --> {"jsonrpc": "2.0", "method": "system.version", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "0.3.0", "id": 1}
+    pub async fn version(&self, id: Value, _params: &[Value]) -> JsonResult {
+        JsonResponse::new(json!(env!("CARGO_PKG_VERSION")), id).into()
+    }
 }
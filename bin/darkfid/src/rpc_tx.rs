@@ -1,83 +1,114 @@
 use std::str::FromStr;
 
-use log::{error, warn};
+use log::{error, info, warn};
+use num_bigint::BigUint;
 use serde_json::{json, Value};
 
 use darkfi::{
-    crypto::{address::Address, keypair::PublicKey, token_id::generate_id},
+    crypto::{address::Address, keypair::PublicKey, token_id::generate_id, types::DrkTokenId},
     rpc::jsonrpc::{
         ErrorCode::{InternalError, InvalidParams},
         JsonError, JsonResponse, JsonResult,
     },
-    util::{decode_base10, serial::serialize, NetworkName},
+    tx::Transaction,
+    util::{
+        decode_base10, encode_base10,
+        serial::{deserialize_hex, serialize, serialize_hex},
+        NetworkName,
+    },
 };
 
-use super::Darkfid;
+use super::{rpc_wallet::token_id_from_str, Darkfid};
 use crate::{server_error, RpcError};
 
+/// Whether `key` can be used as an idempotency key for `tx.transfer`.
+fn is_idempotency_key(key: &str) -> bool {
+    (1..=64).contains(&key.len()) &&
+        key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Recipient, amount and token of a transfer, recorded in the wallet
+/// history once its transaction is broadcast.
+pub struct SentTransfer {
+    address: Address,
+    amount: u64,
+    token_id: DrkTokenId,
+}
+
+/// A transfer transaction built from the `tx.transfer` or `tx.build`
+/// params, not broadcast yet.
+struct TransferTx {
+    tx: Transaction,
+    change: u64,
+    sent: SentTransfer,
+}
+
 impl Darkfid {
-    // RPCAPI:
-    // Transfer a given amount of some token to the given address.
-    // Returns a transaction ID upon success.
-    // --> {"jsonrpc": "2.0", "method": "tx.transfer", "params": ["darkfi" "gdrk", "1DarkFi...", 12.0], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "txID...", "id": 1}
-    pub async fn transfer(&self, id: Value, params: &[Value]) -> JsonResult {
+    /// Parse the `[network, token, address, amount]` params shared by
+    /// `tx.transfer` and `tx.build`, and build the transaction. The amount
+    /// is an integer in base units, or a float from older clients. The
+    /// error is the reply to send back.
+    async fn build_transfer(
+        &self,
+        id: &Value,
+        params: &[Value],
+        method: &str,
+    ) -> std::result::Result<TransferTx, JsonResult> {
         if params.len() != 4 ||
             !params[0].is_string() ||
             !params[1].is_string() ||
             !params[2].is_string() ||
-            !params[3].is_f64()
+            !(params[3].is_u64() || params[3].is_f64())
         {
-            return JsonError::new(InvalidParams, None, id).into()
+            return Err(JsonError::new(InvalidParams, None, id.clone()).into())
         }
 
         let network = params[0].as_str().unwrap();
         let token = params[1].as_str().unwrap();
         let address = params[2].as_str().unwrap();
-        let amount = params[3].as_f64().unwrap();
-
-        if !(*self.synced.lock().await) {
-            error!("transfer(): Blockchain is not yet synced");
-            return server_error(RpcError::NotYetSynced, id)
-        }
 
         let address = match Address::from_str(address) {
             Ok(v) => v,
             Err(e) => {
-                error!("transfer(): Failed parsing address from string: {}", e);
-                return server_error(RpcError::InvalidAddressParam, id)
+                error!("{}(): Failed parsing address from string: {}", method, e);
+                return Err(server_error(RpcError::InvalidAddressParam, id.clone()))
             }
         };
 
         let pubkey = match PublicKey::try_from(address) {
             Ok(v) => v,
             Err(e) => {
-                error!("transfer(): Failed parsing PublicKey from Address: {}", e);
-                return server_error(RpcError::ParseError, id)
+                error!("{}(): Failed parsing PublicKey from Address: {}", method, e);
+                return Err(server_error(RpcError::ParseError, id.clone()))
             }
         };
 
-        let amount = amount.to_string();
-        let amount = match decode_base10(&amount, 8, true) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("transfer(): Failed parsing amount from string: {}", e);
-                return server_error(RpcError::InvalidAmountParam, id)
-            }
-        };
-        let amount: u64 = match amount.try_into() {
-            Ok(v) => v,
-            Err(e) => {
-                error!("transfer(): Failed converting biguint to u64: {}", e);
-                return JsonError::new(InternalError, None, id).into()
+        let amount = match params[3].as_u64() {
+            Some(v) => v,
+            None => {
+                let amount = params[3].as_f64().unwrap().to_string();
+                let amount = match decode_base10(&amount, 8, true) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("{}(): Failed parsing amount from string: {}", method, e);
+                        return Err(server_error(RpcError::InvalidAmountParam, id.clone()))
+                    }
+                };
+                match amount.try_into() {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("{}(): Failed converting biguint to u64: {}", method, e);
+                        return Err(JsonError::new(InternalError, None, id.clone()).into())
+                    }
+                }
             }
         };
 
         let network = match NetworkName::from_str(network) {
             Ok(v) => v,
             Err(e) => {
-                error!("transfer(): Failed parsing NetworkName: {}", e);
-                return server_error(RpcError::NetworkNameError, id)
+                error!("{}(): Failed parsing NetworkName: {}", method, e);
+                return Err(server_error(RpcError::NetworkNameError, id.clone()))
             }
         };
 
@@ -88,13 +119,13 @@ impl Darkfid {
                 match generate_id(&network, token) {
                     Ok(v) => v,
                     Err(e) => {
-                        error!("transfer(): Failed generate_id(): {}", e);
-                        return JsonError::new(InternalError, None, id).into()
+                        error!("{}(): Failed generate_id(): {}", method, e);
+                        return Err(JsonError::new(InternalError, None, id.clone()).into())
                     }
                 }
             };
 
-        let tx = match self
+        let (tx, change) = match self
             .client
             .build_transaction(
                 pubkey,
@@ -107,13 +138,121 @@ impl Darkfid {
         {
             Ok(v) => v,
             Err(e) => {
-                error!("transfer(): Failed building transaction: {}", e);
-                return server_error(RpcError::TxBuildFail, id)
+                error!("{}(): Failed building transaction: {}", method, e);
+                return Err(server_error(RpcError::TxBuildFail, id.clone()))
+            }
+        };
+
+        Ok(TransferTx { tx, change, sent: SentTransfer { address, amount, token_id } })
+    }
+
+    /// Record a broadcast transfer in the wallet history.
+    async fn record_transfer(&self, sent: &SentTransfer, tx_hash: &str, method: &str) {
+        if let Err(e) = self
+            .client
+            .wallet
+            .put_sent_transaction(tx_hash, &sent.address.to_string(), sent.amount, sent.token_id)
+            .await
+        {
+            warn!("{}(): Failed recording transaction in the wallet: {}", method, e);
+        }
+    }
+
+    // RPCAPI:
+    // Transfer a given amount of some token to the given address.
+    // Returns the transaction ID, the fee paid and the value of the change
+    // returned to the wallet upon success. The amount is in base units,
+    // floats are still accepted and taken with 8 decimals.
+    // An optional idempotency key of up to 64 letters, digits, `-` or `_`
+    // can be given last. A retry with the same key and params returns the
+    // reply to the first request instead of sending another transaction.
+    // --> {"jsonrpc": "2.0", "method": "tx.transfer", "params": ["darkfi" "gdrk", "1DarkFi...", 1200000000, "f3a9..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"tx_id": "txID...", "fee": "0", "change": "3.5"}, "id": 1}
+    pub async fn transfer(&self, id: Value, params: &[Value]) -> JsonResult {
+        if !(*self.synced.lock().await) {
+            error!("transfer(): Blockchain is not yet synced");
+            return server_error(RpcError::NotYetSynced, id)
+        }
+
+        if params.len() != 5 {
+            return self.send_transfer(id, params).await
+        }
+
+        let key = match params[4].as_str().filter(|key| is_idempotency_key(key)) {
+            Some(v) => v.to_string(),
+            None => return server_error(RpcError::InvalidIdempotencyKey, id),
+        };
+        let params = &params[..4];
+        let params_hash = blake3::hash(Value::from(params).to_string().as_bytes()).to_hex();
+
+        if !self.transfers_in_flight.lock().await.insert(key.clone()) {
+            warn!("transfer(): Transfer {} is already in progress", key);
+            return server_error(RpcError::TransferInProgress, id)
+        }
+
+        let reply = self.transfer_once(id, params, &key, params_hash.as_str()).await;
+        self.transfers_in_flight.lock().await.remove(&key);
+        reply
+    }
+
+    /// Reply to a `tx.transfer` with an idempotency key, sending the
+    /// transfer only if the key wasn't used yet.
+    async fn transfer_once(
+        &self,
+        id: Value,
+        params: &[Value],
+        key: &str,
+        params_hash: &str,
+    ) -> JsonResult {
+        match self.client.wallet.get_transfer_reply(key).await {
+            Ok(Some((hash, _))) if hash != params_hash => {
+                error!("transfer(): Idempotency key {} was used for another transfer", key);
+                return server_error(RpcError::IdempotencyKeyReused, id)
+            }
+            Ok(Some((_, reply))) => match serde_json::from_str(&reply) {
+                Ok(v) => {
+                    info!(
+                        "transfer(): Transfer {} was already sent, replying with its result",
+                        key
+                    );
+                    return JsonResponse::new(v, id).into()
+                }
+                Err(e) => {
+                    error!("transfer(): Failed decoding the reply to transfer {}: {}", key, e);
+                    return JsonError::new(InternalError, None, id).into()
+                }
+            },
+            Ok(None) => {}
+            Err(e) => {
+                error!("transfer(): Failed looking up transfer {}: {}", key, e);
+                return JsonError::new(InternalError, None, id).into()
             }
+        }
+
+        let reply = self.send_transfer(id, params).await;
+        if let JsonResult::Response(resp) = &reply {
+            if let Err(e) = self
+                .client
+                .wallet
+                .put_transfer_reply(key, params_hash, &resp.result.to_string())
+                .await
+            {
+                warn!("transfer(): Failed recording the reply to transfer {}: {}", key, e);
+            }
+        }
+
+        reply
+    }
+
+    /// Build and broadcast the transfer given by the `tx.transfer` params.
+    async fn send_transfer(&self, id: Value, params: &[Value]) -> JsonResult {
+        let transfer = match self.build_transfer(&id, params, "transfer").await {
+            Ok(v) => v,
+            Err(reply) => return reply,
         };
 
         if let Some(sync_p2p) = &self.sync_p2p {
-            match sync_p2p.broadcast(tx.clone()).await {
+            match sync_p2p.broadcast(transfer.tx.clone()).await {
                 Ok(()) => {}
                 Err(e) => {
                     error!("transfer(): Failed broadcasting transaction: {}", e);
@@ -124,7 +263,105 @@ impl Darkfid {
             warn!("No sync P2P network, not broadcasting transaction.");
         }
 
+        let tx_hash = blake3::hash(&serialize(&transfer.tx)).to_hex().as_str().to_string();
+        self.record_transfer(&transfer.sent, &tx_hash, "transfer").await;
+
+        // Transactions don't carry a fee yet.
+        let decimals = self.token_decimals(transfer.sent.token_id);
+        let fee = encode_base10(BigUint::from(0_u64), decimals);
+        let change = encode_base10(BigUint::from(transfer.change), decimals);
+
+        JsonResponse::new(json!({"tx_id": tx_hash, "fee": fee, "change": change}), id).into()
+    }
+
+    // RPCAPI:
+    // Build a transfer like tx.transfer, without broadcasting it, e.g. on an
+    // offline machine holding the wallet. The coins spent are marked as such
+    // in the wallet. Returns the serialized transaction as hex along with its
+    // ID and the change, to be submitted later with tx.broadcast. The transfer
+    // shows in the wallet history once this daemon broadcasts it.
+    // --> {"jsonrpc": "2.0", "method": "tx.build", "params": ["darkfi" "gdrk", "1DarkFi...", 1200000000], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"tx": "0a1b...", "tx_id": "txID...", "change": "3.5"}, "id": 1}
+    pub async fn build(&self, id: Value, params: &[Value]) -> JsonResult {
+        let transfer = match self.build_transfer(&id, params, "build").await {
+            Ok(v) => v,
+            Err(reply) => return reply,
+        };
+
+        let tx_hash = blake3::hash(&serialize(&transfer.tx)).to_hex().as_str().to_string();
+        let decimals = self.token_decimals(transfer.sent.token_id);
+        self.built_transfers.lock().await.insert(tx_hash.clone(), transfer.sent);
+
+        let change = encode_base10(BigUint::from(transfer.change), decimals);
+        let tx = serialize_hex(&transfer.tx);
+        JsonResponse::new(json!({"tx": tx, "tx_id": tx_hash, "change": change}), id).into()
+    }
+
+    // RPCAPI:
+    // Broadcast a transaction built with tx.build, given as hex.
+    // Returns the transaction ID upon success.
+    // --> {"jsonrpc": "2.0", "method": "tx.broadcast", "params": ["0a1b..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "txID...", "id": 1}
+    pub async fn broadcast(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let tx: Transaction = match deserialize_hex(params[0].as_str().unwrap()) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("broadcast(): Failed decoding transaction: {}", e);
+                return server_error(RpcError::InvalidTransaction, id)
+            }
+        };
+
+        let sync_p2p = match &self.sync_p2p {
+            Some(v) => v,
+            None => {
+                error!("broadcast(): No sync P2P network to broadcast the transaction");
+                return server_error(RpcError::TxBroadcastFail, id)
+            }
+        };
+
+        if let Err(e) = sync_p2p.broadcast(tx.clone()).await {
+            error!("broadcast(): Failed broadcasting transaction: {}", e);
+            return server_error(RpcError::TxBroadcastFail, id)
+        }
+
+        // Transactions built elsewhere aren't ours to record
         let tx_hash = blake3::hash(&serialize(&tx)).to_hex().as_str().to_string();
+        if let Some(sent) = self.built_transfers.lock().await.remove(&tx_hash) {
+            self.record_transfer(&sent, &tx_hash, "broadcast").await;
+        }
+
         JsonResponse::new(json!(tx_hash), id).into()
     }
+
+    // RPCAPI:
+    // Lists the latest transactions sent or received by the wallet, newest
+    // first, up to the given number, only those of a token if its ID is
+    // given. Replies like wallet.get_transactions.
+    // --> {"jsonrpc": "2.0", "method": "tx.history", "params": [20, "tokenID..." or null], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"tx_id": "txID...", "direction": "sent", "counterparty": "1DarkFi...", "amount": "1.5", "decimals": 8, "token": "DRK", "token_id": "tokenID...", "network": "DarkFi", "timestamp": 1650000000}], "id": 1}
+    pub async fn history(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.is_empty() ||
+            params.len() > 2 ||
+            !params[0].is_u64() ||
+            !(params.len() == 1 || params[1].is_string() || params[1].is_null())
+        {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let limit = params[0].as_u64().unwrap().min(u32::MAX as u64) as u32;
+
+        let token_id = match params.get(1).and_then(|token| token.as_str()) {
+            Some(token) => match token_id_from_str(token) {
+                Some(v) => Some(v),
+                None => return server_error(RpcError::InvalidTokenIdParam, id),
+            },
+            None => None,
+        };
+
+        self.transactions(id, limit, token_id).await
+    }
 }
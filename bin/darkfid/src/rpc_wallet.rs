@@ -1,5 +1,8 @@
+use std::{str::FromStr, time::Duration};
+
+use async_std::task;
 use fxhash::FxHashMap;
-use log::{error, warn};
+use log::{error, info, warn};
 use num_bigint::BigUint;
 use pasta_curves::group::ff::PrimeField;
 use serde_json::{json, Value};
@@ -8,17 +11,23 @@ use darkfi::{
     crypto::{
         address::Address,
         keypair::{Keypair, PublicKey, SecretKey},
+        types::DrkTokenId,
+        util::base_from_bytes,
     },
     rpc::jsonrpc::{
         ErrorCode::{InternalError, InvalidParams},
         JsonError, JsonResponse, JsonResult,
     },
-    util::{decode_base10, encode_base10, NetworkName},
+    util::{encode_base10, serial::serialize, NetworkName},
+    ClientFailed,
 };
 
 use super::Darkfid;
 use crate::{server_error, RpcError};
 
+/// Wait before answering a wrong passphrase.
+const UNLOCK_FAILURE_DELAY: Duration = Duration::from_secs(1);
+
 impl Darkfid {
     // RPCAPI:
     // Attempts to generate a new keypair and returns its address upon success.
@@ -139,7 +148,7 @@ impl Darkfid {
             }
         };
 
-        let public = PublicKey::from_secret(secret);
+        let public = PublicKey::from_secret(secret.clone());
         let keypair = Keypair { secret, public };
         let address = Address::from(public).to_string();
 
@@ -178,7 +187,49 @@ impl Darkfid {
             return server_error(RpcError::KeypairNotFound, id)
         }
 
-        let kp = keypairs[idx as usize];
+        let kp = &keypairs[idx as usize];
+        match self.client.set_default_keypair(&kp.public).await {
+            Ok(()) => {}
+            Err(e) => {
+                error!("Failed setting default keypair: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        JsonResponse::new(json!(true), id).into()
+    }
+
+    // RPCAPI:
+    // Sets the default wallet address to the given address, which must be
+    // owned by the wallet. Returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "wallet.set_default", "params": ["1DarkFi..."], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn set_default(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let address = match Address::from_str(params[0].as_str().unwrap()) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("set_default(): Failed parsing address from string: {}", e);
+                return server_error(RpcError::InvalidAddressParam, id)
+            }
+        };
+
+        let keypairs = match self.client.get_keypairs().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed fetching keypairs: {}", e);
+                return server_error(RpcError::KeypairFetch, id)
+            }
+        };
+
+        let kp = match keypairs.iter().find(|kp| Address::from(kp.public) == address) {
+            Some(kp) => kp,
+            None => return server_error(RpcError::KeypairNotFound, id),
+        };
+
         match self.client.set_default_keypair(&kp.public).await {
             Ok(()) => {}
             Err(e) => {
@@ -192,9 +243,11 @@ impl Darkfid {
 
     // RPCAPI:
     // Queries the wallet for known balances.
-    // Returns a map of balances, indexed by `network`, and token ID.
+    // Returns a map of balances, indexed by ticker (or token ID if unknown),
+    // with the amount, network, network token address, token ID and the
+    // token's decimals.
     // --> {"jsonrpc": "2.0", "method": "wallet.get_balances", "params": [], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": [{"btc": [100, "Bitcoin"]}, {...}], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"btc": ["1.5", "bitcoin", "...", "...", 8]}, "id": 1}
     pub async fn get_balances(&self, id: Value, _params: &[Value]) -> JsonResult {
         let balances = match self.client.get_balances().await {
             Ok(v) => v,
@@ -204,52 +257,284 @@ impl Darkfid {
             }
         };
 
-        // k: ticker/drk_addr, v: (amount, network, net_addr, drk_addr)
-        let mut ret: FxHashMap<String, (String, String, String, String)> = FxHashMap::default();
+        // k: ticker/drk_addr, v: (amount, network, net_addr, drk_addr, decimals)
+        let mut sums: FxHashMap<String, (BigUint, String, String, String, u64)> =
+            FxHashMap::default();
 
         for balance in balances.list {
             let drk_addr = bs58::encode(balance.token_id.to_repr()).into_string();
-            let mut amount = BigUint::from(balance.value);
+            let (net_name, net_addr, decimals, ticker) = self.token_info(&drk_addr);
 
-            let (net_name, net_addr) =
-                if let Some((net, tok)) = self.client.tokenlist.by_addr.get(&drk_addr) {
-                    (net, tok.net_address.clone())
-                } else {
-                    warn!("Could not find network name and token info for {}", drk_addr);
-                    (&NetworkName::DarkFi, "unknown".to_string())
-                };
-
-            let mut ticker = None;
-            for (k, v) in self.client.tokenlist.by_net[net_name].0.iter() {
-                if v.net_address == net_addr {
-                    ticker = Some(k.clone());
-                    break
-                }
+            let entry = sums.entry(ticker).or_insert_with(|| {
+                (BigUint::from(0_u64), net_name.to_string(), net_addr, drk_addr, decimals)
+            });
+            entry.0 += balance.value;
+        }
+
+        let ret: FxHashMap<String, (String, String, String, String, u64)> = sums
+            .into_iter()
+            .map(|(ticker, (amount, net_name, net_addr, drk_addr, decimals))| {
+                let amount = encode_base10(amount, decimals as usize);
+                (ticker, (amount, net_name, net_addr, drk_addr, decimals))
+            })
+            .collect();
+
+        JsonResponse::new(json!(ret), id).into()
+    }
+
+    // RPCAPI:
+    // Lists the latest transactions sent or received by the wallet, newest
+    // first, up to the given number. Senders are unknown, so the
+    // counterparty of received transactions is null. Tokens missing from
+    // the token list are named by their token ID.
+    // --> {"jsonrpc": "2.0", "method": "wallet.get_transactions", "params": [20], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"tx_id": "txID...", "direction": "sent", "counterparty": "1DarkFi...", "amount": "1.5", "decimals": 8, "token": "DRK", "token_id": "tokenID...", "network": "DarkFi", "timestamp": 1650000000}], "id": 1}
+    pub async fn get_transactions(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_u64() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let limit = params[0].as_u64().unwrap().min(u32::MAX as u64) as u32;
+        self.transactions(id, limit, None).await
+    }
+
+    /// Reply with the `limit` latest transactions of the wallet, only those
+    /// of `token_id` if given, as described for `wallet.get_transactions`.
+    pub(crate) async fn transactions(
+        &self,
+        id: Value,
+        limit: u32,
+        token_id: Option<DrkTokenId>,
+    ) -> JsonResult {
+        let transactions = match self.client.wallet.get_transactions(limit, token_id).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed fetching transactions from wallet: {}", e);
+                return JsonError::new(InternalError, None, id).into()
             }
+        };
 
-            if ticker.is_none() {
-                ticker = Some(drk_addr.clone())
+        let mut ret = vec![];
+        for tx in transactions {
+            let drk_addr = bs58::encode(tx.token_id.to_repr()).into_string();
+            let (net_name, _, decimals, ticker) = self.token_info(&drk_addr);
+
+            ret.push(json!({
+                "tx_id": tx.tx_id,
+                "direction": tx.direction.as_str(),
+                "counterparty": tx.counterparty,
+                "amount": encode_base10(BigUint::from(tx.value), decimals as usize),
+                "decimals": decimals,
+                "token": ticker,
+                "token_id": drk_addr,
+                "network": net_name.to_string(),
+                "timestamp": tx.timestamp.0,
+            }));
+        }
+
+        JsonResponse::new(json!(ret), id).into()
+    }
+
+    // RPCAPI:
+    // Consolidates the coins of a token owned by the wallet into a single
+    // coin of the default address, for every token with more than one coin
+    // unless a token ID is given. With `dry_run`, nothing is spent and the
+    // transaction IDs are null. Returns, for each swept token, the number
+    // of coins spent, their value, the fee paid and the transaction ID.
+    // --> {"jsonrpc": "2.0", "method": "wallet.sweep", "params": ["tokenID..." or null, false], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"token_id": "tokenID...", "coins": 12, "value": "3.5", "fee": "0", "tx_id": "txID..."}], "id": 1}
+    pub async fn sweep(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 2 ||
+            !(params[0].is_string() || params[0].is_null()) ||
+            !params[1].is_boolean()
+        {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let dry_run = params[1].as_bool().unwrap();
+
+        let token_id = match params[0].as_str() {
+            Some(token) => match token_id_from_str(token) {
+                Some(v) => Some(v),
+                None => return server_error(RpcError::InvalidTokenIdParam, id),
+            },
+            None => None,
+        };
+
+        if !dry_run && !(*self.synced.lock().await) {
+            error!("sweep(): Blockchain is not yet synced");
+            return server_error(RpcError::NotYetSynced, id)
+        }
+
+        let own_coins = match self.client.get_own_coins().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("sweep(): Failed fetching coins from wallet: {}", e);
+                return JsonError::new(InternalError, None, id).into()
             }
+        };
 
-            let ticker = ticker.unwrap();
+        // (token ID, number of coins, value)
+        let mut tokens: Vec<(DrkTokenId, usize, u64)> = vec![];
+        for own_coin in own_coins.iter() {
+            let tok = own_coin.note.token_id;
+            if token_id.is_some_and(|token_id| token_id != tok) {
+                continue
+            }
+            match tokens.iter_mut().find(|(t, _, _)| *t == tok) {
+                Some((_, coins, value)) => {
+                    *coins += 1;
+                    *value = value.saturating_add(own_coin.note.value);
+                }
+                None => tokens.push((tok, 1, own_coin.note.value)),
+            }
+        }
+        tokens.retain(|(_, coins, _)| *coins > 1);
+
+        if token_id.is_some() && tokens.is_empty() {
+            return server_error(RpcError::NothingToSweep, id)
+        }
 
-            if let Some(prev) = ret.get(&ticker) {
-                // TODO: We shouldn't be hardcoding everything to 8 decimals.
-                let prev_amnt = match decode_base10(&prev.0, 8, false) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!("Failed to decode_base10(): {}", e);
-                        return JsonError::new(InternalError, None, id).into()
+        let mut ret = vec![];
+        for (token_id, coins, value) in tokens {
+            let mut tx_hash = None;
+            let mut swept = (coins, value);
+
+            if !dry_run {
+                let state = self.validator_state.read().await.state_machine.clone();
+                let (tx, coins, value) =
+                    match self.client.build_sweep_transaction(token_id, state).await {
+                        Ok(v) => v,
+                        Err(ClientFailed::NothingToSweep(_)) => continue,
+                        Err(e) => {
+                            error!("sweep(): Failed building transaction: {}", e);
+                            return server_error(RpcError::TxBuildFail, id)
+                        }
+                    };
+
+                if let Some(sync_p2p) = &self.sync_p2p {
+                    if let Err(e) = sync_p2p.broadcast(tx.clone()).await {
+                        error!("sweep(): Failed broadcasting transaction: {}", e);
+                        return server_error(RpcError::TxBroadcastFail, id)
                     }
-                };
+                } else {
+                    warn!("No sync P2P network, not broadcasting transaction.");
+                }
 
-                amount += prev_amnt;
+                let hash = blake3::hash(&serialize(&tx)).to_hex().as_str().to_string();
+                let address = Address::from(self.client.main_keypair.lock().await.public);
+                if let Err(e) = self
+                    .client
+                    .wallet
+                    .put_sent_transaction(&hash, &address.to_string(), value, token_id)
+                    .await
+                {
+                    warn!("sweep(): Failed recording transaction in the wallet: {}", e);
+                }
+
+                tx_hash = Some(hash);
+                swept = (coins, value);
             }
 
-            let amount = encode_base10(amount, 8);
-            ret.insert(ticker, (amount, net_name.to_string(), net_addr, drk_addr));
+            let decimals = self.token_decimals(token_id);
+
+            // Transactions don't carry a fee yet.
+            ret.push(json!({
+                "token_id": bs58::encode(token_id.to_repr()).into_string(),
+                "coins": swept.0,
+                "value": encode_base10(BigUint::from(swept.1), decimals),
+                "fee": encode_base10(BigUint::from(0_u64), decimals),
+                "tx_id": tx_hash,
+            }));
         }
 
         JsonResponse::new(json!(ret), id).into()
     }
+
+    // RPCAPI:
+    // Locks the wallet: until it's unlocked, the methods spending coins or
+    // changing the keys fail with "Wallet is locked". The wallet stays
+    // locked across restarts. Returns `true`.
+    // --> {"jsonrpc": "2.0", "method": "wallet.lock", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn lock(&self, id: Value, _params: &[Value]) -> JsonResult {
+        let mut locked = self.wallet_locked.lock().await;
+        if let Err(e) = self.client.wallet.put_locked(true).await {
+            error!("Failed saving the wallet lock state: {}", e);
+            return JsonError::new(InternalError, None, id).into()
+        }
+        *locked = true;
+        info!("Wallet locked");
+        JsonResponse::new(json!(true), id).into()
+    }
+
+    // RPCAPI:
+    // Unlocks the wallet given its password. Returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "wallet.unlock", "params": ["passphrase"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    pub async fn unlock(&self, id: Value, params: &[Value]) -> JsonResult {
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let passphrase = params[0].as_str().unwrap().as_bytes();
+        if !argon2::verify_encoded(&self.wallet_pass_hash, passphrase).unwrap_or(false) {
+            warn!("Wrong passphrase given to unlock the wallet");
+            // Slow down guessing the passphrase
+            task::sleep(UNLOCK_FAILURE_DELAY).await;
+            return server_error(RpcError::WrongPassphrase, id)
+        }
+
+        let mut locked = self.wallet_locked.lock().await;
+        if let Err(e) = self.client.wallet.put_locked(false).await {
+            error!("Failed saving the wallet lock state: {}", e);
+            return JsonError::new(InternalError, None, id).into()
+        }
+        *locked = false;
+        info!("Wallet unlocked");
+        JsonResponse::new(json!(true), id).into()
+    }
+
+    // RPCAPI:
+    // Returns whether the wallet is locked.
+    // --> {"jsonrpc": "2.0", "method": "wallet.is_locked", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": false, "id": 1}
+    pub async fn is_locked(&self, id: Value, _params: &[Value]) -> JsonResult {
+        JsonResponse::new(json!(*self.wallet_locked.lock().await), id).into()
+    }
+}
+
+impl Darkfid {
+    /// Network, network token address, decimals and ticker of a token ID
+    /// encoded in base58. Tokens missing from the token list are named by
+    /// their token ID.
+    fn token_info(&self, drk_addr: &str) -> (NetworkName, String, u64, String) {
+        let (net_name, net_addr, decimals) =
+            if let Some((net, tok)) = self.client.tokenlist.by_addr.get(drk_addr) {
+                (net.clone(), tok.net_address.clone(), tok.decimals)
+            } else {
+                warn!("Could not find network name and token info for {}", drk_addr);
+                (NetworkName::DarkFi, "unknown".to_string(), 8)
+            };
+
+        let ticker = self.client.tokenlist.by_net[&net_name]
+            .0
+            .iter()
+            .find(|(_, v)| v.net_address == net_addr)
+            .map_or_else(|| drk_addr.to_string(), |(k, _)| k.clone());
+
+        (net_name, net_addr, decimals, ticker)
+    }
+
+    /// Decimals of a token, 8 for tokens missing from the token list.
+    pub(crate) fn token_decimals(&self, token_id: DrkTokenId) -> usize {
+        let drk_addr = bs58::encode(token_id.to_repr()).into_string();
+        self.token_info(&drk_addr).2 as usize
+    }
+}
+
+/// Parse a token ID encoded in base58, as returned by `wallet.get_balances`.
+pub(crate) fn token_id_from_str(token_id: &str) -> Option<DrkTokenId> {
+    let bytes = bs58::decode(token_id).into_vec().ok()?;
+    base_from_bytes(bytes.try_into().ok()?).ok()
 }
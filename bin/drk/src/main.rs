@@ -1,20 +1,47 @@
-use std::{process::exit, str::FromStr, time::Instant};
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    future::Future,
+    io::{self, stderr, stdin, stdout, Read, Write},
+    mem::MaybeUninit,
+    net::IpAddr,
+    os::unix::io::AsRawFd,
+    path::PathBuf,
+    process::exit,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use clap::{Parser, Subcommand};
+use async_std::task;
+use clap::{ArgGroup, Parser, Subcommand};
+use prettytable::{format::consts::FORMAT_NO_COLSEP, row, Table};
+use rand::{rngs::OsRng, RngCore};
 
-use serde_json::json;
-use simplelog::{ColorChoice, TermLogger, TerminalMode};
-use url::Url;
+use serde_json::{json, Value};
+use url::{Host, Url};
+use zeroize::{Zeroize, Zeroizing};
 
 use darkfi::{
     cli_desc,
-    crypto::address::Address,
+    crypto::{address::Address, keypair::PublicKey},
+    error::ClientFailed,
     rpc::{client::RpcClient, jsonrpc::JsonRequest},
     util::{
-        cli::{get_log_config, get_log_level},
+        cli::{get_log_config, get_log_level, init_logger},
+        expand_path,
+        parse::{decode_base10, encode_base10},
+        time::{timestamp_to_date, DateFormat},
         NetworkName,
     },
-    Result,
+    Error, Result,
+};
+
+mod output;
+
+use output::{
+    print_json, AddressInspection, Addresses, Airdrop, Balances, BalancesValue, Broadcast, Built,
+    ClientVersion, DefaultAddress, DryRun, Health, History, Locked, Output, Ping, Printer, Sweep,
+    Transfer, Version, WalletAddress, Whoami,
 };
 
 #[derive(Parser)]
@@ -25,19 +52,79 @@ struct Args {
     /// Increase verbosity (-vvv supported)
     verbose: u8,
 
-    #[clap(short, long, default_value = "tcp://127.0.0.1:8340")]
-    /// darkfid JSON-RPC endpoint
-    endpoint: Url,
+    #[clap(long)]
+    /// Log to the given file as well, rotated by size
+    log_file: Option<PathBuf>,
+
+    #[clap(
+        short,
+        long,
+        default_value = "tcp://127.0.0.1:8340",
+        multiple_occurrences(true),
+        use_value_delimiter(true)
+    )]
+    /// darkfid JSON-RPC endpoint (repeat or comma-separate for failover)
+    endpoint: Vec<Url>,
+
+    #[clap(long)]
+    /// Send `ping` or `wallet --balance` to every endpoint at once and
+    /// compare their replies
+    all_endpoints: bool,
+
+    #[clap(long)]
+    /// Don't warn about plaintext connections to remote endpoints
+    insecure: bool,
+
+    #[clap(long, default_value = "30s", parse(try_from_str = parse_duration))]
+    /// Timeout for connecting and for each RPC call (e.g. 500ms, 10s, 2m)
+    timeout: Duration,
+
+    #[clap(long)]
+    /// Print amounts as received, without formatting
+    raw: bool,
+
+    #[clap(long)]
+    /// Don't show a spinner while waiting for replies
+    no_progress: bool,
+
+    #[clap(long)]
+    /// Don't color the output (also disabled by NO_COLOR or when not on a terminal)
+    no_color: bool,
+
+    #[clap(long)]
+    /// Group the integer digits of amounts by thousands with this separator
+    thousands_separator: Option<char>,
+
+    #[clap(long, global = true)]
+    /// Print results and errors as JSON, one object per line
+    json: bool,
+
+    #[clap(subcommand)]
+    command: DrkSubcommand,
+}
 
+/// A line read in the REPL, parsed like the drk arguments.
+#[derive(Parser)]
+#[clap(no_binary_name(true))]
+struct ReplLine {
     #[clap(subcommand)]
     command: DrkSubcommand,
 }
 
 #[derive(Subcommand)]
 enum DrkSubcommand {
-    /// Send a ping request to the RPC
+    /// Send a ping request to the RPC, showing the endpoint, darkfid
+    /// version and latency
     Ping,
 
+    /// Check that darkfid replies to a ping, for monitoring. Prints a single
+    /// status line and exits with 0 when healthy, 2 otherwise
+    Healthcheck {
+        #[clap(short, long)]
+        /// Print nothing, only set the exit code
+        quiet: bool,
+    },
+
     /// Send an airdrop request to the faucet
     Airdrop {
         #[clap(long, parse(try_from_str))]
@@ -49,20 +136,42 @@ enum DrkSubcommand {
         /// JSON-RPC endpoint of the faucet
         faucet_endpoint: Url,
 
-        /// f64 amount requested for airdrop
-        amount: f64,
+        /// Amount requested for airdrop
+        #[clap(parse(try_from_str = parse_drk_amount))]
+        amount: u64,
     },
 
     /// Wallet operations
+    #[clap(group(ArgGroup::new("operation").required(true).args(&[
+        "keygen", "balance", "address", "all-addresses", "default-address", "lock", "unlock"
+    ])))]
     Wallet {
         #[clap(long)]
         /// Generate a new keypair in the wallet
         keygen: bool,
 
+        #[clap(long, requires = "keygen")]
+        /// Number of keypairs to generate, printing one address per line
+        count: Option<u32>,
+
         #[clap(long)]
         /// Query the wallet for known balances
         balance: bool,
 
+        #[clap(long, requires = "balance", conflicts_with = "price-endpoint")]
+        /// Also show the value of the balances, from a JSON file mapping
+        /// tickers or token IDs to their rate in the reference unit
+        prices: Option<PathBuf>,
+
+        #[clap(long, requires = "balance")]
+        /// Also show the value of the balances, from the `prices.get`
+        /// method of this JSON-RPC endpoint
+        price_endpoint: Option<Url>,
+
+        #[clap(long, default_value = "USD")]
+        /// Reference unit the balances are valued in
+        unit: String,
+
         #[clap(long)]
         /// Get the default address in the wallet
         address: bool,
@@ -70,16 +179,115 @@ enum DrkSubcommand {
         #[clap(long)]
         /// Get all addresses in the wallet
         all_addresses: bool,
+
+        #[clap(long, parse(try_from_str))]
+        /// Set the default address in the wallet
+        default_address: Option<Address>,
+
+        #[clap(long)]
+        /// Lock the wallet, refusing transfers and key changes until it's
+        /// unlocked
+        lock: bool,
+
+        #[clap(long)]
+        /// Unlock the wallet, prompting for its passphrase
+        unlock: bool,
     },
 
+    /// Show the default address, number of addresses and balances of the wallet
+    Whoami,
+
     /// Transfer of value
     Transfer {
+        /// Recipient address, or "-" to read transfers from stdin, one per
+        /// line as `<recipient> <amount> [token_id]` or as a JSON object
+        /// with the same fields
+        recipient: String,
+
+        /// Amount to transfer, unless reading from stdin
+        amount: Option<String>,
+
+        /// Coin network
+        #[clap(short, long, default_value = "darkfi", parse(try_from_str))]
+        network: NetworkName,
+
+        /// Token ID, the default for transfers read from stdin
+        #[clap(short, long)]
+        token_id: Option<String>,
+
+        #[clap(long)]
+        /// Validate and print the transfers without sending them
+        dry_run: bool,
+
+        #[clap(long)]
+        /// Send the transfers even if the wallet balance looks too low
+        skip_balance_check: bool,
+    },
+
+    /// Consolidate the coins of a token into a single one, making later
+    /// transfers smaller
+    Sweep {
+        #[clap(long)]
+        /// Ticker or token ID to sweep (default is every token with more
+        /// than one coin)
+        token: Option<String>,
+
+        #[clap(long)]
+        /// Show the coins that would be consolidated without spending them
+        dry_run: bool,
+    },
+
+    /// List the latest transactions of the wallet, newest first
+    History {
+        #[clap(long, default_value = "20")]
+        /// Number of transactions to show
+        limit: u32,
+    },
+
+    /// Decode addresses
+    Address {
+        #[clap(subcommand)]
+        command: AddressSubcommand,
+    },
+
+    /// Build transactions offline and broadcast them later
+    Tx {
+        #[clap(subcommand)]
+        command: TxSubcommand,
+    },
+
+    /// Show the version of drk
+    Version {
+        #[clap(long)]
+        /// Also query the version of darkfid
+        full: bool,
+    },
+
+    /// Read subcommands line by line, reusing the RPC connection
+    /// (exit with `quit` or EOF)
+    Repl,
+}
+
+#[derive(Subcommand)]
+enum AddressSubcommand {
+    /// Decode an address and check it, printing its type, public key and
+    /// checksum, or the validation step it fails at
+    Inspect {
+        /// Address to inspect
+        address: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TxSubcommand {
+    /// Build and sign a transfer without broadcasting it, writing it to a
+    /// file. Run against a darkfid holding the wallet, which can be offline.
+    Build {
         /// Recipient address
-        #[clap(parse(try_from_str))]
-        recipient: Address,
+        recipient: String,
 
         /// Amount to transfer
-        amount: f64,
+        amount: String,
 
         /// Coin network
         #[clap(short, long, default_value = "darkfi", parse(try_from_str))]
@@ -87,141 +295,2112 @@ enum DrkSubcommand {
 
         /// Token ID
         #[clap(short, long)]
-        token_id: String,
+        token_id: Option<String>,
+
+        /// File to write the transaction to
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+
+    /// Broadcast a transaction file written by `drk tx build`
+    Broadcast {
+        /// Transaction file
+        file: PathBuf,
+    },
+
+    /// List the latest transactions of the wallet, newest first
+    List {
+        #[clap(long, default_value = "20")]
+        /// Number of transactions to show
+        limit: u32,
+
+        #[clap(short, long)]
+        /// Only show the transactions of this token ID
+        token_id: Option<String>,
     },
 }
 
-struct Drk {
-    pub rpc_client: RpcClient,
+/// Where `drk wallet --balance` gets the rates to value the balances.
+enum PriceSource {
+    /// JSON file mapping tickers or token IDs to rates
+    File(PathBuf),
+    /// JSON-RPC endpoint replying to `prices.get` with such a mapping
+    Endpoint(Url),
 }
 
-impl Drk {
-    async fn close_connection(&self) -> Result<()> {
-        self.rpc_client.close().await
+/// Version of the transaction files written by `drk tx build`.
+const TX_FILE_VERSION: u64 = 1;
+
+/// Exit code when darkfid can't be reached or doesn't reply in time.
+const EXIT_CONNECTION_ERROR: i32 = 3;
+
+/// Exit code of `drk healthcheck` when darkfid is unhealthy, CRITICAL for
+/// Nagios-style monitoring.
+const EXIT_UNHEALTHY: i32 = 2;
+
+/// Exit code when `--all-endpoints` gets different replies from the
+/// endpoints.
+const EXIT_DIVERGENT: i32 = 4;
+
+/// Parse a duration made of a number and a unit: `ms`, `s`, `m` or `h`.
+fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value.parse().map_err(|_| format!("invalid duration: {}", s))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "s" => Ok(Duration::from_secs(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        "h" => Ok(Duration::from_secs(value * 3600)),
+        _ => Err(format!("invalid duration unit in {}, expected ms, s, m or h", s)),
     }
+}
 
-    async fn ping(&self) -> Result<()> {
-        let start = Instant::now();
-        let req = JsonRequest::new("ping", json!([]));
-        let rep = self.rpc_client.request(req).await?;
-        let latency = Instant::now() - start;
-        println!("Got reply: {}", rep);
-        println!("Latency: {:?}", latency);
-        Ok(())
+/// Parse a decimal amount, e.g. `1.5`, into base units of a token with
+/// `decimals` decimals, exactly. Fails on amounts with more fractional
+/// digits than that instead of rounding them.
+fn parse_amount(amount: &str, decimals: usize) -> Result<u64> {
+    let (integer, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if integer.is_empty() && fraction.is_empty() ||
+        !integer.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit())
+    {
+        return Err(Error::ParseFailed("the amount must be a decimal number such as 1.5"))
+    }
+    if fraction.len() > decimals {
+        return Err(Error::ParseFailed("the amount has more decimals than the token allows"))
     }
 
-    async fn airdrop(&self, address: Option<Address>, endpoint: Url, amount: f64) -> Result<()> {
-        let addr = if address.is_some() {
-            address.unwrap()
-        } else {
-            let req = JsonRequest::new("wallet.get_key", json!([0_i64]));
-            let rep = self.rpc_client.request(req).await?;
-            Address::from_str(rep.as_array().unwrap()[0].as_str().unwrap())?
+    let digits = format!("{}{:0<width$}", integer, fraction, width = decimals);
+    digits.parse().map_err(|_| Error::ParseFailed("the amount is too large"))
+}
+
+/// Parse an amount of DRK given as an argument, in base units.
+fn parse_drk_amount(amount: &str) -> std::result::Result<u64, String> {
+    parse_amount(amount, AMOUNT_DECIMALS).map_err(|e| e.to_string())
+}
+
+/// A transfer to send to darkfid, validated.
+#[derive(Debug, PartialEq)]
+struct TransferRequest {
+    recipient: Address,
+    /// Amount in base units
+    amount: u64,
+    token_id: String,
+    /// Decimals of the token
+    decimals: usize,
+}
+
+impl TransferRequest {
+    /// Validate a transfer, parsing its amount with the decimals of its
+    /// token as given by `decimals`.
+    fn new(
+        recipient: &str,
+        amount: &str,
+        token_id: Option<String>,
+        decimals: &impl Fn(&str) -> usize,
+    ) -> Result<Self> {
+        let recipient = Address::from_str(recipient)?;
+
+        let token_id = match token_id {
+            Some(token_id) if !token_id.is_empty() => token_id,
+            _ => return Err(Error::ParseFailed("missing token ID, pass it with --token-id")),
         };
 
-        println!("Requesting airdrop for {}", addr);
-        let req = JsonRequest::new("airdrop", json!([json!(addr.to_string()), amount]));
-        let rpc_client = RpcClient::new(endpoint).await?;
-        let rep = rpc_client.request(req).await?;
-        rpc_client.close().await?;
+        let decimals = decimals(&token_id);
+        let amount = parse_amount(amount, decimals)?;
+        if amount == 0 {
+            return Err(Error::ParseFailed("transfer amount must be positive"))
+        }
 
-        println!("Success! Transaction ID: {}", rep);
-        Ok(())
+        Ok(Self { recipient, amount, token_id, decimals })
     }
 
-    async fn wallet_keygen(&self) -> Result<()> {
-        let req = JsonRequest::new("wallet.keygen", json!([]));
-        let rep = self.rpc_client.request(req).await?;
-        println!("New address: {}", rep);
-        Ok(())
+    /// Parse a transfer read from stdin: a JSON object with `recipient`,
+    /// `amount` and optionally `token_id`, or the same fields separated by
+    /// whitespace. `token_id` defaults to `default_token_id`.
+    fn parse(
+        line: &str,
+        default_token_id: Option<&str>,
+        decimals: &impl Fn(&str) -> usize,
+    ) -> Result<Self> {
+        let default_token_id = default_token_id.map(String::from);
+
+        if line.starts_with('{') {
+            let value: Value = serde_json::from_str(line)?;
+            let recipient =
+                value["recipient"].as_str().ok_or(Error::ParseFailed("missing recipient"))?;
+            // Amounts can be given as numbers or strings, strings are exact
+            let amount = match &value["amount"] {
+                Value::Number(n) => n.to_string(),
+                Value::String(s) => s.clone(),
+                _ => return Err(Error::ParseFailed("missing amount")),
+            };
+            let token_id = value["token_id"].as_str().map(String::from).or(default_token_id);
+            return Self::new(recipient, &amount, token_id, decimals)
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 2 || fields.len() > 3 {
+            return Err(Error::ParseFailed("expected <recipient> <amount> [token_id]"))
+        }
+        let token_id = fields.get(2).map(|t| t.to_string()).or(default_token_id);
+        Self::new(fields[0], fields[1], token_id, decimals)
     }
+}
 
-    async fn wallet_balance(&self) -> Result<()> {
-        let req = JsonRequest::new("wallet.get_balances", json!([]));
-        let rep = self.rpc_client.request(req).await?;
-        // TODO: Better representation
-        println!("Balances:\n{:#?}", rep);
-        Ok(())
+/// A transaction file written by `drk tx build`, validated.
+#[derive(Debug, PartialEq)]
+struct TxFile {
+    /// Serialized transaction, as hex
+    tx: String,
+    tx_id: String,
+}
+
+impl TxFile {
+    /// Parse the JSON written by `drk tx build`, checking its version and
+    /// that the transaction is hex before sending it anywhere.
+    fn parse(content: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(content)
+            .map_err(|_| Error::ParseFailed("not a transaction file"))?;
+
+        if value["version"].as_u64() != Some(TX_FILE_VERSION) {
+            return Err(Error::ParseFailed("unsupported transaction file version"))
+        }
+
+        let tx = value["tx"].as_str().ok_or(Error::ParseFailed("missing transaction"))?;
+        if tx.is_empty() || tx.len() % 2 != 0 || !tx.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::ParseFailed("the transaction isn't valid hex"))
+        }
+
+        let tx_id = value["tx_id"].as_str().ok_or(Error::ParseFailed("missing transaction ID"))?;
+
+        Ok(Self { tx: tx.to_string(), tx_id: tx_id.to_string() })
     }
+}
 
-    async fn wallet_address(&self) -> Result<()> {
-        let req = JsonRequest::new("wallet.get_key", json!([0_i64]));
-        let rep = self.rpc_client.request(req).await?;
-        println!("Default wallet address: {}", rep);
-        Ok(())
+/// Token ID of `token`, a ticker or token ID from the balances returned
+/// by `wallet.get_balances`. Unknown tokens are taken as token IDs.
+fn resolve_token_id(balances: &Value, token: &str) -> String {
+    balances
+        .as_object()
+        .and_then(|balances| balances.get(token))
+        .and_then(|balance| balance[3].as_str())
+        .unwrap_or(token)
+        .to_string()
+}
+
+/// Rates of tokens in a reference unit, by ticker or token ID, from a
+/// JSON object mapping them to numbers. Negative or non-numeric rates
+/// are an error.
+fn parse_rates(value: &Value) -> Result<HashMap<String, f64>> {
+    let rates = value.as_object().ok_or(Error::ParseFailed("rates must be a JSON object"))?;
+
+    let mut ret = HashMap::new();
+    for (token, rate) in rates {
+        match rate.as_f64() {
+            Some(rate) if rate.is_finite() && rate >= 0.0 => ret.insert(token.clone(), rate),
+            _ => return Err(Error::ParseFailed("rates must be non-negative numbers")),
+        };
+    }
+    Ok(ret)
+}
+
+/// Balances valued in a reference unit.
+#[derive(Debug, PartialEq)]
+struct Valuation {
+    /// Value of each token with a known rate, by ticker
+    priced: Vec<(String, f64)>,
+    /// Tickers of the tokens without a known rate
+    unpriced: Vec<String>,
+    total: f64,
+}
+
+/// Value the balances returned by `wallet.get_balances` with the given
+/// rates, looked up by ticker, then by token ID.
+fn value_balances(balances: &Value, rates: &HashMap<String, f64>) -> Valuation {
+    let mut balances: Vec<(&String, &Value)> =
+        balances.as_object().map(|b| b.iter().collect()).unwrap_or_default();
+    balances.sort_by_key(|(ticker, _)| *ticker);
+
+    let mut valuation = Valuation { priced: vec![], unpriced: vec![], total: 0.0 };
+    for (ticker, balance) in balances {
+        let rate = rates.get(ticker).or_else(|| rates.get(balance[3].as_str().unwrap_or_default()));
+        let amount = balance[0].as_str().and_then(|amount| amount.parse::<f64>().ok());
+        match (rate, amount) {
+            (Some(rate), Some(amount)) => {
+                valuation.priced.push((ticker.clone(), amount * rate));
+                valuation.total += amount * rate;
+            }
+            _ => valuation.unpriced.push(ticker.clone()),
+        }
     }
+    valuation
+}
 
-    async fn wallet_all_addresses(&self) -> Result<()> {
-        let req = JsonRequest::new("wallet.get_key", json!([-1]));
-        let rep = self.rpc_client.request(req).await?;
-        println!("Wallet addresses:\n{:#?}", rep);
-        Ok(())
+/// Ticker of `token_id` in the balances, or the token ID if it has none.
+fn token_name(balances: &Value, token_id: &str) -> String {
+    balances
+        .as_object()
+        .and_then(|balances| balances.iter().find(|(_, b)| b[3].as_str() == Some(token_id)))
+        .map_or(token_id, |(ticker, _)| ticker.as_str())
+        .to_string()
+}
+
+/// Table of the transactions returned by `tx.history` or
+/// `wallet.get_transactions`, formatting the amounts with `amount`, given
+/// the decimals of their token.
+fn history_table(txs: &[Value], amount: impl Fn(&str, usize) -> String) -> Table {
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_COLSEP);
+    table.set_titles(row!["TxID", "Direction", "Counterparty", "Amount", "Token", "Date"]);
+    for tx in txs {
+        let field = |name: &str| tx[name].as_str().unwrap_or("-").to_string();
+        let decimals = tx["decimals"].as_u64().unwrap_or(AMOUNT_DECIMALS as u64) as usize;
+        table.add_row(row![
+            field("tx_id"),
+            field("direction"),
+            field("counterparty"),
+            r->amount(tx["amount"].as_str().unwrap_or_default(), decimals),
+            field("token"),
+            timestamp_to_date(tx["timestamp"].as_i64().unwrap_or_default(), DateFormat::DateTime)
+        ]);
     }
+    table
+}
 
-    async fn tx_transfer(
-        &self,
-        network: NetworkName,
-        token_id: String,
-        recipient: Address,
-        amount: f64,
-    ) -> Result<()> {
-        println!("Attempting to transfer {} tokens to {}", amount, recipient);
+/// Fee darkfid is expected to charge per transfer, in base units.
+/// Transactions don't carry a fee yet.
+const ESTIMATED_FEE: u64 = 0;
 
-        let req = JsonRequest::new(
-            "tx.transfer",
-            json!([network.to_string(), token_id, recipient.to_string(), amount]),
-        );
+/// Balance of `token`, a ticker, network token address or token ID, in the
+/// balances returned by `wallet.get_balances`, on `network` if given.
+fn find_balance<'a>(
+    balances: &'a Value,
+    network: Option<&NetworkName>,
+    token: &str,
+) -> Option<&'a Value> {
+    let network = network.map(|network| network.to_string());
+    let on_network = |b: &Value| match &network {
+        Some(network) => b[1].as_str().is_some_and(|net| net.eq_ignore_ascii_case(network)),
+        None => true,
+    };
+
+    balances
+        .as_object()?
+        .iter()
+        .find(|(ticker, b)| {
+            on_network(b) &&
+                (ticker.eq_ignore_ascii_case(token) ||
+                    b[2].as_str() == Some(token) ||
+                    b[3].as_str() == Some(token))
+        })
+        .map(|(_, b)| b)
+}
+
+/// Decimals of `token`, looked up as in `find_balance`. Tokens missing from
+/// the wallet are taken to have `AMOUNT_DECIMALS`.
+fn token_decimals(balances: &Value, network: Option<&NetworkName>, token: &str) -> usize {
+    find_balance(balances, network, token)
+        .and_then(|b| b[4].as_u64())
+        .map_or(AMOUNT_DECIMALS, |decimals| decimals as usize)
+}
 
-        let rep = self.rpc_client.request(req).await?;
+/// Balance, in base units, of `token` on `network`, looked up as in
+/// `find_balance`. Tokens missing from the wallet have none.
+fn token_balance(balances: &Value, network: &NetworkName, token: &str) -> Result<u64> {
+    let balance = match find_balance(balances, Some(network), token) {
+        Some(balance) => balance,
+        None => return Ok(0),
+    };
 
-        println!("Success! Transaction ID: {}", rep);
-        Ok(())
+    match balance[0].as_str() {
+        Some(amount) => {
+            let decimals = balance[4].as_u64().unwrap_or(AMOUNT_DECIMALS as u64) as usize;
+            to_base_units(amount, decimals)
+        }
+        None => Ok(0),
     }
 }
 
-#[async_std::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+fn to_base_units(amount: &str, decimals: usize) -> Result<u64> {
+    u64::try_from(decode_base10(amount, decimals, true)?)
+        .map_err(|_| Error::ParseFailed("amount too large"))
+}
 
-    let log_level = get_log_level(args.verbose.into());
-    let log_config = get_log_config();
-    TermLogger::init(log_level, log_config, TerminalMode::Mixed, ColorChoice::Auto)?;
+/// Tokens whose balance can't cover the transfers sending them plus their
+/// estimated fees, with the amount needed and the amount available, in
+/// base units.
+fn insufficient_funds(
+    balances: &Value,
+    network: &NetworkName,
+    transfers: &[TransferRequest],
+) -> Result<Vec<(String, u64, u64)>> {
+    let mut needed: Vec<(String, u64)> = vec![];
+    for transfer in transfers {
+        let amount = transfer.amount.saturating_add(ESTIMATED_FEE);
+        match needed.iter_mut().find(|(token, _)| *token == transfer.token_id) {
+            Some((_, total)) => *total = total.saturating_add(amount),
+            None => needed.push((transfer.token_id.clone(), amount)),
+        }
+    }
 
-    let rpc_client = RpcClient::new(args.endpoint).await?;
-    let drk = Drk { rpc_client };
+    let mut insufficient = vec![];
+    for (token, needed) in needed {
+        let available = token_balance(balances, network, &token)?;
+        if needed > available {
+            insufficient.push((token, needed, available));
+        }
+    }
+    Ok(insufficient)
+}
 
-    match args.command {
-        DrkSubcommand::Ping => drk.ping().await,
+/// Read and validate all the transfers given on stdin, failing on the
+/// first invalid one so nothing is sent.
+fn read_transfers(
+    default_token_id: Option<&str>,
+    decimals: &impl Fn(&str) -> usize,
+) -> Result<Vec<TransferRequest>> {
+    let mut transfers = vec![];
 
-        DrkSubcommand::Airdrop { address, faucet_endpoint, amount } => {
-            drk.airdrop(address, faucet_endpoint, amount).await
+    for (i, line) in stdin().lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue
         }
 
-        DrkSubcommand::Wallet { keygen, balance, address, all_addresses } => {
-            if keygen {
-                return drk.wallet_keygen().await
+        match TransferRequest::parse(line, default_token_id, decimals) {
+            Ok(transfer) => transfers.push(transfer),
+            Err(e) => {
+                eprintln!("Invalid transfer on line {}: {}", i + 1, e);
+                return Err(e)
             }
+        }
+    }
 
-            if balance {
-                return drk.wallet_balance().await
-            }
+    if transfers.is_empty() {
+        return Err(Error::ParseFailed("no transfers read from stdin"))
+    }
+
+    Ok(transfers)
+}
+
+/// Where the idempotency keys of the transfers not confirmed by darkfid
+/// yet are kept.
+const TRANSFER_KEYS_PATH: &str = "~/.config/darkfi/drk_transfer_keys.json";
+
+/// The idempotency keys sent with `tx.transfer`, by the params of the
+/// transfer. A key is kept until darkfid replies with the transaction ID,
+/// so running the same transfer again after a failure reuses it and
+/// darkfid doesn't send it twice.
+struct TransferKeys {
+    path: PathBuf,
+    keys: HashMap<String, String>,
+}
+
+impl TransferKeys {
+    fn load(path: PathBuf) -> Result<Self> {
+        let keys = match std::fs::read_to_string(&path) {
+            Ok(keys) => serde_json::from_str(&keys)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self { path, keys })
+    }
+
+    /// Write the keys to a temporary file renamed over the previous one,
+    /// so a crash never leaves the file half written.
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string(&self.keys)?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// The key of the transfer with these params, generated and saved
+    /// before it's first sent.
+    fn get(&mut self, params: &Value) -> Result<String> {
+        let params = params.to_string();
+        if let Some(key) = self.keys.get(&params) {
+            return Ok(key.clone())
+        }
+
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        let key: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        self.keys.insert(params, key.clone());
+        self.save()?;
+        Ok(key)
+    }
+
+    /// Forget the key of a transfer darkfid confirmed.
+    fn remove(&mut self, params: &Value) -> Result<()> {
+        if self.keys.remove(&params.to_string()).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+}
+
+/// Longest passphrase read, so the buffer holding it never reallocates
+/// and leaves copies behind.
+const MAX_PASSPHRASE_LEN: usize = 1024;
+
+/// Read a passphrase from the terminal with echo turned off, even when
+/// stdin is redirected. The returned passphrase is zeroized on drop.
+fn read_passphrase(prompt: &str) -> Result<Zeroizing<String>> {
+    let mut tty = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(tty) => tty,
+        Err(_) => return Err(Error::ParseFailed("a passphrase can only be entered in a terminal")),
+    };
+    tty.write_all(prompt.as_bytes())?;
+    tty.flush()?;
 
-            if address {
-                return drk.wallet_address().await
+    let fd = tty.as_raw_fd();
+    let mut termios = MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: tcgetattr fills the termios struct when it succeeds
+    let original = unsafe {
+        if libc::tcgetattr(fd, termios.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error().into())
+        }
+        termios.assume_init()
+    };
+    let mut silent = original;
+    silent.c_lflag &= !libc::ECHO;
+    silent.c_lflag |= libc::ECHONL;
+    // SAFETY: fd is the open terminal and silent a valid termios struct
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) } != 0 {
+        return Err(io::Error::last_os_error().into())
+    }
+
+    // Read a byte at a time, a buffered reader would keep its own copy
+    let mut bytes = Zeroizing::new(Vec::with_capacity(MAX_PASSPHRASE_LEN));
+    let mut byte = Zeroizing::new([0u8; 1]);
+    let read = loop {
+        match tty.read(&mut *byte) {
+            Ok(0) => break Ok(()),
+            Ok(_) if byte[0] == b'\n' => break Ok(()),
+            Ok(_) if bytes.len() == MAX_PASSPHRASE_LEN => {
+                break Err(Error::ParseFailed("passphrase too long"))
             }
+            Ok(_) => bytes.push(byte[0]),
+            Err(e) => break Err(e.into()),
+        }
+    };
+
+    // SAFETY: same as above, restoring the settings we read
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    read?;
+
+    if bytes.last() == Some(&b'\r') {
+        bytes.pop();
+    }
+    match String::from_utf8(std::mem::take(&mut *bytes)) {
+        Ok(passphrase) => Ok(Zeroizing::new(passphrase)),
+        Err(e) => {
+            drop(Zeroizing::new(e.into_bytes()));
+            Err(Error::ParseFailed("passphrase is not valid UTF-8"))
+        }
+    }
+}
+
+/// Decimal places of DRK, and of the tokens whose decimals are unknown.
+const AMOUNT_DECIMALS: usize = 8;
+
+/// Format a decimal amount with exactly `decimals` decimal places (extra
+/// ones are truncated), optionally grouping the integer digits by thousands.
+fn format_amount(amount: &str, decimals: usize, separator: Option<char>) -> Result<String> {
+    let places = amount.split_once('.').map_or(0, |(_, frac)| frac.len()).max(decimals);
+    let digits = decode_base10(amount, places, true)?.to_string();
+    let digits = format!("{:0>width$}", digits, width = places + 1);
+    let (int, frac) = digits.split_at(digits.len() - places);
 
-            if all_addresses {
-                return drk.wallet_all_addresses().await
+    let int = match separator {
+        Some(sep) => {
+            let mut grouped = String::new();
+            for (i, c) in int.chars().enumerate() {
+                if i > 0 && (int.len() - i) % 3 == 0 {
+                    grouped.push(sep);
+                }
+                grouped.push(c);
             }
+            grouped
+        }
+        None => int.to_string(),
+    };
+
+    if decimals == 0 {
+        return Ok(int)
+    }
+
+    Ok(format!("{}.{}", int, &frac[..decimals]))
+}
+
+struct Drk {
+    pub rpc_client: RpcClient,
+    /// Timeout for connecting and for each RPC call
+    pub timeout: Duration,
+    /// Show a spinner while waiting for replies
+    pub progress: bool,
+    /// Prints the results of the commands
+    pub printer: Printer,
+}
+
+/// Latencies up to these are shown in green, then yellow, then red.
+const LATENCY_GOOD: Duration = Duration::from_millis(100);
+const LATENCY_SLOW: Duration = Duration::from_millis(500);
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+fn latency_color(latency: Duration) -> &'static str {
+    if latency <= LATENCY_GOOD {
+        GREEN
+    } else if latency <= LATENCY_SLOW {
+        YELLOW
+    } else {
+        RED
+    }
+}
+
+/// Delay before the spinner shows up, so fast replies don't flicker.
+const PROGRESS_DELAY: Duration = Duration::from_millis(300);
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Show a spinner with the elapsed time on stderr until it's cancelled.
+async fn spinner() {
+    let start = Instant::now();
+    task::sleep(PROGRESS_DELAY).await;
+
+    for frame in SPINNER.iter().cycle() {
+        eprint!("\r{} Waiting for reply... {:.1}s", frame, start.elapsed().as_secs_f32());
+        stderr().flush().ok();
+        task::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+impl Drk {
+    async fn close_connection(&self) -> Result<()> {
+        self.rpc_client.close().await
+    }
 
-            eprintln!("Run 'drk wallet -h' to see the subcommand usage.");
-            exit(2);
+    /// Await an RPC reply, showing a spinner meanwhile if enabled.
+    async fn with_progress<T>(&self, reply: impl Future<Output = Result<T>>) -> Result<T> {
+        if !self.progress {
+            return reply.await
         }
 
-        DrkSubcommand::Transfer { recipient, amount, network, token_id } => {
-            drk.tx_transfer(network, token_id, recipient, amount).await
+        let start = Instant::now();
+        let spinner = task::spawn(spinner());
+        let result = reply.await;
+        spinner.cancel().await;
+
+        // Clear the spinner line, if it showed up
+        if start.elapsed() >= PROGRESS_DELAY {
+            eprint!("\r\x1b[K");
+            stderr().flush().ok();
         }
-    }?;
+        result
+    }
 
-    drk.close_connection().await
+    async fn request(&self, req: JsonRequest) -> Result<Value> {
+        self.with_progress(self.rpc_client.request(req)).await
+    }
+
+    async fn ping(&self) -> Result<Output> {
+        let start = Instant::now();
+        let req = JsonRequest::new("ping", json!([]));
+        let rep = self.request(req).await?;
+        let latency = Instant::now() - start;
+
+        // Older darkfid versions don't have system.version
+        let req = JsonRequest::new("system.version", json!([]));
+        let version = self.request(req).await.ok().and_then(|v| v.as_str().map(String::from));
+
+        Ok(Output::Ping(Ping {
+            endpoint: self.rpc_client.url().to_string(),
+            version,
+            reply: rep,
+            latency_ms: latency.as_secs_f64() * 1000.0,
+            latency_us: latency.as_micros() as u64,
+        }))
+    }
+
+    async fn healthcheck(&self, quiet: bool) -> Result<Output> {
+        let (healthy, status) = check_health(&self.rpc_client).await;
+        if quiet {
+            return Ok(Output::None)
+        }
+        Ok(Output::Health(Health { healthy, status }))
+    }
+
+    async fn airdrop(
+        &self,
+        address: Option<Address>,
+        endpoint: Url,
+        amount: u64,
+    ) -> Result<Output> {
+        let addr = match address {
+            Some(address) => address,
+            None => {
+                let req = JsonRequest::new("wallet.get_key", json!([0_i64]));
+                let rep = self.request(req).await?;
+                let address = default_address(&rep)?
+                    .ok_or_else(|| Error::JsonRpcError("the wallet has no address".into()))?;
+                Address::from_str(&address)?
+            }
+        };
+
+        if !self.printer.json {
+            println!("Requesting airdrop for {}", addr);
+        }
+        let req = JsonRequest::new("airdrop", json!([json!(addr.to_string()), amount]));
+        let rpc_client = RpcClient::new_with_timeout(endpoint, Some(self.timeout)).await?;
+        let rep = self.with_progress(rpc_client.request(req)).await?;
+        rpc_client.close().await?;
+
+        Ok(Output::Airdrop(Airdrop { address: addr.to_string(), tx_id: rep }))
+    }
+
+    async fn keygen(&self) -> Result<String> {
+        let req = JsonRequest::new("wallet.keygen", json!([]));
+        let rep = self.request(req).await?;
+        rep.as_str().map(String::from).ok_or_else(|| malformed_reply("wallet.keygen", &rep))
+    }
+
+    async fn wallet_keygen(&self) -> Result<Output> {
+        let address = self.keygen().await?;
+        Ok(Output::WalletAddress(WalletAddress {
+            address: Some(address),
+            label: Some("New address"),
+        }))
+    }
+
+    async fn wallet_keygen_count(&self, count: u32) -> Result<Output> {
+        let mut generated = vec![];
+        for _ in 0..count {
+            match self.keygen().await {
+                Ok(address) => {
+                    generated.push(Output::WalletAddress(WalletAddress {
+                        address: Some(address),
+                        label: None,
+                    }));
+                }
+                Err(e) => {
+                    // The connection is closed on error, so we can't go on
+                    eprintln!("Generated {} of {} addresses", generated.len(), count);
+                    return Ok(Output::Partial(generated, e))
+                }
+            }
+        }
+        Ok(Output::Many(generated))
+    }
+
+    async fn wallet_balance(&self, prices: Option<PriceSource>, unit: &str) -> Result<Output> {
+        let req = JsonRequest::new("wallet.get_balances", json!([]));
+        let rep = self.request(req).await?;
+        let balances = balances_json(&rep)?;
+
+        let rates = match prices {
+            Some(PriceSource::File(path)) => {
+                parse_rates(&serde_json::from_str(&std::fs::read_to_string(path)?)?)?
+            }
+            Some(PriceSource::Endpoint(endpoint)) => {
+                let tickers: Vec<&String> = match rep.as_object() {
+                    Some(balances) => balances.keys().collect(),
+                    None => return Err(malformed_reply("wallet.get_balances", &rep)),
+                };
+                let req = JsonRequest::new("prices.get", json!([unit, tickers]));
+                let rpc_client = RpcClient::new_with_timeout(endpoint, Some(self.timeout)).await?;
+                let rates = self.with_progress(rpc_client.request(req)).await?;
+                rpc_client.close().await?;
+                parse_rates(&rates)?
+            }
+            None => return Ok(Output::Balances(Balances { rep, balances, value: None })),
+        };
+
+        let valuation = value_balances(&rep, &rates);
+        let value = BalancesValue {
+            unit: unit.to_string(),
+            priced: valuation
+                .priced
+                .into_iter()
+                .map(|(ticker, value)| (ticker, json!(value)))
+                .collect(),
+            total: valuation.total,
+            unpriced: valuation.unpriced,
+        };
+        Ok(Output::Balances(Balances { rep, balances, value: Some(value) }))
+    }
+
+    async fn wallet_lock(&self) -> Result<Output> {
+        let req = JsonRequest::new("wallet.lock", json!([]));
+        self.request(req).await?;
+        Ok(Output::Locked(Locked { locked: true }))
+    }
+
+    async fn wallet_unlock(&self) -> Result<Output> {
+        self.unlock().await?;
+        Ok(Output::Locked(Locked { locked: false }))
+    }
+
+    /// Prompt for the passphrase and unlock the wallet with it.
+    async fn unlock(&self) -> Result<()> {
+        let mut passphrase = read_passphrase("Wallet passphrase: ")?;
+        // Moved into the request instead of copied, and wiped once sent
+        let params = Value::Array(vec![Value::String(std::mem::take(&mut *passphrase))]);
+        let mut req = JsonRequest::new("wallet.unlock", params);
+        let rep = self.with_progress(self.rpc_client.secret_request(&req)).await;
+        if let Some(Value::String(passphrase)) = req.params.get_mut(0) {
+            passphrase.zeroize();
+        }
+        rep?;
+        Ok(())
+    }
+
+    /// Prompt for the passphrase to unlock the wallet if it's locked, before
+    /// an operation darkfid refuses on a locked wallet.
+    async fn ensure_unlocked(&self) -> Result<()> {
+        let req = JsonRequest::new("wallet.is_locked", json!([]));
+        if self.request(req).await?.as_bool() == Some(true) {
+            eprintln!("The wallet is locked.");
+            self.unlock().await?;
+            eprintln!("Wallet unlocked");
+        }
+        Ok(())
+    }
+
+    async fn wallet_address(&self) -> Result<Output> {
+        let req = JsonRequest::new("wallet.get_key", json!([0_i64]));
+        let rep = self.request(req).await?;
+        Ok(Output::WalletAddress(WalletAddress {
+            address: default_address(&rep)?,
+            label: Some("Default wallet address"),
+        }))
+    }
+
+    /// All the addresses of the wallet, from `wallet.get_key`.
+    async fn addresses(&self) -> Result<Vec<String>> {
+        let req = JsonRequest::new("wallet.get_key", json!([-1]));
+        let rep = self.request(req).await?;
+        rep.as_array()
+            .and_then(|addresses| {
+                addresses.iter().map(|address| address.as_str().map(String::from)).collect()
+            })
+            .ok_or_else(|| malformed_reply("wallet.get_key", &rep))
+    }
+
+    async fn wallet_all_addresses(&self) -> Result<Output> {
+        Ok(Output::Addresses(Addresses { addresses: self.addresses().await? }))
+    }
+
+    async fn wallet_set_default(&self, address: Address) -> Result<Output> {
+        let address = address.to_string();
+        if !self.addresses().await?.contains(&address) {
+            eprintln!("Address {} is not owned by the wallet", address);
+            return Err(Error::InvalidAddress)
+        }
+
+        let req = JsonRequest::new("wallet.set_default", json!([address]));
+        self.request(req).await?;
+        Ok(Output::DefaultAddress(DefaultAddress { default_address: address }))
+    }
+
+    async fn whoami(&self) -> Result<Output> {
+        let req = JsonRequest::new("wallet.get_key", json!([0_i64]));
+        let default_address = default_address(&self.request(req).await?)?;
+
+        let addresses = self.addresses().await?.len();
+
+        let req = JsonRequest::new("wallet.get_balances", json!([]));
+        let rep = self.request(req).await?;
+        let balances = match rep.as_object() {
+            Some(balances) => balances
+                .iter()
+                .map(|(ticker, balance)| (ticker.clone(), balance[0].clone()))
+                .collect(),
+            None => return Err(malformed_reply("wallet.get_balances", &rep)),
+        };
+
+        Ok(Output::Whoami(Whoami { default_address, addresses, balances, rep }))
+    }
+
+    async fn tx_transfer(
+        &self,
+        network: NetworkName,
+        transfer: &TransferRequest,
+        transfer_keys: &mut TransferKeys,
+    ) -> Result<Output> {
+        if !self.printer.json {
+            println!(
+                "Attempting to transfer {} tokens to {}",
+                self.printer.base_amount(transfer.amount, transfer.decimals),
+                transfer.recipient
+            );
+        }
+
+        let params = json!([
+            network.to_string(),
+            transfer.token_id,
+            transfer.recipient.to_string(),
+            transfer.amount
+        ]);
+        let key = transfer_keys.get(&params)?;
+
+        let mut key_params = params.clone();
+        key_params.as_array_mut().unwrap().push(json!(key));
+        let req = JsonRequest::new("tx.transfer", key_params);
+
+        let rep = self.request(req).await?;
+        transfer_keys.remove(&params)?;
+
+        Ok(Output::Transfer(Transfer {
+            tx_id: rep["tx_id"].clone(),
+            recipient: transfer.recipient.to_string(),
+            amount: encode_base10(transfer.amount.into(), transfer.decimals),
+            token_id: transfer.token_id.clone(),
+            fee: rep["fee"].clone(),
+            change: rep["change"].clone(),
+            decimals: transfer.decimals,
+        }))
+    }
+
+    /// Build a transfer without broadcasting it and write it to `output`.
+    async fn tx_build(
+        &self,
+        network: NetworkName,
+        transfer: &TransferRequest,
+        output: &PathBuf,
+    ) -> Result<Output> {
+        let req = JsonRequest::new(
+            "tx.build",
+            json!([
+                network.to_string(),
+                transfer.token_id,
+                transfer.recipient.to_string(),
+                transfer.amount
+            ]),
+        );
+
+        let rep = self.request(req).await?;
+
+        let file = json!({
+            "version": TX_FILE_VERSION,
+            "network": network.to_string(),
+            "token_id": transfer.token_id,
+            "recipient": transfer.recipient.to_string(),
+            "amount": encode_base10(transfer.amount.into(), transfer.decimals),
+            "tx_id": rep["tx_id"],
+            "tx": rep["tx"],
+        });
+        std::fs::write(output, serde_json::to_string_pretty(&file)?)?;
+
+        Ok(Output::Built(Built {
+            tx_id: rep["tx_id"].clone(),
+            output: output.display().to_string(),
+            change: rep["change"].clone(),
+            decimals: transfer.decimals,
+        }))
+    }
+
+    /// Broadcast a transaction file written by `tx_build`.
+    async fn tx_broadcast(&self, file: &PathBuf) -> Result<Output> {
+        let tx_file = TxFile::parse(&std::fs::read_to_string(file)?)?;
+
+        let req = JsonRequest::new("tx.broadcast", json!([tx_file.tx]));
+        let rep = self.request(req).await?;
+
+        if rep.as_str() != Some(tx_file.tx_id.as_str()) {
+            eprintln!("WARNING: darkfid computed another transaction ID than the file's");
+        }
+        Ok(Output::Broadcast(Broadcast { tx_id: rep }))
+    }
+
+    /// Check the wallet `balances` can pay for the transfers and their
+    /// estimated fees, printing the shortfall of every token they can't.
+    fn check_balance(
+        &self,
+        balances: &Value,
+        network: &NetworkName,
+        transfers: &[TransferRequest],
+    ) -> Result<()> {
+        let insufficient = insufficient_funds(balances, network, transfers)?;
+        for (token, needed, available) in insufficient.iter() {
+            let decimals = token_decimals(balances, Some(network), token);
+            eprintln!(
+                "Insufficient funds for {}: {} needed with fees, {} available, {} short",
+                token,
+                self.printer.base_amount(*needed, decimals),
+                self.printer.base_amount(*available, decimals),
+                self.printer.base_amount(needed - available, decimals),
+            );
+        }
+
+        match insufficient.first() {
+            Some((_, needed, available)) => {
+                Err(ClientFailed::NotEnoughValue(needed - available).into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Validate the transfers, from the arguments or from stdin with "-",
+    /// and check the wallet can pay for them unless asked not to. Then send
+    /// them one by one unless it's a dry run.
+    async fn transfer(
+        &self,
+        recipient: String,
+        amount: Option<String>,
+        network: NetworkName,
+        token_id: Option<String>,
+        dry_run: bool,
+        skip_balance_check: bool,
+    ) -> Result<Output> {
+        let req = JsonRequest::new("wallet.get_balances", json!([]));
+        let balances = self.request(req).await?;
+        let decimals = |token: &str| token_decimals(&balances, Some(&network), token);
+
+        let transfers = match (recipient.as_str(), amount) {
+            ("-", None) => read_transfers(token_id.as_deref(), &decimals)?,
+            ("-", Some(_)) => {
+                return Err(Error::ParseFailed("the amount is read from stdin along the recipient"))
+            }
+            (_, Some(amount)) => {
+                vec![TransferRequest::new(&recipient, &amount, token_id, &decimals)?]
+            }
+            (_, None) => return Err(Error::ParseFailed("missing amount to transfer")),
+        };
+
+        if !skip_balance_check {
+            self.check_balance(&balances, &network, &transfers)?;
+        }
+
+        if dry_run {
+            let dry_runs = transfers
+                .iter()
+                .map(|transfer| {
+                    Output::DryRun(DryRun {
+                        dry_run: true,
+                        recipient: transfer.recipient.to_string(),
+                        amount: encode_base10(transfer.amount.into(), transfer.decimals),
+                        token_id: transfer.token_id.clone(),
+                        network: network.to_string(),
+                        decimals: transfer.decimals,
+                    })
+                })
+                .collect();
+            return Ok(Output::Many(dry_runs))
+        }
+
+        self.ensure_unlocked().await?;
+
+        let mut transfer_keys = TransferKeys::load(expand_path(TRANSFER_KEYS_PATH)?)?;
+        let mut sent = vec![];
+        for transfer in transfers.iter() {
+            match self.tx_transfer(network.clone(), transfer, &mut transfer_keys).await {
+                Ok(output) => sent.push(output),
+                Err(e) => return Ok(Output::Partial(sent, e)),
+            }
+        }
+        Ok(Output::Many(sent))
+    }
+
+    async fn sweep(&self, token: Option<String>, dry_run: bool) -> Result<Output> {
+        let req = JsonRequest::new("wallet.get_balances", json!([]));
+        let balances = self.request(req).await?;
+
+        let token_id = token.map(|token| resolve_token_id(&balances, &token));
+        self.ensure_unlocked().await?;
+        let req = JsonRequest::new("wallet.sweep", json!([token_id, dry_run]));
+        let rep = self.request(req).await?;
+
+        Ok(Output::Sweep(Sweep { rep, balances, dry_run }))
+    }
+
+    async fn history(&self, limit: u32) -> Result<Output> {
+        let req = JsonRequest::new("wallet.get_transactions", json!([limit]));
+        let txs = self.request(req).await?;
+        Ok(Output::History(History { txs, empty: "No transactions in the wallet yet" }))
+    }
+
+    async fn tx_list(&self, limit: u32, token_id: Option<String>) -> Result<Output> {
+        let empty = match token_id {
+            Some(_) => "No transactions of this token in the wallet",
+            None => "No transactions in the wallet yet",
+        };
+
+        let req = JsonRequest::new("tx.history", json!([limit, token_id]));
+        let txs = self.request(req).await?;
+        Ok(Output::History(History { txs, empty }))
+    }
+
+    async fn version(&self) -> Result<Output> {
+        let client_version = env!("CARGO_PKG_VERSION");
+        let req = JsonRequest::new("system.version", json!([]));
+        let rep = self.request(req).await?;
+        let server_version = rep.as_str().unwrap_or_default();
+
+        Ok(Output::Version(Version {
+            drk: client_version.to_string(),
+            darkfid: server_version.to_string(),
+            compatible: versions_compatible(client_version, server_version),
+        }))
+    }
+
+    async fn handle_command(&self, command: DrkSubcommand) -> Result<Output> {
+        match command {
+            DrkSubcommand::Ping => self.ping().await,
+
+            DrkSubcommand::Healthcheck { quiet } => self.healthcheck(quiet).await,
+
+            DrkSubcommand::Airdrop { address, faucet_endpoint, amount } => {
+                self.airdrop(address, faucet_endpoint, amount).await
+            }
+
+            DrkSubcommand::Wallet {
+                keygen,
+                count,
+                balance,
+                prices,
+                price_endpoint,
+                unit,
+                address,
+                all_addresses,
+                default_address,
+                lock,
+                unlock,
+            } => {
+                if lock {
+                    return self.wallet_lock().await
+                }
+
+                if unlock {
+                    return self.wallet_unlock().await
+                }
+
+                if keygen {
+                    self.ensure_unlocked().await?;
+                    return match count {
+                        Some(count) => self.wallet_keygen_count(count).await,
+                        None => self.wallet_keygen().await,
+                    }
+                }
+
+                if balance {
+                    let prices = match (prices, price_endpoint) {
+                        (Some(path), _) => Some(PriceSource::File(path)),
+                        (None, Some(endpoint)) => Some(PriceSource::Endpoint(endpoint)),
+                        (None, None) => None,
+                    };
+                    return self.wallet_balance(prices, &unit).await
+                }
+
+                if address {
+                    return self.wallet_address().await
+                }
+
+                if all_addresses {
+                    return self.wallet_all_addresses().await
+                }
+
+                match default_address {
+                    Some(address) => {
+                        self.ensure_unlocked().await?;
+                        self.wallet_set_default(address).await
+                    }
+                    None => unreachable!("clap requires one of the wallet operations"),
+                }
+            }
+
+            DrkSubcommand::Whoami => self.whoami().await,
+
+            DrkSubcommand::Transfer {
+                recipient,
+                amount,
+                network,
+                token_id,
+                dry_run,
+                skip_balance_check,
+            } => {
+                self.transfer(recipient, amount, network, token_id, dry_run, skip_balance_check)
+                    .await
+            }
+
+            DrkSubcommand::Sweep { token, dry_run } => self.sweep(token, dry_run).await,
+
+            DrkSubcommand::History { limit } => self.history(limit).await,
+
+            DrkSubcommand::Tx {
+                command: TxSubcommand::Build { recipient, amount, network, token_id, output },
+            } => {
+                let req = JsonRequest::new("wallet.get_balances", json!([]));
+                let balances = self.request(req).await?;
+                let decimals = |token: &str| token_decimals(&balances, Some(&network), token);
+
+                let transfer = TransferRequest::new(&recipient, &amount, token_id, &decimals)?;
+                self.ensure_unlocked().await?;
+                self.tx_build(network, &transfer, &output).await
+            }
+
+            DrkSubcommand::Tx { command: TxSubcommand::Broadcast { file } } => {
+                self.tx_broadcast(&file).await
+            }
+
+            DrkSubcommand::Tx { command: TxSubcommand::List { limit, token_id } } => {
+                self.tx_list(limit, token_id).await
+            }
+
+            DrkSubcommand::Address { command: AddressSubcommand::Inspect { address } } => {
+                Ok(address_inspection(&address))
+            }
+
+            DrkSubcommand::Version { full: false } => Ok(client_version()),
+
+            DrkSubcommand::Version { full: true } => self.version().await,
+
+            DrkSubcommand::Repl => {
+                eprintln!("Already in the REPL");
+                Ok(Output::None)
+            }
+        }
+    }
+
+    async fn repl(&mut self, endpoints: &[Url]) -> Result<()> {
+        let mut line = String::new();
+
+        loop {
+            print!("drk> ");
+            stdout().flush()?;
+
+            line.clear();
+            if stdin().read_line(&mut line)? == 0 {
+                println!();
+                break
+            }
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue
+            }
+            if line == "quit" {
+                break
+            }
+
+            let command = match ReplLine::try_parse_from(line.split_whitespace()) {
+                Ok(repl_line) => repl_line.command,
+                Err(e) => {
+                    e.print()?;
+                    continue
+                }
+            };
+
+            let result = self.handle_command(command).await;
+            if let Err(e) = result.and_then(|output| self.printer.print(output)) {
+                eprintln!("Error: {}", e);
+                // The RPC client drops the connection on error replies
+                self.rpc_client.close().await.ok();
+                self.rpc_client =
+                    RpcClient::new_with_failover(endpoints, Some(self.timeout)).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the given semver versions are compatible: same major version,
+/// or same minor version while the major version is 0.
+fn versions_compatible(a: &str, b: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split(['.', '-']).take(3).map(|x| x.parse().unwrap_or(0)).collect()
+    };
+
+    let (a, b) = (parse(a), parse(b));
+    if a.len() < 2 || b.len() < 2 || a[0] != b[0] {
+        return false
+    }
+
+    a[0] != 0 || a[1] == b[1]
+}
+
+/// Whether JSON-RPC traffic to `url` can be read on the network: plain
+/// `tcp://` to a host other than the loopback.
+fn is_insecure_endpoint(url: &Url) -> bool {
+    if url.scheme() != "tcp" {
+        return false
+    }
+
+    match url.host() {
+        Some(Host::Ipv4(ip)) => !ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => !ip.is_loopback(),
+        // IPv4 addresses are only parsed for special schemes like http
+        Some(Host::Domain(domain)) => match domain.parse::<IpAddr>() {
+            Ok(ip) => !ip.is_loopback(),
+            Err(_) => domain != "localhost",
+        },
+        None => true,
+    }
+}
+
+/// Status line of a health check given the reply to a ping, and whether
+/// darkfid is healthy.
+fn health_status(endpoint: &str, reply: &Result<Value>, latency: Duration) -> (bool, String) {
+    match reply {
+        Ok(Value::String(pong)) if pong == "pong" => (
+            true,
+            format!(
+                "OK: darkfid at {} replied in {:.1}ms",
+                endpoint,
+                latency.as_secs_f64() * 1000.0
+            ),
+        ),
+        Ok(rep) => (false, format!("CRITICAL: darkfid at {} replied {} to a ping", endpoint, rep)),
+        Err(Error::TimeoutError) => {
+            (false, format!("CRITICAL: darkfid at {} did not reply in time", endpoint))
+        }
+        Err(e) => (false, format!("CRITICAL: darkfid at {}: {}", endpoint, e)),
+    }
+}
+
+/// Ping darkfid, returning whether it is healthy and a status line.
+async fn check_health(rpc_client: &RpcClient) -> (bool, String) {
+    let start = Instant::now();
+    let reply = rpc_client.request(JsonRequest::new("ping", json!([]))).await;
+    health_status(rpc_client.url().as_str(), &reply, start.elapsed())
+}
+
+/// Exit with `EXIT_CONNECTION_ERROR` if darkfid at `endpoint` couldn't
+/// be reached or timed out.
+fn exit_on_connection_error(err: &Error, endpoint: &str, timeout: Duration, json: bool) {
+    let message = match err {
+        Error::TimeoutError => {
+            format!("darkfid at {} did not reply within {:?}", endpoint, timeout)
+        }
+        Error::ConnectFailed | Error::ConnectTimeout => {
+            format!("Unable to connect to darkfid at {}", endpoint)
+        }
+        Error::NetworkOperationFailed => format!("Lost connection to darkfid at {}", endpoint),
+        _ => return,
+    };
+
+    if json {
+        println!("{}", error_json(err, &message));
+    } else {
+        eprintln!("{}", message);
+    }
+    exit(EXIT_CONNECTION_ERROR);
+}
+
+/// Print `err` as a JSON object and exit with 1, if printing JSON.
+fn exit_on_json_error(err: &Error, json: bool) {
+    if json {
+        println!("{}", error_json(err, &err.to_string()));
+        exit(1)
+    }
+}
+
+/// The JSON object printed for an error with `--json`: a stable `code` to
+/// match on and a `message` for humans.
+fn error_json(err: &Error, message: &str) -> Value {
+    let code = match err {
+        Error::TimeoutError => "timeout",
+        Error::ConnectFailed | Error::ConnectTimeout => "connect_failed",
+        Error::NetworkOperationFailed => "connection_lost",
+        Error::JsonRpcError(_) => "rpc_error",
+        Error::ClientFailed(ClientFailed::NotEnoughValue(_)) => "insufficient_funds",
+        Error::ParseFailed(_) | Error::InvalidAddress => "invalid_input",
+        _ => "error",
+    };
+    json!({"code": code, "message": message})
+}
+
+/// The drk version alone, which doesn't need darkfid.
+fn client_version() -> Output {
+    Output::ClientVersion(ClientVersion { drk: env!("CARGO_PKG_VERSION") })
+}
+
+/// Reply of one endpoint to a query sent to all of them.
+struct EndpointReply {
+    endpoint: Url,
+    /// Time to connect and get the reply
+    latency: Duration,
+    reply: Result<Value>,
+}
+
+/// Send a `method` request to every endpoint concurrently, each over its
+/// own connection, and collect the replies in the order of the endpoints.
+async fn query_all_endpoints(
+    endpoints: &[Url],
+    method: &str,
+    timeout: Duration,
+) -> Vec<EndpointReply> {
+    let tasks: Vec<_> = endpoints
+        .iter()
+        .cloned()
+        .map(|endpoint| {
+            let req = JsonRequest::new(method, json!([]));
+            task::spawn(async move {
+                let start = Instant::now();
+                let reply = match RpcClient::new_with_timeout(endpoint.clone(), Some(timeout)).await
+                {
+                    Ok(rpc_client) => {
+                        let reply = rpc_client.request(req).await;
+                        rpc_client.close().await.ok();
+                        reply
+                    }
+                    Err(e) => Err(e),
+                };
+                EndpointReply { endpoint, latency: start.elapsed(), reply }
+            })
+        })
+        .collect();
+
+    let mut replies = vec![];
+    for task in tasks {
+        replies.push(task.await);
+    }
+    replies
+}
+
+/// Table of the balances returned by `wallet.get_balances`, sorted by
+/// symbol, or `None` if there are none. Tokens missing from darkfid's token
+/// list are named by their token ID, their symbol is shown as unknown.
+fn balances_table(balances: &Value, amount: impl Fn(&str, usize) -> String) -> Option<Table> {
+    let mut balances: Vec<(&String, &Value)> = balances.as_object()?.iter().collect();
+    if balances.is_empty() {
+        return None
+    }
+    balances.sort_by_key(|(ticker, _)| *ticker);
+
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_COLSEP);
+    table.set_titles(row!["Token ID", "Symbol", "Amount", "Network"]);
+    for (ticker, balance) in balances {
+        let token_id = balance[3].as_str().unwrap_or_default();
+        let symbol = if ticker == token_id { "-" } else { ticker.as_str() };
+        let decimals = balance[4].as_u64().unwrap_or(AMOUNT_DECIMALS as u64) as usize;
+        table.add_row(row![
+            token_id,
+            symbol,
+            r->amount(balance[0].as_str().unwrap_or_default(), decimals),
+            balance[1].as_str().unwrap_or_default()
+        ]);
+    }
+    Some(table)
+}
+
+/// Error for a reply of darkfid to `method` that isn't of the expected
+/// shape.
+fn malformed_reply(method: &str, rep: &Value) -> Error {
+    Error::JsonRpcError(format!("unexpected reply of darkfid to {}: {}", method, rep))
+}
+
+/// Default address of the wallet from the reply to `wallet.get_key` for
+/// index 0, `None` if the wallet has no addresses.
+fn default_address(rep: &Value) -> Result<Option<String>> {
+    match &rep[0] {
+        Value::String(address) => Ok(Some(address.clone())),
+        Value::Null if rep.is_array() => Ok(None),
+        _ => Err(malformed_reply("wallet.get_key", rep)),
+    }
+}
+
+/// Balances returned by `wallet.get_balances` as JSON objects by ticker.
+fn balances_json(balances: &Value) -> Result<Value> {
+    let balances: serde_json::Map<String, Value> = balances
+        .as_object()
+        .ok_or_else(|| malformed_reply("wallet.get_balances", balances))?
+        .iter()
+        .map(|(ticker, balance)| {
+            let balance = json!({
+                "amount": balance[0],
+                "network": balance[1],
+                "token_id": balance[3],
+                "decimals": balance[4],
+            });
+            (ticker.clone(), balance)
+        })
+        .collect();
+    Ok(Value::Object(balances))
+}
+
+/// Balances returned by `wallet.get_balances` on a single line.
+fn balances_summary(balances: &Value) -> String {
+    let mut balances: Vec<(&String, &Value)> =
+        balances.as_object().map(|b| b.iter().collect()).unwrap_or_default();
+    if balances.is_empty() {
+        return "no balances".into()
+    }
+    balances.sort_by_key(|(ticker, _)| *ticker);
+    let balances: Vec<String> = balances
+        .iter()
+        .map(|(ticker, balance)| format!("{} {}", balance[0].as_str().unwrap_or("?"), ticker))
+        .collect();
+    balances.join(", ")
+}
+
+/// Lines reporting the reply of each endpoint, shown with `describe`, and
+/// the fastest one, and whether the endpoints that replied disagree.
+fn endpoints_report(
+    replies: &[EndpointReply],
+    describe: impl Fn(&Value) -> String,
+) -> (Vec<String>, bool) {
+    let width = replies.iter().map(|r| r.endpoint.as_str().len()).max().unwrap_or(0);
+    let mut lines: Vec<String> = replies
+        .iter()
+        .map(|r| {
+            let reply = match &r.reply {
+                Ok(value) => describe(value),
+                Err(e) => format!("error: {}", e),
+            };
+            let latency = format!("{:.1}ms", r.latency.as_secs_f64() * 1000.0);
+            format!("{:<width$}  {:>9}  {}", r.endpoint.as_str(), latency, reply, width = width)
+        })
+        .collect();
+
+    let ok: Vec<&EndpointReply> = replies.iter().filter(|r| r.reply.is_ok()).collect();
+    if let Some(fastest) = ok.iter().min_by_key(|r| r.latency) {
+        lines.push(format!("Fastest: {}", fastest.endpoint));
+    }
+
+    let divergent = ok.windows(2).any(|w| w[0].reply.as_ref().ok() != w[1].reply.as_ref().ok());
+    if divergent {
+        lines.push("WARNING: the endpoints replied differently, some may be out of sync".into());
+    }
+    (lines, divergent)
+}
+
+/// The replies of each endpoint as printed with `--json`, along with the
+/// fastest one and whether they disagree.
+fn endpoints_report_json(replies: &[EndpointReply], divergent: bool) -> Value {
+    let endpoints: Vec<Value> = replies
+        .iter()
+        .map(|r| {
+            let mut endpoint = json!({
+                "endpoint": r.endpoint.as_str(),
+                "latency_ms": r.latency.as_secs_f64() * 1000.0,
+            });
+            match &r.reply {
+                Ok(value) => endpoint["reply"] = value.clone(),
+                Err(e) => endpoint["error"] = json!(e.to_string()),
+            }
+            endpoint
+        })
+        .collect();
+
+    let fastest = replies.iter().filter(|r| r.reply.is_ok()).min_by_key(|r| r.latency);
+    json!({
+        "endpoints": endpoints,
+        "fastest": fastest.map(|r| r.endpoint.as_str()),
+        "divergent": divergent,
+    })
+}
+
+/// Components of a decoded address, the keys in hex.
+struct AddressParts {
+    prefix: u8,
+    public_key: String,
+    checksum: String,
+}
+
+impl AddressParts {
+    fn lines(&self) -> Vec<String> {
+        vec![
+            format!("Type:       payment (0x{:02x})", self.prefix),
+            format!("Public key: {}", self.public_key),
+            format!("Checksum:   {} (valid)", self.checksum),
+        ]
+    }
+}
+
+/// Decoded components of an address, and the validation step it fails
+/// at if any.
+fn inspect_address(address: &str) -> (Option<AddressParts>, Option<String>) {
+    let address = match Address::parse(address) {
+        Ok(address) => address,
+        Err(e) => return (None, Some(e.to_string())),
+    };
+
+    let to_hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let parts = AddressParts {
+        prefix: address.prefix(),
+        public_key: to_hex(&address.public_key_bytes()),
+        checksum: to_hex(&address.checksum_bytes()),
+    };
+
+    match PublicKey::try_from(address) {
+        Ok(_) => (Some(parts), None),
+        Err(_) => (Some(parts), Some("the public key isn't a point of the curve".into())),
+    }
+}
+
+/// The inspection of an address, along with an error to exit with if
+/// it's invalid.
+fn address_inspection(address: &str) -> Output {
+    let (parts, error) = inspect_address(address);
+    let invalid = error.is_some();
+    let inspection = Output::AddressInspection(AddressInspection {
+        address: address.to_string(),
+        valid: !invalid,
+        prefix: parts.as_ref().map(|p| p.prefix),
+        public_key: parts.as_ref().map(|p| p.public_key.clone()),
+        checksum: parts.as_ref().map(|p| p.checksum.clone()),
+        error,
+        parts,
+    });
+
+    match invalid {
+        true => Output::Partial(vec![inspection], Error::InvalidAddress),
+        false => inspection,
+    }
+}
+
+#[async_std::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    // Health checks report through their status line, keep the logs for -v
+    let log_level = match args.command {
+        DrkSubcommand::Healthcheck { .. } if args.verbose == 0 => simplelog::LevelFilter::Off,
+        _ => get_log_level(args.verbose.into()),
+    };
+    let log_config = get_log_config();
+    init_logger(log_level, log_config, args.log_file.as_deref())?;
+
+    // Colors are only for humans watching a terminal
+    let color =
+        !args.no_color && std::env::var_os("NO_COLOR").is_none() && atty::is(atty::Stream::Stdout);
+    let printer =
+        Printer { json: args.json, raw: args.raw, separator: args.thousands_separator, color };
+
+    // The client version alone doesn't need darkfid
+    if let DrkSubcommand::Version { full: false } = args.command {
+        return printer.print(client_version())
+    }
+
+    // Neither does decoding an address
+    if let DrkSubcommand::Address { command: AddressSubcommand::Inspect { address } } =
+        &args.command
+    {
+        if printer.print(address_inspection(address)).is_err() {
+            exit(1)
+        }
+        return Ok(())
+    }
+
+    // Monitoring only looks at the exit code and a single line, so skip the
+    // warnings and the connection error messages
+    if let DrkSubcommand::Healthcheck { quiet } = args.command {
+        let (healthy, status) =
+            match RpcClient::new_with_failover(&args.endpoint, Some(args.timeout)).await {
+                Ok(rpc_client) => {
+                    let health = check_health(&rpc_client).await;
+                    rpc_client.close().await.ok();
+                    health
+                }
+                Err(e) => {
+                    let endpoints: Vec<String> =
+                        args.endpoint.iter().map(|url| url.to_string()).collect();
+                    health_status(&endpoints.join(", "), &Err(e), Duration::ZERO)
+                }
+            };
+        if !quiet {
+            printer.print(Output::Health(Health { healthy, status }))?;
+        }
+        exit(if healthy { 0 } else { EXIT_UNHEALTHY })
+    }
+
+    if !args.insecure {
+        for endpoint in args.endpoint.iter().filter(|url| is_insecure_endpoint(url)) {
+            eprintln!(
+                "WARNING: {} is a remote endpoint without TLS, wallet requests and replies \
+                 can be read on the network. Use a tcp+tls:// or tor:// endpoint, or pass \
+                 --insecure to silence this warning.",
+                endpoint
+            );
+        }
+    }
+
+    if args.all_endpoints {
+        let (method, describe): (&str, fn(&Value) -> String) = match args.command {
+            DrkSubcommand::Ping => {
+                ("ping", |rep| rep.as_str().map_or_else(|| rep.to_string(), String::from))
+            }
+            DrkSubcommand::Wallet { balance: true, prices: None, price_endpoint: None, .. } => {
+                ("wallet.get_balances", balances_summary)
+            }
+            _ => {
+                eprintln!("--all-endpoints only works with `ping` and `wallet --balance`");
+                exit(1)
+            }
+        };
+
+        let replies = query_all_endpoints(&args.endpoint, method, args.timeout).await;
+        let (lines, divergent) = endpoints_report(&replies, describe);
+        if args.json {
+            print_json(&endpoints_report_json(&replies, divergent))?;
+        } else {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        if replies.iter().all(|r| r.reply.is_err()) {
+            exit(EXIT_CONNECTION_ERROR)
+        }
+        exit(if divergent { EXIT_DIVERGENT } else { 0 })
+    }
+
+    let rpc_client = match RpcClient::new_with_failover(&args.endpoint, Some(args.timeout)).await {
+        Ok(rpc_client) => rpc_client,
+        Err(e) => {
+            let endpoints: Vec<String> = args.endpoint.iter().map(|url| url.to_string()).collect();
+            exit_on_connection_error(&e, &endpoints.join(", "), args.timeout, args.json);
+            exit_on_json_error(&e, args.json);
+            return Err(e)
+        }
+    };
+
+    // The spinner is only for humans watching a terminal
+    let progress = !args.no_progress &&
+        !args.json &&
+        atty::is(atty::Stream::Stdout) &&
+        atty::is(atty::Stream::Stderr);
+
+    let mut drk = Drk { rpc_client, timeout: args.timeout, progress, printer };
+
+    let result = match args.command {
+        DrkSubcommand::Repl => drk.repl(&args.endpoint).await,
+        command => drk.handle_command(command).await.and_then(|output| drk.printer.print(output)),
+    };
+
+    if let Err(e) = result {
+        exit_on_connection_error(&e, drk.rpc_client.url().as_str(), args.timeout, args.json);
+        exit_on_json_error(&e, args.json);
+        return Err(e)
+    }
+
+    drk.close_connection().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use darkfi::crypto::keypair::SecretKey;
+
+    #[test]
+    fn test_format_amount() -> Result<()> {
+        assert_eq!(format_amount("0.3", 8, None)?, "0.30000000");
+        assert_eq!(format_amount("1234567.5", 2, Some(','))?, "1,234,567.50");
+        assert_eq!(format_amount("123456", 0, Some(','))?, "123,456");
+        assert_eq!(format_amount("999.999", 2, Some('\''))?, "999.99");
+        assert_eq!(format_amount("1", 18, None)?, "1.000000000000000000");
+        assert_eq!(format_amount("0.000000001", 9, None)?, "0.000000001");
+        assert_eq!(format_amount("0.123456789", 8, None)?, "0.12345678");
+        assert!(format_amount("abc", 8, None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_transfer_keys() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("drk_transfer_keys_{}.json", std::process::id()));
+        let params =
+            json!(["solana", "SOL", "1SkB92YpWm4Q2ijQHH34cqbKkCZWszsiQgHVjtNeFF2FLMrMt", 1.5]);
+        let other =
+            json!(["solana", "SOL", "1SkB92YpWm4Q2ijQHH34cqbKkCZWszsiQgHVjtNeFF2FLMrMt", 2.0]);
+
+        let mut keys = TransferKeys::load(path.clone())?;
+        let key = keys.get(&params)?;
+        assert_eq!(key.len(), 32);
+        assert_eq!(keys.get(&params)?, key);
+        assert_ne!(keys.get(&other)?, key);
+
+        // A retry after drk exited reuses the key
+        let mut keys = TransferKeys::load(path.clone())?;
+        assert_eq!(keys.get(&params)?, key);
+
+        // Once confirmed, the same transfer gets a new one
+        keys.remove(&params)?;
+        let mut keys = TransferKeys::load(path.clone())?;
+        assert_ne!(keys.get(&params)?, key);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_balances_table() {
+        assert!(balances_table(&json!({}), |a, _| a.to_string()).is_none());
+
+        let balances = json!({
+            "SOL": ["1.5", "solana", "So111", "7Dark", 9],
+            "8Dark": ["0.25", "darkfi", "unknown", "8Dark", 8],
+        });
+        let table = balances_table(&balances, |a, d| format_amount(a, d, None).unwrap()).unwrap();
+        // Without the borders and separators
+        let lines: Vec<String> = table
+            .to_string()
+            .lines()
+            .filter(|line| !line.trim().chars().all(|c| c == '-' || c == '='))
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "Token ID Symbol Amount Network",
+                "8Dark - 0.25000000 darkfi",
+                "7Dark SOL 1.500000000 solana"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_balances_json() {
+        let balances = json!({"SOL": ["1.5", "solana", "So111", "7Dark", 9]});
+        assert_eq!(
+            balances_json(&balances).unwrap(),
+            json!({"SOL": {"amount": "1.5", "network": "solana", "token_id": "7Dark", "decimals": 9}})
+        );
+    }
+
+    #[test]
+    fn test_malformed_replies() {
+        assert!(balances_json(&Value::Null).is_err());
+        assert!(balances_json(&json!(["1.5"])).is_err());
+
+        assert_eq!(default_address(&json!(["7Dark"])).unwrap(), Some("7Dark".into()));
+        assert_eq!(default_address(&json!([null])).unwrap(), None);
+        assert!(default_address(&json!({"0": "7Dark"})).is_err());
+        assert!(default_address(&json!([42])).is_err());
+    }
+
+    #[test]
+    fn test_error_json() {
+        assert_eq!(
+            error_json(&Error::TimeoutError, "darkfid did not reply"),
+            json!({"code": "timeout", "message": "darkfid did not reply"})
+        );
+        let err = Error::JsonRpcError("Wallet is locked".to_string());
+        assert_eq!(error_json(&err, &err.to_string())["code"], "rpc_error");
+        let err = Error::ClientFailed(ClientFailed::NotEnoughValue(5));
+        assert_eq!(error_json(&err, &err.to_string())["code"], "insufficient_funds");
+        assert_eq!(error_json(&Error::InvalidAddress, "")["code"], "invalid_input");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("1.5s").is_err());
+    }
+
+    #[test]
+    fn test_parse_amount() -> Result<()> {
+        assert_eq!(parse_amount("1.5", 8)?, 150_000_000);
+        assert_eq!(parse_amount("2", 8)?, 200_000_000);
+        assert_eq!(parse_amount(".25", 2)?, 25);
+        assert_eq!(parse_amount("3.", 0)?, 3);
+        assert_eq!(parse_amount("1000000.000000001", 9)?, 1_000_000_000_000_001);
+        assert_eq!(parse_amount("0.1", 8)?, 10_000_000);
+
+        assert!(parse_amount("1000000.000000001", 8).is_err());
+        assert!(parse_amount("18446744073709551616", 0).is_err());
+        for amount in ["", ".", "-1", "1e5", "1.2.3", " 1", "abc"] {
+            assert!(parse_amount(amount, 8).is_err(), "{:?}", amount);
+        }
+        assert_eq!(
+            parse_drk_amount("0.000000001"),
+            Err("Parse failed: the amount has more decimals than the token allows".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_transfer() -> Result<()> {
+        let addr = "1SkB92YpWm4Q2ijQHH34cqbKkCZWszsiQgHVjtNeFF2FLMrMt";
+        let expected = TransferRequest {
+            recipient: Address::from_str(addr)?,
+            amount: 150_000_000,
+            token_id: "DRK".into(),
+            decimals: 8,
+        };
+        let decimals = |token: &str| if token == "USDC" { 2 } else { 8 };
+        let parse = |line: &str, default_token_id| {
+            TransferRequest::parse(line, default_token_id, &decimals)
+        };
+
+        assert_eq!(parse(&format!("{} 1.5 DRK", addr), None)?, expected);
+        assert_eq!(parse(&format!("{}  1.5", addr), Some("DRK"))?, expected);
+
+        let line = format!(r#"{{"recipient": "{}", "amount": 1.5, "token_id": "DRK"}}"#, addr);
+        assert_eq!(parse(&line, None)?, expected);
+        let line = format!(r#"{{"recipient": "{}", "amount": "1.5"}}"#, addr);
+        assert_eq!(parse(&line, Some("DRK"))?, expected);
+
+        // Amounts are parsed with the decimals of their token
+        assert_eq!(parse(&format!("{} 1.5 USDC", addr), None)?.amount, 150);
+        assert!(parse(&format!("{} 1.505 USDC", addr), None).is_err());
+
+        // The token ID given on the line wins over the default
+        let line = format!("{} 1.5 DRK", addr);
+        assert_eq!(parse(&line, Some("OTHER"))?.token_id, "DRK");
+
+        assert!(parse(&format!("{} 1.5", addr), None).is_err());
+        assert!(parse(&format!("{} -1 DRK", addr), None).is_err());
+        assert!(parse(&format!("{} abc DRK", addr), None).is_err());
+        assert!(parse(&format!("{} 0 DRK", addr), None).is_err());
+        assert!(parse(&format!("{} 0.000000001 DRK", addr), None).is_err());
+        assert!(parse(&format!("{} 1 DRK extra", addr), None).is_err());
+        assert!(parse("notanaddress 1 DRK", None).is_err());
+        assert!(parse(r#"{"amount": 1, "token_id": "DRK"}"#, None).is_err());
+        assert!(parse(r#"{"recipient": "x""#, None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_token() {
+        let balances = json!({
+            "DRK": ["1.5", "darkfi", "drk", "tokenDRK", 8],
+            "tokenOTHER": ["2", "darkfi", "unknown", "tokenOTHER", 8],
+        });
+        assert_eq!(resolve_token_id(&balances, "DRK"), "tokenDRK");
+        assert_eq!(resolve_token_id(&balances, "tokenOTHER"), "tokenOTHER");
+        assert_eq!(resolve_token_id(&balances, "tokenNEW"), "tokenNEW");
+        assert_eq!(token_name(&balances, "tokenDRK"), "DRK");
+        assert_eq!(token_name(&balances, "tokenNEW"), "tokenNEW");
+    }
+
+    #[test]
+    fn test_insufficient_funds() -> Result<()> {
+        let addr = "1SkB92YpWm4Q2ijQHH34cqbKkCZWszsiQgHVjtNeFF2FLMrMt";
+        let balances = json!({
+            "DRK": ["1.5", "darkfi", "drk", "tokenDRK", 8],
+            "BTC": ["2", "Bitcoin", "btc", "tokenBTC", 8],
+            "USDC": ["2.5", "darkfi", "usdc", "tokenUSDC", 6],
+        });
+        let darkfi = NetworkName::DarkFi;
+        let decimals = |token: &str| token_decimals(&balances, Some(&darkfi), token);
+        let transfer = |amount: &str, token: &str| {
+            TransferRequest::new(addr, amount, Some(token.into()), &decimals)
+        };
+
+        assert_eq!(token_balance(&balances, &darkfi, "drk")?, 150_000_000);
+        assert_eq!(token_balance(&balances, &darkfi, "tokenDRK")?, 150_000_000);
+        assert_eq!(token_balance(&balances, &darkfi, "BTC")?, 0);
+        assert_eq!(token_balance(&balances, &darkfi, "usdc")?, 2_500_000);
+        assert_eq!(token_decimals(&balances, None, "tokenUSDC"), 6);
+        assert_eq!(token_decimals(&balances, None, "GOLD"), AMOUNT_DECIMALS);
+
+        let transfers = vec![transfer("1", "DRK")?, transfer("0.5", "DRK")?];
+        assert!(insufficient_funds(&balances, &darkfi, &transfers)?.is_empty());
+
+        // Transfers of the same token add up
+        let transfers = vec![
+            transfer("1", "DRK")?,
+            transfer("1", "DRK")?,
+            transfer("0.1", "GOLD")?,
+            transfer("2.5", "USDC")?,
+        ];
+        assert_eq!(
+            insufficient_funds(&balances, &darkfi, &transfers)?,
+            vec![
+                ("DRK".to_string(), 200_000_000, 150_000_000),
+                ("GOLD".to_string(), 10_000_000, 0)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_balances() -> Result<()> {
+        let balances = json!({
+            "DRK": ["1.5", "darkfi", "drk", "tokenDRK", 8],
+            "BTC": ["2", "Bitcoin", "btc", "tokenBTC", 8],
+            "GOLD": ["10", "darkfi", "gold", "tokenGOLD", 8],
+        });
+        let rates = parse_rates(&json!({"DRK": 2, "tokenBTC": 30000.5}))?;
+
+        assert_eq!(
+            value_balances(&balances, &rates),
+            Valuation {
+                priced: vec![("BTC".into(), 60001.0), ("DRK".into(), 3.0)],
+                unpriced: vec!["GOLD".into()],
+                total: 60004.0,
+            }
+        );
+
+        assert!(parse_rates(&json!({"DRK": -1})).is_err());
+        assert!(parse_rates(&json!({"DRK": "2"})).is_err());
+        assert!(parse_rates(&json!([2])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_table() {
+        let txs = vec![
+            json!({"tx_id": "ab12", "direction": "sent", "counterparty": "1DarkFi",
+                   "amount": "1.5", "token": "DRK", "timestamp": 1650000000}),
+            json!({"tx_id": "cd34", "direction": "received", "counterparty": null,
+                   "amount": "10", "decimals": 2, "token": "BTC", "timestamp": 1650000000}),
+        ];
+        let date = timestamp_to_date(1650000000, DateFormat::DateTime);
+        let table =
+            history_table(&txs, |amount, decimals| format_amount(amount, decimals, None).unwrap())
+                .to_string();
+        // Without the borders and separators
+        let lines: Vec<&str> = table
+            .lines()
+            .filter(|line| !line.trim().chars().all(|c| c == '-' || c == '='))
+            .map(|line| line.trim_end())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                " TxID  Direction  Counterparty  Amount      Token  Date".to_string(),
+                format!(" ab12  sent       1DarkFi       1.50000000  DRK    {}", date),
+                format!(" cd34  received   -                  10.00  BTC    {}", date),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_insecure_endpoint() {
+        let insecure = |url: &str| is_insecure_endpoint(&Url::parse(url).unwrap());
+        assert!(insecure("tcp://10.0.0.2:8340"));
+        assert!(insecure("tcp://darkfid.example.com:8340"));
+        assert!(!insecure("tcp://127.0.0.1:8340"));
+        assert!(!insecure("tcp://[::1]:8340"));
+        assert!(!insecure("tcp://localhost:8340"));
+        assert!(!insecure("tcp+tls://10.0.0.2:8340"));
+        assert!(!insecure("tor://abcdef.onion:8340"));
+        assert!(!insecure("unix:///tmp/darkfid.sock"));
+    }
+
+    #[test]
+    fn test_versions_compatible() {
+        assert!(versions_compatible("0.3.0", "0.3.2"));
+        assert!(!versions_compatible("0.3.0", "0.4.0"));
+        assert!(versions_compatible("1.2.0", "1.5.1"));
+        assert!(!versions_compatible("1.2.0", "2.2.0"));
+        assert!(versions_compatible("0.3.0-alpha", "0.3.1"));
+        assert!(!versions_compatible("0.3.0", ""));
+    }
+
+    #[test]
+    fn test_latency_color() {
+        assert_eq!(latency_color(Duration::from_millis(5)), GREEN);
+        assert_eq!(latency_color(LATENCY_GOOD), GREEN);
+        assert_eq!(latency_color(Duration::from_millis(250)), YELLOW);
+        assert_eq!(latency_color(Duration::from_secs(2)), RED);
+    }
+
+    #[test]
+    fn test_health_status() {
+        let latency = Duration::from_millis(12);
+        let endpoint = "tcp://127.0.0.1:8340";
+
+        let (healthy, status) = health_status(endpoint, &Ok(json!("pong")), latency);
+        assert!(healthy);
+        assert_eq!(status, "OK: darkfid at tcp://127.0.0.1:8340 replied in 12.0ms");
+
+        let (healthy, status) = health_status(endpoint, &Ok(json!(null)), latency);
+        assert!(!healthy);
+        assert_eq!(status, "CRITICAL: darkfid at tcp://127.0.0.1:8340 replied null to a ping");
+
+        let (healthy, status) = health_status(endpoint, &Err(Error::TimeoutError), latency);
+        assert!(!healthy);
+        assert!(status.starts_with("CRITICAL:"));
+        assert!(!status.contains('\n'));
+    }
+
+    #[test]
+    fn test_endpoints_report() {
+        let reply = |port: u16, ms: u64, reply: Result<Value>| EndpointReply {
+            endpoint: Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap(),
+            latency: Duration::from_millis(ms),
+            reply,
+        };
+        let balances = json!({"SOL": ["1.5", "solana", "", "id1", 8], "BTC": ["0.2", "bitcoin", "", "id2", 8]});
+        assert_eq!(balances_summary(&balances), "0.2 BTC, 1.5 SOL");
+        assert_eq!(balances_summary(&json!({})), "no balances");
+
+        let replies = vec![
+            reply(8340, 30, Ok(balances.clone())),
+            reply(8341, 12, Ok(balances.clone())),
+            reply(8342, 1000, Err(Error::TimeoutError)),
+        ];
+        let (lines, divergent) = endpoints_report(&replies, balances_summary);
+        assert!(!divergent);
+        assert_eq!(lines[0], "tcp://127.0.0.1:8340     30.0ms  0.2 BTC, 1.5 SOL");
+        assert!(lines[2].starts_with("tcp://127.0.0.1:8342   1000.0ms  error: "));
+        assert_eq!(lines[3], "Fastest: tcp://127.0.0.1:8341");
+        assert_eq!(lines.len(), 4);
+
+        let report = endpoints_report_json(&replies, divergent);
+        assert_eq!(report["fastest"], "tcp://127.0.0.1:8341");
+        assert_eq!(report["endpoints"][0]["reply"], balances);
+        assert!(report["endpoints"][2]["error"].is_string());
+        assert_eq!(report["divergent"], false);
+
+        // A node missing a transaction reports another balance
+        let behind = json!({"SOL": ["1.5", "solana", "", "id1", 8]});
+        let replies = vec![reply(8340, 30, Ok(balances)), reply(8341, 12, Ok(behind))];
+        let (lines, divergent) = endpoints_report(&replies, balances_summary);
+        assert!(divergent);
+        assert!(lines.last().unwrap().starts_with("WARNING:"));
+    }
+
+    #[test]
+    fn test_inspect_address() -> Result<()> {
+        let mut secret = [0u8; 32];
+        secret[0] = 42;
+        let public = PublicKey::from_secret(SecretKey::from_bytes(secret)?);
+        let address = Address::from(public);
+
+        let (parts, error) = inspect_address(&address.to_string());
+        assert_eq!(error, None);
+        let lines = parts.unwrap().lines();
+        assert_eq!(lines[0], "Type:       payment (0x00)");
+        let public_hex: String = public.to_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(lines[1], format!("Public key: {}", public_hex));
+
+        let mut bytes = address.0;
+        bytes[36] ^= 1;
+        let (parts, error) = inspect_address(&Address(bytes).to_string());
+        assert!(parts.is_none());
+        assert!(error.unwrap().starts_with("checksum mismatch"));
+
+        let (_, error) = inspect_address("9GmLk7kkbxhsbLTYFMeg6FyuQJV9Na2GcJYFNrs3VLkv");
+        assert_eq!(error.unwrap(), "wrong length: 32 bytes instead of 37");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_tx_file() -> Result<()> {
+        let file = r#"{"version": 1, "network": "darkfi", "tx_id": "abcd", "tx": "0a1B"}"#;
+        assert_eq!(
+            TxFile::parse(file)?,
+            TxFile { tx: "0a1B".to_string(), tx_id: "abcd".to_string() }
+        );
+
+        assert!(TxFile::parse("0a1b").is_err());
+        assert!(TxFile::parse(r#"{"version": 2, "tx_id": "abcd", "tx": "0a1b"}"#).is_err());
+        assert!(TxFile::parse(r#"{"version": 1, "tx_id": "abcd", "tx": "0a1"}"#).is_err());
+        assert!(TxFile::parse(r#"{"version": 1, "tx_id": "abcd", "tx": "0x1b"}"#).is_err());
+        assert!(TxFile::parse(r#"{"version": 1, "tx_id": "abcd", "tx": ""}"#).is_err());
+        assert!(TxFile::parse(r#"{"version": 1, "tx": "0a1b"}"#).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,485 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use darkfi::{util::parse::encode_base10, Error, Result};
+
+use crate::{
+    balances_table, format_amount, history_table, latency_color, token_decimals, token_name,
+    AddressParts, RESET,
+};
+
+/// Result of a `ping`.
+#[derive(Serialize)]
+pub struct Ping {
+    pub endpoint: String,
+    /// darkfid version, unknown for the versions without `system.version`
+    pub version: Option<String>,
+    pub reply: Value,
+    pub latency_ms: f64,
+    pub latency_us: u64,
+}
+
+/// Result of a health check.
+#[derive(Serialize)]
+pub struct Health {
+    pub healthy: bool,
+    pub status: String,
+}
+
+/// Result of an airdrop request to the faucet.
+#[derive(Serialize)]
+pub struct Airdrop {
+    pub address: String,
+    pub tx_id: Value,
+}
+
+/// An address of the wallet, shown after `label` if there is one.
+#[derive(Serialize)]
+pub struct WalletAddress {
+    /// None for the default address of a wallet without addresses
+    pub address: Option<String>,
+    #[serde(skip)]
+    pub label: Option<&'static str>,
+}
+
+/// All the addresses of the wallet.
+#[derive(Serialize)]
+pub struct Addresses {
+    pub addresses: Vec<String>,
+}
+
+/// The new default address of the wallet.
+#[derive(Serialize)]
+pub struct DefaultAddress {
+    pub default_address: String,
+}
+
+/// Whether the wallet is locked, after locking or unlocking it.
+#[derive(Serialize)]
+pub struct Locked {
+    pub locked: bool,
+}
+
+/// Value of the balances in a reference unit.
+#[derive(Serialize)]
+pub struct BalancesValue {
+    pub unit: String,
+    /// Value of each token with a known rate, by ticker
+    pub priced: Map<String, Value>,
+    pub total: f64,
+    /// Tickers of the tokens without a known rate
+    pub unpriced: Vec<String>,
+}
+
+/// Balances of the wallet, and their value if asked for.
+#[derive(Serialize)]
+pub struct Balances {
+    /// As returned by `wallet.get_balances`
+    #[serde(skip)]
+    pub rep: Value,
+    /// Balances as objects by ticker
+    pub balances: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<BalancesValue>,
+}
+
+/// Summary of the wallet.
+#[derive(Serialize)]
+pub struct Whoami {
+    pub default_address: Option<String>,
+    pub addresses: usize,
+    /// Amount of each token, by ticker
+    pub balances: Map<String, Value>,
+    /// As returned by `wallet.get_balances`
+    #[serde(skip)]
+    pub rep: Value,
+}
+
+/// A transfer sent.
+#[derive(Serialize)]
+pub struct Transfer {
+    pub tx_id: Value,
+    pub recipient: String,
+    pub amount: String,
+    pub token_id: String,
+    pub fee: Value,
+    pub change: Value,
+    /// Decimals of the token
+    #[serde(skip)]
+    pub decimals: usize,
+}
+
+/// A transfer validated but not sent.
+#[derive(Serialize)]
+pub struct DryRun {
+    pub dry_run: bool,
+    pub recipient: String,
+    pub amount: String,
+    pub token_id: String,
+    pub network: String,
+    /// Decimals of the token
+    #[serde(skip)]
+    pub decimals: usize,
+}
+
+/// A transfer built and written to a file, not broadcast yet.
+#[derive(Serialize)]
+pub struct Built {
+    pub tx_id: Value,
+    pub output: String,
+    pub change: Value,
+    /// Decimals of the token
+    #[serde(skip)]
+    pub decimals: usize,
+}
+
+/// A transaction broadcast from a file.
+#[derive(Serialize)]
+pub struct Broadcast {
+    pub tx_id: Value,
+}
+
+/// Result of a sweep, printed as returned by `wallet.sweep` with `--json`.
+pub struct Sweep {
+    pub rep: Value,
+    /// As returned by `wallet.get_balances`, to name the tokens
+    pub balances: Value,
+    pub dry_run: bool,
+}
+
+/// Transactions of the wallet, printed as returned by darkfid with
+/// `--json`.
+pub struct History {
+    pub txs: Value,
+    /// Shown when there are no transactions
+    pub empty: &'static str,
+}
+
+/// Versions of drk and darkfid.
+#[derive(Serialize)]
+pub struct Version {
+    pub drk: String,
+    pub darkfid: String,
+    pub compatible: bool,
+}
+
+/// Version of drk alone.
+#[derive(Serialize)]
+pub struct ClientVersion {
+    pub drk: &'static str,
+}
+
+/// Decoded address, and the validation step it fails at if any.
+#[derive(Serialize)]
+pub struct AddressInspection {
+    pub address: String,
+    pub valid: bool,
+    pub prefix: Option<u8>,
+    pub public_key: Option<String>,
+    pub checksum: Option<String>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub parts: Option<AddressParts>,
+}
+
+/// What a command prints. Commands return it instead of printing it
+/// themselves, so each result is printed once, as JSON or for humans.
+pub enum Output {
+    /// Nothing to print
+    None,
+    /// Several results, e.g. one per transfer
+    Many(Vec<Output>),
+    /// The results of a command that failed midway, printed before the
+    /// error it stopped on is returned
+    Partial(Vec<Output>, Error),
+    Ping(Ping),
+    Health(Health),
+    Airdrop(Airdrop),
+    WalletAddress(WalletAddress),
+    Addresses(Addresses),
+    DefaultAddress(DefaultAddress),
+    Locked(Locked),
+    Balances(Balances),
+    Whoami(Whoami),
+    Transfer(Transfer),
+    DryRun(DryRun),
+    Built(Built),
+    Broadcast(Broadcast),
+    Sweep(Sweep),
+    History(History),
+    Version(Version),
+    ClientVersion(ClientVersion),
+    AddressInspection(AddressInspection),
+}
+
+impl Output {
+    /// The result as the JSON object printed with `--json`.
+    pub fn to_json(&self) -> Result<Value> {
+        let value = match self {
+            Self::None | Self::Many(_) | Self::Partial(..) => Value::Null,
+            Self::Ping(v) => serde_json::to_value(v)?,
+            Self::Health(v) => serde_json::to_value(v)?,
+            Self::Airdrop(v) => serde_json::to_value(v)?,
+            Self::WalletAddress(v) => serde_json::to_value(v)?,
+            Self::Addresses(v) => serde_json::to_value(v)?,
+            Self::DefaultAddress(v) => serde_json::to_value(v)?,
+            Self::Locked(v) => serde_json::to_value(v)?,
+            Self::Balances(v) => serde_json::to_value(v)?,
+            Self::Whoami(v) => serde_json::to_value(v)?,
+            Self::Transfer(v) => serde_json::to_value(v)?,
+            Self::DryRun(v) => serde_json::to_value(v)?,
+            Self::Built(v) => serde_json::to_value(v)?,
+            Self::Broadcast(v) => serde_json::to_value(v)?,
+            Self::Sweep(v) => v.rep.clone(),
+            Self::History(v) => v.txs.clone(),
+            Self::Version(v) => serde_json::to_value(v)?,
+            Self::ClientVersion(v) => serde_json::to_value(v)?,
+            Self::AddressInspection(v) => serde_json::to_value(v)?,
+        };
+        Ok(value)
+    }
+}
+
+/// Prints the results of the commands, as JSON or for humans.
+pub struct Printer {
+    /// Print results as JSON
+    pub json: bool,
+    /// Print amounts without formatting
+    pub raw: bool,
+    /// Thousands separator for formatted amounts
+    pub separator: Option<char>,
+    /// Color the output
+    pub color: bool,
+}
+
+impl Printer {
+    /// Format an amount for display, unless raw output was requested.
+    pub fn amount(&self, amount: &str, decimals: usize) -> String {
+        if self.raw {
+            return amount.to_string()
+        }
+
+        format_amount(amount, decimals, self.separator).unwrap_or_else(|_| amount.to_string())
+    }
+
+    /// Format an amount in base units of a token with `decimals` decimals
+    /// for display.
+    pub fn base_amount(&self, amount: u64, decimals: usize) -> String {
+        self.amount(&encode_base10(amount.into(), decimals), decimals)
+    }
+
+    /// Wrap `text` in the given color, if the output is colored.
+    pub fn paint(&self, text: &str, color: &str) -> String {
+        if !self.color {
+            return text.to_string()
+        }
+        format!("{}{}{}", color, text, RESET)
+    }
+
+    /// Print the results of a command, each on a single line of JSON with
+    /// `--json`, then return the error the command stopped on, if any.
+    pub fn print(&self, output: Output) -> Result<()> {
+        match output {
+            Output::None => Ok(()),
+            Output::Many(outputs) => {
+                for output in outputs {
+                    self.print(output)?;
+                }
+                Ok(())
+            }
+            Output::Partial(outputs, error) => {
+                for output in outputs {
+                    self.print(output)?;
+                }
+                Err(error)
+            }
+            output if self.json => print_json(&output.to_json()?),
+            output => {
+                self.print_human(&output);
+                Ok(())
+            }
+        }
+    }
+
+    fn print_human(&self, output: &Output) {
+        match output {
+            Output::None | Output::Many(_) | Output::Partial(..) => {}
+
+            Output::Ping(ping) => {
+                let latency = Duration::from_micros(ping.latency_us);
+                let latency_str = format!("{:.1}ms", ping.latency_ms);
+                let reply =
+                    ping.reply.as_str().map_or_else(|| ping.reply.to_string(), String::from);
+                println!("{:<10}{}", "Endpoint:", ping.endpoint);
+                println!("{:<10}{}", "Version:", ping.version.as_deref().unwrap_or("unknown"));
+                println!("{:<10}{}", "Reply:", reply);
+                println!("{:<10}{}", "Latency:", self.paint(&latency_str, latency_color(latency)));
+            }
+
+            Output::Health(health) => println!("{}", health.status),
+
+            Output::Airdrop(airdrop) => {
+                println!("Success! Transaction ID: {}", as_text(&airdrop.tx_id));
+            }
+
+            Output::WalletAddress(address) => {
+                let text = address.address.as_deref().unwrap_or("none");
+                match address.label {
+                    Some(label) => println!("{}: {}", label, text),
+                    None => println!("{}", text),
+                }
+            }
+
+            Output::Addresses(addresses) => {
+                println!("Wallet addresses:");
+                for address in addresses.addresses.iter() {
+                    println!("  {}", address);
+                }
+            }
+
+            Output::DefaultAddress(address) => {
+                println!("Default wallet address set to {}", address.default_address);
+            }
+
+            Output::Locked(Locked { locked: true }) => println!("Wallet locked"),
+            Output::Locked(Locked { locked: false }) => println!("Wallet unlocked"),
+
+            Output::Balances(balances) => {
+                self.print_balances(&balances.rep);
+                if let Some(value) = &balances.value {
+                    println!("Value in {}:", value.unit);
+                    for (ticker, amount) in value.priced.iter() {
+                        let amount = amount.as_f64().unwrap_or_default();
+                        println!("  {:.2} {} ({})", amount, value.unit, ticker);
+                    }
+                    println!("Total: {:.2} {}", value.total, value.unit);
+                    if !value.unpriced.is_empty() {
+                        println!("Unpriced: {}", value.unpriced.join(", "));
+                    }
+                }
+            }
+
+            Output::Whoami(whoami) => {
+                println!(
+                    "Default address: {}",
+                    whoami.default_address.as_deref().unwrap_or("none")
+                );
+                println!("Addresses: {}", whoami.addresses);
+                self.print_balances(&whoami.rep);
+            }
+
+            Output::Transfer(transfer) => {
+                println!("Success! Transaction ID: {}", as_text(&transfer.tx_id));
+                if let Some(fee) = transfer.fee.as_str() {
+                    println!("Fee paid: {}", self.amount(fee, transfer.decimals));
+                }
+                if let Some(change) = transfer.change.as_str() {
+                    println!("Change returned: {}", self.amount(change, transfer.decimals));
+                }
+            }
+
+            Output::DryRun(dry_run) => println!(
+                "Would transfer {} {} tokens to {} on {}",
+                self.amount(&dry_run.amount, dry_run.decimals),
+                dry_run.token_id,
+                dry_run.recipient,
+                dry_run.network
+            ),
+
+            Output::Built(built) => {
+                println!("Transaction {} written to {}", as_text(&built.tx_id), built.output);
+                if let Some(change) = built.change.as_str() {
+                    println!("Change returned: {}", self.amount(change, built.decimals));
+                }
+            }
+
+            Output::Broadcast(broadcast) => {
+                println!("Success! Transaction ID: {}", as_text(&broadcast.tx_id));
+            }
+
+            Output::Sweep(sweep) => self.print_sweep(sweep),
+
+            Output::History(history) => {
+                let txs = history.txs.as_array().cloned().unwrap_or_default();
+                if txs.is_empty() {
+                    println!("{}", history.empty);
+                    return
+                }
+                history_table(&txs, |amount, decimals| self.amount(amount, decimals)).printstd();
+            }
+
+            Output::Version(version) => {
+                println!("drk {}", version.drk);
+                println!("darkfid {}", version.darkfid);
+                if !version.compatible {
+                    eprintln!(
+                        "Warning: drk {} may not work with darkfid {}",
+                        version.drk, version.darkfid
+                    );
+                }
+            }
+
+            Output::ClientVersion(version) => println!("drk {}", version.drk),
+
+            Output::AddressInspection(inspection) => {
+                for line in inspection.parts.iter().flat_map(|p| p.lines()) {
+                    println!("{}", line);
+                }
+                if let Some(error) = &inspection.error {
+                    eprintln!("Invalid address: {}", error);
+                }
+            }
+        }
+    }
+
+    /// Print the balances returned by `wallet.get_balances` as a table.
+    fn print_balances(&self, rep: &Value) {
+        match balances_table(rep, |amount, decimals| self.amount(amount, decimals)) {
+            Some(table) => {
+                table.printstd();
+            }
+            None => println!("No balances"),
+        }
+    }
+
+    fn print_sweep(&self, sweep: &Sweep) {
+        let swept = sweep.rep.as_array().cloned().unwrap_or_default();
+        if swept.is_empty() {
+            println!("Nothing to sweep, no token has more than one coin");
+            return
+        }
+
+        for swept in swept.iter() {
+            let token_id = swept["token_id"].as_str().unwrap_or_default();
+            let name = token_name(&sweep.balances, token_id);
+            let decimals = token_decimals(&sweep.balances, None, token_id);
+            let coins = swept["coins"].as_u64().unwrap_or_default();
+            let value = self.amount(swept["value"].as_str().unwrap_or_default(), decimals);
+
+            if sweep.dry_run {
+                println!("Would sweep {} coins of {} into one coin of {}", coins, name, value);
+                continue
+            }
+
+            println!("Swept {} coins of {} into one coin of {}", coins, name, value);
+            println!("  Transaction ID: {}", swept["tx_id"].as_str().unwrap_or_default());
+            if let Some(fee) = swept["fee"].as_str() {
+                println!("  Fee paid: {}", self.amount(fee, decimals));
+            }
+        }
+    }
+}
+
+/// A string of a reply as is, anything else as JSON.
+fn as_text(value: &Value) -> String {
+    value.as_str().map_or_else(|| value.to_string(), String::from)
+}
+
+/// Print a result with `--json`, on a single line so that several results
+/// can be read one per line.
+pub fn print_json(value: &Value) -> Result<()> {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
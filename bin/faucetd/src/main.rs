@@ -116,6 +116,10 @@ struct Args {
     #[structopt(short, parse(from_occurrences))]
     /// Increase verbosity (-vvv supported)
     verbose: u8,
+
+    #[structopt(long)]
+    /// Log to the given file as well (default is $DARKFI_LOG or /tmp/darkfi.log)
+    log_file: Option<String>,
 }
 
 pub struct Faucetd {
@@ -166,11 +170,16 @@ impl Faucetd {
 
     // RPCAPI:
     // Processes an airdrop request and airdrops requested amount to address.
+    // The amount is in base units, floats are still accepted and taken with
+    // 8 decimals.
     // Returns the transaction ID upon success.
-    // --> {"jsonrpc": "2.0", "method": "airdrop", "params": ["1DarkFi...", 1.42], "id": 1}
+    // --> {"jsonrpc": "2.0", "method": "airdrop", "params": ["1DarkFi...", 142000000], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "txID", "id": 1}
     async fn airdrop(&self, id: Value, params: &[Value]) -> JsonResult {
-        if params.len() != 2 || !params[0].is_string() || !params[1].is_f64() {
+        if params.len() != 2 ||
+            !params[0].is_string() ||
+            !(params[1].is_u64() || params[1].is_f64())
+        {
             return JsonError::new(InvalidParams, None, id).into()
         }
 
@@ -195,12 +204,17 @@ impl Faucetd {
             }
         };
 
-        let amount = params[1].as_f64().unwrap().to_string();
-        let amount = match decode_base10(&amount, 8, true) {
-            Ok(v) => v,
-            Err(_) => {
-                error!("airdrop(): Failed parsing amount from string");
-                return server_error(RpcError::ParseError, id)
+        let amount = match params[1].as_u64() {
+            Some(v) => BigUint::from(v),
+            None => {
+                let amount = params[1].as_f64().unwrap().to_string();
+                match decode_base10(&amount, 8, true) {
+                    Ok(v) => v,
+                    Err(_) => {
+                        error!("airdrop(): Failed parsing amount from string");
+                        return server_error(RpcError::ParseError, id)
+                    }
+                }
             }
         };
 
@@ -231,7 +245,7 @@ impl Faucetd {
             }
         };
 
-        let tx = match self
+        let (tx, _) = match self
             .client
             .build_transaction(
                 pubkey,
@@ -12,10 +12,11 @@ use log::{debug, error, info, warn};
 use rand::rngs::OsRng;
 use smol::future;
 use structopt_toml::StructOptToml;
+use zeroize::Zeroizing;
 
 use darkfi::{
     async_daemonize, net,
-    raft::{NetMsg, ProtocolRaft, Raft},
+    raft::{NetMsg, ProtocolRaft, Raft, RaftSettings},
     rpc::server::listen_and_serve,
     util::{
         cli::{get_log_config, get_log_level, spawn_config},
@@ -168,7 +169,14 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
     let datastore_path = expand_path(&settings.datastore)?;
     let net_settings = settings.net;
     let datastore_raft = datastore_path.join("ircd.db");
-    let mut raft = Raft::<Privmsg>::new(net_settings.inbound.clone(), datastore_raft)?;
+    let mut raft = Raft::<Privmsg>::new(
+        net_settings.inbound.clone(),
+        datastore_raft,
+        RaftSettings {
+            cluster_secret: settings.raft_secret.map(|secret| Zeroizing::new(secret.into_bytes())),
+            ..RaftSettings::default()
+        },
+    )?;
     let raft_sender = raft.get_broadcast();
     let raft_receiver = raft.get_commits();
 
@@ -182,13 +190,23 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
 
     let seen_net_msg = Arc::new(Mutex::new(vec![]));
     let raft_node_id = raft.id.clone();
+    let raft_mac_key = raft.mac_key();
     registry
         .register(net::SESSION_ALL, move |channel, p2p| {
             let raft_node_id = raft_node_id.clone();
             let sender = p2p_send_channel.clone();
             let seen_net_msg_cloned = seen_net_msg.clone();
+            let raft_mac_key = raft_mac_key.clone();
             async move {
-                ProtocolRaft::init(raft_node_id, channel, sender, p2p, seen_net_msg_cloned).await
+                ProtocolRaft::init(
+                    raft_node_id,
+                    channel,
+                    sender,
+                    p2p,
+                    seen_net_msg_cloned,
+                    raft_mac_key,
+                )
+                .await
             }
         })
         .await;
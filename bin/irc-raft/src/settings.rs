@@ -45,12 +45,20 @@ pub struct Args {
     #[structopt(long)]
     pub autojoin: Vec<String>,
 
+    /// Secret shared by the nodes, authenticating raft messages
+    #[structopt(long)]
+    pub raft_secret: Option<String>,
+
     #[structopt(flatten)]
     pub net: SettingsOpt,
 
     /// Increase verbosity
     #[structopt(short, parse(from_occurrences))]
     pub verbose: u8,
+
+    /// Log to the given file as well (default is $DARKFI_LOG or /tmp/darkfi.log)
+    #[structopt(long)]
+    pub log_file: Option<String>,
 }
 
 /// This struct holds information about preconfigured channels.
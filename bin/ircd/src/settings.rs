@@ -44,6 +44,10 @@ pub struct Args {
     /// Increase verbosity
     #[structopt(short, parse(from_occurrences))]
     pub verbose: u8,
+
+    /// Log to the given file as well (default is $DARKFI_LOG or /tmp/darkfi.log)
+    #[structopt(long)]
+    pub log_file: Option<String>,
 }
 
 /// This struct holds information about preconfigured channels.
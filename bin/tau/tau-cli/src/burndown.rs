@@ -0,0 +1,167 @@
+use chrono::NaiveDateTime;
+
+use crate::primitives::TaskInfo;
+
+const DAY: i64 = 24 * 3600;
+
+/// Number of lines the bars of the chart span.
+const CHART_HEIGHT: usize = 10;
+
+/// Estimated effort left in a project over time, see `tau burndown`.
+#[derive(Debug, PartialEq)]
+pub struct Burndown {
+    /// Effort left at the end of each day, as `(timestamp, effort)`, the
+    /// last one being now
+    pub samples: Vec<(i64, f32)>,
+    /// Tasks of the project without an estimate, which count as zero
+    pub unestimated: Vec<String>,
+}
+
+/// Whether `task` is in `project` or one of its subprojects.
+fn in_project(task: &TaskInfo, project: &str) -> bool {
+    task.project
+        .iter()
+        .any(|p| p == project || p.strip_prefix(project).is_some_and(|rest| rest.starts_with('.')))
+}
+
+impl Burndown {
+    /// Effort left in `project` each day of the last `days` days, from the
+    /// `tasks` as listed by `get_tasks` and `get_stop_tasks`. The current
+    /// estimates are used for the whole period.
+    pub fn new(tasks: &[TaskInfo], project: &str, days: u32, now: i64) -> Self {
+        let tasks: Vec<&TaskInfo> = tasks.iter().filter(|t| in_project(t, project)).collect();
+
+        let samples = (0..=days as i64)
+            .rev()
+            .map(|day| {
+                let ts = now - day * DAY;
+                let effort = tasks
+                    .iter()
+                    .filter(|task| task.created_at <= ts && task.state_at(ts) != "stop")
+                    .filter_map(|task| task.estimate)
+                    .sum();
+                (ts, effort)
+            })
+            .collect();
+
+        let mut unestimated: Vec<&TaskInfo> =
+            tasks.into_iter().filter(|task| task.estimate.is_none()).collect();
+        unestimated.sort_by_key(|task| task.id);
+
+        Self { samples, unestimated: unestimated.iter().map(|task| task.display_id()).collect() }
+    }
+}
+
+fn day_label(ts: i64) -> String {
+    NaiveDateTime::from_timestamp(ts, 0).format("%d %b").to_string()
+}
+
+/// Lines of an ASCII chart of the burndown, a column per day.
+pub fn burndown_chart(burndown: &Burndown) -> Vec<String> {
+    let max = burndown.samples.iter().map(|(_, effort)| *effort).fold(0.0, f32::max);
+    if max == 0.0 {
+        return vec!["No estimated effort in this period.".into()]
+    }
+
+    let heights: Vec<usize> = burndown
+        .samples
+        .iter()
+        .map(|(_, effort)| (effort / max * CHART_HEIGHT as f32).round() as usize)
+        .collect();
+
+    let axis_width = format!("{:.1}", max).len();
+    let mut lines = vec![];
+    for level in (1..=CHART_HEIGHT).rev() {
+        let label = match level {
+            CHART_HEIGHT => format!("{:.1}", max),
+            _ => String::new(),
+        };
+        let bars: String = heights.iter().map(|h| if *h >= level { '#' } else { ' ' }).collect();
+        lines.push(format!("{:>w$} |{}", label, bars.trim_end(), w = axis_width));
+    }
+    lines.push(format!("{:>w$} +{}", "0.0", "-".repeat(heights.len()), w = axis_width));
+
+    let first = day_label(burndown.samples[0].0);
+    let last = day_label(burndown.samples[burndown.samples.len() - 1].0);
+    let gap = heights.len().saturating_sub(first.len() + last.len()).max(1);
+    lines.push(format!("{:w$}  {}{}{}", "", first, " ".repeat(gap), last, w = axis_width));
+
+    lines
+}
+
+pub fn print_burndown(burndown: &Burndown, project: &str) {
+    println!("Effort left in {}:", project);
+    println!();
+    for line in burndown_chart(burndown) {
+        println!("{}", line);
+    }
+
+    if let Some((_, effort)) = burndown.samples.last() {
+        println!();
+        println!("Left now: {}", effort);
+    }
+
+    if !burndown.unestimated.is_empty() {
+        println!(
+            "Without an estimate, counted as zero: {} (set one with `tau update <ID> --estimate`)",
+            burndown.unestimated.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(
+        id: u32,
+        project: &str,
+        estimate: Option<f32>,
+        created_at: i64,
+        events: &[(&str, i64)],
+    ) -> TaskInfo {
+        TaskInfo {
+            project: vec![project.into()],
+            created_at,
+            estimate,
+            ..TaskInfo::with_events(id, events)
+        }
+    }
+
+    #[test]
+    fn test_burndown() {
+        let now = 10 * DAY;
+        let tasks = vec![
+            // Open for the whole period
+            task(1, "tau", Some(3.0), 0, &[]),
+            // Created 2 days ago
+            task(2, "tau.cli", Some(2.0), 8 * DAY, &[]),
+            // Stopped 1 day ago, then reopened just before now
+            task(3, "tau", Some(5.0), 0, &[("stop", 9 * DAY - 1), ("open", now - 1)]),
+            // Not estimated
+            task(4, "tau", None, 0, &[]),
+            // In another project
+            task(5, "taud", Some(8.0), 0, &[]),
+            task(6, "raft", None, 0, &[]),
+        ];
+
+        let burndown = Burndown::new(&tasks, "tau", 3, now);
+        assert_eq!(
+            burndown.samples,
+            vec![(7 * DAY, 8.0), (8 * DAY, 10.0), (9 * DAY, 5.0), (10 * DAY, 10.0)]
+        );
+        assert_eq!(burndown.unestimated, vec!["4"]);
+
+        let chart = burndown_chart(&burndown);
+        assert_eq!(chart.len(), CHART_HEIGHT + 2);
+        assert_eq!(chart[0], "10.0 | # #");
+        assert_eq!(chart[2], "     |## #");
+        assert_eq!(chart[5], "     |####");
+        assert_eq!(chart[10], " 0.0 +----");
+        assert_eq!(chart[11], "      08 Jan 11 Jan");
+
+        let burndown = Burndown::new(&tasks, "raft", 3, now);
+        assert_eq!(burndown_chart(&burndown), vec!["No estimated effort in this period."]);
+        assert_eq!(burndown.unestimated, vec!["6"]);
+    }
+}
@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use darkfi::{
+    util::{cli::Config, path::expand_path},
+    Error, Result,
+};
+
+use crate::{
+    oplog::{append_operation, OperationEntry, DEFAULT_OPERATIONS_LOG_MAX_SIZE},
+    primitives::{has_field, BaseTask},
+    util::adjust_due,
+};
+
+pub const CONFIG_FILE: &str = "tau_config.toml";
+
+pub const DEFAULT_ENDPOINT: &str = "tcp://127.0.0.1:11055";
+
+/// Per-user defaults, read from `tau_config.toml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TauConfig {
+    /// taud JSON-RPC endpoint used when `--endpoint` isn't given
+    pub endpoint: Option<Url>,
+    /// Projects for new tasks added without `project:`
+    pub project: Vec<String>,
+    /// Assignees for new tasks added without `assign:`
+    pub assign: Vec<String>,
+    /// Time of day tasks are due, as "HH:MM" (noon when unset)
+    pub due_time: Option<String>,
+    /// Roll due dates falling on a weekend over to the next Monday
+    pub due_business_days: bool,
+    /// Filters of `tau` and `tau list` when none are given (ex: ["open"])
+    pub default_filter: Vec<String>,
+    /// File each command run is appended to, as a line of JSON (unset: none)
+    pub operations_log: Option<String>,
+    /// Size in bytes the operations log is rotated at (1 MiB when unset)
+    pub operations_log_max_size: Option<u64>,
+    /// Lowest rank, lower ranks given to tasks are raised to it (unset: none)
+    pub rank_min: Option<f32>,
+    /// Highest rank, higher ranks given to tasks are lowered to it (unset: none)
+    pub rank_max: Option<f32>,
+    /// Show descriptions and comments as written, like `--no-markdown`
+    pub no_markdown: bool,
+}
+
+impl TauConfig {
+    /// Load the config file. A missing file means no defaults.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        let config = Config::<Self>::load(path)?;
+        config.due_time()?;
+        config.check_rank_range()?;
+        Ok(config)
+    }
+
+    fn check_rank_range(&self) -> Result<()> {
+        if self.rank_min.is_some_and(|min| !min.is_finite()) ||
+            self.rank_max.is_some_and(|max| !max.is_finite())
+        {
+            return Err(Error::ParseFailed("rank_min and rank_max must be finite numbers"))
+        }
+        if let (Some(min), Some(max)) = (self.rank_min, self.rank_max) {
+            if min > max {
+                return Err(Error::ParseFailed("rank_min must not be greater than rank_max"))
+            }
+        }
+        Ok(())
+    }
+
+    /// Bring the rank of a task within the configured range.
+    pub fn apply_rank(&self, task: &mut BaseTask) {
+        let min = self.rank_min.unwrap_or(f32::MIN);
+        let max = self.rank_max.unwrap_or(f32::MAX);
+        task.rank = task.rank.map(|rank| rank.max(min).min(max));
+    }
+
+    /// Time of day tasks are due.
+    fn due_time(&self) -> Result<NaiveTime> {
+        match &self.due_time {
+            Some(time) => NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| Error::ParseFailed("due_time must be given as HH:MM")),
+            None => Ok(NaiveTime::from_hms(12, 0, 0)),
+        }
+    }
+
+    /// Move the due date of a task to the configured time of day, and to a
+    /// business day if asked to.
+    pub fn apply_due(&self, task: &mut BaseTask) {
+        let time = self.due_time().unwrap_or_else(|_| NaiveTime::from_hms(12, 0, 0));
+        task.due = task.due.map(|due| adjust_due(due, time, self.due_business_days));
+    }
+
+    /// The filters given on the command line, or the default ones if none
+    /// were. `all` overrides the default without filtering anything.
+    pub fn filters(&self, filters: Vec<String>) -> Vec<String> {
+        if filters.is_empty() {
+            return self.default_filter.clone()
+        }
+        filters
+    }
+
+    /// Append a command and its result to the operations log, if one is
+    /// configured.
+    pub fn log_operation(&self, command: &str, args: &[String], result: &Result<()>) -> Result<()> {
+        let path = match &self.operations_log {
+            Some(path) => expand_path(path)?,
+            None => return Ok(()),
+        };
+        let max_size = self.operations_log_max_size.unwrap_or(DEFAULT_OPERATIONS_LOG_MAX_SIZE);
+        append_operation(&path, max_size, &OperationEntry::new(command, args, result))
+    }
+
+    /// Prefill the fields of a new task that were omitted on the command line.
+    /// An explicit empty value (e.g. `project:`) keeps the field empty.
+    pub fn apply_defaults(&self, task: &mut BaseTask, values: &[String]) {
+        if !has_field(values, "project") {
+            task.project = self.project.clone();
+        }
+        if !has_field(values, "assign") {
+            task.assign = self.assign.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::task_from_cli;
+
+    #[test]
+    fn test_apply_defaults() -> Result<()> {
+        let config = TauConfig {
+            project: vec!["tau".into()],
+            assign: vec!["dark".into()],
+            ..TauConfig::default()
+        };
+
+        let values: Vec<String> = vec!["title".into()];
+        let mut task = task_from_cli(values.clone())?;
+        config.apply_defaults(&mut task, &values);
+        assert_eq!(task.project, vec!["tau".to_string()]);
+        assert_eq!(task.assign, vec!["dark".to_string()]);
+
+        let values: Vec<String> = vec!["title".into(), "project:net".into(), "assign:".into()];
+        let mut task = task_from_cli(values.clone())?;
+        config.apply_defaults(&mut task, &values);
+        assert_eq!(task.project, vec!["net".to_string()]);
+        assert!(task.assign.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filters() {
+        let config = TauConfig::default();
+        assert!(config.filters(vec![]).is_empty());
+
+        let config = TauConfig { default_filter: vec!["open".into()], ..TauConfig::default() };
+        assert_eq!(config.filters(vec![]), vec!["open".to_string()]);
+        assert_eq!(config.filters(vec!["all".into()]), vec!["all".to_string()]);
+        assert_eq!(config.filters(vec!["pause".into()]), vec!["pause".to_string()]);
+    }
+
+    #[test]
+    fn test_apply_due() -> Result<()> {
+        let values: Vec<String> = vec!["title".into(), "due:1503".into()];
+        let mut task = task_from_cli(values.clone())?;
+        let due = task.due;
+        TauConfig::default().apply_due(&mut task);
+        assert_eq!(task.due, due);
+
+        let config = TauConfig { due_time: Some("17:00".into()), ..TauConfig::default() };
+        config.apply_due(&mut task);
+        assert_eq!(task.due, due.map(|due| due + 5 * 3600));
+
+        let config = TauConfig { due_time: Some("5pm".into()), ..TauConfig::default() };
+        assert!(config.due_time().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_rank() -> Result<()> {
+        let mut task = task_from_cli(vec!["title".into(), "rank:12.5".into()])?;
+        TauConfig::default().apply_rank(&mut task);
+        assert_eq!(task.rank, Some(12.5));
+
+        let config =
+            TauConfig { rank_min: Some(0.0), rank_max: Some(10.0), ..TauConfig::default() };
+        config.check_rank_range()?;
+        config.apply_rank(&mut task);
+        assert_eq!(task.rank, Some(10.0));
+
+        let mut task = task_from_cli(vec!["title".into(), "rank:-3".into()])?;
+        config.apply_rank(&mut task);
+        assert_eq!(task.rank, Some(0.0));
+
+        assert!(task_from_cli(vec!["title".into(), "rank:NaN".into()]).is_err());
+        assert!(task_from_cli(vec!["title".into(), "rank:inf".into()]).is_err());
+
+        let config = TauConfig { rank_min: Some(5.0), rank_max: Some(1.0), ..TauConfig::default() };
+        assert!(config.check_rank_range().is_err());
+        let config = TauConfig { rank_max: Some(f32::NAN), ..TauConfig::default() };
+        assert!(config.check_rank_range().is_err());
+        Ok(())
+    }
+}
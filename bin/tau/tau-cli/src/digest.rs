@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use darkfi::util::time::{timestamp_to_date, DateFormat};
+
+use crate::primitives::TaskInfo;
+
+/// Assignee name gathering the tasks that have none.
+const UNASSIGNED: &str = "(unassigned)";
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DigestTask {
+    /// Project ID if the task has one, ID otherwise
+    pub id: String,
+    pub title: String,
+}
+
+impl From<&TaskInfo> for DigestTask {
+    fn from(task: &TaskInfo) -> Self {
+        Self { id: task.display_id(), title: task.title.clone() }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct AssigneeDigest {
+    /// Tasks created since the start of the digest
+    pub created: Vec<DigestTask>,
+    /// Tasks stopped since the start of the digest
+    pub completed: Vec<DigestTask>,
+    /// Tasks currently open
+    pub in_progress: Vec<DigestTask>,
+}
+
+impl AssigneeDigest {
+    fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.completed.is_empty() && self.in_progress.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Digest {
+    /// Timestamp the digest starts from
+    pub since: i64,
+    /// What happened to the tasks of each assignee. A task with several
+    /// assignees is listed for each of them.
+    pub by_assignee: BTreeMap<String, AssigneeDigest>,
+}
+
+impl Digest {
+    /// Digest of the `active` tasks, as listed by `get_tasks`, and of the
+    /// `stopped` ones since the `since` timestamp.
+    pub fn new(active: &[TaskInfo], stopped: &[TaskInfo], since: i64) -> Self {
+        let mut by_assignee: BTreeMap<String, AssigneeDigest> = BTreeMap::new();
+
+        for task in active.iter().chain(stopped.iter()) {
+            let created = task.created_at >= since;
+            let completed = task.stopped_at().is_some_and(|stopped_at| stopped_at >= since);
+            let in_progress = task.state() == "open";
+            if !created && !completed && !in_progress {
+                continue
+            }
+
+            let mut assignees = task.assign.clone();
+            if assignees.is_empty() {
+                assignees.push(UNASSIGNED.into());
+            }
+
+            for assignee in assignees {
+                let digest = by_assignee.entry(assignee).or_default();
+                if created {
+                    digest.created.push(task.into());
+                }
+                if completed {
+                    digest.completed.push(task.into());
+                }
+                if in_progress {
+                    digest.in_progress.push(task.into());
+                }
+            }
+        }
+
+        Self { since, by_assignee }
+    }
+}
+
+/// Write the digest as plain text, ready to paste in a chat.
+pub fn digest_as_string(digest: &Digest) -> String {
+    let since = timestamp_to_date(digest.since, DateFormat::Date);
+    let assignees: Vec<_> = digest.by_assignee.iter().filter(|(_, d)| !d.is_empty()).collect();
+    if assignees.is_empty() {
+        return format!("Nothing to report since {}\n", since)
+    }
+
+    let mut text = format!("Digest since {}\n", since);
+    for (assignee, digest) in assignees {
+        text.push_str(&format!("\n{}\n", assignee));
+        for (title, tasks) in [
+            ("Created", &digest.created),
+            ("Completed", &digest.completed),
+            ("In progress", &digest.in_progress),
+        ] {
+            if tasks.is_empty() {
+                continue
+            }
+            text.push_str(&format!("  {}:\n", title));
+            for task in tasks {
+                text.push_str(&format!("    {} {}\n", task.id, task.title));
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 3600;
+
+    fn task(id: u32, assign: &[&str], created_at: i64, events: &[(&str, i64)]) -> TaskInfo {
+        TaskInfo {
+            title: format!("task {}", id),
+            assign: assign.iter().map(|a| a.to_string()).collect(),
+            created_at,
+            ..TaskInfo::with_events(id, events)
+        }
+    }
+
+    #[test]
+    fn test_digest() {
+        let since = 100 * DAY;
+        let active = vec![
+            task(1, &["dark"], since + DAY, &[]),
+            task(2, &["dark", "fi"], 0, &[("pause", since + DAY)]),
+            task(3, &[], 0, &[]),
+        ];
+        let stopped = vec![
+            task(4, &["fi"], 0, &[("stop", since + DAY)]),
+            task(5, &["fi"], 0, &[("stop", since - DAY)]),
+        ];
+
+        let digest = Digest::new(&active, &stopped, since);
+        let ids = |tasks: &[DigestTask]| tasks.iter().map(|t| t.id.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            digest.by_assignee.keys().collect::<Vec<_>>(),
+            vec!["(unassigned)", "dark", "fi"]
+        );
+
+        let dark = &digest.by_assignee["dark"];
+        assert_eq!(ids(&dark.created), vec!["1"]);
+        assert!(dark.completed.is_empty());
+        assert_eq!(ids(&dark.in_progress), vec!["1"]);
+
+        // Paused tasks and tasks stopped before the digest are left out
+        let fi = &digest.by_assignee["fi"];
+        assert!(fi.created.is_empty());
+        assert_eq!(ids(&fi.completed), vec!["4"]);
+        assert!(fi.in_progress.is_empty());
+
+        assert_eq!(ids(&digest.by_assignee["(unassigned)"].in_progress), vec!["3"]);
+
+        let text = digest_as_string(&digest);
+        assert!(text.contains("\ndark\n  Created:\n    1 task 1\n  In progress:\n    1 task 1\n"));
+        assert!(text.contains("\nfi\n  Completed:\n    4 task 4\n"));
+
+        let empty = Digest::new(&[], &stopped, since + 2 * DAY);
+        assert!(digest_as_string(&empty).starts_with("Nothing to report since"));
+    }
+}
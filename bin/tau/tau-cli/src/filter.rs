@@ -1,18 +1,59 @@
-use chrono::{Datelike, NaiveDateTime, Utc};
+use std::cmp::Ordering;
+
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use log::error;
 use serde_json::Value;
 
-use crate::{primitives::TaskInfo, TaskEvent};
+use crate::primitives::{TaskInfo, BLOCKED};
 
 /// Helper function to check task's state
 fn check_task_state(task: &TaskInfo, state: &str) -> bool {
-    let last_state = task.events.last().unwrap_or(&TaskEvent::default()).action.clone();
-    state == last_state
+    state == task.state()
+}
+
+/// Resolve "DDMM" to the latest such date up to `today`, as tasks can
+/// only have been created in the past.
+pub fn ddmm_to_past_date(ddmm: &str, today: NaiveDate) -> Option<NaiveDate> {
+    if ddmm.len() != 4 || ddmm.parse::<u32>().is_err() {
+        return None
+    }
+    let (day, month) = (ddmm[..2].parse::<u32>().unwrap(), ddmm[2..].parse::<u32>().unwrap());
+
+    let mut year = today.year();
+    if month > today.month() || (month == today.month() && day > today.day()) {
+        year -= 1;
+    }
+
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// Parse a `created` filter (`created>DDMM`, `created<DDMM`, `created:DDMM`
+/// or `created:today`) into how the creation date of the matching tasks
+/// compares to the given date.
+fn parse_created_filter(filter: &str, today: NaiveDate) -> Option<(Ordering, NaiveDate)> {
+    let filter = filter.strip_prefix("created")?;
+    let ordering = match filter.chars().next()? {
+        ':' => Ordering::Equal,
+        '>' => Ordering::Greater,
+        '<' => Ordering::Less,
+        _ => return None,
+    };
+
+    let date = match &filter[1..] {
+        "today" => today,
+        ddmm => ddmm_to_past_date(ddmm, today)?,
+    };
+
+    Some((ordering, date))
 }
 
 pub fn apply_filter(tasks: &mut Vec<TaskInfo>, filter: &str) {
     match filter {
+        // Overrides the default filter of the config file
+        "all" => {}
         "open" => tasks.retain(|task| check_task_state(task, "open")),
         "pause" => tasks.retain(|task| check_task_state(task, "pause")),
+        BLOCKED => tasks.retain(|task| check_task_state(task, BLOCKED)),
 
         _ if filter.len() == 4 && filter.parse::<u32>().is_ok() => {
             let (month, year) =
@@ -44,6 +85,26 @@ pub fn apply_filter(tasks: &mut Vec<TaskInfo>, filter: &str) {
             }
         }
 
+        _ if filter.starts_with("tag:") => {
+            // Tags are stored lowercased
+            let tag = filter["tag:".len()..].trim().to_lowercase();
+            tasks.retain(|task| task.tags.contains(&tag))
+        }
+
+        _ if filter.starts_with("created") => {
+            match parse_created_filter(filter, Local::today().naive_local()) {
+                Some((ordering, date)) => tasks.retain(|task| {
+                    let created = Local.timestamp(task.created_at, 0).date().naive_local();
+                    created.cmp(&date) == ordering
+                }),
+                None => error!(
+                    "Invalid filter \"{}\", must be created>DDMM, created<DDMM, \
+                     created:DDMM or created:today",
+                    filter
+                ),
+            }
+        }
+
         _ if filter.contains("rank:") => {
             let kv: Vec<&str> = filter.split(':').collect();
             if kv.len() == 3 {
@@ -63,3 +124,32 @@ pub fn apply_filter(tasks: &mut Vec<TaskInfo>, filter: &str) {
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_created_filter() {
+        // Wednesday 15 June 2022
+        let today = NaiveDate::from_ymd(2022, 6, 15);
+        assert_eq!(parse_created_filter("created:today", today), Some((Ordering::Equal, today)));
+        assert_eq!(
+            parse_created_filter("created>1006", today),
+            Some((Ordering::Greater, NaiveDate::from_ymd(2022, 6, 10)))
+        );
+        assert_eq!(
+            parse_created_filter("created<2006", today),
+            Some((Ordering::Less, NaiveDate::from_ymd(2021, 6, 20)))
+        );
+        assert_eq!(
+            parse_created_filter("created:0101", today),
+            Some((Ordering::Equal, NaiveDate::from_ymd(2022, 1, 1)))
+        );
+
+        assert_eq!(parse_created_filter("created:3102", today), None);
+        assert_eq!(parse_created_filter("created=1006", today), None);
+        assert_eq!(parse_created_filter("created>", today), None);
+        assert_eq!(parse_created_filter("created", today), None);
+    }
+}
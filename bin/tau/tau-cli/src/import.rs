@@ -0,0 +1,261 @@
+use std::path::Path;
+
+use crate::primitives::{parse_rank, BaseTask};
+
+/// A line of an import file that couldn't be turned into a task.
+#[derive(Debug, PartialEq)]
+pub struct ImportError {
+    /// Line number, starting from 1
+    pub line: usize,
+    pub reason: String,
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+/// Parse the tasks of an import file, as CSV if its extension is `.csv`
+/// and as a plain or markdown list otherwise. Malformed lines are
+/// returned as errors along with the tasks parsed from the other lines.
+pub fn parse_import(path: &Path, content: &str) -> (Vec<(usize, BaseTask)>, Vec<ImportError>) {
+    let is_csv = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        parse_csv(content)
+    } else {
+        parse_list(content)
+    }
+}
+
+/// One task per line, or per markdown list item. Headings, blank lines
+/// and the markers of list items and checkboxes are skipped. Words
+/// starting with `@`, `#` and `!` set the assignees, the projects and
+/// the rank, the remaining words make the title.
+fn parse_list(content: &str) -> (Vec<(usize, BaseTask)>, Vec<ImportError>) {
+    let mut tasks = vec![];
+    let mut errors = vec![];
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("# ") || line.starts_with("##") {
+            continue
+        }
+
+        let line = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|marker| line.strip_prefix(marker))
+            .unwrap_or(line)
+            .trim_start();
+        let line = ["[ ] ", "[x] ", "[X] "]
+            .iter()
+            .find_map(|checkbox| line.strip_prefix(checkbox))
+            .unwrap_or(line);
+
+        match task_from_line(line) {
+            Ok(task) => tasks.push((i + 1, task)),
+            Err(reason) => errors.push(ImportError { line: i + 1, reason }),
+        }
+    }
+
+    (tasks, errors)
+}
+
+fn task_from_line(line: &str) -> std::result::Result<BaseTask, String> {
+    let mut task = new_task();
+    let mut title = vec![];
+
+    for word in line.split_whitespace() {
+        if let Some(assign) = word.strip_prefix('@').filter(|s| !s.is_empty()) {
+            task.assign.push(assign.into());
+        } else if let Some(project) = word.strip_prefix('#').filter(|s| !s.is_empty()) {
+            task.project.push(project.into());
+        } else if let Some(rank) = word.strip_prefix('!').filter(|s| !s.is_empty()) {
+            task.rank = Some(parse_rank(rank)?);
+        } else {
+            title.push(word);
+        }
+    }
+
+    task.title = title.join(" ");
+    if task.title.is_empty() {
+        return Err("missing title".into())
+    }
+
+    Ok(task)
+}
+
+/// CSV with a header row, as written by `tau list --format csv`. The
+/// `Title` column is required, `Project`, `Assigned` and `Rank` are
+/// optional and any other column is ignored. Projects and assignees are
+/// separated by commas within their field.
+fn parse_csv(content: &str) -> (Vec<(usize, BaseTask)>, Vec<ImportError>) {
+    let mut tasks = vec![];
+    let mut errors = vec![];
+
+    let mut lines = content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+
+    let header = match lines.next() {
+        Some((_, header)) => split_csv_line(header).unwrap_or_default(),
+        None => return (tasks, errors),
+    };
+    let column = |names: &[&str]| {
+        header.iter().position(|h| names.iter().any(|name| h.trim().eq_ignore_ascii_case(name)))
+    };
+
+    let title_col = match column(&["title"]) {
+        Some(col) => col,
+        None => {
+            errors.push(ImportError { line: 1, reason: "missing Title column".into() });
+            return (tasks, errors)
+        }
+    };
+    let project_col = column(&["project"]);
+    let assign_col = column(&["assigned", "assign"]);
+    let rank_col = column(&["rank"]);
+
+    for (i, line) in lines {
+        let line_no = i + 1;
+        let fields = match split_csv_line(line) {
+            Ok(fields) => fields,
+            Err(reason) => {
+                errors.push(ImportError { line: line_no, reason });
+                continue
+            }
+        };
+        let field = |col: Option<usize>| col.and_then(|c| fields.get(c)).map(|f| f.trim());
+
+        let mut task = new_task();
+        task.title = field(Some(title_col)).unwrap_or_default().to_string();
+        if task.title.is_empty() {
+            errors.push(ImportError { line: line_no, reason: "missing title".into() });
+            continue
+        }
+
+        task.project = split_list(field(project_col));
+        task.assign = split_list(field(assign_col));
+
+        match field(rank_col).filter(|r| !r.is_empty()).map(parse_rank).transpose() {
+            Ok(rank) => task.rank = rank,
+            Err(reason) => {
+                errors.push(ImportError { line: line_no, reason });
+                continue
+            }
+        }
+
+        tasks.push((line_no, task));
+    }
+
+    (tasks, errors)
+}
+
+/// Split a CSV line on commas, unquoting the fields quoted with double
+/// quotes. Quoted fields can't span several lines.
+fn split_csv_line(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err("unterminated quoted field".into())
+    }
+
+    fields.push(field);
+    Ok(fields)
+}
+
+fn split_list(field: Option<&str>) -> Vec<String> {
+    field
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.into())
+        .collect()
+}
+
+/// Imported tasks have an empty description rather than opening an editor.
+fn new_task() -> BaseTask {
+    BaseTask { desc: Some(String::new()), ..BaseTask::default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        let content = "# Backlog\n\n\
+                       - [ ] write docs @dark #tau !2.5\n\
+                       * fix sync #raft #tau\n\
+                       plain line task\n\
+                       - @dark #tau\n\
+                       - broken rank !high\n";
+        let (tasks, errors) = parse_import(Path::new("tasks.md"), content);
+
+        assert_eq!(tasks.len(), 3);
+        let (line, task) = &tasks[0];
+        assert_eq!(*line, 3);
+        assert_eq!(task.title, "write docs");
+        assert_eq!(task.assign, vec!["dark".to_string()]);
+        assert_eq!(task.project, vec!["tau".to_string()]);
+        assert_eq!(task.rank, Some(2.5));
+        assert_eq!(task.desc.as_deref(), Some(""));
+
+        assert_eq!(tasks[1].1.project, vec!["raft".to_string(), "tau".to_string()]);
+        assert_eq!(tasks[2].1.title, "plain line task");
+
+        assert_eq!(
+            errors,
+            vec![
+                ImportError { line: 6, reason: "missing title".into() },
+                ImportError { line: 7, reason: "invalid rank \"high\"".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let content = "ID,Title,Project,Assigned,Due,Rank\n\
+                       1,\"write docs, then review\",\"tau,raft\",dark,,2\n\
+                       2,,tau,,,\n\
+                       3,fix sync,,,,abc\n\
+                       4,\"unterminated,,,,\n\
+                       5,plain,,,,\n";
+        let (tasks, errors) = parse_import(Path::new("tasks.CSV"), content);
+
+        assert_eq!(tasks.len(), 2);
+        let (line, task) = &tasks[0];
+        assert_eq!(*line, 2);
+        assert_eq!(task.title, "write docs, then review");
+        assert_eq!(task.project, vec!["tau".to_string(), "raft".to_string()]);
+        assert_eq!(task.assign, vec!["dark".to_string()]);
+        assert_eq!(task.rank, Some(2.0));
+        assert_eq!(tasks[1].1.title, "plain");
+        assert_eq!(tasks[1].1.rank, None);
+
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 4, 5]);
+
+        let (tasks, errors) = parse_import(Path::new("tasks.csv"), "Name\nfoo\n");
+        assert!(tasks.is_empty());
+        assert_eq!(errors, vec![ImportError { line: 1, reason: "missing Title column".into() }]);
+    }
+}
@@ -1,36 +1,87 @@
-use std::process::exit;
+use std::{
+    env, fs,
+    io::{stdin, stdout},
+    path::PathBuf,
+    process::exit,
+};
 
-use clap::{Parser, Subcommand};
+use clap::{ArgEnum, CommandFactory, FromArgMatches, Parser, Subcommand};
 use log::error;
-use simplelog::{ColorChoice, TermLogger, TerminalMode};
 use url::Url;
 
 use darkfi::{
     rpc::client::RpcClient,
-    util::cli::{get_log_config, get_log_level},
+    util::{
+        cli::{get_log_config, get_log_level, init_logger},
+        path::get_config_path,
+        Timestamp,
+    },
     Result,
 };
 
+mod burndown;
+mod config;
+mod digest;
 mod filter;
+mod import;
+mod markdown;
+mod oplog;
 mod primitives;
+mod prompt;
 mod rpc;
+mod stats;
+mod undo;
 mod util;
 mod view;
 
-use primitives::{task_from_cli, TaskEvent};
-use util::{desc_in_editor, due_as_timestamp};
-use view::{comments_as_string, print_task_info, print_task_list};
+use burndown::{print_burndown, Burndown};
+use config::{TauConfig, CONFIG_FILE, DEFAULT_ENDPOINT};
+use digest::{digest_as_string, Digest};
+use filter::apply_filter;
+use import::parse_import;
+use primitives::{
+    duplicate_task, move_project, normalize_tags, rerank, task_from_cli, BaseTask, TaskEvent,
+    TaskRef,
+};
+use prompt::prompt_task;
+use stats::{print_stats, TaskStats};
+use undo::{save_operation, undo, Operation};
+use util::{
+    confirm, desc_in_editor, due_as_timestamp, git_head, since_as_timestamp, SINCE_FORMATS,
+};
+use view::{
+    comments_as_string, duration_as_string, print_task_board, print_task_info, print_task_list,
+    print_task_list_delimited, Page,
+};
 
 #[derive(Parser)]
 #[clap(name = "tau", version)]
 struct Args {
     #[clap(short, parse(from_occurrences))]
-    /// Increase verbosity (-vvv supported)
+    /// Increase verbosity (-vvv supported, -vv echoes added and updated tasks)
     verbose: u8,
 
-    #[clap(short, long, default_value = "tcp://127.0.0.1:11055")]
-    /// taud JSON-RPC endpoint
-    endpoint: Url,
+    #[clap(short, long, conflicts_with = "verbose")]
+    /// Only print the task ID after add and update
+    quiet: bool,
+
+    #[clap(long)]
+    /// Log to the given file as well, rotated by size
+    log_file: Option<PathBuf>,
+
+    #[clap(short, long)]
+    /// taud JSON-RPC endpoint (defaults to the config file value, then to
+    /// tcp://127.0.0.1:11055)
+    endpoint: Option<Url>,
+
+    #[clap(short, long)]
+    /// Config file with the endpoint and defaults for new tasks
+    config: Option<String>,
+
+    #[clap(long)]
+    /// Show descriptions and comments as written instead of rendering
+    /// their markdown
+    no_markdown: bool,
 
     /// Search filters (zero or more)
     filters: Vec<String>,
@@ -41,60 +92,366 @@ struct Args {
 
 #[derive(Subcommand)]
 enum TauSubcommand {
-    /// Add a new task
-    Add { values: Vec<String> },
+    /// Add a new task. Project and assignees default to the config file
+    /// values unless given, e.g. `project:` adds a task with no project.
+    Add {
+        #[clap(short, long)]
+        /// Ask for each field in turn, starting from the given values
+        interactive: bool,
+        #[clap(short, long = "tag", multiple_occurrences(true))]
+        /// Tag the task, lowercased (repeatable)
+        tags: Vec<String>,
+        #[clap(long, parse(try_from_str = parse_estimate))]
+        /// Effort the task takes, in hours or points
+        estimate: Option<f32>,
+        values: Vec<String>,
+    },
+
+    /// Add a new task copying an existing one, except its history
+    Dup {
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
+        /// Values overriding the copied ones (ex: "new title" project:blockchain)
+        values: Vec<String>,
+    },
+
+    /// Add the tasks listed in a file, one per line or markdown list item,
+    /// with optional @assignee, #project and !rank words. Files ending in
+    /// .csv are read as CSV like the output of `tau list --format csv`.
+    Import {
+        #[clap(short, long)]
+        /// File to import
+        file: PathBuf,
+        #[clap(long)]
+        /// Add nothing if any line is malformed
+        strict: bool,
+    },
 
     /// Update/Edit an existing task by ID
     Update {
-        /// Task ID
-        task_id: u64,
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
+        #[clap(short, long = "tag", multiple_occurrences(true))]
+        /// Replace the tags of the task, lowercased (repeatable)
+        tags: Vec<String>,
+        #[clap(long, parse(try_from_str = parse_estimate))]
+        /// Effort the task takes, in hours or points
+        estimate: Option<f32>,
         /// Values (ex: project:blockchain)
         values: Vec<String>,
     },
 
     /// Set or Get task state
     State {
-        /// Task ID
-        task_id: u64,
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
         /// Set task state
         state: Option<String>,
     },
 
     /// Set or Get comment for a task
     Comment {
-        /// Task ID
-        task_id: u64,
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
         /// Comment content
         content: Option<String>,
     },
 
     /// Get task info by ID
-    Info { task_id: u64 },
+    Info { task_id: TaskRef },
+
+    /// Move a task to the trash, hiding it from lists and filters
+    Delete { task_id: TaskRef },
+
+    /// List the tasks in the trash
+    Trash {
+        /// Search filters (zero or more)
+        filters: Vec<String>,
+    },
+
+    /// Bring a task back from the trash, in the state it had before.
+    /// It gets a new ID if another task took it in the meantime.
+    Restore { task_id: TaskRef },
+
+    /// Set a task as blocked
+    Block {
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
+        #[clap(short, long)]
+        /// Why the task can't progress
+        reason: String,
+    },
+
+    /// Set a blocked task back to open
+    Unblock { task_id: TaskRef },
+
+    /// Attach a URL or a file path to a task
+    Attach {
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
+        /// URL or file path
+        link: String,
+    },
+
+    /// Link a task to the branch and the commit checked out in the current
+    /// directory
+    GitLink {
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
+    },
+
+    /// Move the tasks of a project and its subprojects to another project,
+    /// e.g. `--from project:crypto.zk --to zk` turns `crypto.zk.halo2`
+    /// into `zk.halo2`
+    Move {
+        #[clap(long)]
+        /// Project to move (ex: project:old)
+        from: String,
+        #[clap(long)]
+        /// New project name
+        to: String,
+        #[clap(short, long)]
+        /// Don't ask for confirmation when moving many tasks
+        yes: bool,
+        /// Search filters narrowing the tasks to move (zero or more)
+        filters: Vec<String>,
+    },
+
+    /// Add items to the checklist of a task, or tick them off
+    Check {
+        #[clap(subcommand)]
+        command: CheckSubcommand,
+    },
+
+    /// Spread the ranks of open tasks evenly, removing ties
+    Rerank,
+
+    /// Undo the last add, update, state or comment operation
+    Undo,
+
+    /// Start tracking time spent on a task
+    Start { task_id: TaskRef },
+
+    /// Stop tracking time spent on a task
+    Stop { task_id: TaskRef },
+
+    /// Get total time tracked on a task
+    Time { task_id: TaskRef },
+
+    /// Show tasks on a board, grouped by state
+    Board {
+        /// Search filters (zero or more)
+        filters: Vec<String>,
+    },
+
+    /// List tasks
+    List {
+        #[clap(short, long, arg_enum, default_value = "table")]
+        /// Output format
+        format: ListFormat,
+
+        #[clap(long)]
+        /// Show the tags of the tasks in a column
+        tags: bool,
+
+        #[clap(long)]
+        /// Show at most this many tasks
+        limit: Option<usize>,
+
+        #[clap(long, default_value = "0", conflicts_with = "page")]
+        /// Skip this many of the highest ranked tasks
+        offset: usize,
+
+        #[clap(long, requires = "limit", parse(try_from_str = parse_page))]
+        /// Show the given page of `--limit` tasks, starting from 1
+        page: Option<usize>,
+
+        /// Search filters (zero or more)
+        filters: Vec<String>,
+    },
+
+    /// Show counts of tasks by state and project, the average age of
+    /// open tasks and how many were completed lately
+    Stats {
+        #[clap(short, long, arg_enum, default_value = "table")]
+        /// Output format
+        format: StatsFormat,
+    },
+
+    /// Show the tasks created, completed and in progress since a given
+    /// day, grouped by assignee, as text ready to paste in a chat
+    Digest {
+        #[clap(long, default_value = "yesterday", parse(try_from_str = parse_since))]
+        /// Start of the digest (ex: yesterday, -3d, monday, 1503)
+        since: i64,
+
+        #[clap(long)]
+        /// Print as JSON
+        json: bool,
+    },
+
+    /// Plot the estimated effort left in a project day by day
+    Burndown {
+        #[clap(long)]
+        /// Project, subprojects included
+        project: String,
+
+        #[clap(long, default_value = "30")]
+        /// Number of days to plot
+        days: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum CheckSubcommand {
+    /// Add an item to the end of the checklist
+    Add {
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
+        /// Checklist item
+        item: String,
+    },
+
+    /// Mark an item as done, or as not done if it was
+    Toggle {
+        /// Task ID or project ID (ex: zk-12)
+        task_id: TaskRef,
+        /// Number of the item, starting from 1 as shown by `tau info`
+        index: usize,
+    },
+}
+
+fn parse_since(since: &str) -> std::result::Result<i64, String> {
+    since_as_timestamp(since)
+        .ok_or_else(|| format!("invalid date \"{}\", must be one of: {}", since, SINCE_FORMATS))
+}
+
+fn parse_estimate(estimate: &str) -> std::result::Result<f32, String> {
+    match estimate.parse::<f32>() {
+        Ok(estimate) if estimate.is_finite() && estimate >= 0.0 => Ok(estimate),
+        _ => Err(format!("invalid estimate \"{}\", must be a positive number", estimate)),
+    }
+}
+
+fn parse_page(page: &str) -> std::result::Result<usize, String> {
+    match page.parse() {
+        Ok(page) if page > 0 => Ok(page),
+        _ => Err(format!("invalid page \"{}\", pages start from 1", page)),
+    }
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum ListFormat {
+    Table,
+    Csv,
+    Tsv,
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum StatsFormat {
+    Table,
+    Json,
 }
 
 pub struct Tau {
     pub rpc_client: RpcClient,
 }
 
+/// Number of tasks above which `tau move` asks for confirmation.
+const MOVE_CONFIRM_THRESHOLD: usize = 10;
+
+/// Verbosity at which added and updated tasks are echoed as JSON.
+const ECHO_TASK_VERBOSITY: u8 = 2;
+
+/// Report an added or updated task: only its ID when quiet, the stored
+/// task as JSON at high verbosity, and a short summary otherwise.
+async fn print_result(
+    tau: &Tau,
+    task_id: u64,
+    action: &str,
+    quiet: bool,
+    verbose: u8,
+) -> Result<()> {
+    if quiet {
+        println!("{}", task_id);
+    } else if verbose >= ECHO_TASK_VERBOSITY {
+        let task = tau.get_task_by_id(task_id).await?;
+        println!("{}", serde_json::to_string_pretty(&task)?);
+    } else {
+        println!("{} task {}", action, task_id);
+    }
+    Ok(())
+}
+
 #[async_std::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // Without a subcommand, tau lists the tasks
+    let command = matches.subcommand_name().unwrap_or("list").to_string();
+    let argv: Vec<String> = env::args().skip(1).collect();
 
     let log_level = get_log_level(args.verbose.into());
     let log_config = get_log_config();
-    TermLogger::init(log_level, log_config, TerminalMode::Mixed, ColorChoice::Auto)?;
+    init_logger(log_level, log_config, args.log_file.as_deref())?;
+
+    let config = TauConfig::load(get_config_path(args.config, CONFIG_FILE)?)?;
+    let endpoint = match args.endpoint.or_else(|| config.endpoint.clone()) {
+        Some(endpoint) => endpoint,
+        None => Url::parse(DEFAULT_ENDPOINT)?,
+    };
 
-    let rpc_client = RpcClient::new(args.endpoint).await?;
+    let rpc_client = RpcClient::new(endpoint).await?;
     let tau = Tau { rpc_client };
 
+    // Escape codes would end up in files and pipes
+    let markdown = !(args.no_markdown || config.no_markdown) && atty::is(atty::Stream::Stdout);
+
+    let result =
+        run_command(&tau, &config, args.command, args.filters, args.quiet, args.verbose, markdown)
+            .await;
+    if let Err(e) = config.log_operation(&command, &argv, &result) {
+        error!("Unable to write to the operations log: {}", e);
+    }
+    result?;
+
+    tau.close_connection().await
+}
+
+/// Run the given subcommand, or list the tasks matching the filters
+/// without one.
+async fn run_command(
+    tau: &Tau,
+    config: &TauConfig,
+    command: Option<TauSubcommand>,
+    filters: Vec<String>,
+    quiet: bool,
+    verbose: u8,
+    markdown: bool,
+) -> Result<()> {
     // Allowed states for a task
     let states = ["stop", "open", "pause"];
 
     // Parse subcommands
-    match args.command {
+    match command {
         Some(sc) => match sc {
-            TauSubcommand::Add { values } => {
-                let mut task = task_from_cli(values)?;
+            TauSubcommand::Add { interactive, tags, estimate, values } => {
+                let mut task = task_from_cli(values.clone())?;
+                task.tags = normalize_tags(&tags);
+                task.estimate = estimate;
+                config.apply_defaults(&mut task, &values);
+
+                if interactive {
+                    if !atty::is(atty::Stream::Stdin) {
+                        error!(
+                            "tau add --interactive needs a terminal, give the fields as \
+                             arguments instead (ex: tau add \"title\" project:tau rank:2)"
+                        );
+                        exit(1);
+                    }
+                    task = prompt_task(&mut stdin().lock(), &mut stdout(), task, desc_in_editor)?;
+                }
+
                 if task.title.is_empty() {
                     error!("Please provide a title for the task.");
                     exit(1);
@@ -103,62 +460,290 @@ async fn main() -> Result<()> {
                 if task.desc.is_none() {
                     task.desc = desc_in_editor()?;
                 };
+                config.apply_due(&mut task);
+                config.apply_rank(&mut task);
 
-                return tau.add(task).await
+                let title = task.title.clone();
+                let task_id = tau.add(task).await?;
+                save_operation(&Operation::Add { task_id, title })?;
+                print_result(tau, task_id, "Added", quiet, verbose).await
             }
 
-            TauSubcommand::Update { task_id, values } => {
-                let task = task_from_cli(values)?;
-                tau.update(task_id, task).await
+            TauSubcommand::Dup { task_id, values } => {
+                let task_id = tau.resolve(&task_id).await?;
+                let source = tau.get_task_by_id(task_id).await?;
+                let mut task = duplicate_task(&source, values)?;
+                config.apply_due(&mut task);
+                config.apply_rank(&mut task);
+
+                let title = task.title.clone();
+                let task_id = tau.add(task).await?;
+                save_operation(&Operation::Add { task_id, title })?;
+                print_result(tau, task_id, "Added", quiet, verbose).await
             }
 
-            TauSubcommand::State { task_id, state } => match state {
-                Some(state) => {
-                    let state = state.trim().to_lowercase();
-                    if states.contains(&state.as_str()) {
-                        tau.set_state(task_id, &state).await
-                    } else {
-                        error!(
-                            "Task state can only be one of the following {}: {:?}",
-                            states.len(),
-                            states
-                        );
+            TauSubcommand::Import { file, strict } => {
+                let content = fs::read_to_string(&file)?;
+                let (tasks, errors) = parse_import(&file, &content);
+
+                for e in errors.iter() {
+                    error!("{}: {}", file.display(), e);
+                }
+                if strict && !errors.is_empty() {
+                    error!("No tasks were added, fix the lines above or drop --strict.");
+                    exit(1);
+                }
+
+                for (_, mut task) in tasks {
+                    config.apply_rank(&mut task);
+                    let title = task.title.clone();
+                    let task_id = tau.add(task).await?;
+                    save_operation(&Operation::Add { task_id, title })?;
+                    print_result(tau, task_id, "Added", quiet, verbose).await?;
+                }
+                Ok(())
+            }
+
+            TauSubcommand::Update { task_id, tags, estimate, values } => {
+                let task_id = tau.resolve(&task_id).await?;
+                let mut task = task_from_cli(values)?;
+                task.tags = normalize_tags(&tags);
+                task.estimate = estimate;
+                config.apply_due(&mut task);
+                config.apply_rank(&mut task);
+                let before = tau.get_task_by_id(task_id).await?;
+                let op = Operation::update(task_id, &before, &task);
+                tau.update(task_id, task).await?;
+                save_operation(&op)?;
+                print_result(tau, task_id, "Updated", quiet, verbose).await
+            }
+
+            TauSubcommand::State { task_id, state } => {
+                let task_id = tau.resolve(&task_id).await?;
+                match state {
+                    Some(state) => {
+                        let state = state.trim().to_lowercase();
+                        if states.contains(&state.as_str()) {
+                            let before = tau.get_task_by_id(task_id).await?.state();
+                            tau.set_state(task_id, &state).await?;
+                            save_operation(&Operation::State { task_id, before, after: state })
+                        } else {
+                            error!(
+                                "Task state can only be one of the following {}: {:?}",
+                                states.len(),
+                                states
+                            );
+                            Ok(())
+                        }
+                    }
+                    None => {
+                        let task = tau.get_task_by_id(task_id).await?;
+                        println!("Task {}: {}", task_id, task.state());
                         Ok(())
                     }
                 }
-                None => {
-                    let task = tau.get_task_by_id(task_id).await?;
-                    let state = &task.events.last().unwrap_or(&TaskEvent::default()).action.clone();
-                    println!("Task {}: {}", task_id, state);
-                    Ok(())
-                }
-            },
+            }
 
-            TauSubcommand::Comment { task_id, content } => match content {
-                Some(content) => tau.set_comment(task_id, content.trim()).await,
-                None => {
-                    let task = tau.get_task_by_id(task_id).await?;
-                    let comments = comments_as_string(task.comments);
-                    println!("Comments {}:\n{}", task_id, comments);
-                    Ok(())
+            TauSubcommand::Comment { task_id, content } => {
+                let task_id = tau.resolve(&task_id).await?;
+                match content {
+                    Some(content) => {
+                        let content = content.trim().to_string();
+                        tau.set_comment(task_id, &content).await?;
+                        save_operation(&Operation::Comment { task_id, content })
+                    }
+                    None => {
+                        let task = tau.get_task_by_id(task_id).await?;
+                        let comments = comments_as_string(task.comments, markdown);
+                        println!("Comments {}:\n{}", task_id, comments);
+                        Ok(())
+                    }
                 }
-            },
+            }
 
             TauSubcommand::Info { task_id } => {
+                let task_id = tau.resolve(&task_id).await?;
+                let task = tau.get_task_by_id(task_id).await?;
+                print_task_info(task, markdown)
+            }
+
+            TauSubcommand::Delete { task_id } => {
+                let task_id = tau.resolve(&task_id).await?;
+                tau.delete(task_id).await?;
+                if quiet {
+                    println!("{}", task_id);
+                } else {
+                    println!("Moved task {} to the trash, see `tau trash`", task_id);
+                }
+                Ok(())
+            }
+
+            TauSubcommand::Trash { filters } => {
+                let tasks = tau.get_trash_tasks().await?;
+                print_task_list(tasks, filters, false, Page::default())
+            }
+
+            TauSubcommand::Restore { task_id } => {
+                let task_id = tau.resolve_trashed(&task_id).await?;
+                let task_id = tau.restore(task_id).await?;
+                print_result(tau, task_id, "Restored", quiet, verbose).await
+            }
+
+            TauSubcommand::Block { task_id, reason } => {
+                let task_id = tau.resolve(&task_id).await?;
+                tau.block(task_id, &reason).await
+            }
+
+            TauSubcommand::Unblock { task_id } => {
+                let task_id = tau.resolve(&task_id).await?;
+                tau.unblock(task_id).await
+            }
+
+            TauSubcommand::Attach { task_id, link } => {
+                let task_id = tau.resolve(&task_id).await?;
+                tau.attach(task_id, &link).await
+            }
+
+            TauSubcommand::GitLink { task_id } => {
+                let (branch, commit) = match git_head()? {
+                    Some(head) => head,
+                    None => {
+                        error!("Not in a git repository with commits");
+                        exit(1);
+                    }
+                };
+                let task_id = tau.resolve(&task_id).await?;
+                tau.set_git_ref(task_id, branch.as_deref(), &commit).await?;
+                match branch {
+                    Some(branch) => println!("Linked task {} to {} ({})", task_id, branch, commit),
+                    None => println!("Linked task {} to {}", task_id, commit),
+                }
+                Ok(())
+            }
+
+            TauSubcommand::Move { from, to, yes, filters } => {
+                let from = from.strip_prefix("project:").unwrap_or(&from).trim().to_string();
+                let to = to.strip_prefix("project:").unwrap_or(&to).trim().to_string();
+                if from.is_empty() || to.is_empty() {
+                    error!("Please provide the project to move and its new name.");
+                    exit(1);
+                }
+
+                let mut tasks = tau.get_tasks().await?;
+                for filter in filters {
+                    apply_filter(&mut tasks, &filter);
+                }
+                let moves = move_project(&tasks, &from, &to);
+
+                let question = format!("Move {} tasks from {} to {}?", moves.len(), from, to);
+                if moves.len() <= MOVE_CONFIRM_THRESHOLD || yes || confirm(&question)? {
+                    for (task_id, project) in moves.iter() {
+                        let task = BaseTask { project: project.clone(), ..BaseTask::default() };
+                        tau.update(*task_id, task).await?;
+                    }
+                    println!("Moved {} tasks from {} to {}", moves.len(), from, to);
+                }
+                Ok(())
+            }
+
+            TauSubcommand::Check { command: CheckSubcommand::Add { task_id, item } } => {
+                let task_id = tau.resolve(&task_id).await?;
+                tau.check_add(task_id, &item).await
+            }
+
+            TauSubcommand::Check { command: CheckSubcommand::Toggle { task_id, index } } => {
+                let task_id = tau.resolve(&task_id).await?;
+                if index == 0 {
+                    error!("Checklist items are numbered from 1, see `tau info {}`", task_id);
+                    exit(1);
+                }
+                let done = tau.check_toggle(task_id, index - 1).await?;
+                let status = if done { "done" } else { "not done" };
+                println!("Item {} of task {} is {}", index, task_id, status);
+                Ok(())
+            }
+
+            TauSubcommand::Rerank => {
+                let tasks = tau.get_tasks().await?;
+                for (task_id, rank) in rerank(&tasks) {
+                    let task = BaseTask { rank: Some(rank), ..BaseTask::default() };
+                    tau.update(task_id, task).await?;
+                }
+                Ok(())
+            }
+
+            TauSubcommand::Undo => undo(tau).await,
+
+            TauSubcommand::Start { task_id } => {
+                let task_id = tau.resolve(&task_id).await?;
+                tau.start_timer(task_id).await
+            }
+
+            TauSubcommand::Stop { task_id } => {
+                let task_id = tau.resolve(&task_id).await?;
+                tau.stop_timer(task_id).await
+            }
+
+            TauSubcommand::Time { task_id } => {
+                let task_id = tau.resolve(&task_id).await?;
                 let task = tau.get_task_by_id(task_id).await?;
-                print_task_info(task)
+                let tracked = task.tracked_time(Timestamp::current_time().0);
+                println!("Task {}: {}", task_id, duration_as_string(tracked));
+                Ok(())
+            }
+
+            TauSubcommand::Board { filters } => {
+                let tasks = tau.get_tasks().await?;
+                print_task_board(tasks, filters)
+            }
+
+            TauSubcommand::List { format, tags, limit, offset, page, filters } => {
+                let tasks = tau.get_tasks().await?;
+                let filters = config.filters(filters);
+                let page = Page::new(limit, offset, page);
+                match format {
+                    ListFormat::Table => print_task_list(tasks, filters, tags, page),
+                    ListFormat::Csv => print_task_list_delimited(tasks, filters, ',', tags, page),
+                    ListFormat::Tsv => print_task_list_delimited(tasks, filters, '\t', tags, page),
+                }
+            }
+
+            TauSubcommand::Stats { format } => {
+                let active = tau.get_tasks().await?;
+                let stopped = tau.get_stop_tasks().await?;
+                let stats = TaskStats::new(&active, &stopped, Timestamp::current_time().0);
+                match format {
+                    StatsFormat::Table => print_stats(&stats),
+                    StatsFormat::Json => {
+                        println!("{}", serde_json::to_string_pretty(&stats)?);
+                        Ok(())
+                    }
+                }
+            }
+
+            TauSubcommand::Digest { since, json } => {
+                let active = tau.get_tasks().await?;
+                let stopped = tau.get_stop_tasks().await?;
+                let digest = Digest::new(&active, &stopped, since);
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&digest)?);
+                } else {
+                    print!("{}", digest_as_string(&digest));
+                }
+                Ok(())
+            }
+
+            TauSubcommand::Burndown { project, days } => {
+                let mut tasks = tau.get_tasks().await?;
+                tasks.extend(tau.get_stop_tasks().await?);
+                let burndown = Burndown::new(&tasks, &project, days, Timestamp::current_time().0);
+                print_burndown(&burndown, &project);
+                Ok(())
             }
         },
         None => {
-            let task_ids = tau.get_ids().await?;
-            let mut tasks = vec![];
-            for id in task_ids {
-                tasks.push(tau.get_task_by_id(id).await?);
-            }
-            print_task_list(tasks, args.filters)?;
-            Ok(())
+            let tasks = tau.get_tasks().await?;
+            print_task_list(tasks, config.filters(filters), false, Page::default())
         }
-    }?;
-
-    tau.close_connection().await
+    }
 }
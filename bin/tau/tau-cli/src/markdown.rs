@@ -0,0 +1,154 @@
+//! Terminal rendering of the markdown in descriptions and comments, turned
+//! off with `--no-markdown` or `no_markdown` in the config file.
+//!
+//! Only headings, lists, code blocks, `**bold**`, `*italic*`, `` `code` ``
+//! and links are handled, anything else is shown as written. Links are
+//! shown with their URL, never opened.
+
+const BOLD: &str = "\x1b[1m";
+const BOLD_OFF: &str = "\x1b[22m";
+const ITALIC: &str = "\x1b[3m";
+const ITALIC_OFF: &str = "\x1b[23m";
+const UNDERLINE: &str = "\x1b[4m";
+const UNDERLINE_OFF: &str = "\x1b[24m";
+const CODE: &str = "\x1b[36m";
+const CODE_OFF: &str = "\x1b[39m";
+
+/// Render `text` with ANSI escape codes.
+pub fn render_markdown(text: &str) -> String {
+    let mut lines = vec![];
+    let mut in_code_block = false;
+
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue
+        }
+
+        lines.push(if in_code_block {
+            format!("{}{}{}", CODE, line, CODE_OFF)
+        } else {
+            render_line(line)
+        });
+    }
+
+    lines.join("\n")
+}
+
+fn render_line(line: &str) -> String {
+    let content = line.trim_start();
+    let indent = &line[..line.len() - content.len()];
+
+    let hashes = content.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&hashes) {
+        if let Some(heading) = content[hashes..].strip_prefix(' ') {
+            let heading = render_inline(heading.trim());
+            return format!(
+                "{}{}{}{}{}{}",
+                indent, BOLD, UNDERLINE, heading, UNDERLINE_OFF, BOLD_OFF
+            )
+        }
+    }
+
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = content.strip_prefix(bullet) {
+            return format!("{}• {}", indent, render_inline(item))
+        }
+    }
+
+    format!("{}{}", indent, render_inline(content))
+}
+
+fn render_inline(text: &str) -> String {
+    let mut rendered = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        match styled_span(rest) {
+            Some((span, after)) => {
+                rendered.push_str(&span);
+                rest = after;
+            }
+            None => {
+                rendered.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    rendered
+}
+
+/// The rendered span `text` starts with, if any, and the text after it.
+fn styled_span(text: &str) -> Option<(String, &str)> {
+    if let Some(rest) = text.strip_prefix('\\') {
+        let escaped = rest.chars().next().filter(|c| c.is_ascii_punctuation())?;
+        return Some((escaped.to_string(), &rest[1..]))
+    }
+
+    if let Some(rest) = text.strip_prefix('`') {
+        let end = rest.find('`')?;
+        return Some((format!("{}{}{}", CODE, &rest[..end], CODE_OFF), &rest[end + 1..]))
+    }
+
+    if let Some(rest) = text.strip_prefix("**") {
+        let end = rest.find("**").filter(|end| is_emphasis(&rest[..*end]))?;
+        let bold = render_inline(&rest[..end]);
+        return Some((format!("{}{}{}", BOLD, bold, BOLD_OFF), &rest[end + 2..]))
+    }
+
+    if let Some(rest) = text.strip_prefix('*') {
+        let end = rest.find('*').filter(|end| is_emphasis(&rest[..*end]))?;
+        let italic = render_inline(&rest[..end]);
+        return Some((format!("{}{}{}", ITALIC, italic, ITALIC_OFF), &rest[end + 1..]))
+    }
+
+    if let Some(rest) = text.strip_prefix('[') {
+        let (label, rest) = rest.split_once("](").filter(|(label, _)| !label.contains(']'))?;
+        let (url, rest) = rest.split_once(')').filter(|(url, _)| !url.contains(' '))?;
+        let link = format!("{} <{}{}{}>", render_inline(label), UNDERLINE, url, UNDERLINE_OFF);
+        return Some((link, rest))
+    }
+
+    None
+}
+
+/// Whether `text` can be emphasized, so that e.g. `2 * 3 * 4` isn't.
+fn is_emphasis(text: &str) -> bool {
+    !text.is_empty() &&
+        !text.starts_with(char::is_whitespace) &&
+        !text.ends_with(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_markdown() {
+        assert_eq!(render_markdown("plain text"), "plain text");
+        assert_eq!(
+            render_markdown("a **bold** and *italic* `code_span`"),
+            "a \x1b[1mbold\x1b[22m and \x1b[3mitalic\x1b[23m \x1b[36mcode_span\x1b[39m"
+        );
+        assert_eq!(
+            render_markdown("**nested `code`**"),
+            "\x1b[1mnested \x1b[36mcode\x1b[39m\x1b[22m"
+        );
+        assert_eq!(
+            render_markdown("see [the spec](https://dark.fi/spec)"),
+            "see the spec <\x1b[4mhttps://dark.fi/spec\x1b[24m>"
+        );
+        assert_eq!(render_markdown("## Steps"), "\x1b[1m\x1b[4mSteps\x1b[24m\x1b[22m");
+        assert_eq!(render_markdown("- one\n  * two\n1. three"), "• one\n  • two\n1. three");
+        assert_eq!(
+            render_markdown("```\nlet *x* = 1;\n```\ndone"),
+            "\x1b[36mlet *x* = 1;\x1b[39m\ndone"
+        );
+
+        // Left as written
+        for text in ["2 * 3 * 4", "**unclosed", "`unclosed", "[not](a link)", "#tag", "\\*"] {
+            assert_eq!(render_markdown(text), text.replace("\\*", "*"));
+        }
+    }
+}
@@ -1,8 +1,10 @@
-use darkfi::{util::Timestamp, Result};
+use std::cmp::Ordering;
+
+use darkfi::{util::Timestamp, Error, Result};
 
 use crate::due_as_timestamp;
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
 pub struct BaseTask {
     pub title: String,
     pub desc: Option<String>,
@@ -10,6 +12,10 @@ pub struct BaseTask {
     pub project: Vec<String>,
     pub due: Option<i64>,
     pub rank: Option<f32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub estimate: Option<f32>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -26,31 +32,273 @@ pub struct TaskInfo {
     pub created_at: i64,
     pub events: Vec<TaskEvent>,
     pub comments: Vec<Comment>,
+    #[serde(default)]
+    pub links: Vec<String>,
+    #[serde(default)]
+    pub project_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Checklist items, with whether they're done
+    #[serde(default)]
+    pub checklist: Vec<(String, bool)>,
+    /// Git branch and commit the task is linked to
+    #[serde(default)]
+    pub git_ref: Option<GitRef>,
+    /// Effort the task takes, in hours or points
+    #[serde(default)]
+    pub estimate: Option<f32>,
+}
+
+impl TaskInfo {
+    /// The project ID of the task if it has one, its ID otherwise.
+    pub fn display_id(&self) -> String {
+        self.project_id.clone().unwrap_or_else(|| self.id.to_string())
+    }
+
+    /// Current state of the task, ignoring the timer and update events.
+    pub fn state(&self) -> String {
+        let default = TaskEvent::default();
+        let event = self.events.iter().rev().find(|ev| ev.changes_state()).unwrap_or(&default);
+        event.action.clone()
+    }
+
+    /// State the task was in at `ts`, or "open" if it had no state yet.
+    pub fn state_at(&self, ts: i64) -> String {
+        let event = self.events.iter().rev().find(|ev| ev.changes_state() && ev.timestamp.0 <= ts);
+        event.map(|ev| ev.action.clone()).unwrap_or_else(|| TaskEvent::default().action)
+    }
+
+    /// Why the task is blocked, if it is.
+    pub fn block_reason(&self) -> Option<&str> {
+        let event = self.events.iter().rev().find(|ev| ev.changes_state())?;
+        if event.action != BLOCKED {
+            return None
+        }
+        event.reason.as_deref()
+    }
+
+    /// When the task was stopped, if that's its current state.
+    pub fn stopped_at(&self) -> Option<i64> {
+        let event = self.events.iter().rev().find(|ev| ev.changes_state())?;
+        if event.action != "stop" {
+            return None
+        }
+        Some(event.timestamp.0)
+    }
+
+    /// Completion of the checklist as "done/total", if the task has one.
+    pub fn checklist_progress(&self) -> Option<String> {
+        if self.checklist.is_empty() {
+            return None
+        }
+        let done = self.checklist.iter().filter(|(_, done)| *done).count();
+        Some(format!("{}/{}", done, self.checklist.len()))
+    }
+
+    /// Whether the task is still to be done past its due timestamp.
+    pub fn is_overdue(&self, now: i64) -> bool {
+        self.state() != "stop" && self.due.is_some_and(|due| due < now)
+    }
+
+    /// Total time in seconds tracked on the task, with a running timer
+    /// counted up to `now`.
+    pub fn tracked_time(&self, now: i64) -> i64 {
+        let mut total = 0;
+        let mut started: Option<i64> = None;
+
+        for event in self.events.iter() {
+            match event.action.as_str() {
+                TIMER_START => started = started.or(Some(event.timestamp.0)),
+                TIMER_STOP => {
+                    if let Some(start) = started.take() {
+                        // Clocks of different nodes may disagree, never count
+                        // an interval as negative.
+                        total += (event.timestamp.0 - start).max(0);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(start) = started {
+            total += (now - start).max(0);
+        }
+
+        total
+    }
+}
+
+#[cfg(test)]
+impl TaskInfo {
+    /// A task with the given ID and `(action, timestamp)` events and every
+    /// other field empty, for tests to fill in with struct update syntax.
+    pub fn with_events(id: u32, events: &[(&str, i64)]) -> Self {
+        Self {
+            ref_id: String::new(),
+            id,
+            title: String::new(),
+            desc: String::new(),
+            owner: String::new(),
+            assign: vec![],
+            project: vec![],
+            due: None,
+            rank: 0.0,
+            created_at: 0,
+            events: events
+                .iter()
+                .map(|(action, ts)| TaskEvent {
+                    action: action.to_string(),
+                    timestamp: Timestamp(*ts),
+                    reason: None,
+                    author: None,
+                })
+                .collect(),
+            comments: vec![],
+            links: vec![],
+            project_id: None,
+            tags: vec![],
+            checklist: vec![],
+            git_ref: None,
+            estimate: None,
+        }
+    }
 }
 
+/// New ranks for the open tasks, spread evenly from 1 (lowest) up to
+/// the number of open tasks, keeping their current order. Ties are
+/// broken by ID, older tasks ranking higher. Only the tasks whose rank
+/// changes are returned, as `(id, rank)`.
+pub fn rerank(tasks: &[TaskInfo]) -> Vec<(u64, f32)> {
+    let mut open: Vec<&TaskInfo> = tasks.iter().filter(|task| task.state() == "open").collect();
+    open.sort_by(|a, b| cmp_rank(a.rank, b.rank).then(a.id.cmp(&b.id)));
+
+    let len = open.len();
+    open.into_iter()
+        .enumerate()
+        .map(|(i, task)| (task, (len - i) as f32))
+        .filter(|(task, rank)| task.rank != *rank)
+        .map(|(task, rank)| (task.id as u64, rank))
+        .collect()
+}
+
+/// Order of two ranks, highest first. A NaN rank, which tasks saved before
+/// ranks were validated may have, comes last rather than panicking.
+pub fn cmp_rank(a: f32, b: f32) -> Ordering {
+    let key = |rank: f32| if rank.is_nan() { f32::NEG_INFINITY } else { rank };
+    key(b).total_cmp(&key(a))
+}
+
+/// Parse a rank, which must be a finite number.
+pub fn parse_rank(rank: &str) -> std::result::Result<f32, String> {
+    match rank.parse::<f32>() {
+        Ok(rank) if rank.is_finite() => Ok(rank),
+        _ => Err(format!("invalid rank \"{}\"", rank)),
+    }
+}
+
+/// Rename `project` if it is `from` or one of its subprojects, e.g.
+/// `crypto.zk.halo2` becomes `zk.halo2` when moving `crypto.zk` to `zk`.
+fn rename_project(project: &str, from: &str, to: &str) -> Option<String> {
+    if project == from {
+        return Some(to.to_string())
+    }
+    let rest = project.strip_prefix(from)?.strip_prefix('.')?;
+    Some(format!("{}.{}", to, rest))
+}
+
+/// New projects for the tasks in the project `from` or its subprojects,
+/// moved to `to`. Only the tasks whose projects change are returned, as
+/// `(id, projects)`.
+pub fn move_project(tasks: &[TaskInfo], from: &str, to: &str) -> Vec<(u64, Vec<String>)> {
+    tasks
+        .iter()
+        .filter_map(|task| {
+            let mut projects: Vec<String> = vec![];
+            for project in task.project.iter() {
+                let project = rename_project(project, from, to).unwrap_or_else(|| project.clone());
+                if !projects.contains(&project) {
+                    projects.push(project);
+                }
+            }
+            (projects != task.project).then_some((task.id as u64, projects))
+        })
+        .collect()
+}
+
+/// Event actions marking the start and the end of a work interval.
+pub const TIMER_START: &str = "timer_start";
+pub const TIMER_STOP: &str = "timer_stop";
+
+/// Event action recording a change of the task's fields.
+pub const UPDATE: &str = "update";
+
+/// State of a task that can't progress, set along with a reason.
+pub const BLOCKED: &str = "blocked";
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct TaskEvent {
     pub action: String,
     pub timestamp: Timestamp,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Who did it, unknown for the events saved before it was recorded
+    #[serde(default)]
+    pub author: Option<String>,
+}
+
+impl TaskEvent {
+    pub fn is_timer(&self) -> bool {
+        self.action == TIMER_START || self.action == TIMER_STOP
+    }
+
+    /// Whether the event sets the task's state, unlike timers and updates.
+    pub fn changes_state(&self) -> bool {
+        !self.is_timer() && self.action != UPDATE
+    }
 }
 
 impl std::fmt::Display for TaskEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "action: {}, timestamp: {}", self.action, self.timestamp)
+        write!(f, "action: {}, timestamp: {}", self.action, self.timestamp)?;
+        if let Some(author) = &self.author {
+            write!(f, ", author: {}", author)?;
+        }
+        Ok(())
     }
 }
 
 impl Default for TaskEvent {
     fn default() -> Self {
-        Self { action: "open".into(), timestamp: Timestamp::current_time() }
+        Self {
+            action: "open".into(),
+            timestamp: Timestamp::current_time(),
+            reason: None,
+            author: None,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct GitRef {
+    /// None for a detached HEAD
+    pub branch: Option<String>,
+    pub commit: String,
+}
+
+impl std::fmt::Display for GitRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.branch {
+            Some(branch) => write!(f, "{} ({})", branch, self.commit),
+            None => write!(f, "{}", self.commit),
+        }
     }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct Comment {
-    content: String,
-    author: String,
-    timestamp: Timestamp,
+    pub content: String,
+    pub author: String,
+    pub timestamp: Timestamp,
 }
 
 impl std::fmt::Display for Comment {
@@ -59,6 +307,77 @@ impl std::fmt::Display for Comment {
     }
 }
 
+/// A task given on the command line, by its ID or by the ID numbering it
+/// within its project (e.g. `zk-12`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum TaskRef {
+    Id(u64),
+    ProjectId(String),
+}
+
+impl std::str::FromStr for TaskRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Ok(id) = s.parse() {
+            return Ok(Self::Id(id))
+        }
+
+        match s.rsplit_once('-') {
+            Some((project, number)) if !project.is_empty() && number.parse::<u32>().is_ok() => {
+                Ok(Self::ProjectId(s.to_string()))
+            }
+            _ => Err(format!("invalid task ID \"{}\", expected e.g. 12 or zk-12", s)),
+        }
+    }
+}
+
+impl TaskRef {
+    /// Whether `task` is the one referred to.
+    pub fn matches(&self, task: &TaskInfo) -> bool {
+        match self {
+            Self::Id(id) => *id == task.id as u64,
+            Self::ProjectId(project_id) => task.project_id.as_ref() == Some(project_id),
+        }
+    }
+}
+
+/// Whether `values` from the command line set the given field, even to
+/// an empty value.
+pub fn has_field(values: &[String], name: &str) -> bool {
+    values.iter().any(|val| matches!(val.split_once(':'), Some((field, _)) if field == name))
+}
+
+/// Lowercase the tags so they don't end up with near-duplicates, dropping
+/// the blank and repeated ones.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = vec![];
+    for tag in tags.iter().map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()) {
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// A new task copying the title, description, projects, assignees, tags,
+/// rank and estimate of `task`, overridden by the fields given in `values`.
+pub fn duplicate_task(task: &TaskInfo, values: Vec<String>) -> Result<BaseTask> {
+    let (has_project, has_assign) = (has_field(&values, "project"), has_field(&values, "assign"));
+    let changes = task_from_cli(values)?;
+
+    Ok(BaseTask {
+        title: if changes.title.is_empty() { task.title.clone() } else { changes.title },
+        desc: Some(changes.desc.unwrap_or_else(|| task.desc.clone())),
+        project: if has_project { changes.project } else { task.project.clone() },
+        assign: if has_assign { changes.assign } else { task.assign.clone() },
+        due: changes.due,
+        rank: Some(changes.rank.unwrap_or(task.rank)),
+        tags: task.tags.clone(),
+        estimate: task.estimate,
+    })
+}
+
 pub fn task_from_cli(values: Vec<String>) -> Result<BaseTask> {
     let mut title = String::new();
     let mut desc = None;
@@ -79,7 +398,7 @@ pub fn task_from_cli(values: Vec<String>) -> Result<BaseTask> {
         }
 
         if field[0] == "project" {
-            project = field[1].split(',').map(|s| s.into()).collect();
+            project = field[1].split(',').filter(|s| !s.is_empty()).map(|s| s.into()).collect();
         }
 
         if field[0] == "desc" {
@@ -87,7 +406,7 @@ pub fn task_from_cli(values: Vec<String>) -> Result<BaseTask> {
         }
 
         if field[0] == "assign" {
-            assign = field[1].split(',').map(|s| s.into()).collect();
+            assign = field[1].split(',').filter(|s| !s.is_empty()).map(|s| s.into()).collect();
         }
 
         if field[0] == "due" {
@@ -95,9 +414,205 @@ pub fn task_from_cli(values: Vec<String>) -> Result<BaseTask> {
         }
 
         if field[0] == "rank" {
-            rank = Some(field[1].parse::<f32>()?);
+            rank = Some(
+                parse_rank(field[1])
+                    .map_err(|_| Error::ParseFailed("the rank must be a finite number"))?,
+            );
         }
     }
 
-    Ok(BaseTask { title, desc, project, assign, due, rank })
+    Ok(BaseTask { title, desc, project, assign, due, rank, tags: vec![], estimate: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task_with_events(events: &[(&str, i64)]) -> TaskInfo {
+        TaskInfo::with_events(0, events)
+    }
+
+    #[test]
+    fn test_tracked_time() {
+        let task = task_with_events(&[
+            (TIMER_START, 100),
+            ("pause", 120),
+            (TIMER_STOP, 160),
+            (UPDATE, 180),
+            (TIMER_START, 200),
+            (TIMER_STOP, 230),
+        ]);
+        assert_eq!(task.tracked_time(1000), 90);
+        assert_eq!(task.state(), "pause");
+
+        // A running timer is counted up to now
+        let task = task_with_events(&[(TIMER_START, 100), (TIMER_STOP, 150), (TIMER_START, 300)]);
+        assert_eq!(task.tracked_time(400), 150);
+
+        // Intervals never count as negative
+        let task = task_with_events(&[(TIMER_START, 200), (TIMER_STOP, 100)]);
+        assert_eq!(task.tracked_time(1000), 0);
+
+        let task = task_with_events(&[]);
+        assert_eq!(task.tracked_time(1000), 0);
+        assert_eq!(task.state(), "open");
+    }
+
+    #[test]
+    fn test_stopped_at() {
+        let task = task_with_events(&[("stop", 100), ("open", 200), ("stop", 300)]);
+        assert_eq!(task.stopped_at(), Some(300));
+
+        let task = task_with_events(&[("stop", 100), (TIMER_STOP, 200)]);
+        assert_eq!(task.stopped_at(), Some(100));
+
+        let task = task_with_events(&[("stop", 100), ("open", 200)]);
+        assert_eq!(task.stopped_at(), None);
+        assert_eq!(task_with_events(&[]).stopped_at(), None);
+    }
+
+    #[test]
+    fn test_is_overdue() {
+        let mut task = task_with_events(&[]);
+        assert!(!task.is_overdue(1000));
+        task.due = Some(500);
+        assert!(task.is_overdue(1000));
+        assert!(!task.is_overdue(500));
+
+        let mut task = task_with_events(&[("stop", 600)]);
+        task.due = Some(500);
+        assert!(!task.is_overdue(1000));
+    }
+
+    #[test]
+    fn test_checklist_progress() {
+        let mut task = task_with_events(&[]);
+        assert_eq!(task.checklist_progress(), None);
+        task.checklist = vec![("spec".into(), true), ("code".into(), false), ("docs".into(), true)];
+        assert_eq!(task.checklist_progress(), Some("2/3".into()));
+    }
+
+    #[test]
+    fn test_git_ref_display() {
+        let commit = "0123456789abcdef0123456789abcdef01234567".to_string();
+        let git_ref = GitRef { branch: Some("main".into()), commit: commit.clone() };
+        assert_eq!(git_ref.to_string(), format!("main ({})", commit));
+        let git_ref = GitRef { branch: None, commit: commit.clone() };
+        assert_eq!(git_ref.to_string(), commit);
+    }
+
+    #[test]
+    fn test_block_reason() {
+        let mut task = task_with_events(&[(BLOCKED, 100), (TIMER_START, 200)]);
+        task.events[0].reason = Some("waiting for review".into());
+        assert_eq!(task.state(), BLOCKED);
+        assert_eq!(task.block_reason(), Some("waiting for review"));
+
+        task.events.push(TaskEvent {
+            action: "open".into(),
+            timestamp: Timestamp(300),
+            reason: None,
+            author: Some("dark".into()),
+        });
+        assert_eq!(task.block_reason(), None);
+    }
+
+    #[test]
+    fn test_duplicate_task() -> Result<()> {
+        let task = TaskInfo {
+            title: "write docs".into(),
+            desc: "for tau".into(),
+            assign: vec!["dark".into()],
+            project: vec!["tau".into()],
+            due: Some(1000),
+            rank: 2.0,
+            ..task_with_events(&[(TIMER_START, 100)])
+        };
+
+        let dup = duplicate_task(&task, vec![])?;
+        assert_eq!(dup.title, "write docs");
+        assert_eq!(dup.desc.as_deref(), Some("for tau"));
+        assert_eq!(dup.assign, vec!["dark".to_string()]);
+        assert_eq!(dup.project, vec!["tau".to_string()]);
+        assert_eq!(dup.due, None);
+        assert_eq!(dup.rank, Some(2.0));
+
+        let values = vec!["follow-up".into(), "assign:".into(), "rank:3".into()];
+        let dup = duplicate_task(&task, values)?;
+        assert_eq!(dup.title, "follow-up");
+        assert!(dup.assign.is_empty());
+        assert_eq!(dup.project, vec!["tau".to_string()]);
+        assert_eq!(dup.rank, Some(3.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rerank() {
+        let ranks = [(1, 5.0, "open"), (2, 1.0, "open"), (3, 5.0, "open"), (4, 9.0, "stop")];
+        let tasks: Vec<TaskInfo> = ranks
+            .iter()
+            .map(|(id, rank, state)| TaskInfo {
+                id: *id,
+                rank: *rank,
+                ..task_with_events(&[(state, 0)])
+            })
+            .collect();
+
+        // Task 2 already has the lowest rank, and stopped tasks are left alone
+        assert_eq!(rerank(&tasks), vec![(1, 3.0), (3, 2.0)]);
+
+        assert!(rerank(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_move_project() {
+        let projects = [
+            (1, vec!["crypto.zk"]),
+            (2, vec!["crypto.zk.halo2", "net"]),
+            (3, vec!["crypto.zkp"]),
+            (4, vec!["zk", "crypto.zk"]),
+            (5, vec!["net"]),
+        ];
+        let tasks: Vec<TaskInfo> = projects
+            .iter()
+            .map(|(id, project)| TaskInfo {
+                id: *id,
+                project: project.iter().map(|p| p.to_string()).collect(),
+                ..task_with_events(&[])
+            })
+            .collect();
+
+        let to_vec = |v: &[&str]| v.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+        assert_eq!(
+            move_project(&tasks, "crypto.zk", "zk"),
+            vec![(1, to_vec(&["zk"])), (2, to_vec(&["zk.halo2", "net"])), (4, to_vec(&["zk"]))]
+        );
+
+        assert!(move_project(&tasks, "crypto", "crypto").is_empty());
+        assert!(move_project(&tasks, "p2p", "net").is_empty());
+    }
+
+    #[test]
+    fn test_task_ref() {
+        assert_eq!("12".parse(), Ok(TaskRef::Id(12)));
+        assert_eq!("zk-12".parse(), Ok(TaskRef::ProjectId("zk-12".into())));
+        assert_eq!("zk-proofs-3".parse(), Ok(TaskRef::ProjectId("zk-proofs-3".into())));
+        assert!("zk".parse::<TaskRef>().is_err());
+        assert!("zk-".parse::<TaskRef>().is_err());
+        assert!("-12".parse::<TaskRef>().is_err());
+
+        let task = TaskInfo { id: 3, project_id: Some("zk-12".into()), ..task_with_events(&[]) };
+        assert!(TaskRef::Id(3).matches(&task));
+        assert!(TaskRef::ProjectId("zk-12".into()).matches(&task));
+        assert!(!TaskRef::ProjectId("zk-1".into()).matches(&task));
+        assert!(!TaskRef::Id(12).matches(&task));
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags: Vec<String> =
+            ["Bug", " urgent ", "BUG", "", "ui"].iter().map(|t| t.to_string()).collect();
+        assert_eq!(normalize_tags(&tags), vec!["bug", "urgent", "ui"]);
+        assert!(normalize_tags(&[]).is_empty());
+    }
 }
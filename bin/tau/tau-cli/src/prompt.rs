@@ -0,0 +1,178 @@
+use std::io::{self, BufRead, Write};
+
+use darkfi::{
+    util::time::{timestamp_to_date, DateFormat},
+    Result,
+};
+
+use crate::{
+    primitives::{parse_rank, BaseTask},
+    util::due_as_timestamp,
+};
+
+/// Answer clearing a field that has a default.
+const NONE: &str = "-";
+
+/// Ask for the fields of a new task one by one, starting from `task`,
+/// which holds the values given as arguments and the config defaults.
+/// An empty answer keeps the value shown in brackets and `-` clears it.
+/// The description is written in the editor started by `editor`.
+pub fn prompt_task<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    mut task: BaseTask,
+    editor: impl FnOnce() -> Result<Option<String>>,
+) -> Result<BaseTask> {
+    loop {
+        let answer = ask(input, output, "Title", &task.title)?;
+        if !answer.is_empty() {
+            task.title = answer;
+        }
+        if !task.title.is_empty() {
+            break
+        }
+        writeln!(output, "A title is required.")?;
+    }
+
+    if task.desc.is_none() {
+        let answer = ask(input, output, "Write a description in the editor? (y/n)", "y")?;
+        task.desc = if answer.is_empty() || answer.eq_ignore_ascii_case("y") {
+            editor()?
+        } else {
+            Some(String::new())
+        };
+    }
+
+    task.project = ask_list(input, output, "Projects (comma-separated)", task.project)?;
+    task.assign = ask_list(input, output, "Assignees (comma-separated)", task.assign)?;
+
+    loop {
+        let current = task.due.map(|due| timestamp_to_date(due, DateFormat::Date));
+        let answer =
+            ask(input, output, "Due (e.g. 1503, friday, +2w)", current.as_deref().unwrap_or(""))?;
+        match answer.as_str() {
+            "" => break,
+            NONE => {
+                task.due = None;
+                break
+            }
+            // due_as_timestamp() explains what is wrong
+            due => {
+                if let Some(due) = due_as_timestamp(due) {
+                    task.due = Some(due);
+                    break
+                }
+            }
+        }
+    }
+
+    loop {
+        let current = task.rank.map(|rank| rank.to_string());
+        let answer = ask(input, output, "Rank", current.as_deref().unwrap_or(""))?;
+        match answer.as_str() {
+            "" => break,
+            NONE => {
+                task.rank = None;
+                break
+            }
+            rank => match parse_rank(rank) {
+                Ok(rank) => {
+                    task.rank = Some(rank);
+                    break
+                }
+                Err(_) => writeln!(output, "The rank must be a finite number.")?,
+            },
+        }
+    }
+
+    Ok(task)
+}
+
+/// Print the question with the current value and read the trimmed answer.
+/// Running out of input is an error, so a required field can't loop forever.
+fn ask<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    current: &str,
+) -> Result<String> {
+    if current.is_empty() {
+        write!(output, "{}: ", question)?;
+    } else {
+        write!(output, "{} [{}]: ", question, current)?;
+    }
+    output.flush()?;
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+    Ok(answer.trim().to_string())
+}
+
+fn ask_list<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    current: Vec<String>,
+) -> Result<Vec<String>> {
+    let answer = ask(input, output, question, &current.join(", "))?;
+    Ok(match answer.as_str() {
+        "" => current,
+        NONE => vec![],
+        answer => answer
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn prompt(answers: &str, task: BaseTask) -> Result<BaseTask> {
+        let mut output = vec![];
+        prompt_task(&mut Cursor::new(answers), &mut output, task, || Ok(Some("from editor".into())))
+    }
+
+    #[test]
+    fn test_prompt_task() -> Result<()> {
+        // A title is required, invalid dues and ranks are asked again
+        let task = prompt(
+            "\n\nfix sync\n\nraft, net\n\nnot a date\n+3d\nhigh\n2.5\n",
+            BaseTask::default(),
+        )?;
+        assert_eq!(task.title, "fix sync");
+        assert_eq!(task.desc.as_deref(), Some("from editor"));
+        assert_eq!(task.project, vec!["raft".to_string(), "net".to_string()]);
+        assert!(task.assign.is_empty());
+        assert_eq!(task.due, due_as_timestamp("+3d"));
+        assert_eq!(task.rank, Some(2.5));
+
+        // Empty answers keep the defaults, "-" clears them
+        let defaults = BaseTask {
+            title: "write docs".into(),
+            project: vec!["tau".into()],
+            assign: vec!["dark".into()],
+            rank: Some(1.0),
+            ..BaseTask::default()
+        };
+        let task = prompt("\nn\n\n-\n\n-\n", defaults)?;
+        assert_eq!(task.title, "write docs");
+        assert_eq!(task.desc.as_deref(), Some(""));
+        assert_eq!(task.project, vec!["tau".to_string()]);
+        assert!(task.assign.is_empty());
+        assert_eq!(task.due, None);
+        assert_eq!(task.rank, None);
+
+        // Running out of answers
+        assert!(prompt("\n", BaseTask::default()).is_err());
+
+        Ok(())
+    }
+}
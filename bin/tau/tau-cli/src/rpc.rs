@@ -1,10 +1,10 @@
 use log::debug;
 use serde_json::json;
 
-use darkfi::{rpc::jsonrpc::JsonRequest, Result};
+use darkfi::{rpc::jsonrpc::JsonRequest, Error, Result};
 
 use crate::{
-    primitives::{BaseTask, TaskInfo},
+    primitives::{BaseTask, TaskInfo, TaskRef},
     Tau,
 };
 
@@ -13,13 +13,13 @@ impl Tau {
         self.rpc_client.close().await
     }
 
-    /// Add a new task.
-    pub async fn add(&self, task: BaseTask) -> Result<()> {
+    /// Add a new task and return its ID.
+    pub async fn add(&self, task: BaseTask) -> Result<u64> {
         let req = JsonRequest::new("add", json!([task]));
         let rep = self.rpc_client.request(req).await?;
 
         debug!("Got reply: {:?}", rep);
-        Ok(())
+        Ok(serde_json::from_value(rep)?)
     }
 
     /// Get all task ids.
@@ -62,6 +62,88 @@ impl Tau {
         Ok(())
     }
 
+    /// Set a task as blocked for the given reason.
+    pub async fn block(&self, id: u64, reason: &str) -> Result<()> {
+        let req = JsonRequest::new("block", json!([id, reason]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Set a blocked task back to open.
+    pub async fn unblock(&self, id: u64) -> Result<()> {
+        let req = JsonRequest::new("unblock", json!([id]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Attach a URL or a file path to a task.
+    pub async fn attach(&self, id: u64, link: &str) -> Result<()> {
+        let req = JsonRequest::new("attach", json!([id, link]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Add an item to the checklist of a task.
+    pub async fn check_add(&self, id: u64, item: &str) -> Result<()> {
+        let req = JsonRequest::new("check_add", json!([id, item]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Toggle the checklist item of a task at `index`, starting from 0.
+    /// Returns whether the item is now done.
+    pub async fn check_toggle(&self, id: u64, index: usize) -> Result<bool> {
+        let req = JsonRequest::new("check_toggle", json!([id, index]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(serde_json::from_value(rep)?)
+    }
+
+    /// Link a task to a git commit, and to the branch it's on if any.
+    pub async fn set_git_ref(&self, id: u64, branch: Option<&str>, commit: &str) -> Result<()> {
+        let req = JsonRequest::new("set_git_ref", json!([id, branch, commit]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Remove a comment previously set on a task.
+    pub async fn remove_comment(&self, id: u64, content: &str) -> Result<()> {
+        let req = JsonRequest::new("remove_comment", json!([id, content]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Start the timer on a task.
+    pub async fn start_timer(&self, id: u64) -> Result<()> {
+        let req = JsonRequest::new("start_timer", json!([id]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Stop the timer on a task.
+    pub async fn stop_timer(&self, id: u64) -> Result<()> {
+        let req = JsonRequest::new("stop_timer", json!([id]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
     /// Get task data by its ID.
     pub async fn get_task_by_id(&self, id: u64) -> Result<TaskInfo> {
         let req = JsonRequest::new("get_task_by_id", json!([id]));
@@ -69,4 +151,76 @@ impl Tau {
 
         Ok(serde_json::from_value(rep)?)
     }
+
+    /// ID of the open task given by its ID or its project ID.
+    pub async fn resolve(&self, task: &TaskRef) -> Result<u64> {
+        match task {
+            TaskRef::Id(id) => Ok(*id),
+            TaskRef::ProjectId(project_id) => {
+                let req = JsonRequest::new("get_task_by_id", json!([project_id]));
+                let task: TaskInfo = serde_json::from_value(self.rpc_client.request(req).await?)?;
+                Ok(task.id as u64)
+            }
+        }
+    }
+
+    /// ID of the task in the trash given by its ID or its project ID.
+    pub async fn resolve_trashed(&self, task: &TaskRef) -> Result<u64> {
+        match task {
+            TaskRef::Id(id) => Ok(*id),
+            TaskRef::ProjectId(_) => self
+                .get_trash_tasks()
+                .await?
+                .iter()
+                .filter(|t| task.matches(t))
+                .map(|t| t.id as u64)
+                .next()
+                .ok_or(Error::ParseFailed("no task with this project ID in the trash")),
+        }
+    }
+
+    /// Get data for the stopped tasks, which `get_tasks` leaves out.
+    pub async fn get_stop_tasks(&self) -> Result<Vec<TaskInfo>> {
+        let req = JsonRequest::new("get_stop_tasks", json!([]));
+        let rep = self.rpc_client.request(req).await?;
+
+        Ok(serde_json::from_value(rep)?)
+    }
+
+    /// Move a task to the trash.
+    pub async fn delete(&self, id: u64) -> Result<()> {
+        let req = JsonRequest::new("delete", json!([id]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(())
+    }
+
+    /// Bring a task back from the trash and return its ID, which changes
+    /// if another task took it in the meantime.
+    pub async fn restore(&self, id: u64) -> Result<u64> {
+        let req = JsonRequest::new("restore", json!([id]));
+        let rep = self.rpc_client.request(req).await?;
+
+        debug!("Got reply: {:?}", rep);
+        Ok(serde_json::from_value(rep)?)
+    }
+
+    /// Get data for the tasks in the trash.
+    pub async fn get_trash_tasks(&self) -> Result<Vec<TaskInfo>> {
+        let req = JsonRequest::new("get_trash_tasks", json!([]));
+        let rep = self.rpc_client.request(req).await?;
+
+        Ok(serde_json::from_value(rep)?)
+    }
+
+    /// Get data for all tasks.
+    pub async fn get_tasks(&self) -> Result<Vec<TaskInfo>> {
+        let mut tasks = vec![];
+        for id in self.get_ids().await? {
+            tasks.push(self.get_task_by_id(id).await?);
+        }
+
+        Ok(tasks)
+    }
 }
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use prettytable::{format::consts::FORMAT_NO_COLSEP, row, Table};
+use serde::Serialize;
+
+use darkfi::Result;
+
+use crate::{primitives::TaskInfo, view::duration_as_string};
+
+/// Windows, in seconds, over which completed tasks are counted.
+const WEEK: i64 = 7 * 24 * 3600;
+const MONTH: i64 = 30 * 24 * 3600;
+
+/// Project name counting the tasks that have none.
+const NO_PROJECT: &str = "(none)";
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct TaskStats {
+    /// Number of tasks in each state, stopped ones included
+    pub by_state: BTreeMap<String, usize>,
+    /// Number of tasks not stopped in each project. A task with several
+    /// projects counts in each of them.
+    pub by_project: BTreeMap<String, usize>,
+    /// Average age in seconds of the tasks not stopped
+    pub average_open_age: i64,
+    /// Tasks stopped in the last 7 days
+    pub completed_last_week: usize,
+    /// Tasks stopped in the last 30 days
+    pub completed_last_month: usize,
+}
+
+impl TaskStats {
+    /// Statistics of the `active` tasks, as listed by `get_tasks`, and of
+    /// the `stopped` ones, as of `now`.
+    pub fn new(active: &[TaskInfo], stopped: &[TaskInfo], now: i64) -> Self {
+        let mut stats = Self::default();
+
+        for task in active.iter().chain(stopped.iter()) {
+            *stats.by_state.entry(task.state()).or_default() += 1;
+        }
+
+        for task in active {
+            if task.project.is_empty() {
+                *stats.by_project.entry(NO_PROJECT.into()).or_default() += 1;
+            }
+            for project in task.project.iter() {
+                *stats.by_project.entry(project.clone()).or_default() += 1;
+            }
+        }
+
+        if !active.is_empty() {
+            let total_age: i64 = active.iter().map(|task| (now - task.created_at).max(0)).sum();
+            stats.average_open_age = total_age / active.len() as i64;
+        }
+
+        for stopped_at in stopped.iter().filter_map(|task| task.stopped_at()) {
+            if now - stopped_at <= WEEK {
+                stats.completed_last_week += 1;
+            }
+            if now - stopped_at <= MONTH {
+                stats.completed_last_month += 1;
+            }
+        }
+
+        stats
+    }
+}
+
+pub fn print_stats(stats: &TaskStats) -> Result<()> {
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_COLSEP);
+    table.set_titles(row!["State", "Tasks"]);
+    for (state, count) in stats.by_state.iter() {
+        table.add_row(row![state, count]);
+    }
+    table.printstd();
+    println!();
+
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_COLSEP);
+    table.set_titles(row!["Project", "Open tasks"]);
+    for (project, count) in stats.by_project.iter() {
+        table.add_row(row![project, count]);
+    }
+    table.printstd();
+    println!();
+
+    let mut table = Table::new();
+    table.set_format(*FORMAT_NO_COLSEP);
+    table.add_row(row!["Average age of open tasks", duration_as_string(stats.average_open_age)]);
+    table.add_row(row!["Completed in the last week", stats.completed_last_week]);
+    table.add_row(row!["Completed in the last month", stats.completed_last_month]);
+    table.printstd();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 3600;
+
+    fn task(project: &[&str], created_at: i64, events: &[(&str, i64)]) -> TaskInfo {
+        TaskInfo {
+            project: project.iter().map(|p| p.to_string()).collect(),
+            created_at,
+            ..TaskInfo::with_events(0, events)
+        }
+    }
+
+    #[test]
+    fn test_task_stats() {
+        let now = 100 * DAY;
+        let active = vec![
+            task(&["tau"], now - 2 * DAY, &[]),
+            task(&["tau", "raft"], now - 4 * DAY, &[("pause", now - DAY)]),
+            task(&[], now, &[("stop", now - DAY), ("open", now)]),
+        ];
+        let stopped = vec![
+            task(&["tau"], 0, &[("stop", now - 2 * DAY)]),
+            task(&["tau"], 0, &[("stop", now - 10 * DAY), ("timer_stop", now)]),
+            task(&["raft"], 0, &[("stop", now - 40 * DAY)]),
+        ];
+
+        let stats = TaskStats::new(&active, &stopped, now);
+        let counts = |items: &[(&str, usize)]| {
+            items.iter().map(|(k, v)| (k.to_string(), *v)).collect::<BTreeMap<_, _>>()
+        };
+        assert_eq!(stats.by_state, counts(&[("open", 2), ("pause", 1), ("stop", 3)]));
+        assert_eq!(stats.by_project, counts(&[("(none)", 1), ("raft", 1), ("tau", 2)]));
+        assert_eq!(stats.average_open_age, 2 * DAY);
+        assert_eq!(stats.completed_last_week, 1);
+        assert_eq!(stats.completed_last_month, 2);
+
+        assert_eq!(TaskStats::new(&[], &[], now), TaskStats::default());
+    }
+}
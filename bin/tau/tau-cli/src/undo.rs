@@ -0,0 +1,231 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use darkfi::{util::path::join_config_path, Result};
+
+use crate::{
+    primitives::{BaseTask, TaskInfo},
+    util::confirm,
+    Tau,
+};
+
+const UNDO_FILE: &str = "tau_undo.json";
+
+/// Task fields that can be changed with `tau update`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskFields {
+    title: String,
+    desc: String,
+    assign: Vec<String>,
+    project: Vec<String>,
+    due: Option<i64>,
+    rank: f32,
+    // undo files saved before tags were added have none
+    #[serde(default)]
+    tags: Vec<String>,
+    // nor estimates
+    #[serde(default)]
+    estimate: Option<f32>,
+}
+
+impl From<&TaskInfo> for TaskFields {
+    fn from(task: &TaskInfo) -> Self {
+        Self {
+            title: task.title.clone(),
+            desc: task.desc.clone(),
+            assign: task.assign.clone(),
+            project: task.project.clone(),
+            due: task.due,
+            rank: task.rank,
+            tags: task.tags.clone(),
+            estimate: task.estimate,
+        }
+    }
+}
+
+impl TaskFields {
+    /// Fields expected after taud applies the given update.
+    fn updated_with(&self, task: &BaseTask) -> Self {
+        let mut fields = self.clone();
+        if !task.title.is_empty() {
+            fields.title = task.title.clone();
+        }
+        if let Some(desc) = &task.desc {
+            fields.desc = desc.clone();
+        }
+        if !task.assign.is_empty() {
+            fields.assign = task.assign.clone();
+        }
+        if !task.project.is_empty() {
+            fields.project = task.project.clone();
+        }
+        if task.due.is_some() {
+            fields.due = task.due;
+        }
+        if let Some(rank) = task.rank {
+            fields.rank = rank;
+        }
+        if !task.tags.is_empty() {
+            fields.tags = task.tags.clone();
+        }
+        if task.estimate.is_some() {
+            fields.estimate = task.estimate;
+        }
+        fields
+    }
+}
+
+/// The latest mutating operation, with what is needed to revert it.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Operation {
+    Add { task_id: u64, title: String },
+    Update { task_id: u64, before: Box<TaskFields>, after: Box<TaskFields> },
+    State { task_id: u64, before: String, after: String },
+    Comment { task_id: u64, content: String },
+}
+
+impl Operation {
+    pub fn update(task_id: u64, before: &TaskInfo, task: &BaseTask) -> Self {
+        let before = TaskFields::from(before);
+        let after = before.updated_with(task);
+        Self::Update { task_id, before: Box::new(before), after: Box::new(after) }
+    }
+
+    fn task_id(&self) -> u64 {
+        match self {
+            Self::Add { task_id, .. } |
+            Self::Update { task_id, .. } |
+            Self::State { task_id, .. } |
+            Self::Comment { task_id, .. } => *task_id,
+        }
+    }
+
+    /// Whether the task still looks like this operation left it.
+    fn is_current(&self, task: &TaskInfo) -> bool {
+        match self {
+            Self::Add { title, .. } => task.title == *title && task.state() == "open",
+            Self::Update { after, .. } => TaskFields::from(task) == **after,
+            Self::State { after, .. } => task.state() == *after,
+            // Removing our own comment doesn't overwrite anyone else's changes
+            Self::Comment { .. } => true,
+        }
+    }
+}
+
+fn undo_path() -> Result<PathBuf> {
+    join_config_path(Path::new(UNDO_FILE))
+}
+
+/// Record the operation, replacing the previous one.
+pub fn save_operation(op: &Operation) -> Result<()> {
+    let path = undo_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, op)?;
+    Ok(())
+}
+
+fn load_operation() -> Result<Option<Operation>> {
+    let path = undo_path()?;
+    if !path.exists() {
+        return Ok(None)
+    }
+
+    let reader = BufReader::new(File::open(path)?);
+    Ok(Some(serde_json::from_reader(reader)?))
+}
+
+/// Revert the latest operation recorded by `save_operation()`.
+pub async fn undo(tau: &Tau) -> Result<()> {
+    let op = match load_operation()? {
+        Some(op) => op,
+        None => {
+            println!("Nothing to undo.");
+            return Ok(())
+        }
+    };
+
+    let task_id = op.task_id();
+    let task = tau.get_task_by_id(task_id).await?;
+
+    if !op.is_current(&task) {
+        warn!("Task {} was changed since the last operation", task_id);
+        if !confirm("Undo anyway and overwrite those changes?")? {
+            return Ok(())
+        }
+    }
+
+    match &op {
+        Operation::Add { .. } => tau.set_state(task_id, "stop").await?,
+        Operation::Update { before, .. } => {
+            // taud ignores empty lists and a missing due date or estimate,
+            // so these can't be cleared if they were set by the update.
+            let task = BaseTask {
+                title: before.title.clone(),
+                desc: Some(before.desc.clone()),
+                assign: before.assign.clone(),
+                project: before.project.clone(),
+                due: before.due,
+                rank: Some(before.rank),
+                tags: before.tags.clone(),
+                estimate: before.estimate,
+            };
+            tau.update(task_id, task).await?
+        }
+        Operation::State { before, .. } => tau.set_state(task_id, before).await?,
+        Operation::Comment { content, .. } => tau.remove_comment(task_id, content).await?,
+    }
+
+    fs::remove_file(undo_path()?)?;
+    println!("Reverted the last operation on task {}", task_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updated_with() {
+        let before = TaskFields {
+            title: "title".into(),
+            desc: "desc".into(),
+            assign: vec!["dark".into()],
+            project: vec![],
+            due: None,
+            rank: 1.0,
+            tags: vec!["bug".into()],
+            estimate: None,
+        };
+
+        let update = BaseTask {
+            title: String::new(),
+            desc: None,
+            assign: vec![],
+            project: vec!["tau".into()],
+            due: Some(1000),
+            rank: Some(2.0),
+            tags: vec![],
+            estimate: Some(3.0),
+        };
+
+        let after = before.updated_with(&update);
+        assert_eq!(after.title, "title");
+        assert_eq!(after.desc, "desc");
+        assert_eq!(after.assign, vec!["dark".to_string()]);
+        assert_eq!(after.project, vec!["tau".to_string()]);
+        assert_eq!(after.due, Some(1000));
+        assert_eq!(after.rank, 2.0);
+        assert_eq!(after.tags, vec!["bug".to_string()]);
+        assert_eq!(after.estimate, Some(3.0));
+    }
+}
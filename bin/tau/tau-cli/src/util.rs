@@ -1,41 +1,166 @@
 use std::{
     env,
     fs::{self, File},
-    io::Write,
-    process::Command,
+    io::{stdin, stdout, Write},
+    process::{Command, Stdio},
 };
 
-use chrono::{Datelike, Local, NaiveDate};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
 use log::error;
 
 use darkfi::{util::Timestamp, Result};
 
-/// Parse due date (e.g. "1503" for 15 March) as i64 timestamp.
+use crate::filter::ddmm_to_past_date;
+
+/// Ask a yes/no question on the terminal, defaulting to no.
+pub fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    stdout().flush()?;
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+const DUE_FORMATS: &str = "DDMM (e.g. \"1503\" for 15 March), \"today\", \"tomorrow\", \
+                           \"+3d\", \"+2w\" or a weekday (e.g. \"friday\", \"next monday\")";
+
+/// Parse due date as i64 timestamp. Accepts "DDMM" (e.g. "1503" for
+/// 15 March) as well as dates relative to today, like "tomorrow", "+3d",
+/// "+2w", "friday" or "next monday".
 pub fn due_as_timestamp(due: &str) -> Option<i64> {
-    if due.len() != 4 || !due.parse::<u32>().is_ok() {
-        error!("Due date must be digits of length 4 (e.g. \"1503\" for 15 March)");
-        return None
+    let due = due.trim().to_lowercase();
+    let today = Local::today().naive_local();
+
+    let date = if due.len() == 4 && due.parse::<u32>().is_ok() {
+        ddmm_to_date(&due, today)?
+    } else {
+        relative_to_date(&due, today)?
+    };
+
+    Some(date.and_hms(12, 0, 0).timestamp())
+}
+
+/// Move a due timestamp to `time` on the same day. With `business_days`,
+/// a due date falling on a weekend rolls over to the next Monday.
+pub fn adjust_due(due: i64, time: NaiveTime, business_days: bool) -> i64 {
+    let mut date = NaiveDateTime::from_timestamp(due, 0).date();
+    if business_days {
+        date = match date.weekday() {
+            Weekday::Sat => date + Duration::days(2),
+            Weekday::Sun => date + Duration::days(1),
+            _ => date,
+        };
     }
+    date.and_time(time).timestamp()
+}
+
+/// Resolve "DDMM" to the next such date, starting from `today`.
+fn ddmm_to_date(due: &str, today: NaiveDate) -> Option<NaiveDate> {
     let (day, month) = (due[..2].parse::<u32>().unwrap(), due[2..].parse::<u32>().unwrap());
 
-    if day > 31 || month > 12 {
+    let mut year = today.year();
+
+    // Ensure the due date is in future
+    if month < today.month() || (month == today.month() && day < today.day()) {
+        year += 1;
+    }
+
+    let date = NaiveDate::from_ymd_opt(year, month, day);
+    if date.is_none() {
         error!("Invalid or out-of-range date");
-        return None
     }
+    date
+}
 
-    let mut year = Local::today().year();
+/// Resolve a relative date expression against `today`.
+fn relative_to_date(due: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let days = match due {
+        "today" => Some(0),
+        "tomorrow" => Some(1),
+        _ => match due.strip_prefix('+') {
+            Some(offset) => parse_offset(offset),
+            None => {
+                // Weekdays always refer to the next one to come, so "friday"
+                // on a Friday means a week from today.
+                let weekday = due.strip_prefix("next ").unwrap_or(due);
+                weekday.parse::<Weekday>().ok().map(|w| {
+                    let ahead =
+                        (7 + w.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+                    if ahead == 0 {
+                        7
+                    } else {
+                        ahead as i64
+                    }
+                })
+            }
+        },
+    };
 
-    // Ensure the due date is in future
-    if month < Local::today().month() {
-        year += 1;
+    let date = days.and_then(|d| today.checked_add_signed(Duration::days(d)));
+    if date.is_none() {
+        error!("Invalid due date \"{}\", must be one of: {}", due, DUE_FORMATS);
     }
+    date
+}
 
-    if month == Local::today().month() && day < Local::today().day() {
-        year += 1;
+pub const SINCE_FORMATS: &str = "DDMM (e.g. \"1503\" for 15 March), \"today\", \"yesterday\", \
+                                 \"-3d\", \"-2w\" or a weekday (e.g. \"monday\")";
+
+/// Parse the start of a reporting period as the i64 timestamp of the
+/// start of that day. Accepts "DDMM" (e.g. "1503" for the last 15 March)
+/// as well as dates going back from today, like "yesterday", "-3d", "-2w"
+/// or "monday".
+pub fn since_as_timestamp(since: &str) -> Option<i64> {
+    let since = since.trim().to_lowercase();
+    let today = Local::today().naive_local();
+
+    let date = if since.len() == 4 && since.parse::<u32>().is_ok() {
+        ddmm_to_past_date(&since, today)?
+    } else {
+        past_relative_to_date(&since, today)?
+    };
+
+    Some(date.and_hms(0, 0, 0).timestamp())
+}
+
+/// Resolve a date expression going back from `today`. Weekdays refer to
+/// the last one, so "monday" on a Monday means a week ago.
+fn past_relative_to_date(since: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let days = match since {
+        "today" => Some(0),
+        "yesterday" => Some(1),
+        _ => match since.strip_prefix('-') {
+            Some(offset) => parse_offset(offset),
+            None => since.parse::<Weekday>().ok().map(|w| {
+                let behind =
+                    (7 + today.weekday().num_days_from_monday() - w.num_days_from_monday()) % 7;
+                if behind == 0 {
+                    7
+                } else {
+                    behind as i64
+                }
+            }),
+        },
+    };
+
+    days.and_then(|d| today.checked_sub_signed(Duration::days(d)))
+}
+
+/// Parse an offset in days or weeks (e.g. "3d", "2w") as a number of days.
+fn parse_offset(offset: &str) -> Option<i64> {
+    if offset.len() < 2 {
+        return None
     }
 
-    let dt = NaiveDate::from_ymd(year, month, day).and_hms(12, 0, 0);
-    Some(dt.timestamp())
+    let (n, unit) = offset.split_at(offset.len() - 1);
+    let n = n.parse::<u32>().ok()? as i64;
+
+    match unit {
+        "d" => Some(n),
+        "w" => Some(n * 7),
+        _ => None,
+    }
 }
 
 /// Start up the preferred editor to edit a task's description.
@@ -69,3 +194,94 @@ pub fn desc_in_editor() -> Result<Option<String>> {
     }
     Ok(Some(lines.join("\n")))
 }
+
+/// Branch checked out in the working directory, none when HEAD is
+/// detached, and the commit of HEAD. None outside of a git repository or
+/// before the first commit.
+pub fn git_head() -> Result<Option<(Option<String>, String)>> {
+    let commit = match git_output(&["rev-parse", "--verify", "-q", "HEAD"])? {
+        Some(commit) => commit,
+        None => return Ok(None),
+    };
+    let branch = git_output(&["symbolic-ref", "--short", "-q", "HEAD"])?;
+    Ok(Some((branch, commit)))
+}
+
+/// Output of a git command, none if it fails.
+fn git_output(args: &[&str]) -> Result<Option<String>> {
+    let output = Command::new("git").args(args).stderr(Stdio::null()).output()?;
+    if !output.status.success() {
+        return Ok(None)
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ddmm_to_date() {
+        // Wednesday 15 June 2022
+        let today = NaiveDate::from_ymd(2022, 6, 15);
+        assert_eq!(ddmm_to_date("2006", today), Some(NaiveDate::from_ymd(2022, 6, 20)));
+        assert_eq!(ddmm_to_date("1006", today), Some(NaiveDate::from_ymd(2023, 6, 10)));
+        assert_eq!(ddmm_to_date("0101", today), Some(NaiveDate::from_ymd(2023, 1, 1)));
+        assert_eq!(ddmm_to_date("3102", today), None);
+        assert_eq!(ddmm_to_date("0113", today), None);
+    }
+
+    #[test]
+    fn test_relative_to_date() {
+        // Wednesday 15 June 2022
+        let today = NaiveDate::from_ymd(2022, 6, 15);
+        assert_eq!(relative_to_date("today", today), Some(today));
+        assert_eq!(relative_to_date("tomorrow", today), Some(NaiveDate::from_ymd(2022, 6, 16)));
+        assert_eq!(relative_to_date("+3d", today), Some(NaiveDate::from_ymd(2022, 6, 18)));
+        assert_eq!(relative_to_date("+2w", today), Some(NaiveDate::from_ymd(2022, 6, 29)));
+        assert_eq!(relative_to_date("friday", today), Some(NaiveDate::from_ymd(2022, 6, 17)));
+        assert_eq!(relative_to_date("fri", today), Some(NaiveDate::from_ymd(2022, 6, 17)));
+        assert_eq!(relative_to_date("next monday", today), Some(NaiveDate::from_ymd(2022, 6, 20)));
+        assert_eq!(relative_to_date("wednesday", today), Some(NaiveDate::from_ymd(2022, 6, 22)));
+
+        assert_eq!(relative_to_date("+3", today), None);
+        assert_eq!(relative_to_date("+d", today), None);
+        assert_eq!(relative_to_date("+-3d", today), None);
+        assert_eq!(relative_to_date("+3y", today), None);
+        assert_eq!(relative_to_date("someday", today), None);
+    }
+
+    #[test]
+    fn test_adjust_due() {
+        let noon = NaiveTime::from_hms(12, 0, 0);
+        let evening = NaiveTime::from_hms(17, 30, 0);
+        // Friday 17 June 2022 and Saturday 18 June 2022, at noon
+        let friday = NaiveDate::from_ymd(2022, 6, 17).and_time(noon).timestamp();
+        let saturday = NaiveDate::from_ymd(2022, 6, 18).and_time(noon).timestamp();
+        let monday = NaiveDate::from_ymd(2022, 6, 20);
+
+        assert_eq!(adjust_due(saturday, noon, false), saturday);
+        assert_eq!(
+            adjust_due(friday, evening, true),
+            NaiveDate::from_ymd(2022, 6, 17).and_time(evening).timestamp()
+        );
+        assert_eq!(adjust_due(saturday, evening, true), monday.and_time(evening).timestamp());
+        let sunday = saturday + 24 * 3600;
+        assert_eq!(adjust_due(sunday, noon, true), monday.and_time(noon).timestamp());
+    }
+
+    #[test]
+    fn test_since() {
+        // Wednesday 15 June 2022
+        let today = NaiveDate::from_ymd(2022, 6, 15);
+        let past = |since| past_relative_to_date(since, today);
+        assert_eq!(past("today"), Some(today));
+        assert_eq!(past("yesterday"), Some(NaiveDate::from_ymd(2022, 6, 14)));
+        assert_eq!(past("-3d"), Some(NaiveDate::from_ymd(2022, 6, 12)));
+        assert_eq!(past("-2w"), Some(NaiveDate::from_ymd(2022, 6, 1)));
+        assert_eq!(past("monday"), Some(NaiveDate::from_ymd(2022, 6, 13)));
+        assert_eq!(past("wednesday"), Some(NaiveDate::from_ymd(2022, 6, 8)));
+        assert_eq!(past("+3d"), None);
+        assert_eq!(past("someday"), None);
+    }
+}
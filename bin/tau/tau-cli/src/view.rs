@@ -5,18 +5,84 @@ use prettytable::{
 };
 
 use darkfi::{
-    util::time::{timestamp_to_date, DateFormat},
+    util::{
+        time::{timestamp_to_date, DateFormat},
+        Timestamp,
+    },
     Result,
 };
 
 use crate::{
     filter::apply_filter,
-    primitives::{Comment, TaskInfo},
+    markdown::render_markdown,
+    primitives::{cmp_rank, Comment, TaskInfo, BLOCKED, TIMER_START, TIMER_STOP, UPDATE},
     TaskEvent,
 };
 
-pub fn print_task_list(tasks: Vec<TaskInfo>, filters: Vec<String>) -> Result<()> {
-    let mut tasks = tasks;
+/// Apply the given filters and sort the tasks by rank, highest first.
+fn filter_and_sort(mut tasks: Vec<TaskInfo>, filters: Vec<String>) -> Vec<TaskInfo> {
+    for filter in filters {
+        apply_filter(&mut tasks, &filter);
+    }
+
+    tasks.sort_by(|a, b| cmp_rank(a.rank, b.rank));
+    tasks
+}
+
+/// A page of a task list, taken once the tasks are filtered and sorted so
+/// the highest ranked tasks come first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Page {
+    /// Number of tasks skipped
+    pub offset: usize,
+    /// Maximum number of tasks shown, all of them when unset
+    pub limit: Option<usize>,
+}
+
+impl Page {
+    /// Page from the `--limit`, `--offset` and `--page` options. Pages are
+    /// numbered from 1 and are `limit` tasks long.
+    pub fn new(limit: Option<usize>, offset: usize, page: Option<usize>) -> Self {
+        let offset = match (page, limit) {
+            (Some(page), Some(limit)) => page.saturating_sub(1) * limit,
+            _ => offset,
+        };
+        Self { offset, limit }
+    }
+
+    /// Whether the list is cut at all.
+    fn is_paged(&self) -> bool {
+        self.offset > 0 || self.limit.is_some()
+    }
+
+    fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+        items.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect()
+    }
+
+    /// Footer telling which of the `total` tasks are shown.
+    fn footer(&self, total: usize) -> String {
+        let first = self.offset.min(total);
+        let last = match self.limit {
+            Some(limit) => (first + limit).min(total),
+            None => total,
+        };
+        if first == last {
+            format!("showing none of {}", total)
+        } else {
+            format!("showing {}–{} of {}", first + 1, last, total)
+        }
+    }
+}
+
+pub fn print_task_list(
+    tasks: Vec<TaskInfo>,
+    filters: Vec<String>,
+    show_tags: bool,
+    page: Page,
+) -> Result<()> {
+    let tasks = filter_and_sort(tasks, filters);
+    let total = tasks.len();
+    let tasks = page.apply(tasks);
 
     let mut table = Table::new();
     table.set_format(
@@ -25,14 +91,13 @@ pub fn print_task_list(tasks: Vec<TaskInfo>, filters: Vec<String>) -> Result<()>
             .separators(&[LinePosition::Title], LineSeparator::new('-', ' ', ' ', ' '))
             .build(),
     );
-    table.set_titles(row!["ID", "Title", "Project", "Assigned", "Due", "Rank"]);
-
-    for filter in filters {
-        apply_filter(&mut tasks, &filter);
+    let mut titles = row!["ID", "Title", "Project", "Assigned", "Due", "Rank"];
+    if show_tags {
+        titles.insert_cell(4, cell!("Tags"));
     }
+    table.set_titles(titles);
 
-    tasks.sort_by(|a, b| b.rank.partial_cmp(&a.rank).unwrap());
-
+    let now = Timestamp::current_time().0;
     let mut min_rank = 0.0;
     let mut max_rank = 0.0;
 
@@ -45,23 +110,30 @@ pub fn print_task_list(tasks: Vec<TaskInfo>, filters: Vec<String>) -> Result<()>
     }
 
     for task in tasks {
-        let state = task.events.last().unwrap_or(&TaskEvent::default()).action.clone();
+        let state = task.state();
 
         let (max_style, min_style, mid_style, gen_style) = if state == "open" {
             ("bFC", "Fb", "Fc", "")
+        } else if state == BLOCKED {
+            ("bFr", "bFr", "bFr", "Fr")
         } else {
             ("iFYBd", "iFYBd", "iFYBd", "iFYBd")
         };
 
         let rank = task.rank.to_string();
+        let title = match task.checklist_progress() {
+            Some(progress) => format!("{} [{}]", task.title, progress),
+            None => task.title.clone(),
+        };
+        let due_style = if task.is_overdue(now) { "bFr" } else { gen_style };
 
-        table.add_row(Row::new(vec![
-            Cell::new(&task.id.to_string()).style_spec(gen_style),
-            Cell::new(&task.title).style_spec(gen_style),
+        let mut row = Row::new(vec![
+            Cell::new(&task.display_id()).style_spec(gen_style),
+            Cell::new(&title).style_spec(gen_style),
             Cell::new(&task.project.join(", ")).style_spec(gen_style),
             Cell::new(&task.assign.join(", ")).style_spec(gen_style),
             Cell::new(&timestamp_to_date(task.due.unwrap_or(0), DateFormat::Date))
-                .style_spec(gen_style),
+                .style_spec(due_style),
             if task.rank == max_rank {
                 Cell::new(&rank).style_spec(max_style)
             } else if task.rank == min_rank {
@@ -69,30 +141,142 @@ pub fn print_task_list(tasks: Vec<TaskInfo>, filters: Vec<String>) -> Result<()>
             } else {
                 Cell::new(&rank).style_spec(mid_style)
             },
-        ]));
+        ]);
+        if show_tags {
+            row.insert_cell(4, Cell::new(&task.tags.join(", ")).style_spec(gen_style));
+        }
+        table.add_row(row);
     }
 
     table.printstd();
+    if page.is_paged() {
+        println!("{}", page.footer(total));
+    }
+    Ok(())
+}
+
+/// Task states shown as board columns, with their column titles.
+const BOARD_COLUMNS: [(&str, &str); 4] =
+    [("open", "Open"), (BLOCKED, "Blocked"), ("pause", "Paused"), ("stop", "Done")];
+
+/// Print the tasks as a kanban-style board, with a column per state.
+pub fn print_task_board(tasks: Vec<TaskInfo>, filters: Vec<String>) -> Result<()> {
+    let tasks = filter_and_sort(tasks, filters);
+
+    let mut board = Table::new();
+    board.set_format(
+        FormatBuilder::new()
+            .column_separator('|')
+            .padding(1, 1)
+            .separators(&[LinePosition::Title], LineSeparator::new('-', '+', '+', '+'))
+            .build(),
+    );
+    board.set_titles(Row::new(
+        BOARD_COLUMNS.iter().map(|(_, title)| Cell::new(title).style_spec("bc")).collect(),
+    ));
+
+    let columns = BOARD_COLUMNS
+        .iter()
+        .map(|(state, _)| {
+            let mut column = Table::new();
+            column.set_format(*FORMAT_NO_COLSEP);
+            column.set_titles(row!["ID", "Title", "Rank"]);
+            for task in tasks.iter().filter(|task| task.state() == *state) {
+                column.add_row(row![task.display_id(), task.title, task.rank]);
+            }
+            Cell::new(&column.to_string())
+        })
+        .collect();
+    board.add_row(Row::new(columns));
+
+    board.printstd();
     Ok(())
 }
 
-pub fn print_task_info(taskinfo: TaskInfo) -> Result<()> {
-    let current_state = &taskinfo.events.last().unwrap_or(&TaskEvent::default()).action.clone();
+/// Print the task list as delimited rows (CSV, TSV) with a header,
+/// suitable for piping into other tools.
+pub fn print_task_list_delimited(
+    tasks: Vec<TaskInfo>,
+    filters: Vec<String>,
+    delimiter: char,
+    show_tags: bool,
+    page: Page,
+) -> Result<()> {
+    let tasks = page.apply(filter_and_sort(tasks, filters));
+    let sep = delimiter.to_string();
+
+    let mut header = vec!["ID", "Title", "Project", "Assigned", "Due", "Rank"];
+    if show_tags {
+        header.insert(4, "Tags");
+    }
+    println!("{}", header.join(&sep));
+
+    for task in tasks {
+        let due = match task.due {
+            Some(due) => timestamp_to_date(due, DateFormat::Date),
+            None => String::new(),
+        };
+
+        let mut fields = vec![
+            task.id.to_string(),
+            task.title,
+            task.project.join(","),
+            task.assign.join(","),
+            due,
+            task.rank.to_string(),
+        ];
+        if show_tags {
+            fields.insert(4, task.tags.join(","));
+        }
+
+        let row: Vec<String> = fields.iter().map(|f| quote_field(f, delimiter)).collect();
+        println!("{}", row.join(&sep));
+    }
+
+    Ok(())
+}
+
+/// Quote a field if it contains the delimiter, a double quote or a line
+/// break. Inner double quotes are escaped by doubling them.
+fn quote_field(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn print_task_info(taskinfo: TaskInfo, markdown: bool) -> Result<()> {
+    let current_state = &match taskinfo.block_reason() {
+        Some(reason) => format!("{} ({})", BLOCKED, reason),
+        None => taskinfo.state(),
+    };
     let due = timestamp_to_date(taskinfo.due.unwrap_or(0), DateFormat::Date);
     let created_at = timestamp_to_date(taskinfo.created_at, DateFormat::DateTime);
+    let id = match &taskinfo.project_id {
+        Some(project_id) => format!("{} ({})", taskinfo.id, project_id),
+        None => taskinfo.id.to_string(),
+    };
+
+    let desc = if markdown { render_markdown(&taskinfo.desc) } else { taskinfo.desc.clone() };
 
     let mut table = table!(
         [Bd => "ref_id", &taskinfo.ref_id],
-        ["id", &taskinfo.id.to_string()],
+        ["id", id],
         [Bd => "owner", &taskinfo.owner],
         ["title", &taskinfo.title],
-        [Bd => "desc", &taskinfo.desc.to_string()],
+        [Bd => "desc", desc],
         ["assign", taskinfo.assign.join(", ")],
         [Bd => "project", taskinfo.project.join(", ")],
-        ["due", due],
-        [Bd => "rank", &taskinfo.rank.to_string()],
+        ["tags", taskinfo.tags.join(", ")],
+        [Bd => "due", due],
+        ["rank", &taskinfo.rank.to_string()],
+        [Bd => "estimate", taskinfo.estimate.map(|e| e.to_string()).unwrap_or_default()],
         ["created_at", created_at],
-        [Bd => "current_state", current_state]);
+        [Bd => "current_state", current_state],
+        ["links", taskinfo.links.join("\n")],
+        [Bd => "checklist", checklist_as_string(&taskinfo.checklist)],
+        ["git", taskinfo.git_ref.as_ref().map(|r| r.to_string()).unwrap_or_default()]);
 
     table.set_format(
         FormatBuilder::new()
@@ -111,9 +295,27 @@ pub fn print_task_info(taskinfo: TaskInfo) -> Result<()> {
     Ok(())
 }
 
-pub fn comments_as_string(comments: Vec<Comment>) -> String {
+/// Checklist items numbered from 1, as taken by `tau check toggle`, under
+/// the completion of the checklist.
+fn checklist_as_string(checklist: &[(String, bool)]) -> String {
+    if checklist.is_empty() {
+        return String::new()
+    }
+
+    let done = checklist.iter().filter(|(_, done)| *done).count();
+    let mut lines = vec![format!("{}/{} done", done, checklist.len())];
+    for (i, (item, done)) in checklist.iter().enumerate() {
+        lines.push(format!("[{}] {}. {}", if *done { "x" } else { " " }, i + 1, item));
+    }
+    lines.join("\n")
+}
+
+pub fn comments_as_string(comments: Vec<Comment>, markdown: bool) -> String {
     let mut comments_str = String::new();
-    for comment in comments {
+    for mut comment in comments {
+        if markdown {
+            comment.content = render_markdown(&comment.content);
+        }
         comments_str.push_str(&format!("{}\n", comment));
     }
     comments_str.pop();
@@ -123,7 +325,120 @@ pub fn comments_as_string(comments: Vec<Comment>) -> String {
 pub fn events_as_string(events: Vec<TaskEvent>) -> String {
     let mut events_str = String::new();
     for event in events {
-        events_str.push_str(&format!("State changed to {} at {}\n", event.action, event.timestamp));
+        let line = match event.action.as_str() {
+            TIMER_START => format!("Timer started at {}", event.timestamp),
+            TIMER_STOP => format!("Timer stopped at {}", event.timestamp),
+            UPDATE => format!("Updated at {}", event.timestamp),
+            _ => format!("State changed to {} at {}", event.action, event.timestamp),
+        };
+        events_str.push_str(&line);
+        if let Some(author) = &event.author {
+            events_str.push_str(&format!(" by {}", author));
+        }
+        if event.action == BLOCKED {
+            events_str.push_str(&format!(": {}", event.reason.unwrap_or_default()));
+        } else if let Some(reason) = event.reason {
+            // e.g. why taud auto-paused the task
+            events_str.push_str(&format!(": {}", reason));
+        }
+        events_str.push('\n');
     }
     events_str
 }
+
+/// Format a duration in seconds as hours, minutes and seconds.
+pub fn duration_as_string(secs: i64) -> String {
+    format!("{}h {:02}m {:02}s", secs / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_field() {
+        assert_eq!(quote_field("darkfi", ','), "darkfi");
+        assert_eq!(quote_field("darkfi,tau", ','), "\"darkfi,tau\"");
+        assert_eq!(quote_field("darkfi,tau", '\t'), "darkfi,tau");
+        assert_eq!(quote_field("a\tb", '\t'), "\"a\tb\"");
+        assert_eq!(quote_field("say \"hi\"", ','), "\"say \"\"hi\"\"\"");
+        assert_eq!(quote_field("line\nbreak", ','), "\"line\nbreak\"");
+    }
+
+    #[test]
+    fn test_filter_and_sort_nan_rank() {
+        let task = |id: u32, rank: f32| TaskInfo { rank, ..TaskInfo::with_events(id, &[]) };
+        let tasks = vec![task(1, 1.0), task(2, f32::NAN), task(3, 3.0), task(4, -2.0)];
+        let ids: Vec<u32> = filter_and_sort(tasks, vec![]).iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![3, 1, 4, 2]);
+    }
+
+    #[test]
+    fn test_events_as_string() {
+        let timestamp = darkfi::util::Timestamp(1650000000);
+        let event = |action: &str, reason: Option<&str>, author: Option<&str>| TaskEvent {
+            action: action.into(),
+            timestamp,
+            reason: reason.map(String::from),
+            author: author.map(String::from),
+        };
+        let events = vec![
+            event(BLOCKED, Some("waiting for review"), Some("dark")),
+            event(UPDATE, None, Some("dark")),
+            event("open", None, None),
+            event("pause", Some("auto-paused, no activity for 14 days"), Some("taud")),
+        ];
+        assert_eq!(
+            events_as_string(events),
+            format!(
+                "State changed to blocked at {0} by dark: waiting for review\n\
+                 Updated at {0} by dark\n\
+                 State changed to open at {0}\n\
+                 State changed to pause at {0} by taud: auto-paused, no activity for 14 days\n",
+                timestamp
+            )
+        );
+    }
+
+    #[test]
+    fn test_page() {
+        let items: Vec<u32> = (1..=45).collect();
+
+        let page = Page::default();
+        assert!(!page.is_paged());
+        assert_eq!(page.apply(items.clone()).len(), 45);
+        assert_eq!(page.footer(45), "showing 1–45 of 45");
+
+        let page = Page::new(Some(20), 0, None);
+        assert_eq!(page.apply(items.clone()), (1..=20).collect::<Vec<u32>>());
+        assert_eq!(page.footer(45), "showing 1–20 of 45");
+
+        let page = Page::new(Some(20), 0, Some(3));
+        assert_eq!(page, Page { offset: 40, limit: Some(20) });
+        assert_eq!(page.apply(items.clone()), (41..=45).collect::<Vec<u32>>());
+        assert_eq!(page.footer(45), "showing 41–45 of 45");
+
+        let page = Page::new(None, 10, None);
+        assert!(page.is_paged());
+        assert_eq!(page.apply(items.clone()).len(), 35);
+        assert_eq!(page.footer(45), "showing 11–45 of 45");
+
+        let page = Page::new(Some(20), 50, None);
+        assert!(page.apply(items).is_empty());
+        assert_eq!(page.footer(45), "showing none of 45");
+    }
+
+    #[test]
+    fn test_checklist_as_string() {
+        assert_eq!(checklist_as_string(&[]), "");
+        let checklist = vec![("spec".to_string(), true), ("code".to_string(), false)];
+        assert_eq!(checklist_as_string(&checklist), "1/2 done\n[x] 1. spec\n[ ] 2. code");
+    }
+
+    #[test]
+    fn test_duration_as_string() {
+        assert_eq!(duration_as_string(0), "0h 00m 00s");
+        assert_eq!(duration_as_string(3725), "1h 02m 05s");
+        assert_eq!(duration_as_string(90000), "25h 00m 00s");
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use darkfi::{util::cli::Config, Result};
+
+use crate::task_info::TaskInfo;
+
+/// A task was saved for the first time.
+pub const CREATED: &str = "created";
+/// The state of a task changed.
+pub const STATE: &str = "state";
+/// Any field of an existing task changed, including its state.
+pub const UPDATED: &str = "updated";
+
+const EVENTS: [&str; 3] = [CREATED, STATE, UPDATED];
+
+/// A shell command run on task events, configured in `taud_config.toml`
+/// as a `[[hooks]]` table.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Hook {
+    /// Events triggering the hook
+    pub events: Vec<String>,
+    /// Command run with `sh -c`. The task is given as JSON on stdin and
+    /// its fields in `TAU_*` environment variables.
+    pub command: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub hooks: Vec<Hook>,
+}
+
+impl HooksConfig {
+    /// Load the hooks from the config file, warning about unknown events.
+    pub fn load(path: PathBuf) -> Result<Vec<Hook>> {
+        let hooks = Config::<Self>::load(path)?.hooks;
+
+        for hook in hooks.iter() {
+            for event in hook.events.iter().filter(|ev| !EVENTS.contains(&ev.as_str())) {
+                warn!(
+                    target: "tau",
+                    "unknown hook event \"{}\", must be one of: {:?}", event, EVENTS
+                );
+            }
+        }
+
+        Ok(hooks)
+    }
+}
+
+/// Events of saving `after` over `before`, the task stored until now.
+pub fn task_events(before: Option<&TaskInfo>, after: &TaskInfo) -> Vec<&'static str> {
+    match before {
+        None => vec![CREATED],
+        Some(before) if before == after => vec![],
+        Some(before) if before.get_state() != after.get_state() => vec![STATE, UPDATED],
+        Some(_) => vec![UPDATED],
+    }
+}
+
+/// Environment variables describing `task` to a hook run on `event`:
+/// `TAU_EVENT`, `TAU_STATE` and one `TAU_<FIELD>` per task field but the
+/// events and comments, with lists joined by commas.
+fn hook_env(event: &str, task: &TaskInfo) -> Vec<(String, String)> {
+    let mut env = vec![
+        ("TAU_EVENT".to_string(), event.to_string()),
+        ("TAU_STATE".to_string(), task.get_state()),
+    ];
+
+    if let Ok(Value::Object(fields)) = serde_json::to_value(task) {
+        // Events and comments are only given in the JSON
+        for (name, value) in fields.into_iter().filter(|(n, _)| n != "events" && n != "comments") {
+            let value = match value {
+                Value::Array(items) => items
+                    .iter()
+                    .map(|i| i.as_str().map_or_else(|| i.to_string(), |s| s.to_string()))
+                    .collect::<Vec<String>>()
+                    .join(","),
+                Value::String(s) => s,
+                Value::Null => String::new(),
+                value => value.to_string(),
+            };
+            env.push((format!("TAU_{}", name.to_uppercase()), value));
+        }
+    }
+
+    env
+}
+
+/// Run the hooks configured for the events of saving `after`, in the
+/// background. Failures are logged, they never block saving the task.
+pub fn run_hooks(hooks: &[Hook], events: &[&str], after: &TaskInfo) {
+    for event in events {
+        for hook in hooks.iter().filter(|h| h.events.iter().any(|e| e == event)) {
+            let command = hook.command.clone();
+            let env = hook_env(event, after);
+            let json = match serde_json::to_vec(after) {
+                Ok(json) => json,
+                Err(e) => {
+                    error!(target: "tau", "hook \"{}\": unable to encode the task: {}", command, e);
+                    continue
+                }
+            };
+
+            std::thread::spawn(move || {
+                if let Err(e) = run_command(&command, env, &json) {
+                    error!(target: "tau", "hook \"{}\" failed: {}", command, e);
+                }
+            });
+        }
+    }
+}
+
+fn run_command(command: &str, env: Vec<(String, String)>, json: &[u8]) -> std::io::Result<()> {
+    debug!(target: "tau", "run hook \"{}\"", command);
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    // The command may not read its stdin at all
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(json).ok();
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::other(status.to_string()))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use super::*;
+    use crate::error::TaudResult;
+
+    #[test]
+    fn events_and_env() -> TaudResult<()> {
+        let path = "/tmp/test_tau_hooks";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let task = TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 1.5, &dataset_path)?;
+        assert_eq!(task_events(None, &task), vec![CREATED]);
+        assert!(task_events(Some(&task), &task).is_empty());
+
+        let mut updated = task.clone();
+        updated.set_project(&["tau".into(), "raft".into()]);
+        assert_eq!(task_events(Some(&task), &updated), vec![UPDATED]);
+
+        let mut paused = updated.clone();
+        paused.set_state("pause", "NICKNAME");
+        assert_eq!(task_events(Some(&updated), &paused), vec![STATE, UPDATED]);
+
+        let env = hook_env(STATE, &paused);
+        let var = |name: &str| env.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+        assert_eq!(var("TAU_EVENT"), Some(STATE));
+        assert_eq!(var("TAU_STATE"), Some("pause"));
+        assert_eq!(var("TAU_TITLE"), Some("test_title"));
+        assert_eq!(var("TAU_PROJECT"), Some("tau,raft"));
+        assert_eq!(var("TAU_ASSIGN"), Some(""));
+        assert_eq!(var("TAU_DUE"), Some(""));
+        assert_eq!(var("TAU_RANK"), Some("1.5"));
+        assert_eq!(var("TAU_EVENTS"), None);
+        assert_eq!(var("TAU_COMMENTS"), None);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use async_std::sync::{Arc, Mutex};
 use std::path::PathBuf;
 
 use async_trait::async_trait;
@@ -6,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 use darkfi::{
+    raft::{ApplyStatus, RoleChange},
     rpc::{
         jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResult},
         server::RequestHandler,
@@ -17,13 +19,18 @@ use darkfi::{
 use crate::{
     error::{to_json_result, TaudError, TaudResult},
     month_tasks::MonthTasks,
-    task_info::{Comment, TaskInfo},
+    task_info::{Comment, TaskInfo, BLOCKED, STATES, TRASHED},
+    util::find_free_id,
+    EncryptedTask,
 };
 
 pub struct JsonRpcInterface {
     dataset_path: PathBuf,
     notify_queue_sender: async_channel::Sender<Option<TaskInfo>>,
     nickname: String,
+    raft_status: Arc<Mutex<RoleChange>>,
+    apply_status: ApplyStatus<EncryptedTask>,
+    project_ids: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -34,6 +41,12 @@ struct BaseTaskInfo {
     project: Vec<String>,
     due: Option<Timestamp>,
     rank: Option<f32>,
+    // older clients don't send tags
+    #[serde(default)]
+    tags: Vec<String>,
+    // nor estimates
+    #[serde(default)]
+    estimate: Option<f32>,
 }
 
 // TODO: Make more like RPC in darkfid, this implies the method categories,
@@ -57,7 +70,21 @@ impl RequestHandler for JsonRpcInterface {
             Some("update") => self.update(params).await,
             Some("set_state") => self.set_state(params).await,
             Some("set_comment") => self.set_comment(params).await,
+            Some("remove_comment") => self.remove_comment(params).await,
+            Some("attach") => self.attach(params).await,
+            Some("check_add") => self.check_add(params).await,
+            Some("check_toggle") => self.check_toggle(params).await,
+            Some("set_git_ref") => self.set_git_ref(params).await,
+            Some("block") => self.block(params).await,
+            Some("unblock") => self.unblock(params).await,
+            Some("start_timer") => self.start_timer(params).await,
+            Some("stop_timer") => self.stop_timer(params).await,
             Some("get_task_by_id") => self.get_task_by_id(params).await,
+            Some("get_stop_tasks") => self.get_stop_tasks(params).await,
+            Some("delete") => self.delete(params).await,
+            Some("restore") => self.restore(params).await,
+            Some("get_trash_tasks") => self.get_trash_tasks(params).await,
+            Some("get_raft_status") => self.get_raft_status(params).await,
             Some(_) | None => return JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         };
 
@@ -70,12 +97,15 @@ impl JsonRpcInterface {
         notify_queue_sender: async_channel::Sender<Option<TaskInfo>>,
         dataset_path: PathBuf,
         nickname: String,
+        raft_status: Arc<Mutex<RoleChange>>,
+        apply_status: ApplyStatus<EncryptedTask>,
+        project_ids: bool,
     ) -> Self {
-        Self { notify_queue_sender, dataset_path, nickname }
+        Self { notify_queue_sender, dataset_path, nickname, raft_status, apply_status, project_ids }
     }
 
     // RPCAPI:
-    // Add new task and returns `true` upon success.
+    // Add new task and returns its ID upon success.
     // --> {"jsonrpc": "2.0", "method": "add",
     //      "params":
     //          [{
@@ -84,11 +114,13 @@ impl JsonRpcInterface {
     //          assign: [..],
     //          project: [..],
     //          "due": ..,
-    //          "rank": ..
+    //          "rank": ..,
+    //          "tags": [..],
+    //          "estimate": ..
     //          }],
     //      "id": 1
     //      }
-    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": task_id, "id": 1}
     async fn add(&self, params: &[Value]) -> TaudResult<Value> {
         debug!(target: "tau", "JsonRpc::add() params {:?}", params);
 
@@ -103,10 +135,16 @@ impl JsonRpcInterface {
         )?;
         new_task.set_project(&task.project);
         new_task.set_assign(&task.assign);
+        new_task.set_tags(&task.tags);
+        new_task.set_estimate(task.estimate)?;
+        if self.project_ids {
+            new_task.assign_project_id(&self.dataset_path)?;
+        }
+        let task_id = new_task.get_id();
 
         self.notify_queue_sender.send(Some(new_task)).await.map_err(Error::from)?;
 
-        Ok(json!(true))
+        Ok(json!(task_id))
     }
 
     // RPCAPI:
@@ -143,9 +181,6 @@ impl JsonRpcInterface {
     // --> {"jsonrpc": "2.0", "method": "set_state", "params": [task_id, state], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
     async fn set_state(&self, params: &[Value]) -> TaudResult<Value> {
-        // Allowed states for a task
-        let states = ["stop", "open", "pause"];
-
         debug!(target: "tau", "JsonRpc::set_state() params {:?}", params);
 
         if params.len() != 2 {
@@ -154,14 +189,59 @@ impl JsonRpcInterface {
 
         let state: String = serde_json::from_value(params[1].clone())?;
 
+        if state == BLOCKED {
+            return Err(TaudError::InvalidData("use block to set a task as blocked".into()))
+        }
+        if state == TRASHED {
+            return Err(TaudError::InvalidData("use delete to move a task to the trash".into()))
+        }
+
         let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
 
-        if states.contains(&state.as_str()) {
-            task.set_state(&state);
+        if STATES.contains(&state.as_str()) {
+            task.set_state(&state, &self.nickname);
+        }
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Set a task as blocked for the given reason and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "block", "params": [task_id, reason], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn block(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::block() params {:?}", params);
+
+        if params.len() != 2 {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
         }
 
+        let reason: String = serde_json::from_value(params[1].clone())?;
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.block(&reason, &self.nickname)?;
+
         self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Set a blocked task back to open and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "unblock", "params": [task_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn unblock(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::unblock() params {:?}", params);
+
+        if params.len() != 1 {
+            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        }
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.unblock(&self.nickname)?;
 
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
         Ok(json!(true))
     }
 
@@ -186,7 +266,154 @@ impl JsonRpcInterface {
     }
 
     // RPCAPI:
-    // Get a task by id.
+    // Attach a URL or a file path to a task and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "attach", "params": [task_id, link], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn attach(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::attach() params {:?}", params);
+
+        if params.len() != 2 {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
+        }
+
+        let link: String = serde_json::from_value(params[1].clone())?;
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.add_link(&link)?;
+        task.set_updated(&self.nickname);
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Add an item to the checklist of a task and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "check_add", "params": [task_id, item], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn check_add(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::check_add() params {:?}", params);
+
+        if params.len() != 2 {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
+        }
+
+        let item: String = serde_json::from_value(params[1].clone())?;
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.add_checklist_item(&item)?;
+        task.set_updated(&self.nickname);
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Toggle the checklist item of a task at the given index, starting from 0,
+    // and returns whether the item is now done.
+    // --> {"jsonrpc": "2.0", "method": "check_toggle", "params": [task_id, index], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn check_toggle(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::check_toggle() params {:?}", params);
+
+        if params.len() != 2 {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
+        }
+
+        let index: usize = serde_json::from_value(params[1].clone())?;
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        let done = task.toggle_checklist_item(index)?;
+        task.set_updated(&self.nickname);
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(done))
+    }
+
+    // RPCAPI:
+    // Link a task to a git commit, and to its branch unless it's null, and
+    // returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "set_git_ref", "params": [task_id, branch, commit], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn set_git_ref(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::set_git_ref() params {:?}", params);
+
+        if params.len() != 3 {
+            return Err(TaudError::InvalidData("len of params should be 3".into()))
+        }
+
+        let branch: Option<String> = serde_json::from_value(params[1].clone())?;
+        let commit: String = serde_json::from_value(params[2].clone())?;
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.set_git_ref(branch.as_deref(), &commit)?;
+        task.set_updated(&self.nickname);
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Remove the latest comment with the given content written by this node
+    // from a task, and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "remove_comment", "params": [task_id, comment_content], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn remove_comment(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::remove_comment() params {:?}", params);
+
+        if params.len() != 2 {
+            return Err(TaudError::InvalidData("len of params should be 2".into()))
+        }
+
+        let comment_content: String = serde_json::from_value(params[1].clone())?;
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        if !task.remove_comment(&comment_content, &self.nickname) {
+            return Err(TaudError::InvalidData("comment not found".into()))
+        }
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Start tracking work time on a task and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "start_timer", "params": [task_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn start_timer(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::start_timer() params {:?}", params);
+
+        if params.len() != 1 {
+            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        }
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.start_timer(&self.nickname)?;
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Stop tracking work time on a task and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "stop_timer", "params": [task_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn stop_timer(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::stop_timer() params {:?}", params);
+
+        if params.len() != 1 {
+            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        }
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.stop_timer(&self.nickname)?;
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Get a task by id. Like the other methods taking a task_id, it also
+    // accepts the project ID of the task, e.g. "zk-12".
     // --> {"jsonrpc": "2.0", "method": "get_task_by_id", "params": [task_id], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "task", "id": 1}
     async fn get_task_by_id(&self, params: &[Value]) -> TaudResult<Value> {
@@ -201,17 +428,114 @@ impl JsonRpcInterface {
         Ok(json!(task))
     }
 
-    fn load_task_by_id(&self, task_id: &Value) -> TaudResult<TaskInfo> {
-        let task_id: u64 = serde_json::from_value(task_id.clone())?;
+    // RPCAPI:
+    // Get the stopped tasks, which aren't listed by get_ids.
+    // --> {"jsonrpc": "2.0", "method": "get_stop_tasks", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": ["task", ...], "id": 1}
+    async fn get_stop_tasks(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::get_stop_tasks() params {:?}", params);
+        let tasks = TaskInfo::load_with_state("stop", &self.dataset_path)?;
+        Ok(json!(tasks))
+    }
+
+    // RPCAPI:
+    // Move a task to the trash and returns `true` upon success.
+    // --> {"jsonrpc": "2.0", "method": "delete", "params": [task_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn delete(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::delete() params {:?}", params);
+
+        if params.len() != 1 {
+            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        }
+
+        let mut task: TaskInfo = self.load_task_by_id(&params[0])?;
+        task.trash(&self.nickname)?;
+
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(true))
+    }
+
+    // RPCAPI:
+    // Bring a task back from the trash and returns its ID upon success. It
+    // gets a new ID if another task took it in the meantime.
+    // --> {"jsonrpc": "2.0", "method": "restore", "params": [task_id], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": task_id, "id": 1}
+    async fn restore(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::restore() params {:?}", params);
+
+        if params.len() != 1 {
+            return Err(TaudError::InvalidData("len of params should be 1".into()))
+        }
+
+        let task_id: u64 = serde_json::from_value(params[0].clone())?;
+
+        // IDs are reused, several trashed tasks may share one
+        let mut task = TaskInfo::load_with_state(TRASHED, &self.dataset_path)?
+            .into_iter()
+            .filter(|t| (t.get_id() as u64) == task_id)
+            .max_by_key(|t| t.trashed_at().map(|ts| ts.0))
+            .ok_or(TaudError::InvalidId)?;
+        task.restore(&self.nickname)?;
+
+        let task_ids: Vec<u32> = MonthTasks::load_current_open_tasks(&self.dataset_path)?
+            .into_iter()
+            .map(|t| t.get_id())
+            .collect();
+        if task_ids.contains(&task.get_id()) {
+            task.set_id(find_free_id(&task_ids));
+        }
+        let task_id = task.get_id();
 
+        self.notify_queue_sender.send(Some(task)).await.map_err(Error::from)?;
+        Ok(json!(task_id))
+    }
+
+    // RPCAPI:
+    // Get the tasks in the trash, which aren't listed by get_ids.
+    // --> {"jsonrpc": "2.0", "method": "get_trash_tasks", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": ["task", ...], "id": 1}
+    async fn get_trash_tasks(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::get_trash_tasks() params {:?}", params);
+        let tasks = TaskInfo::load_with_state(TRASHED, &self.dataset_path)?;
+        Ok(json!(tasks))
+    }
+
+    // RPCAPI:
+    // Get the raft role of the node, its term and the leader it knows, if any,
+    // along with the number of commits and how many of them are not saved yet.
+    // --> {"jsonrpc": "2.0", "method": "get_raft_status", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"role": "follower", "term": 3, "leader": "a1b2...", "commit_length": 42, "apply_lag": 0}, "id": 1}
+    async fn get_raft_status(&self, params: &[Value]) -> TaudResult<Value> {
+        debug!(target: "tau", "JsonRpc::get_raft_status() params {:?}", params);
+        let status = self.raft_status.lock().await;
+        Ok(json!({
+            "role": format!("{:?}", status.role).to_lowercase(),
+            "term": status.term,
+            "leader": status.leader.as_ref().map(|id| hex::encode(&id.0)),
+            "commit_length": self.apply_status.commit_length(),
+            "apply_lag": self.apply_status.lag(),
+        }))
+    }
+
+    /// Load an open task by its ID, or by its project ID (e.g. "zk-12").
+    fn load_task_by_id(&self, task_id: &Value) -> TaudResult<TaskInfo> {
         let tasks = MonthTasks::load_current_open_tasks(&self.dataset_path)?;
-        let task = tasks.into_iter().find(|t| (t.get_id() as u64) == task_id);
+
+        let task = match task_id.as_str() {
+            Some(project_id) => tasks.into_iter().find(|t| t.get_project_id() == Some(project_id)),
+            None => {
+                let task_id: u64 = serde_json::from_value(task_id.clone())?;
+                tasks.into_iter().find(|t| (t.get_id() as u64) == task_id)
+            }
+        };
 
         task.ok_or(TaudError::InvalidId)
     }
 
     fn check_params_for_update(&self, task_id: &Value, fields: &Value) -> TaudResult<TaskInfo> {
         let mut task: TaskInfo = self.load_task_by_id(task_id)?;
+        let before = task.clone();
 
         if !fields.is_object() {
             return Err(TaudError::InvalidData("Invalid task's data".into()))
@@ -228,10 +552,10 @@ impl JsonRpcInterface {
         }
 
         if fields.contains_key("desc") {
-            let description = fields.get("description");
-            if let Some(description) = description {
-                let description: String = serde_json::from_value(description.clone())?;
-                task.set_desc(&description);
+            let description = fields.get("desc").unwrap().clone();
+            let description: Option<String> = serde_json::from_value(description)?;
+            if let Some(d) = description {
+                task.set_desc(&d);
             }
         }
 
@@ -245,6 +569,13 @@ impl JsonRpcInterface {
             }
         }
 
+        if let Some(estimate) = fields.get("estimate") {
+            let estimate: Option<f32> = serde_json::from_value(estimate.clone())?;
+            if estimate.is_some() {
+                task.set_estimate(estimate)?;
+            }
+        }
+
         if fields.contains_key("due") {
             let due = fields.get("due").unwrap().clone();
             let due: Option<Option<Timestamp>> = serde_json::from_value(due)?;
@@ -266,9 +597,24 @@ impl JsonRpcInterface {
             let project: Vec<String> = serde_json::from_value(project)?;
             if !project.is_empty() {
                 task.set_project(&project);
+                if self.project_ids {
+                    task.assign_project_id(&self.dataset_path)?;
+                }
             }
         }
 
+        if fields.contains_key("tags") {
+            let tags = fields.get("tags").unwrap().clone();
+            let tags: Vec<String> = serde_json::from_value(tags)?;
+            if !tags.is_empty() {
+                task.set_tags(&tags);
+            }
+        }
+
+        if task != before {
+            task.set_updated(&self.nickname);
+        }
+
         Ok(task)
     }
 }
@@ -1,5 +1,10 @@
 use async_std::sync::{Arc, Mutex};
-use std::{env, fs::create_dir_all};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{create_dir_all, remove_dir_all},
+    path::{Path, PathBuf},
+};
 
 use async_executor::Executor;
 use crypto_box::{aead::Aead, Box, SecretKey, KEY_SIZE};
@@ -7,21 +12,24 @@ use futures::{select, FutureExt};
 use log::{debug, error, info, warn};
 use smol::future;
 use structopt_toml::StructOptToml;
+use zeroize::Zeroizing;
 
 use darkfi::{
     async_daemonize, net,
-    raft::{NetMsg, ProtocolRaft, Raft},
+    raft::{NetMsg, ProtocolRaft, Raft, RaftSettings, Role, RoleChange},
     rpc::server::listen_and_serve,
     util::{
         cli::{get_log_config, get_log_level, spawn_config},
         expand_path,
         path::get_config_path,
-        serial::{deserialize, serialize, SerialDecodable, SerialEncodable},
+        serial::{SerialDecodable, SerialEncodable},
+        sleep, Timestamp,
     },
     Error, Result,
 };
 
 mod error;
+mod hooks;
 mod jsonrpc;
 mod month_tasks;
 mod settings;
@@ -29,13 +37,21 @@ mod task_info;
 mod util;
 
 use crate::{
-    error::TaudResult,
+    error::{TaudError, TaudResult},
+    hooks::{run_hooks, task_events, Hook, HooksConfig},
     jsonrpc::JsonRpcInterface,
+    month_tasks::MonthTasks,
     settings::{Args, CONFIG_FILE, CONFIG_FILE_CONTENTS},
     task_info::TaskInfo,
     util::{load, save},
 };
 
+/// Size of the XSalsa20 nonces of crypto_box
+const NONCE_SIZE: usize = 24;
+
+/// Seconds between two checks for stale tasks, see `stale_after`
+const STALE_CHECK_INTERVAL: u64 = 3600;
+
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
 pub struct EncryptedTask {
     nonce: Vec<u8>,
@@ -52,8 +68,7 @@ fn encrypt_task(
     let msg_box = Box::new(&public_key, secret_key);
 
     let nonce = crypto_box::generate_nonce(rng);
-    let payload = &serialize(task)[..];
-    let payload = msg_box.encrypt(&nonce, payload)?;
+    let payload = msg_box.encrypt(&nonce, &task.to_payload()[..])?;
 
     let nonce = nonce.to_vec();
     Ok(EncryptedTask { nonce, payload })
@@ -64,12 +79,108 @@ fn decrypt_task(encrypt_task: &EncryptedTask, secret_key: &SecretKey) -> TaudRes
     let public_key = secret_key.public_key();
     let msg_box = Box::new(&public_key, secret_key);
 
+    // Converting a nonce of another size panics
     let nonce = encrypt_task.nonce.as_slice();
+    if nonce.len() != NONCE_SIZE {
+        return Err(TaudError::EncryptionError(format!("invalid nonce length: {}", nonce.len())))
+    }
     let decrypted_task = msg_box.decrypt(nonce.into(), &encrypt_task.payload[..])?;
 
-    let task = deserialize(&decrypted_task)?;
+    TaskInfo::from_payload(&decrypted_task)
+}
+
+/// Save `task`, then run the hooks for the changes from the version
+/// stored until now.
+fn save_task(task: &TaskInfo, hooks: &[Hook], datastore_path: &Path) -> TaudResult<()> {
+    let before = TaskInfo::load(&task.ref_id, datastore_path).ok();
+    task.save(datastore_path)?;
+    run_hooks(hooks, &task_events(before.as_ref(), task), task);
+    Ok(())
+}
+
+/// Save the tasks replayed from the raft commits, in commit order, with
+/// the last committed version of each, and rebuild the month tasks from
+/// them alone. Local task files missing from the commits, e.g. for tasks
+/// created while no leader was elected, are kept with a warning. Hooks
+/// don't run for replayed tasks.
+fn save_replayed(replayed: Vec<TaskInfo>, datastore_path: &Path) -> TaudResult<()> {
+    let mut tasks: Vec<TaskInfo> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for task in replayed {
+        match positions.get(&task.ref_id) {
+            Some(&i) => tasks[i] = task,
+            None => {
+                positions.insert(task.ref_id.clone(), tasks.len());
+                tasks.push(task);
+            }
+        }
+    }
+
+    let mut local = TaskInfo::load_all(datastore_path)?;
+    local.retain(|task| !positions.contains_key(&task.ref_id));
+    local.sort_by(|a, b| a.ref_id.cmp(&b.ref_id));
+    for task in local {
+        warn!(target: "tau", "task {} was never committed, keeping the local copy", task.ref_id);
+        tasks.push(task);
+    }
+
+    remove_dir_all(datastore_path.join("month")).map_err(Error::from)?;
+    create_dir_all(datastore_path.join("month")).map_err(Error::from)?;
+    for task in &tasks {
+        task.save(datastore_path)?;
+    }
+
+    Ok(())
+}
+
+/// Rebuild the tasks from the raft commits, so that every node derives
+/// them from the same entries instead of its own task files.
+fn rebuild_tasks(
+    raft: &Raft<EncryptedTask>,
+    secret_key: &SecretKey,
+    datastore_path: &Path,
+) -> TaudResult<()> {
+    let mut replayed = vec![];
+    let commits = raft.replay(|index, encrypted| {
+        match decrypt_task(&encrypted, secret_key) {
+            Ok(task) => replayed.push(task),
+            Err(e) => warn!(target: "tau", "unable to decrypt commit {}: {}", index, e),
+        }
+        Ok(())
+    })?;
+
+    save_replayed(replayed, datastore_path)?;
+    info!(target: "tau", "rebuilt the tasks from {} raft commits", commits);
+    Ok(())
+}
+
+/// Pause the open tasks untouched for `days` days, or only flag them when
+/// `flag_only`. Only the raft leader does it, so nodes don't each record an
+/// event, and the changes are shared like the ones made over RPC.
+async fn mark_stale_tasks(
+    days: u64,
+    flag_only: bool,
+    author: String,
+    datastore_path: PathBuf,
+    raft_status: Arc<Mutex<RoleChange>>,
+    sender: async_channel::Sender<Option<TaskInfo>>,
+) -> TaudResult<()> {
+    loop {
+        sleep(STALE_CHECK_INTERVAL).await;
+        if raft_status.lock().await.role != Role::Leader {
+            continue
+        }
 
-    Ok(task)
+        let now = Timestamp::current_time();
+        for mut task in MonthTasks::load_current_open_tasks(&datastore_path)? {
+            if !task.is_stale(days, now) {
+                continue
+            }
+            info!(target: "tau", "task {} has no activity for {} days", task.get_id(), days);
+            task.mark_stale(days, flag_only, &author);
+            sender.send(Some(task)).await.map_err(Error::from)?;
+        }
+    }
 }
 
 async_daemonize!(realmain);
@@ -78,6 +189,8 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
 
     let nickname =
         if settings.nickname.is_some() { settings.nickname } else { env::var("USER").ok() };
+    // The nickname is the author of comments and task events
+    let nickname = nickname.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
 
     if nickname.is_none() {
         error!("Provide a nickname in config file");
@@ -93,11 +206,11 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
     let secret_key = if settings.key_gen {
         info!(target: "tau", "generating a new secret key");
         let secret = SecretKey::generate(&mut rng);
-        let sk_string = hex::encode(secret.as_bytes());
+        let sk_string = Zeroizing::new(hex::encode(secret.as_bytes()));
         save::<String>(&datastore_path.join("secret_key"), &sk_string)?;
         secret
     } else {
-        let loaded_key = load::<String>(&datastore_path.join("secret_key"));
+        let loaded_key = load::<String>(&datastore_path.join("secret_key")).map(Zeroizing::new);
 
         if loaded_key.is_err() {
             error!(
@@ -107,36 +220,97 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
             return Ok(())
         }
 
-        let sk_bytes = hex::decode(loaded_key.unwrap())?;
-        let sk_bytes: [u8; KEY_SIZE] = sk_bytes.as_slice().try_into()?;
-        SecretKey::try_from(sk_bytes)?
+        // Every copy of the key bytes is wiped once the key is built
+        let sk_bytes = Zeroizing::new(hex::decode(loaded_key.unwrap().as_str())?);
+        let sk_bytes: Zeroizing<[u8; KEY_SIZE]> = Zeroizing::new(sk_bytes.as_slice().try_into()?);
+        SecretKey::from(*sk_bytes)
     };
 
+    let net_settings = settings.net;
+
     //
-    // RPC
+    //Raft
     //
+    let datastore_raft = datastore_path.join("tau.db");
+    let mut raft = Raft::<EncryptedTask>::new(
+        net_settings.inbound.clone(),
+        datastore_raft,
+        // Every node of the workspace shares the secret key
+        RaftSettings {
+            cluster_secret: Some(Zeroizing::new(secret_key.as_bytes().to_vec())),
+            election_priority: settings.election_priority,
+            apply_lag_warning: settings.apply_lag_warning,
+            log_batch_size: settings.log_batch_size,
+            ..RaftSettings::default()
+        },
+    )?;
+
+    if let Err(e) = rebuild_tasks(&raft, &secret_key, &datastore_path) {
+        error!(target: "tau", "unable to rebuild the tasks from the raft commits: {}", e);
+        return Ok(())
+    }
 
-    let (rpc_snd, rpc_rcv) = async_channel::unbounded::<Option<TaskInfo>>();
+    match TaskInfo::purge_trash(&datastore_path, settings.trash_retention) {
+        Ok(0) => {}
+        Ok(purged) => info!(target: "tau", "purged {} tasks from the trash", purged),
+        Err(e) => warn!(target: "tau", "unable to purge the trash: {}", e),
+    }
 
-    let rpc_interface =
-        Arc::new(JsonRpcInterface::new(rpc_snd, datastore_path.clone(), nickname.unwrap()));
+    let hooks = HooksConfig::load(get_config_path(settings.config.clone(), CONFIG_FILE)?)?;
 
-    let executor_cloned = executor.clone();
-    let rpc_listener_task =
-        executor_cloned.spawn(listen_and_serve(settings.rpc_listen.clone(), rpc_interface));
+    let raft_proposer = raft.get_proposer();
+    let commits = raft.get_commits();
 
-    let net_settings = settings.net;
+    // The role is logged and kept for the get_raft_status RPC
+    let raft_status = Arc::new(Mutex::new(raft.current_role()));
+    let role_changes = raft.get_role_changes();
+    let raft_status_cloned = raft_status.clone();
+    let role_changes_task = executor.spawn(async move {
+        while let Ok(change) = role_changes.recv().await {
+            info!(
+                target: "tau",
+                "raft role is now {:?} in term {}, leader: {}",
+                change.role,
+                change.term,
+                change.leader.as_ref().map_or("unknown".into(), |id| hex::encode(&id.0))
+            );
+            *raft_status_cloned.lock().await = change;
+        }
+    });
 
     //
-    //Raft
+    // RPC
     //
-    let datastore_raft = datastore_path.join("tau.db");
-    let mut raft = Raft::<EncryptedTask>::new(net_settings.inbound.clone(), datastore_raft)?;
 
-    let raft_sender = raft.get_broadcast();
-    let commits = raft.get_commits();
+    let (rpc_snd, rpc_rcv) = async_channel::unbounded::<Option<TaskInfo>>();
+
+    let nickname = nickname.unwrap();
+    let stale_tasks_task = (settings.stale_after > 0).then(|| {
+        executor.spawn(mark_stale_tasks(
+            settings.stale_after,
+            settings.stale_flag_only,
+            nickname.clone(),
+            datastore_path.clone(),
+            raft_status.clone(),
+            rpc_snd.clone(),
+        ))
+    });
+
+    let rpc_interface = Arc::new(JsonRpcInterface::new(
+        rpc_snd,
+        datastore_path.clone(),
+        nickname,
+        raft_status,
+        raft.apply_status(),
+        settings.project_ids,
+    ));
+
+    let executor_cloned = executor.clone();
+    let rpc_listener_task =
+        executor_cloned.spawn(listen_and_serve(settings.rpc_listen.clone(), rpc_interface));
 
     let datastore_path_cloned = datastore_path.clone();
+    let executor_cloned = executor.clone();
     let recv_update: smol::Task<TaudResult<()>> = executor.spawn(async move {
         info!(target: "tau", "Start initial sync");
         loop {
@@ -146,8 +320,22 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
                     if let Some(tk) = task {
                         info!(target: "tau", "save the received task {:?}", tk);
                         let encrypted_task = encrypt_task(&tk, &secret_key,&mut rng)?;
-                        tk.save(&datastore_path_cloned)?;
-                        raft_sender.send(encrypted_task).await.map_err(Error::from)?;
+                        save_task(&tk, &hooks, &datastore_path_cloned)?;
+                        let committed = raft_proposer.propose(encrypted_task).await?;
+                        let ref_id = tk.ref_id.clone();
+                        executor_cloned
+                            .spawn(async move {
+                                match committed.await {
+                                    Ok(Ok(index)) => {
+                                        debug!(target: "tau", "task {} committed at {}", ref_id, index)
+                                    }
+                                    Ok(Err(e)) => {
+                                        warn!(target: "tau", "task {} may not be shared: {}", ref_id, e)
+                                    }
+                                    Err(_) => {}
+                                }
+                            })
+                            .detach();
                     }
                 }
                 task = commits.recv().fuse() => {
@@ -161,7 +349,7 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
 
                     let task = task.unwrap();
                     info!(target: "tau", "receive update from the commits {:?}", task);
-                    task.save(&datastore_path_cloned)?;
+                    save_task(&task, &hooks, &datastore_path_cloned)?;
                 }
             }
         }
@@ -177,13 +365,23 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
 
     let seen_net_msg = Arc::new(Mutex::new(vec![]));
     let raft_node_id = raft.id.clone();
+    let raft_mac_key = raft.mac_key();
     registry
         .register(net::SESSION_ALL, move |channel, p2p| {
             let raft_node_id = raft_node_id.clone();
             let sender = p2p_send_channel.clone();
             let seen_net_msg_cloned = seen_net_msg.clone();
+            let raft_mac_key = raft_mac_key.clone();
             async move {
-                ProtocolRaft::init(raft_node_id, channel, sender, p2p, seen_net_msg_cloned).await
+                ProtocolRaft::init(
+                    raft_node_id,
+                    channel,
+                    sender,
+                    p2p,
+                    seen_net_msg_cloned,
+                    raft_mac_key,
+                )
+                .await
             }
         })
         .await;
@@ -200,6 +398,10 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
         signal.send(()).await.unwrap();
         rpc_listener_task.cancel().await;
         recv_update.cancel().await;
+        role_changes_task.cancel().await;
+        if let Some(task) = stale_tasks_task {
+            task.cancel().await;
+        }
         p2p_run_task.cancel().await;
     })
     .unwrap();
@@ -209,3 +411,63 @@ async fn realmain(settings: Args, executor: Arc<Executor<'_>>) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::remove_dir_all;
+
+    use super::*;
+
+    #[test]
+    fn decrypt_invalid_nonce() -> TaudResult<()> {
+        let path = Path::new("/tmp/test_tau_nonce");
+        remove_dir_all(path).ok();
+        create_dir_all(path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut rng = crypto_box::rand_core::OsRng;
+        let secret_key = SecretKey::generate(&mut rng);
+        let task = TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, path)?;
+        let mut encrypted = encrypt_task(&task, &secret_key, &mut rng)?;
+
+        encrypted.nonce.pop();
+        assert!(decrypt_task(&encrypted, &secret_key).is_err());
+        encrypted.nonce = vec![0; NONCE_SIZE + 1];
+        assert!(decrypt_task(&encrypted, &secret_key).is_err());
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn save_replayed_tasks() -> TaudResult<()> {
+        let path = Path::new("/tmp/test_tau_replay");
+        remove_dir_all(path).ok();
+        create_dir_all(path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut committed = TaskInfo::new("committed", "", "NICKNAME", None, 0.0, path)?;
+        committed.save(path)?;
+        let first = committed.clone();
+        committed.set_title("committed again");
+        let mut stopped = TaskInfo::new("stopped", "", "NICKNAME", None, 0.0, path)?;
+        stopped.save(path)?;
+        let local = TaskInfo::new("local", "", "NICKNAME", None, 0.0, path)?;
+        local.save(path)?;
+
+        // The local copy of a committed task differs from the commits
+        let mut diverged = stopped.clone();
+        diverged.set_title("diverged");
+        diverged.save(path)?;
+        stopped.set_state("stop", "NICKNAME");
+
+        save_replayed(vec![first, stopped.clone(), committed.clone()], path)?;
+
+        assert_eq!(TaskInfo::load(&committed.ref_id, path)?, committed);
+        assert_eq!(TaskInfo::load(&stopped.ref_id, path)?, stopped);
+        let open = MonthTasks::load_current_open_tasks(path)?;
+        assert_eq!(open, vec![committed, local]);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+}
@@ -122,7 +122,7 @@ impl MonthTasks {
 
     pub fn load_current_open_tasks(dataset_path: &Path) -> TaudResult<Vec<TaskInfo>> {
         let mt = Self::load_or_create(None, dataset_path)?;
-        Ok(mt.objects(dataset_path)?.into_iter().filter(|t| t.get_state() != "stop").collect())
+        Ok(mt.objects(dataset_path)?.into_iter().filter(|t| t.is_active()).collect())
     }
 }
 
@@ -27,10 +27,34 @@ pub struct Args {
     /// Increase verbosity
     #[structopt(short, parse(from_occurrences))]
     pub verbose: u8,
+    /// Log to the given file as well (default is $DARKFI_LOG or /tmp/darkfi.log)
+    #[structopt(long)]
+    pub log_file: Option<String>,
     /// Generate a new secret key
     #[structopt(long)]
     pub key_gen: bool,
     /// Current display name    
     #[structopt(long)]
     pub nickname: Option<String>,
+    /// Raft election priority (0-10), nodes with a higher one tend to lead
+    #[structopt(long, default_value = "10")]
+    pub election_priority: u8,
+    /// Warn when saving tasks falls more than this many raft commits behind
+    #[structopt(long, default_value = "128")]
+    pub apply_lag_warning: u64,
+    /// Most raft log entries sent to a node at once while it catches up
+    #[structopt(long, default_value = "256")]
+    pub log_batch_size: usize,
+    /// Days deleted tasks are kept in the trash, purged on startup (0 keeps them forever)
+    #[structopt(long, default_value = "30")]
+    pub trash_retention: u64,
+    /// Also number new tasks within their first project, e.g. zk-12
+    #[structopt(long)]
+    pub project_ids: bool,
+    /// Days without activity after which open tasks are paused (0 never pauses them)
+    #[structopt(long, default_value = "0")]
+    pub stale_after: u64,
+    /// Tag stale tasks as "stale" instead of pausing them
+    #[structopt(long)]
+    pub stale_flag_only: bool,
 }
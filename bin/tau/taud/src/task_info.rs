@@ -1,13 +1,16 @@
 use std::{
-    io,
+    fs, io,
     path::{Path, PathBuf},
 };
 
 use log::debug;
 use serde::{Deserialize, Serialize};
+use url::Url;
 
 use darkfi::util::{
-    serial::{Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt},
+    serial::{
+        deserialize, serialize, Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt,
+    },
     Timestamp,
 };
 
@@ -17,18 +20,140 @@ use crate::{
     util::{find_free_id, load, random_ref_id, save},
 };
 
+/// Event actions marking the start and the end of a work interval on a
+/// task. Unlike the other actions, they don't change the task's state.
+const TIMER_START: &str = "timer_start";
+const TIMER_STOP: &str = "timer_stop";
+
+fn is_timer_action(action: &str) -> bool {
+    action == TIMER_START || action == TIMER_STOP
+}
+
+/// Event action recording a change of the task's fields, which doesn't
+/// change its state either.
+pub const UPDATE: &str = "update";
+
+fn changes_state(action: &str) -> bool {
+    !is_timer_action(action) && action != UPDATE
+}
+
+/// States a task can be set to. A task can only be blocked with a reason,
+/// see [`TaskInfo::block`].
+pub const STATES: [&str; 4] = ["stop", "open", "pause", BLOCKED];
+pub const BLOCKED: &str = "blocked";
+
+/// State of a deleted task, kept until it's restored or purged, see
+/// [`TaskInfo::trash`].
+pub const TRASHED: &str = "trash";
+
+const SECS_PER_DAY: i64 = 24 * 3600;
+
+/// Tag given to stale tasks by daemons flagging them instead of pausing
+/// them, see [`TaskInfo::mark_stale`].
+pub const STALE_TAG: &str = "stale";
+
+/// First byte of the versioned payloads, see [`TaskInfo::to_payload`].
+/// Unversioned payloads start with the length of the ref_id as a VarInt,
+/// which only starts with 0xff past u32::MAX.
+const PAYLOAD_MARKER: u8 = 0xff;
+
+/// Version of the TaskInfo and TaskEvent encoding, to bump whenever their
+/// fields change, keeping a way to decode the older versions.
+const PAYLOAD_VERSION: u8 = 1;
+
 #[derive(Clone, Debug, Serialize, Deserialize, SerialEncodable, SerialDecodable, PartialEq)]
 struct TaskEvent {
     action: String,
     timestamp: Timestamp,
+    // why the task was blocked or auto-paused, events saved before it was
+    // added have none
+    #[serde(default)]
+    reason: Option<String>,
+    // who did it, events saved before it was added have none
+    #[serde(default)]
+    author: Option<String>,
 }
 
 impl TaskEvent {
-    fn new(action: String) -> Self {
-        Self { action, timestamp: Timestamp::current_time() }
+    fn new(action: String, author: &str) -> Self {
+        // A blank author is the same as an unknown one
+        let author = Some(author.trim()).filter(|a| !a.is_empty()).map(String::from);
+        Self { action, timestamp: Timestamp::current_time(), reason: None, author }
+    }
+}
+
+/// TaskEvent as encoded in the unversioned payloads.
+#[derive(SerialEncodable, SerialDecodable)]
+struct LegacyTaskEvent {
+    action: String,
+    timestamp: Timestamp,
+}
+
+struct LegacyTaskEvents(Vec<LegacyTaskEvent>);
+
+/// TaskInfo as encoded in the unversioned payloads, before any of the
+/// fields after `comments` were added.
+#[derive(SerialEncodable, SerialDecodable)]
+struct LegacyTaskInfo {
+    ref_id: String,
+    id: u32,
+    title: String,
+    desc: String,
+    owner: String,
+    assign: TaskAssigns,
+    project: TaskProjects,
+    due: Option<Timestamp>,
+    rank: f32,
+    created_at: Timestamp,
+    events: LegacyTaskEvents,
+    comments: TaskComments,
+}
+
+impl From<LegacyTaskInfo> for TaskInfo {
+    fn from(task: LegacyTaskInfo) -> Self {
+        let events = task
+            .events
+            .0
+            .into_iter()
+            .map(|ev| TaskEvent {
+                action: ev.action,
+                timestamp: ev.timestamp,
+                reason: None,
+                author: None,
+            })
+            .collect();
+
+        Self {
+            ref_id: task.ref_id,
+            id: task.id,
+            title: task.title,
+            desc: task.desc,
+            owner: task.owner,
+            assign: task.assign,
+            project: task.project,
+            due: task.due,
+            rank: task.rank,
+            created_at: task.created_at,
+            events: TaskEvents(events),
+            comments: task.comments,
+            links: TaskLinks::default(),
+            project_id: None,
+            tags: TaskTags::default(),
+            checklist: TaskChecklist::default(),
+            git_ref: None,
+            estimate: None,
+        }
     }
 }
 
+/// Git branch and commit a task is worked on, see [`TaskInfo::set_git_ref`].
+#[derive(Clone, Debug, Serialize, Deserialize, SerialEncodable, SerialDecodable, PartialEq)]
+pub struct GitRef {
+    // none for a detached HEAD
+    branch: Option<String>,
+    commit: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, SerialDecodable, SerialEncodable, PartialEq)]
 pub struct Comment {
     content: String,
@@ -54,6 +179,12 @@ pub struct TaskComments(Vec<Comment>);
 pub struct TaskProjects(Vec<String>);
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct TaskAssigns(Vec<String>);
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct TaskLinks(Vec<String>);
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct TaskTags(Vec<String>);
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct TaskChecklist(Vec<(String, bool)>);
 
 #[derive(Clone, Debug, Serialize, Deserialize, SerialEncodable, SerialDecodable, PartialEq)]
 pub struct TaskInfo {
@@ -69,6 +200,29 @@ pub struct TaskInfo {
     created_at: Timestamp,
     events: TaskEvents,
     comments: TaskComments,
+    // tasks saved before links were added have none
+    #[serde(default)]
+    links: TaskLinks,
+    // numbered within the first project (e.g. zk-12) by the daemons
+    // started with project_ids
+    #[serde(default)]
+    project_id: Option<String>,
+    // freeform labels, unrelated to the projects and the assignees, tasks
+    // saved before tags were added have none
+    #[serde(default)]
+    tags: TaskTags,
+    // items to tick off, with whether they're done, tasks saved before
+    // checklists were added have none
+    #[serde(default)]
+    checklist: TaskChecklist,
+    // branch and commit the task is linked to, tasks saved before git refs
+    // were added have none
+    #[serde(default)]
+    git_ref: Option<GitRef>,
+    // effort left, in hours or points, tasks saved before estimates were
+    // added have none
+    #[serde(default)]
+    estimate: Option<f32>,
 }
 
 impl TaskInfo {
@@ -109,24 +263,94 @@ impl TaskInfo {
             created_at,
             comments: TaskComments(vec![]),
             events: TaskEvents(vec![]),
+            links: TaskLinks(vec![]),
+            project_id: None,
+            tags: TaskTags(vec![]),
+            checklist: TaskChecklist(vec![]),
+            git_ref: None,
+            estimate: None,
         })
     }
 
+    /// Encode the task for the raft commits, prefixed with the version of
+    /// its encoding.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut payload = vec![PAYLOAD_MARKER, PAYLOAD_VERSION];
+        payload.extend(serialize(self));
+        payload
+    }
+
+    /// Decode a task from the raft commits. Payloads committed before the
+    /// encoding was versioned decode without any of the newer fields.
+    pub fn from_payload(payload: &[u8]) -> TaudResult<Self> {
+        match payload {
+            [PAYLOAD_MARKER, PAYLOAD_VERSION, task @ ..] => Ok(deserialize(task)?),
+            [PAYLOAD_MARKER, version, ..] => {
+                Err(TaudError::InvalidData(format!("unknown task encoding version: {}", version)))
+            }
+            _ => Ok(deserialize::<LegacyTaskInfo>(payload)?.into()),
+        }
+    }
+
     pub fn load(ref_id: &str, dataset_path: &Path) -> TaudResult<Self> {
         debug!(target: "tau", "TaskInfo::load()");
         let task = load::<Self>(&Self::get_path(ref_id, dataset_path))?;
         Ok(task)
     }
 
+    /// Load every task, whatever its state.
+    pub fn load_all(dataset_path: &Path) -> TaudResult<Vec<Self>> {
+        debug!(target: "tau", "TaskInfo::load_all()");
+        let entries = fs::read_dir(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut tasks = vec![];
+        for entry in entries {
+            let ref_id = entry.map_err(darkfi::Error::from)?.file_name();
+            tasks.push(Self::load(&ref_id.to_string_lossy(), dataset_path)?);
+        }
+
+        Ok(tasks)
+    }
+
+    /// Load the tasks in the given state. Stopped and trashed tasks aren't
+    /// listed in the month tasks, so every task file is read.
+    pub fn load_with_state(state: &str, dataset_path: &Path) -> TaudResult<Vec<Self>> {
+        debug!(target: "tau", "TaskInfo::load_with_state()");
+        let mut tasks = Self::load_all(dataset_path)?;
+        tasks.retain(|task| task.get_state() == state);
+        Ok(tasks)
+    }
+
+    /// Remove the tasks trashed more than `retention_days` days ago. Zero
+    /// days keeps the trash forever. Returns the number of removed tasks.
+    pub fn purge_trash(dataset_path: &Path, retention_days: u64) -> TaudResult<usize> {
+        debug!(target: "tau", "TaskInfo::purge_trash()");
+        if retention_days == 0 {
+            return Ok(0)
+        }
+
+        let oldest = Timestamp::current_time().0 - retention_days as i64 * SECS_PER_DAY;
+        let mut purged = 0;
+        for task in Self::load_with_state(TRASHED, dataset_path)? {
+            if task.trashed_at().is_some_and(|ts| ts.0 < oldest) {
+                fs::remove_file(Self::get_path(&task.ref_id, dataset_path))
+                    .map_err(darkfi::Error::from)?;
+                purged += 1;
+            }
+        }
+
+        Ok(purged)
+    }
+
     pub fn save(&self, dataset_path: &Path) -> TaudResult<()> {
         debug!(target: "tau", "TaskInfo::save()");
         save::<Self>(&Self::get_path(&self.ref_id, dataset_path), self)
             .map_err(TaudError::Darkfi)?;
 
-        if self.get_state() == "stop" {
-            self.deactivate(dataset_path)?;
-        } else {
+        if self.is_active() {
             self.activate(dataset_path)?;
+        } else {
+            self.deactivate(dataset_path)?;
         }
 
         Ok(())
@@ -148,10 +372,58 @@ impl TaskInfo {
 
     pub fn get_state(&self) -> String {
         debug!(target: "tau", "TaskInfo::get_state()");
-        if let Some(ev) = self.events.0.last() {
-            ev.action.clone()
-        } else {
-            "open".into()
+        match self.events.0.iter().rev().find(|ev| changes_state(&ev.action)) {
+            Some(ev) => ev.action.clone(),
+            None => "open".into(),
+        }
+    }
+
+    /// Whether the task is neither stopped nor in the trash.
+    pub fn is_active(&self) -> bool {
+        let state = self.get_state();
+        state != "stop" && state != TRASHED
+    }
+
+    /// When the task was moved to the trash, if it's there.
+    pub fn trashed_at(&self) -> Option<Timestamp> {
+        let event = self.events.0.iter().rev().find(|ev| changes_state(&ev.action))?;
+        (event.action == TRASHED).then_some(event.timestamp)
+    }
+
+    /// When the task was last touched: its latest event or comment, or its
+    /// creation if it has neither.
+    pub fn last_activity(&self) -> Timestamp {
+        let event = self.events.0.iter().map(|ev| ev.timestamp);
+        let comment = self.comments.0.iter().map(|c| c.timestamp);
+        event.chain(comment).fold(self.created_at, |last, ts| if ts > last { ts } else { last })
+    }
+
+    /// Whether the task is open and untouched for more than `days` days,
+    /// and not flagged as stale already.
+    pub fn is_stale(&self, days: u64, now: Timestamp) -> bool {
+        self.get_state() == "open" &&
+            !self.tags.0.iter().any(|t| t == STALE_TAG) &&
+            self.last_activity().0 < now.0 - days as i64 * SECS_PER_DAY
+    }
+
+    /// Pause a task left untouched for `days` days, recording why, or only
+    /// tag it as stale when `flag_only`. Stale tasks are never stopped.
+    pub fn mark_stale(&mut self, days: u64, flag_only: bool, author: &str) {
+        debug!(target: "tau", "TaskInfo::mark_stale()");
+        if flag_only {
+            self.tags.0.push(STALE_TAG.into());
+            return
+        }
+
+        let mut event = TaskEvent::new("pause".into(), author);
+        event.reason = Some(format!("auto-paused, no activity for {} days", days));
+        self.events.0.push(event);
+    }
+
+    fn is_timer_running(&self) -> bool {
+        match self.events.0.iter().rev().find(|ev| is_timer_action(&ev.action)) {
+            Some(ev) => ev.action == TIMER_START,
+            None => false,
         }
     }
 
@@ -165,6 +437,37 @@ impl TaskInfo {
         self.id
     }
 
+    pub fn set_id(&mut self, id: u32) {
+        debug!(target: "tau", "TaskInfo::set_id()");
+        self.id = id;
+    }
+
+    pub fn get_project_id(&self) -> Option<&str> {
+        self.project_id.as_deref()
+    }
+
+    /// Number the task within its first project, e.g. `zk-12`, unless it
+    /// has a project ID already or no project. The number follows the
+    /// highest one given in the project, stopped and trashed tasks
+    /// included, so numbers aren't reused.
+    pub fn assign_project_id(&mut self, dataset_path: &Path) -> TaudResult<()> {
+        let project = match self.project.0.first() {
+            Some(project) if self.project_id.is_none() => project,
+            _ => return Ok(()),
+        };
+
+        let last = Self::load_all(dataset_path)?
+            .iter()
+            .filter_map(|task| task.project_id.as_deref()?.rsplit_once('-'))
+            .filter(|(prefix, _)| prefix == project)
+            .filter_map(|(_, number)| number.parse::<u32>().ok())
+            .max()
+            .unwrap_or(0);
+
+        self.project_id = Some(format!("{}-{}", project, last + 1));
+        Ok(())
+    }
+
     pub fn set_title(&mut self, title: &str) {
         debug!(target: "tau", "TaskInfo::set_title()");
         self.title = title.into();
@@ -185,11 +488,118 @@ impl TaskInfo {
         self.project = TaskProjects(project.to_owned());
     }
 
+    /// Set the tags, lowercased so they don't end up with near-duplicates.
+    /// Blank and repeated tags are dropped.
+    pub fn set_tags(&mut self, tags: &[String]) {
+        debug!(target: "tau", "TaskInfo::set_tags()");
+        let mut normalized: Vec<String> = vec![];
+        for tag in tags.iter().map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()) {
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        self.tags = TaskTags(normalized);
+    }
+
     pub fn set_comment(&mut self, c: Comment) {
         debug!(target: "tau", "TaskInfo::set_comment()");
         self.comments.0.push(c);
     }
 
+    /// Remove the latest comment matching the given content and author.
+    /// Returns `false` if there is no such comment.
+    pub fn remove_comment(&mut self, content: &str, author: &str) -> bool {
+        debug!(target: "tau", "TaskInfo::remove_comment()");
+        match self.comments.0.iter().rposition(|c| c.content == content && c.author == author) {
+            Some(i) => {
+                self.comments.0.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Attach a URL or a file path to the task.
+    pub fn add_link(&mut self, link: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::add_link()");
+        let link = link.trim();
+
+        if link.is_empty() || link.chars().any(char::is_control) {
+            return Err(TaudError::InvalidData("link should be a URL or a file path".into()))
+        }
+
+        // Anything with a scheme must be a valid URL, the rest is a path
+        if link.contains("://") && Url::parse(link).is_err() {
+            return Err(TaudError::InvalidData(format!("malformed URL: {}", link)))
+        }
+
+        if self.links.0.iter().any(|l| l == link) {
+            return Err(TaudError::InvalidData("link is already attached".into()))
+        }
+
+        self.links.0.push(link.into());
+        Ok(())
+    }
+
+    /// Link the task to a git commit, and to the branch it's on unless
+    /// HEAD is detached. Replaces the previous link.
+    pub fn set_git_ref(&mut self, branch: Option<&str>, commit: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::set_git_ref()");
+        let commit = commit.trim().to_lowercase();
+
+        // Full SHA-1 or SHA-256 object names
+        if !(commit.len() == 40 || commit.len() == 64) ||
+            !commit.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(TaudError::InvalidData(format!("invalid commit hash: {}", commit)))
+        }
+
+        let branch = branch.map(str::trim);
+        if branch.is_some_and(|b| b.is_empty() || b.chars().any(|c| c.is_control() || c == ' ')) {
+            return Err(TaudError::InvalidData("invalid branch name".into()))
+        }
+
+        self.git_ref = Some(GitRef { branch: branch.map(String::from), commit });
+        Ok(())
+    }
+
+    /// Add an item, not done yet, to the end of the checklist.
+    pub fn add_checklist_item(&mut self, item: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::add_checklist_item()");
+        let item = item.trim();
+
+        if item.is_empty() || item.chars().any(char::is_control) {
+            return Err(TaudError::InvalidData("checklist items should be a single line".into()))
+        }
+
+        self.checklist.0.push((item.into(), false));
+        Ok(())
+    }
+
+    /// Mark the checklist item at `index` (starting from 0) as done, or
+    /// as not done if it was. Returns whether the item is now done.
+    pub fn toggle_checklist_item(&mut self, index: usize) -> TaudResult<bool> {
+        debug!(target: "tau", "TaskInfo::toggle_checklist_item()");
+        match self.checklist.0.get_mut(index) {
+            Some((_, done)) => {
+                *done = !*done;
+                Ok(*done)
+            }
+            None => Err(TaudError::InvalidData(format!("no checklist item {}", index))),
+        }
+    }
+
+    /// Set the effort the task takes, in hours or points, or clear it.
+    pub fn set_estimate(&mut self, estimate: Option<f32>) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::set_estimate()");
+        if estimate.is_some_and(|e| !e.is_finite() || e < 0.0) {
+            return Err(TaudError::InvalidData("estimate should be a positive number".into()))
+        }
+
+        self.estimate = estimate;
+        Ok(())
+    }
+
     pub fn set_rank(&mut self, r: f32) {
         debug!(target: "tau", "TaskInfo::set_rank()");
         self.rank = r;
@@ -200,12 +610,89 @@ impl TaskInfo {
         self.due = d;
     }
 
-    pub fn set_state(&mut self, action: &str) {
+    /// Record that `author` changed the task's fields.
+    pub fn set_updated(&mut self, author: &str) {
+        debug!(target: "tau", "TaskInfo::set_updated()");
+        self.events.0.push(TaskEvent::new(UPDATE.into(), author));
+    }
+
+    pub fn set_state(&mut self, action: &str, author: &str) {
         debug!(target: "tau", "TaskInfo::set_state()");
         if self.get_state() == action {
             return
         }
-        self.events.0.push(TaskEvent::new(action.into()));
+        self.events.0.push(TaskEvent::new(action.into(), author));
+    }
+
+    pub fn block(&mut self, reason: &str, author: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::block()");
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(TaudError::InvalidData("a reason is needed to block a task".into()))
+        }
+
+        let mut event = TaskEvent::new(BLOCKED.into(), author);
+        event.reason = Some(reason.into());
+        self.events.0.push(event);
+        Ok(())
+    }
+
+    pub fn unblock(&mut self, author: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::unblock()");
+        if self.get_state() != BLOCKED {
+            return Err(TaudError::InvalidData("task is not blocked".into()))
+        }
+        self.set_state("open", author);
+        Ok(())
+    }
+
+    /// Move the task to the trash. Unlike stopping it, this hides it from
+    /// everything but the trash until it's restored.
+    pub fn trash(&mut self, author: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::trash()");
+        if self.get_state() == TRASHED {
+            return Err(TaudError::InvalidData("task is already in the trash".into()))
+        }
+        self.events.0.push(TaskEvent::new(TRASHED.into(), author));
+        Ok(())
+    }
+
+    /// Bring the task back from the trash, in the state it had before.
+    pub fn restore(&mut self, author: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::restore()");
+        let mut states = self.events.0.iter().rev().filter(|ev| changes_state(&ev.action));
+        if states.next().map(|ev| ev.action.as_str()) != Some(TRASHED) {
+            return Err(TaudError::InvalidData("task is not in the trash".into()))
+        }
+
+        // The block reason is restored along with the state
+        let event = match states.next() {
+            Some(before) => TaskEvent {
+                reason: before.reason.clone(),
+                ..TaskEvent::new(before.action.clone(), author)
+            },
+            None => TaskEvent::new("open".into(), author),
+        };
+        self.events.0.push(event);
+        Ok(())
+    }
+
+    pub fn start_timer(&mut self, author: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::start_timer()");
+        if self.is_timer_running() {
+            return Err(TaudError::InvalidData("timer is already running".into()))
+        }
+        self.events.0.push(TaskEvent::new(TIMER_START.into(), author));
+        Ok(())
+    }
+
+    pub fn stop_timer(&mut self, author: &str) -> TaudResult<()> {
+        debug!(target: "tau", "TaskInfo::stop_timer()");
+        if !self.is_timer_running() {
+            return Err(TaudError::InvalidData("timer is not running".into()))
+        }
+        self.events.0.push(TaskEvent::new(TIMER_STOP.into(), author));
+        Ok(())
     }
 }
 
@@ -220,6 +707,18 @@ impl Decodable for TaskEvents {
         Ok(Self(decode_vec(d)?))
     }
 }
+impl Encodable for LegacyTaskEvents {
+    fn encode<S: io::Write>(&self, s: S) -> darkfi::Result<usize> {
+        encode_vec(&self.0, s)
+    }
+}
+
+impl Decodable for LegacyTaskEvents {
+    fn decode<D: io::Read>(d: D) -> darkfi::Result<Self> {
+        Ok(Self(decode_vec(d)?))
+    }
+}
+
 impl Encodable for TaskComments {
     fn encode<S: io::Write>(&self, s: S) -> darkfi::Result<usize> {
         encode_vec(&self.0, s)
@@ -243,6 +742,42 @@ impl Decodable for TaskProjects {
     }
 }
 
+impl Encodable for TaskLinks {
+    fn encode<S: io::Write>(&self, s: S) -> darkfi::Result<usize> {
+        encode_vec(&self.0, s)
+    }
+}
+
+impl Decodable for TaskLinks {
+    fn decode<D: io::Read>(d: D) -> darkfi::Result<Self> {
+        Ok(Self(decode_vec(d)?))
+    }
+}
+
+impl Encodable for TaskTags {
+    fn encode<S: io::Write>(&self, s: S) -> darkfi::Result<usize> {
+        encode_vec(&self.0, s)
+    }
+}
+
+impl Decodable for TaskTags {
+    fn decode<D: io::Read>(d: D) -> darkfi::Result<Self> {
+        Ok(Self(decode_vec(d)?))
+    }
+}
+
+impl Encodable for TaskChecklist {
+    fn encode<S: io::Write>(&self, s: S) -> darkfi::Result<usize> {
+        encode_vec(&self.0, s)
+    }
+}
+
+impl Decodable for TaskChecklist {
+    fn decode<D: io::Read>(d: D) -> darkfi::Result<Self> {
+        Ok(Self(decode_vec(d)?))
+    }
+}
+
 impl Encodable for TaskAssigns {
     fn encode<S: io::Write>(&self, s: S) -> darkfi::Result<usize> {
         encode_vec(&self.0, s)
@@ -272,3 +807,438 @@ fn decode_vec<T: Decodable, D: io::Read>(mut d: D) -> darkfi::Result<Vec<T>> {
     }
     Ok(ret)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use super::*;
+
+    const TEST_DATA_PATH: &str = "/tmp/test_tau_timer";
+
+    #[test]
+    fn start_and_stop_timer() -> TaudResult<()> {
+        remove_dir_all(TEST_DATA_PATH).ok();
+        let dataset_path = PathBuf::from(TEST_DATA_PATH);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+
+        // A timer that isn't running can't be stopped
+        assert!(task.stop_timer("NICKNAME").is_err());
+
+        task.set_state("pause", "NICKNAME");
+        task.start_timer("NICKNAME")?;
+        assert!(task.start_timer("NICKNAME").is_err());
+        assert_eq!(task.get_state(), "pause");
+
+        task.stop_timer("NICKNAME")?;
+        assert!(task.stop_timer("NICKNAME").is_err());
+        assert_eq!(task.get_state(), "pause");
+
+        task.start_timer("NICKNAME")?;
+        assert_eq!(task.events.0.len(), 4);
+
+        remove_dir_all(TEST_DATA_PATH).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn mark_stale() -> TaudResult<()> {
+        let path = "/tmp/test_tau_stale";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let now = Timestamp::current_time();
+        let mut task = TaskInfo::new("stale", "", "NICKNAME", None, 0.0, &dataset_path)?;
+        assert!(!task.is_stale(14, now));
+
+        task.created_at = Timestamp(now.0 - 20 * SECS_PER_DAY);
+        assert!(task.is_stale(14, now));
+        assert!(!task.is_stale(30, now));
+
+        // Comments are activity too
+        let mut commented = task.clone();
+        commented.set_comment(Comment::new("still on it", "NICKNAME"));
+        assert_eq!(commented.last_activity(), commented.comments.0[0].timestamp);
+        assert!(!commented.is_stale(14, now));
+
+        let mut flagged = task.clone();
+        flagged.mark_stale(14, true, "taud");
+        assert_eq!(flagged.get_state(), "open");
+        assert_eq!(flagged.tags.0, vec![STALE_TAG]);
+        assert!(!flagged.is_stale(14, now));
+
+        task.mark_stale(14, false, "taud");
+        assert_eq!(task.get_state(), "pause");
+        assert_eq!(
+            task.events.0[0].reason.as_deref(),
+            Some("auto-paused, no activity for 14 days")
+        );
+        assert!(!task.is_stale(14, now));
+
+        // Only open tasks are paused, never stopped ones
+        let mut stopped = TaskInfo::new("stopped", "", "NICKNAME", None, 0.0, &dataset_path)?;
+        stopped.created_at = Timestamp(now.0 - 20 * SECS_PER_DAY);
+        stopped.set_state("stop", "NICKNAME");
+        stopped.events.0[0].timestamp = stopped.created_at;
+        assert!(!stopped.is_stale(14, now));
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn load_with_state() -> TaudResult<()> {
+        let path = "/tmp/test_tau_stopped";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let open = TaskInfo::new("open", "", "NICKNAME", None, 0.0, &dataset_path)?;
+        open.save(&dataset_path)?;
+        let mut stopped = TaskInfo::new("stopped", "", "NICKNAME", None, 0.0, &dataset_path)?;
+        stopped.set_state("stop", "NICKNAME");
+        stopped.save(&dataset_path)?;
+
+        assert_eq!(TaskInfo::load_with_state("stop", &dataset_path)?, vec![stopped]);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn assign_project_id() -> TaudResult<()> {
+        let path = "/tmp/test_tau_project_id";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let new_task = |project: &[&str]| -> TaudResult<TaskInfo> {
+            let mut task = TaskInfo::new("title", "", "NICKNAME", None, 0.0, &dataset_path)?;
+            task.set_project(&project.iter().map(|p| p.to_string()).collect::<Vec<_>>());
+            task.assign_project_id(&dataset_path)?;
+            task.save(&dataset_path)?;
+            Ok(task)
+        };
+
+        assert_eq!(new_task(&["zk"])?.get_project_id(), Some("zk-1"));
+        assert_eq!(new_task(&["net", "zk"])?.get_project_id(), Some("net-1"));
+        assert_eq!(new_task(&["zk-proofs"])?.get_project_id(), Some("zk-proofs-1"));
+        assert_eq!(new_task(&[])?.get_project_id(), None);
+
+        // Numbers of stopped tasks aren't reused
+        let mut stopped = new_task(&["zk"])?;
+        assert_eq!(stopped.get_project_id(), Some("zk-2"));
+        stopped.set_state("stop", "NICKNAME");
+        stopped.save(&dataset_path)?;
+        assert_eq!(new_task(&["zk"])?.get_project_id(), Some("zk-3"));
+
+        // A task keeps its project ID when its projects change
+        stopped.set_project(&["net".into()]);
+        stopped.assign_project_id(&dataset_path)?;
+        assert_eq!(stopped.get_project_id(), Some("zk-2"));
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn block_and_unblock() -> TaudResult<()> {
+        let path = "/tmp/test_tau_block";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+
+        assert!(task.unblock("NICKNAME").is_err());
+        assert!(task.block("  ", "NICKNAME").is_err());
+
+        task.block("waiting for review", "NICKNAME")?;
+        assert_eq!(task.get_state(), BLOCKED);
+        assert_eq!(task.events.0[0].reason.as_deref(), Some("waiting for review"));
+        assert_eq!(task.events.0[0].author.as_deref(), Some("NICKNAME"));
+
+        // Timers and updates don't change the state
+        task.start_timer("NICKNAME")?;
+        task.set_updated("NICKNAME");
+        assert_eq!(task.get_state(), BLOCKED);
+        task.unblock(" ")?;
+        assert_eq!(task.get_state(), "open");
+        assert_eq!(task.events.0.last().unwrap().author, None);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn trash_and_restore() -> TaudResult<()> {
+        let path = "/tmp/test_tau_trash";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+        assert!(task.restore("NICKNAME").is_err());
+
+        task.block("waiting for review", "NICKNAME")?;
+        task.trash("NICKNAME")?;
+        assert!(task.trash("NICKNAME").is_err());
+        task.save(&dataset_path)?;
+        assert!(!task.is_active());
+        assert!(MonthTasks::load_current_open_tasks(&dataset_path)?.is_empty());
+        assert_eq!(TaskInfo::load_with_state(TRASHED, &dataset_path)?, vec![task.clone()]);
+
+        // Recently trashed tasks are kept
+        assert_eq!(TaskInfo::purge_trash(&dataset_path, 1)?, 0);
+
+        task.restore("NICKNAME")?;
+        assert_eq!(task.get_state(), BLOCKED);
+        assert_eq!(task.events.0.last().unwrap().reason.as_deref(), Some("waiting for review"));
+        task.save(&dataset_path)?;
+        assert_eq!(MonthTasks::load_current_open_tasks(&dataset_path)?, vec![task.clone()]);
+
+        // Tasks trashed before the retention period are removed
+        task.trash("NICKNAME")?;
+        task.events.0.last_mut().unwrap().timestamp.0 -= 2 * SECS_PER_DAY;
+        task.save(&dataset_path)?;
+        assert_eq!(TaskInfo::purge_trash(&dataset_path, 0)?, 0);
+        assert_eq!(TaskInfo::purge_trash(&dataset_path, 1)?, 1);
+        assert!(TaskInfo::load(&task.ref_id, &dataset_path).is_err());
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn add_link() -> TaudResult<()> {
+        let path = "/tmp/test_tau_links";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+
+        task.add_link("https://github.com/darkrenaissance/darkfi/pull/1")?;
+        task.add_link(" ~/docs/design.md ")?;
+        assert_eq!(task.links.0[1], "~/docs/design.md");
+
+        assert!(task.add_link("~/docs/design.md").is_err());
+        assert!(task.add_link("https://exa mple.com").is_err());
+        assert!(task.add_link("").is_err());
+        assert!(task.add_link("two\nlines").is_err());
+        assert_eq!(task.links.0.len(), 2);
+
+        // Tasks saved without links still load
+        let mut json = serde_json::to_value(&task).unwrap();
+        json.as_object_mut().unwrap().remove("links");
+        let task: TaskInfo = serde_json::from_value(json).unwrap();
+        assert!(task.links.0.is_empty());
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn checklist() -> TaudResult<()> {
+        let path = "/tmp/test_tau_checklist";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+
+        task.add_checklist_item(" write the spec ")?;
+        task.add_checklist_item("review")?;
+        assert!(task.add_checklist_item(" ").is_err());
+        assert!(task.add_checklist_item("two\nlines").is_err());
+
+        assert!(task.toggle_checklist_item(1)?);
+        assert!(task.toggle_checklist_item(2).is_err());
+        assert_eq!(
+            task.checklist.0,
+            vec![("write the spec".to_string(), false), ("review".to_string(), true)]
+        );
+        assert!(!task.toggle_checklist_item(1)?);
+
+        // The checklist goes through raft with the rest of the task
+        task.toggle_checklist_item(0)?;
+        let decoded: TaskInfo = deserialize(&serialize(&task))?;
+        assert_eq!(decoded.checklist, task.checklist);
+
+        // Tasks saved without a checklist still load
+        let mut json = serde_json::to_value(&task).unwrap();
+        json.as_object_mut().unwrap().remove("checklist");
+        let task: TaskInfo = serde_json::from_value(json).unwrap();
+        assert!(task.checklist.0.is_empty());
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn git_ref() -> TaudResult<()> {
+        let path = "/tmp/test_tau_git_ref";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+
+        let commit = "0123456789ABCDEF0123456789abcdef01234567";
+        assert!(task.set_git_ref(Some("main"), &commit[1..]).is_err());
+        assert!(task.set_git_ref(Some("main"), &commit.replace('A', "g")).is_err());
+        assert!(task.set_git_ref(Some(""), commit).is_err());
+        assert!(task.set_git_ref(Some("two words"), commit).is_err());
+        assert_eq!(task.git_ref, None);
+
+        task.set_git_ref(Some(" feature/tau "), commit)?;
+        assert_eq!(
+            task.git_ref,
+            Some(GitRef { branch: Some("feature/tau".into()), commit: commit.to_lowercase() })
+        );
+        task.set_git_ref(None, commit)?;
+        assert_eq!(task.git_ref.as_ref().unwrap().branch, None);
+
+        // The git ref goes through raft with the rest of the task
+        let decoded: TaskInfo = deserialize(&serialize(&task))?;
+        assert_eq!(decoded.git_ref, task.git_ref);
+
+        // Tasks saved without a git ref still load
+        let mut json = serde_json::to_value(&task).unwrap();
+        json.as_object_mut().unwrap().remove("git_ref");
+        let task: TaskInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(task.git_ref, None);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn set_tags() -> TaudResult<()> {
+        let path = "/tmp/test_tau_tags";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+
+        let tags: Vec<String> =
+            ["Bug", " urgent ", "bug", "", "UI"].iter().map(|t| t.to_string()).collect();
+        task.set_tags(&tags);
+        assert_eq!(task.tags.0, vec!["bug", "urgent", "ui"]);
+
+        // Tags survive the raft serialization
+        let task2: TaskInfo = deserialize(&serialize(&task))?;
+        assert_eq!(task2.tags, task.tags);
+
+        // Tasks saved without tags still load
+        let mut json = serde_json::to_value(&task).unwrap();
+        json.as_object_mut().unwrap().remove("tags");
+        let task: TaskInfo = serde_json::from_value(json).unwrap();
+        assert!(task.tags.0.is_empty());
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn set_estimate() -> TaudResult<()> {
+        let path = "/tmp/test_tau_estimate";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+        assert_eq!(task.estimate, None);
+
+        task.set_estimate(Some(2.5))?;
+        assert_eq!(task.estimate, Some(2.5));
+        assert!(task.set_estimate(Some(-1.0)).is_err());
+        assert!(task.set_estimate(Some(f32::NAN)).is_err());
+        assert_eq!(task.estimate, Some(2.5));
+
+        // Estimates survive the raft serialization
+        let task2: TaskInfo = deserialize(&serialize(&task))?;
+        assert_eq!(task2.estimate, Some(2.5));
+
+        task.set_estimate(None)?;
+        assert_eq!(task.estimate, None);
+
+        // Tasks saved without an estimate still load
+        let mut json = serde_json::to_value(&task).unwrap();
+        json.as_object_mut().unwrap().remove("estimate");
+        let task: TaskInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(task.estimate, None);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn decode_payload() -> TaudResult<()> {
+        let path = "/tmp/test_tau_payload";
+        remove_dir_all(path).ok();
+        let dataset_path = PathBuf::from(path);
+        create_dir_all(dataset_path.join("month")).map_err(darkfi::Error::from)?;
+        create_dir_all(dataset_path.join("task")).map_err(darkfi::Error::from)?;
+
+        let mut task =
+            TaskInfo::new("test_title", "test_desc", "NICKNAME", None, 0.0, &dataset_path)?;
+        task.set_state("pause", "NICKNAME");
+        task.set_tags(&["bug".to_string()]);
+        assert_eq!(TaskInfo::from_payload(&task.to_payload())?, task);
+
+        let mut payload = task.to_payload();
+        payload[1] = PAYLOAD_VERSION + 1;
+        assert!(TaskInfo::from_payload(&payload).is_err());
+
+        // Tasks committed by older daemons decode without the newer fields
+        let legacy = LegacyTaskInfo {
+            ref_id: task.ref_id.clone(),
+            id: task.id,
+            title: task.title.clone(),
+            desc: task.desc.clone(),
+            owner: task.owner.clone(),
+            assign: task.assign.clone(),
+            project: task.project.clone(),
+            due: task.due,
+            rank: task.rank,
+            created_at: task.created_at,
+            events: LegacyTaskEvents(vec![LegacyTaskEvent {
+                action: "pause".into(),
+                timestamp: task.events.0[0].timestamp,
+            }]),
+            comments: task.comments.clone(),
+        };
+        let decoded = TaskInfo::from_payload(&serialize(&legacy))?;
+        assert_eq!(decoded.title, task.title);
+        assert_eq!(decoded.get_state(), "pause");
+        assert_eq!(decoded.events.0[0].author, None);
+        assert!(decoded.tags.0.is_empty());
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+}
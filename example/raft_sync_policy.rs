@@ -0,0 +1,42 @@
+use std::{fs::remove_dir_all, time::Instant};
+
+use darkfi::{
+    raft::{DataStore, SyncPolicy},
+    Result,
+};
+
+const WRITES: u64 = 2000;
+
+// Compare the write throughput of the raft datastore across the
+// available sync policies.
+fn bench(name: &str, sync_policy: SyncPolicy) -> Result<()> {
+    let path = format!("/tmp/raft_sync_policy_bench_{}", name);
+    remove_dir_all(&path).ok();
+
+    let datastore = DataStore::<u64>::new(&path, sync_policy)?;
+
+    let start = Instant::now();
+    for i in 0..WRITES {
+        datastore.commits.insert(&i)?;
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:<10} {} writes in {:?} ({:.0} writes/s)",
+        name,
+        WRITES,
+        elapsed,
+        WRITES as f64 / elapsed.as_secs_f64()
+    );
+
+    drop(datastore);
+    remove_dir_all(&path).ok();
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    bench("always", SyncPolicy::Always)?;
+    bench("batched", SyncPolicy::Batched(500))?;
+    bench("buffered", SyncPolicy::Buffered)?;
+    Ok(())
+}
@@ -96,7 +96,7 @@ impl MemoryState {
             // If it's our own coin, witness it and append to the vector.
             if let Some((note, secret)) = self.try_decrypt_note(enc_note) {
                 let leaf_position = self.tree.witness().unwrap();
-                let nullifier = Nullifier::new(secret, note.serial);
+                let nullifier = Nullifier::new(secret.clone(), note.serial);
                 let own_coin = OwnCoin { coin, note, secret, nullifier, leaf_position };
                 self.own_coins.push(own_coin);
             }
@@ -109,7 +109,7 @@ impl MemoryState {
             // .. attempt to decrypt the note ...
             if let Ok(note) = ciphertext.decrypt(secret) {
                 // ... and return the decrypted note for this coin.
-                return Some((note, *secret))
+                return Some((note, secret.clone()))
             }
         }
 
@@ -120,7 +120,7 @@ impl MemoryState {
 
 fn main() -> Result<()> {
     let cashier_signature_secret = SecretKey::random(&mut OsRng);
-    let cashier_signature_public = PublicKey::from_secret(cashier_signature_secret);
+    let cashier_signature_public = PublicKey::from_secret(cashier_signature_secret.clone());
 
     let faucet_signature_secret = SecretKey::random(&mut OsRng);
     let faucet_signature_public = PublicKey::from_secret(faucet_signature_secret);
@@ -139,7 +139,7 @@ fn main() -> Result<()> {
         burn_vk,
         cashier_signature_public,
         faucet_signature_public,
-        secrets: vec![keypair.secret],
+        secrets: vec![keypair.secret.clone()],
     };
 
     let token_id =
@@ -172,7 +172,7 @@ fn main() -> Result<()> {
 
     // Now spend
     let owncoin = &state.own_coins[0];
-    let note = owncoin.note;
+    let note = owncoin.note.clone();
     let leaf_position = owncoin.leaf_position;
     let root = state.tree.root(0).unwrap();
     let merkle_path = state.tree.authentication_path(leaf_position, &root).unwrap();
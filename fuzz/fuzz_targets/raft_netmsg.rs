@@ -0,0 +1,44 @@
+//! Decode arbitrary bytes as raft messages, the way `Raft` handles the
+//! messages of its peers. Decoding must fail with an error, never panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use darkfi::{
+    raft::{
+        BroadcastMsgRequest, LogRequest, LogResponse, NetMsg, NetMsgMethod, SyncRequest,
+        SyncResponse, VoteRequest, VoteResponse,
+    },
+    util::serial::deserialize,
+};
+
+fn decode_payload(method: &NetMsgMethod, payload: &[u8]) {
+    match method {
+        NetMsgMethod::LogResponse => drop(deserialize::<LogResponse>(payload)),
+        NetMsgMethod::LogRequest => drop(deserialize::<LogRequest>(payload)),
+        NetMsgMethod::VoteResponse => drop(deserialize::<VoteResponse>(payload)),
+        NetMsgMethod::VoteRequest => drop(deserialize::<VoteRequest>(payload)),
+        NetMsgMethod::BroadcastRequest => drop(deserialize::<BroadcastMsgRequest>(payload)),
+        NetMsgMethod::SyncRequest => drop(deserialize::<SyncRequest>(payload)),
+        NetMsgMethod::SyncResponse => drop(deserialize::<SyncResponse>(payload)),
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = deserialize::<NetMsg>(data) {
+        decode_payload(&msg.method, &msg.payload);
+    }
+
+    // The payload decoders on their own
+    for method in [
+        NetMsgMethod::LogResponse,
+        NetMsgMethod::LogRequest,
+        NetMsgMethod::VoteResponse,
+        NetMsgMethod::VoteRequest,
+        NetMsgMethod::BroadcastRequest,
+        NetMsgMethod::SyncRequest,
+        NetMsgMethod::SyncResponse,
+    ] {
+        decode_payload(&method, data);
+    }
+});
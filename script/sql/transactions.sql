@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS transactions(
+	tx_id TEXT NOT NULL,
+	direction TEXT NOT NULL,
+	counterparty TEXT,
+	value BLOB NOT NULL,
+	token_id BLOB NOT NULL,
+	timestamp INTEGER NOT NULL,
+	PRIMARY KEY (tx_id, direction)
+);
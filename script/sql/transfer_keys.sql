@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS transfer_keys(
+	key TEXT PRIMARY KEY NOT NULL,
+	params_hash TEXT NOT NULL,
+	reply TEXT NOT NULL,
+	timestamp INTEGER NOT NULL
+);
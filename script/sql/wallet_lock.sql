@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS wallet_lock(
+	id INTEGER PRIMARY KEY CHECK (id = 0),
+	locked INTEGER NOT NULL
+);
@@ -143,7 +143,7 @@ impl ValidatorState {
         faucet_pubkeys: Vec<PublicKey>,
     ) -> Result<ValidatorStatePtr> {
         let secret = SecretKey::random(&mut OsRng);
-        let public = PublicKey::from_secret(secret);
+        let public = PublicKey::from_secret(secret.clone());
         let consensus = ConsensusState::new(genesis_ts, genesis_data)?;
         let blockchain = Blockchain::new(db, genesis_ts, genesis_data)?;
         let unconfirmed_txs = vec![];
@@ -929,7 +929,7 @@ impl ValidatorState {
         notify: Option<async_channel::Sender<(PublicKey, u64)>>,
     ) -> Result<()> {
         let secret_keys: Vec<SecretKey> =
-            self.client.get_keypairs().await?.iter().map(|x| x.secret).collect();
+            self.client.get_keypairs().await?.into_iter().map(|x| x.secret).collect();
 
         debug!("update_canon_state(): Acquiring state machine lock");
         let mut state = self.state_machine.lock().await;
@@ -12,21 +12,100 @@ enum AddressType {
     Payment = 0,
 }
 
+/// Length of a decoded address: the type, the public key and the checksum
+const ADDRESS_LEN: usize = 37;
+
+/// The validation step a string fails to parse as an address at, see
+/// [`Address::parse`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AddressError {
+    /// Not a base58 string
+    Base58,
+    /// Decodes to this many bytes instead of 37
+    Length(usize),
+    /// Starts with this unknown address type
+    Prefix(u8),
+    /// Its checksum isn't the one of its type and public key
+    Checksum { expected: [u8; 4], found: [u8; 4] },
+}
+
+impl std::fmt::Display for AddressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Base58 => write!(f, "not a base58 string"),
+            Self::Length(len) => {
+                write!(f, "wrong length: {} bytes instead of {}", len, ADDRESS_LEN)
+            }
+            Self::Prefix(prefix) => write!(f, "unknown address type prefix 0x{:02x}", prefix),
+            Self::Checksum { expected, found } => write!(
+                f,
+                "checksum mismatch: expected {}, found {}",
+                bytes_to_hex(expected),
+                bytes_to_hex(found)
+            ),
+        }
+    }
+}
+
+fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
 pub struct Address(pub [u8; 37]);
 
 impl Address {
-    fn is_valid_address(address: Vec<u8>) -> bool {
-        if address.starts_with(&[AddressType::Payment as u8]) && address.len() == 37 {
-            // hash the version + publickey to check the checksum
-            let mut hasher = sha2::Sha256::new();
-            hasher.update(&address[..33]);
-            let payload_hash = hasher.finalize().to_vec();
-
-            payload_hash[..4] == address[33..]
-        } else {
-            false
+    /// Parse a base58 address, checking its length, its type prefix and
+    /// its checksum in this order, and telling which one failed.
+    pub fn parse(address: &str) -> std::result::Result<Self, AddressError> {
+        let bytes = bs58::decode(&address).into_vec().map_err(|_| AddressError::Base58)?;
+
+        if bytes.len() != ADDRESS_LEN {
+            return Err(AddressError::Length(bytes.len()))
         }
+
+        if bytes[0] != AddressType::Payment as u8 {
+            return Err(AddressError::Prefix(bytes[0]))
+        }
+
+        let expected = Self::checksum(&bytes[..33]);
+        if bytes[33..] != expected {
+            let mut found = [0u8; 4];
+            found.copy_from_slice(&bytes[33..]);
+            return Err(AddressError::Checksum { expected, found })
+        }
+
+        let mut bytes_arr = [0u8; ADDRESS_LEN];
+        bytes_arr.copy_from_slice(&bytes);
+        Ok(Self(bytes_arr))
+    }
+
+    /// First 4 bytes of the hash of the version and the public key.
+    fn checksum(payload: &[u8]) -> [u8; 4] {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(payload);
+        let mut checksum = [0u8; 4];
+        checksum.copy_from_slice(&hasher.finalize()[..4]);
+        checksum
+    }
+
+    /// The address type prefix.
+    pub fn prefix(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// The bytes of the public key, which may not be a valid point.
+    pub fn public_key_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&self.0[1..33]);
+        bytes
+    }
+
+    /// The checksum ending the address.
+    pub fn checksum_bytes(&self) -> [u8; 4] {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&self.0[33..]);
+        bytes
     }
 }
 
@@ -42,17 +121,7 @@ impl FromStr for Address {
     type Err = Error;
 
     fn from_str(address: &str) -> Result<Self> {
-        let bytes = bs58::decode(&address).into_vec();
-
-        if let Ok(v) = bytes {
-            if Self::is_valid_address(v.clone()) {
-                let mut bytes_arr = [0u8; 37];
-                bytes_arr.copy_from_slice(v.as_slice());
-                return Ok(Self(bytes_arr))
-            }
-        }
-
-        Err(Error::InvalidAddress)
+        Self::parse(address).map_err(|_| Error::InvalidAddress)
     }
 }
 
@@ -66,13 +135,10 @@ impl From<PublicKey> for Address {
         // add public key
         address.append(&mut publickey);
 
-        // hash the version + publickey
-        let mut hasher = sha2::Sha256::new();
-        hasher.update(address.clone());
-        let payload_hash = hasher.finalize().to_vec();
-
-        // add the 4 first bytes from the hash as checksum
-        address.append(&mut payload_hash[..4].to_vec());
+        // add the 4 first bytes from the hash of the version + publickey
+        // as checksum
+        let checksum = Self::checksum(&address);
+        address.extend_from_slice(&checksum);
 
         let mut payment_address = [0u8; 37];
         payment_address.copy_from_slice(address.as_slice());
@@ -117,4 +183,32 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_address_errors() {
+        let keypair = Keypair::random(&mut OsRng);
+        let address = Address::from(keypair.public);
+        assert_eq!(Address::parse(&address.to_string()), Ok(address));
+        assert_eq!(address.prefix(), 0);
+        assert_eq!(address.public_key_bytes(), keypair.public.to_bytes());
+
+        assert_eq!(Address::parse("0OIl"), Err(AddressError::Base58));
+
+        let encode = |bytes: &[u8]| bs58::encode(bytes).into_string();
+        assert_eq!(Address::parse(&encode(&address.0[..36])), Err(AddressError::Length(36)));
+
+        let mut bytes = address.0;
+        bytes[0] = 7;
+        assert_eq!(Address::parse(&encode(&bytes)), Err(AddressError::Prefix(7)));
+
+        let mut bytes = address.0;
+        bytes[36] ^= 1;
+        let mut found = address.checksum_bytes();
+        found[3] ^= 1;
+        assert_eq!(
+            Address::parse(&encode(&bytes)),
+            Err(AddressError::Checksum { expected: address.checksum_bytes(), found })
+        );
+        assert!(Address::from_str(&encode(&bytes)).is_err());
+    }
 }
@@ -10,7 +10,7 @@ use rand::rngs::OsRng;
 use super::{
     nullifier::Nullifier,
     proof::{Proof, ProvingKey, VerifyingKey},
-    util::{mod_r_p, pedersen_commitment_scalar, pedersen_commitment_u64},
+    util::{mod_r_p, pedersen_commitment_scalar, pedersen_commitment_u64, value_to_base},
 };
 use crate::{
     crypto::{
@@ -55,7 +55,7 @@ impl BurnRevealedValues {
         let coords = public_key.0.to_affine().coordinates().unwrap();
 
         let messages =
-            [*coords.x(), *coords.y(), DrkValue::from(value), token_id, serial, coin_blind];
+            [*coords.x(), *coords.y(), value_to_base(value), token_id, serial, coin_blind];
 
         let coin =
             poseidon::Hash::<_, poseidon::P128Pow5T3, poseidon::ConstantLength<6>, 3, 2>::init()
@@ -129,10 +129,10 @@ pub fn create_burn_proof(
         token_blind,
         serial,
         coin_blind,
-        secret,
+        secret.clone(),
         leaf_position,
         merkle_path.clone(),
-        signature_secret,
+        signature_secret.clone(),
     );
 
     let leaf_position: u64 = leaf_position.into();
@@ -140,7 +140,7 @@ pub fn create_burn_proof(
     let c = BurnContract {
         secret_key: Value::known(secret.0),
         serial: Value::known(serial),
-        value: Value::known(DrkValue::from(value)),
+        value: Value::known(value_to_base(value)),
         token: Value::known(token_id),
         coin_blind: Value::known(coin_blind),
         value_blind: Value::known(value_blind),
@@ -2,10 +2,21 @@ pub mod fixed_bases;
 pub mod sinsemilla;
 pub mod util;
 
-pub use fixed_bases::{NullifierK, OrchardFixedBases, OrchardFixedBasesFull, ValueCommitV, H};
+pub use fixed_bases::{
+    NullifierK, OrchardFixedBases, OrchardFixedBasesFull, ValueCommitV, COMMIT_IVK_PERSONALIZATION,
+    H, NOTE_COMMITMENT_PERSONALIZATION, ORCHARD_PERSONALIZATION, VALUE_COMMITMENT_PERSONALIZATION,
+};
+pub use sinsemilla::MERKLE_CRH_PERSONALIZATION;
 
 pub const DRK_SCHNORR_DOMAIN: &[u8] = b"DarkFi_Schnorr";
 
+/// Blake2b persona of `prf_scalar`. Changing it changes every derived scalar.
+pub const DRK_PRF_DOMAIN: &[u8] = b"DarkFi_PRF";
+
+/// SWU hash-to-curve personalization of the per-asset value commitment
+/// generators, see `pedersen_commitment_asset`.
+pub const DRK_ASSET_VALUE_COMMITMENT_PERSONALIZATION: &str = "DarkFi:Asset-cv";
+
 pub const MERKLE_DEPTH_ORCHARD: usize = 32;
 
 pub const MERKLE_DEPTH: u8 = MERKLE_DEPTH_ORCHARD as u8;
@@ -1,4 +1,4 @@
-use std::{convert::TryFrom, io, str::FromStr};
+use std::{convert::TryFrom, hint::black_box, str::FromStr};
 
 use halo2_gadgets::ecc::chip::FixedPoint;
 use pasta_curves::{
@@ -9,14 +9,15 @@ use pasta_curves::{
     pallas,
 };
 use rand::RngCore;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::{
     crypto::{address::Address, constants::NullifierK, util::mod_r_p},
-    util::serial::{Decodable, Encodable, ReadExt, SerialDecodable, SerialEncodable, WriteExt},
+    util::serial::{Decodable, Encodable, SerialDecodable, SerialEncodable},
     Error, Result,
 };
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 #[cfg(feature = "serde")]
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct Keypair {
@@ -26,7 +27,7 @@ pub struct Keypair {
 
 impl Keypair {
     pub fn new(secret: SecretKey) -> Self {
-        let public = PublicKey::from_secret(secret);
+        let public = PublicKey::from_secret(secret.clone());
         Self { secret, public }
     }
 
@@ -36,7 +37,7 @@ impl Keypair {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug, SerialDecodable, SerialEncodable)]
+#[derive(Clone, PartialEq, Debug, SerialDecodable, SerialEncodable)]
 pub struct SecretKey(pub pallas::Base);
 
 impl SecretKey {
@@ -45,7 +46,7 @@ impl SecretKey {
         Self(x)
     }
 
-    pub fn to_bytes(self) -> [u8; 32] {
+    pub fn to_bytes(&self) -> [u8; 32] {
         self.0.to_repr()
     }
 
@@ -57,6 +58,23 @@ impl SecretKey {
     }
 }
 
+impl Zeroize for SecretKey {
+    fn zeroize(&mut self) {
+        // pallas::Base has no Zeroize impl, black_box keeps the store
+        // from being optimized out
+        self.0 = pallas::Base::zero();
+        black_box(&mut self.0);
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SecretKey {}
+
 #[derive(Copy, Clone, PartialEq, Debug, SerialDecodable, SerialEncodable)]
 pub struct PublicKey(pub pallas::Point);
 
@@ -107,66 +125,6 @@ impl TryFrom<Address> for PublicKey {
     }
 }
 
-impl Encodable for pallas::Base {
-    fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
-        s.write_slice(&self.to_repr()[..])?;
-        Ok(32)
-    }
-}
-
-impl Decodable for pallas::Base {
-    fn decode<D: io::Read>(mut d: D) -> Result<Self> {
-        let mut bytes = [0u8; 32];
-        d.read_slice(&mut bytes)?;
-        let result = pallas::Base::from_repr(bytes);
-        if result.is_some().into() {
-            Ok(result.unwrap())
-        } else {
-            Err(Error::BadOperationType)
-        }
-    }
-}
-
-impl Encodable for pallas::Scalar {
-    fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
-        s.write_slice(&self.to_repr()[..])?;
-        Ok(32)
-    }
-}
-
-impl Decodable for pallas::Scalar {
-    fn decode<D: io::Read>(mut d: D) -> Result<Self> {
-        let mut bytes = [0u8; 32];
-        d.read_slice(&mut bytes)?;
-        let result = pallas::Scalar::from_repr(bytes);
-        if result.is_some().into() {
-            Ok(result.unwrap())
-        } else {
-            Err(Error::BadOperationType)
-        }
-    }
-}
-
-impl Encodable for pallas::Point {
-    fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
-        s.write_slice(&self.to_bytes()[..])?;
-        Ok(32)
-    }
-}
-
-impl Decodable for pallas::Point {
-    fn decode<D: io::Read>(mut d: D) -> Result<Self> {
-        let mut bytes = [0u8; 32];
-        d.read_slice(&mut bytes)?;
-        let result = Self::from_bytes(&bytes);
-        if result.is_some().into() {
-            Ok(result.unwrap())
-        } else {
-            Err(Error::BadOperationType)
-        }
-    }
-}
-
 #[cfg(feature = "serde")]
 impl serde::Serialize for SecretKey {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
@@ -261,6 +219,8 @@ impl<'de> serde::Deserialize<'de> for PublicKey {
 
 #[cfg(test)]
 mod tests {
+    use rand::rngs::OsRng;
+
     use super::*;
     use crate::{
         crypto::util::pedersen_commitment_scalar,
@@ -306,4 +266,12 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_zeroize_secret_key() {
+        let mut secret = SecretKey::random(&mut OsRng);
+        assert_ne!(secret.0, pallas::Base::zero());
+        secret.zeroize();
+        assert_eq!(secret.to_bytes(), [0u8; 32]);
+    }
 }
@@ -11,8 +11,8 @@ use crate::{
         coin::Coin,
         keypair::PublicKey,
         proof::{Proof, ProvingKey, VerifyingKey},
-        types::{DrkCoinBlind, DrkSerial, DrkTokenId, DrkValue, DrkValueBlind, DrkValueCommit},
-        util::{mod_r_p, pedersen_commitment_scalar, pedersen_commitment_u64},
+        types::{DrkCoinBlind, DrkSerial, DrkTokenId, DrkValueBlind, DrkValueCommit},
+        util::{mod_r_p, pedersen_commitment_scalar, pedersen_commitment_u64, value_to_base},
     },
     util::serial::{SerialDecodable, SerialEncodable},
     zk::circuit::mint_contract::MintContract,
@@ -41,7 +41,7 @@ impl MintRevealedValues {
 
         let coords = public_key.0.to_affine().coordinates().unwrap();
         let messages =
-            [*coords.x(), *coords.y(), DrkValue::from(value), token_id, serial, coin_blind];
+            [*coords.x(), *coords.y(), value_to_base(value), token_id, serial, coin_blind];
 
         let coin =
             poseidon::Hash::<_, poseidon::P128Pow5T3, poseidon::ConstantLength<6>, 3, 2>::init()
@@ -92,7 +92,7 @@ pub fn create_mint_proof(
     let c = MintContract {
         pub_x: Value::known(*coords.x()),
         pub_y: Value::known(*coords.y()),
-        value: Value::known(DrkValue::from(value)),
+        value: Value::known(value_to_base(value)),
         token: Value::known(token_id),
         serial: Value::known(serial),
         coin_blind: Value::known(coin_blind),
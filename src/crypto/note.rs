@@ -1,5 +1,9 @@
+use std::hint::black_box;
+
 use crypto_api_chachapoly::ChachaPolyIetf;
+use pasta_curves::{group::ff::Field, pallas};
 use rand::rngs::OsRng;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::{
     crypto::{
@@ -16,7 +20,7 @@ pub const NOTE_PLAINTEXT_SIZE: usize = 32 + 8 + 32 + 32 + 32 + 32;
 pub const AEAD_TAG_SIZE: usize = 16;
 pub const ENC_CIPHERTEXT_SIZE: usize = NOTE_PLAINTEXT_SIZE + AEAD_TAG_SIZE;
 
-#[derive(Copy, Clone, Debug, PartialEq, SerialEncodable, SerialDecodable)]
+#[derive(Clone, Debug, PartialEq, SerialEncodable, SerialDecodable)]
 pub struct Note {
     pub serial: DrkSerial,
     pub value: u64,
@@ -26,10 +30,33 @@ pub struct Note {
     pub token_blind: DrkValueBlind,
 }
 
+/// Wipes the serial and blinds, along with the value and token.
+impl Zeroize for Note {
+    fn zeroize(&mut self) {
+        self.serial = pallas::Base::zero();
+        self.value.zeroize();
+        self.token_id = pallas::Base::zero();
+        self.coin_blind = pallas::Base::zero();
+        self.value_blind = pallas::Scalar::zero();
+        self.token_blind = pallas::Scalar::zero();
+        // The field elements have no Zeroize impl, black_box keeps the
+        // stores from being optimized out
+        black_box(self);
+    }
+}
+
+impl Drop for Note {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for Note {}
+
 impl Note {
     pub fn encrypt(&self, public: &PublicKey) -> Result<EncryptedNote> {
         let ephem_secret = SecretKey::random(&mut OsRng);
-        let ephem_public = PublicKey::from_secret(ephem_secret);
+        let ephem_public = PublicKey::from_secret(ephem_secret.clone());
         let shared_secret = sapling_ka_agree(&ephem_secret, public);
         let key = kdf_sapling(&shared_secret, &ephem_public);
 
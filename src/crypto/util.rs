@@ -1,14 +1,29 @@
+use std::io;
+
 use blake2b_simd::Params;
+use halo2_proofs::arithmetic::best_multiexp;
 use pasta_curves::{
     arithmetic::{CurveExt, FieldExt},
-    group::ff::PrimeField,
+    group::{
+        ff::{Field, PrimeField},
+        prime::PrimeCurveAffine,
+        Curve, GroupEncoding,
+    },
     pallas,
 };
+use rand::{rngs::OsRng, RngCore};
 
-use super::constants::fixed_bases::{
-    VALUE_COMMITMENT_PERSONALIZATION, VALUE_COMMITMENT_R_BYTES, VALUE_COMMITMENT_V_BYTES,
+use super::constants::{
+    fixed_bases::{
+        VALUE_COMMITMENT_PERSONALIZATION, VALUE_COMMITMENT_R_BYTES, VALUE_COMMITMENT_V_BYTES,
+    },
+    DRK_ASSET_VALUE_COMMITMENT_PERSONALIZATION, DRK_PRF_DOMAIN,
+};
+use crate::{
+    crypto::{constants::util::gen_const_array, types::*},
+    util::serial::{Decodable, Encodable, ReadExt, WriteExt},
+    Error, Result,
 };
-use crate::crypto::{constants::util::gen_const_array, types::*};
 
 pub fn hash_to_scalar(persona: &[u8], a: &[u8], b: &[u8]) -> pallas::Scalar {
     let mut hasher = Params::new().hash_length(64).personal(persona).to_state();
@@ -18,17 +33,161 @@ pub fn hash_to_scalar(persona: &[u8], a: &[u8], b: &[u8]) -> pallas::Scalar {
     pallas::Scalar::from_bytes_wide(ret.as_array())
 }
 
+/// Keyed pseudorandom function deriving a scalar from `key`, a `label` and
+/// a `counter`, e.g. to derive nonces or blinds deterministically.
+///
+/// This is `hash_to_scalar` with the `DRK_PRF_DOMAIN` persona over
+/// `len(key) || key` and `len(label) || label || counter`, with the lengths
+/// and the counter as 8-byte little-endian integers. The lengths keep
+/// different keys and labels from hashing the same bytes. The output is
+/// pinned by test vectors: derivations must stay reproducible across
+/// versions, so this encoding must never change.
+pub fn prf_scalar(key: &[u8], label: &[u8], counter: u64) -> pallas::Scalar {
+    let mut a = Vec::with_capacity(8 + key.len());
+    a.extend_from_slice(&(key.len() as u64).to_le_bytes());
+    a.extend_from_slice(key);
+
+    let mut b = Vec::with_capacity(16 + label.len());
+    b.extend_from_slice(&(label.len() as u64).to_le_bytes());
+    b.extend_from_slice(label);
+    b.extend_from_slice(&counter.to_le_bytes());
+
+    hash_to_scalar(DRK_PRF_DOMAIN, &a, &b)
+}
+
+/// Hash `input` to a point on the Pallas curve, using the SWU
+/// hash-to-curve from `pasta_curves` with `persona` as the domain prefix.
+///
+/// Points derived under distinct personas are independent generators:
+/// their discrete log relations are unknown, so each protocol should use
+/// its own persona, like the `*_PERSONALIZATION` constants do. Within one
+/// persona the same `input` always gives the same point, so inputs must be
+/// encoded unambiguously when several values are hashed together.
+///
+/// # Panics
+///
+/// Panics if `persona` is longer than 227 bytes.
+pub fn hash_to_pallas_point(persona: &str, input: &[u8]) -> pallas::Point {
+    pallas::Point::hash_to_curve(persona)(input)
+}
+
 #[allow(non_snake_case)]
 pub fn pedersen_commitment_scalar(value: pallas::Scalar, blind: DrkValueBlind) -> DrkValueCommit {
-    let hasher = DrkValueCommit::hash_to_curve(VALUE_COMMITMENT_PERSONALIZATION);
-    let V = hasher(&VALUE_COMMITMENT_V_BYTES);
-    let R = hasher(&VALUE_COMMITMENT_R_BYTES);
+    let V = hash_to_pallas_point(VALUE_COMMITMENT_PERSONALIZATION, &VALUE_COMMITMENT_V_BYTES);
+    let R = hash_to_pallas_point(VALUE_COMMITMENT_PERSONALIZATION, &VALUE_COMMITMENT_R_BYTES);
 
     V * value + R * blind
 }
 
 pub fn pedersen_commitment_u64(value: u64, blind: DrkValueBlind) -> DrkValueCommit {
-    pedersen_commitment_scalar(mod_r_p(DrkValue::from(value)), blind)
+    pedersen_commitment_scalar(value_to_scalar(value), blind)
+}
+
+/// Value commitment generator of the asset `asset_id`, hashed to the curve
+/// from the asset ID under `DRK_ASSET_VALUE_COMMITMENT_PERSONALIZATION`.
+pub fn asset_value_generator(asset_id: DrkTokenId) -> pallas::Point {
+    hash_to_pallas_point(DRK_ASSET_VALUE_COMMITMENT_PERSONALIZATION, &asset_id.to_repr())
+}
+
+/// Commitment to `value` of the asset `asset_id`. Unlike
+/// `pedersen_commitment_u64`, the value generator is derived from the asset
+/// ID, so commitments to different assets can't be added up into a valid
+/// commitment to either of them. The blind generator is the same as for
+/// `pedersen_commitment_u64`.
+#[allow(non_snake_case)]
+pub fn pedersen_commitment_asset(
+    asset_id: DrkTokenId,
+    value: u64,
+    blind: DrkValueBlind,
+) -> DrkValueCommit {
+    let V = asset_value_generator(asset_id);
+    let R = hash_to_pallas_point(VALUE_COMMITMENT_PERSONALIZATION, &VALUE_COMMITMENT_R_BYTES);
+
+    V * value_to_scalar(value) + R * blind
+}
+
+/// Sum of the value commitments to `values` with `blinds`, the same point
+/// as adding up their `pedersen_commitment_scalar`s but with only one
+/// multi-scalar multiplication.
+///
+/// # Panics
+///
+/// Panics if `values` and `blinds` don't have the same length.
+#[allow(non_snake_case)]
+pub fn pedersen_commitment_sum(
+    values: &[pallas::Scalar],
+    blinds: &[DrkValueBlind],
+) -> DrkValueCommit {
+    assert_eq!(values.len(), blinds.len());
+    let V = hash_to_pallas_point(VALUE_COMMITMENT_PERSONALIZATION, &VALUE_COMMITMENT_V_BYTES);
+    let R = hash_to_pallas_point(VALUE_COMMITMENT_PERSONALIZATION, &VALUE_COMMITMENT_R_BYTES);
+
+    let value = values.iter().fold(pallas::Scalar::zero(), |acc, v| acc + v);
+    let blind = blinds.iter().fold(DrkValueBlind::zero(), |acc, b| acc + b);
+    multiscalar_mul(&[value, blind], &[V, R])
+}
+
+/// Multi-scalar multiplication: the sum of `points[i] * scalars[i]`.
+///
+/// Uses Pippenger's bucket method, much faster than multiplying the
+/// points one by one and adding them up once there are more than a few.
+///
+/// # Panics
+///
+/// Panics if `scalars` and `points` don't have the same length.
+pub fn multiscalar_mul(scalars: &[pallas::Scalar], points: &[pallas::Point]) -> pallas::Point {
+    assert_eq!(scalars.len(), points.len());
+    let mut bases = vec![pallas::Affine::identity(); points.len()];
+    pallas::Point::batch_normalize(points, &mut bases);
+    best_multiexp(scalars, &bases)
+}
+
+/// Sample a uniformly random blind for a value commitment from `rng`.
+///
+/// Tests can pass a seeded RNG to get reproducible commitments, anything
+/// else should use [`os_random_value_blind`].
+pub fn random_value_blind(rng: impl RngCore) -> DrkValueBlind {
+    DrkValueBlind::random(rng)
+}
+
+/// Sample a blind for a value commitment from the OS RNG.
+pub fn os_random_value_blind() -> DrkValueBlind {
+    random_value_blind(OsRng)
+}
+
+/// Converts a value to the base field element used in proofs and coins.
+pub fn value_to_base(value: u64) -> DrkValue {
+    DrkValue::from(value)
+}
+
+/// Converts a value to a scalar, as used in value commitments.
+pub fn value_to_scalar(value: u64) -> pallas::Scalar {
+    mod_r_p(value_to_base(value))
+}
+
+/// Converts a base field element back to a value.
+///
+/// Fails with [`Error::ValueOutOfRange`] instead of truncating when the
+/// element is greater than `u64::MAX`.
+pub fn base_to_u64_checked(x: DrkValue) -> Result<u64> {
+    repr_to_u64_checked(x.to_repr())
+}
+
+/// Converts a scalar back to a value, failing like [`base_to_u64_checked`].
+pub fn scalar_to_u64_checked(x: pallas::Scalar) -> Result<u64> {
+    repr_to_u64_checked(x.to_repr())
+}
+
+/// Field element representations are little-endian, so the value fits in
+/// a u64 if all but the first 8 bytes are zero.
+fn repr_to_u64_checked(repr: [u8; 32]) -> Result<u64> {
+    if repr[8..].iter().any(|b| *b != 0) {
+        return Err(Error::ValueOutOfRange)
+    }
+
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&repr[..8]);
+    Ok(u64::from_le_bytes(bytes))
 }
 
 /// Converts from pallas::Base to pallas::Scalar (aka $x \pmod{r_\mathbb{P}}$).
@@ -39,6 +198,77 @@ pub fn mod_r_p(x: pallas::Base) -> pallas::Scalar {
     pallas::Scalar::from_repr(x.to_repr()).unwrap()
 }
 
+/// Decode a base field element from its canonical little-endian bytes,
+/// rejecting values that aren't reduced modulo the field order.
+pub fn base_from_bytes(bytes: [u8; 32]) -> Result<pallas::Base> {
+    Option::from(pallas::Base::from_repr(bytes)).ok_or(Error::NonCanonicalFieldElement)
+}
+
+/// Decode a scalar field element from its canonical little-endian bytes,
+/// rejecting values that aren't reduced modulo the field order.
+pub fn scalar_from_bytes(bytes: [u8; 32]) -> Result<pallas::Scalar> {
+    Option::from(pallas::Scalar::from_repr(bytes)).ok_or(Error::NonCanonicalFieldElement)
+}
+
+/// Decode a point from its compressed encoding, the x coordinate with the
+/// sign of y in the top bit. Bytes that aren't a point on the curve, or
+/// aren't its canonical encoding, are rejected.
+pub fn point_from_bytes(bytes: &[u8; 32]) -> Result<pallas::Point> {
+    let point: pallas::Point =
+        Option::from(pallas::Point::from_bytes(bytes)).ok_or(Error::InvalidPallasPoint)?;
+
+    // pasta_curves rejects non-canonical encodings already, don't rely on it
+    if point.to_bytes() != *bytes {
+        return Err(Error::InvalidPallasPoint)
+    }
+    Ok(point)
+}
+
+impl Encodable for pallas::Base {
+    fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
+        s.write_slice(&self.to_repr()[..])?;
+        Ok(32)
+    }
+}
+
+impl Decodable for pallas::Base {
+    fn decode<D: io::Read>(mut d: D) -> Result<Self> {
+        let mut bytes = [0u8; 32];
+        d.read_slice(&mut bytes)?;
+        base_from_bytes(bytes)
+    }
+}
+
+impl Encodable for pallas::Scalar {
+    fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
+        s.write_slice(&self.to_repr()[..])?;
+        Ok(32)
+    }
+}
+
+impl Decodable for pallas::Scalar {
+    fn decode<D: io::Read>(mut d: D) -> Result<Self> {
+        let mut bytes = [0u8; 32];
+        d.read_slice(&mut bytes)?;
+        scalar_from_bytes(bytes)
+    }
+}
+
+impl Encodable for pallas::Point {
+    fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
+        s.write_slice(&self.to_bytes()[..])?;
+        Ok(32)
+    }
+}
+
+impl Decodable for pallas::Point {
+    fn decode<D: io::Read>(mut d: D) -> Result<Self> {
+        let mut bytes = [0u8; 32];
+        d.read_slice(&mut bytes)?;
+        point_from_bytes(&bytes)
+    }
+}
+
 /// The sequence of bits representing a u64 in little-endian order.
 ///
 /// # Panics
@@ -49,3 +279,251 @@ pub fn i2lebsp<const NUM_BITS: usize>(int: u64) -> [bool; NUM_BITS] {
     assert!(NUM_BITS <= 64);
     gen_const_array(|mask: usize| (int & (1 << mask)) != 0)
 }
+
+#[cfg(test)]
+mod tests {
+    use group::{Curve, Group};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        crypto::constants::{fixed_bases::value_commit_v, ORCHARD_PERSONALIZATION},
+        util::serial::{deserialize, serialize},
+    };
+
+    #[test]
+    fn test_hash_to_pallas_point() {
+        let v = hash_to_pallas_point(VALUE_COMMITMENT_PERSONALIZATION, &VALUE_COMMITMENT_V_BYTES);
+        assert_eq!(v.to_affine(), value_commit_v::generator());
+
+        // Different personas or inputs give different points
+        let r = hash_to_pallas_point(VALUE_COMMITMENT_PERSONALIZATION, &VALUE_COMMITMENT_R_BYTES);
+        let other = hash_to_pallas_point(ORCHARD_PERSONALIZATION, &VALUE_COMMITMENT_V_BYTES);
+        assert_ne!(v, r);
+        assert_ne!(v, other);
+    }
+
+    #[test]
+    fn test_prf_scalar_vectors() {
+        let key = [0x42; 32];
+        let vectors: [(&[u8], &[u8], u64, &str); 4] = [
+            (b"", b"", 0, "b871c9e3278e4ed19bcf424003b3c0ced1e7041be8a36712d27085f9734cc034"),
+            (&key, b"nonce", 0, "ed7b0025b80850cf30d0ca79a495b21bdfc222a023e573d2d1fb2f8c6327722c"),
+            (&key, b"nonce", 1, "b4c54895f44c3d58a4d22d254f682809049657f30fc22ba60ec8bb69e4c0ce07"),
+            (
+                &key,
+                b"blind",
+                u64::MAX,
+                "82723e3cdc429cee5246fa41e8c6d32887cc475cdf3454a2aa1a5c7c4dddf915",
+            ),
+        ];
+        for (key, label, counter, expected) in vectors {
+            assert_eq!(hex::encode(prf_scalar(key, label, counter).to_repr()), expected);
+        }
+
+        // Moving bytes between the key and the label changes the output
+        assert_ne!(prf_scalar(b"ab", b"c", 0), prf_scalar(b"a", b"bc", 0));
+    }
+
+    #[test]
+    fn test_value_conversions() -> Result<()> {
+        for value in [0, 1, 42, u64::MAX - 1, u64::MAX] {
+            assert_eq!(base_to_u64_checked(value_to_base(value))?, value);
+            assert_eq!(scalar_to_u64_checked(value_to_scalar(value))?, value);
+        }
+
+        // Just past u64::MAX
+        let over = value_to_base(u64::MAX) + DrkValue::one();
+        assert!(matches!(base_to_u64_checked(over), Err(Error::ValueOutOfRange)));
+        let over = value_to_scalar(u64::MAX) + pallas::Scalar::one();
+        assert!(matches!(scalar_to_u64_checked(over), Err(Error::ValueOutOfRange)));
+
+        // Wrapped around the modulus
+        let negative = -value_to_base(1);
+        assert!(matches!(base_to_u64_checked(negative), Err(Error::ValueOutOfRange)));
+        let negative = -value_to_scalar(1);
+        assert!(matches!(scalar_to_u64_checked(negative), Err(Error::ValueOutOfRange)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pallas_serialization() -> Result<()> {
+        for _ in 0..10 {
+            let base = pallas::Base::random(&mut OsRng);
+            assert_eq!(deserialize::<pallas::Base>(&serialize(&base))?, base);
+            let scalar = pallas::Scalar::random(&mut OsRng);
+            assert_eq!(deserialize::<pallas::Scalar>(&serialize(&scalar))?, scalar);
+            let point = pallas::Point::random(&mut OsRng);
+            assert_eq!(deserialize::<pallas::Point>(&serialize(&point))?, point);
+        }
+        let identity = pallas::Point::identity();
+        assert_eq!(deserialize::<pallas::Point>(&serialize(&identity))?, identity);
+
+        // The moduli, the smallest non-canonical encodings. The low byte of
+        // the moduli minus one is zero, so adding one doesn't carry.
+        let mut p = (-pallas::Base::one()).to_repr();
+        p[0] += 1;
+        assert!(matches!(base_from_bytes(p), Err(Error::NonCanonicalFieldElement)));
+        assert!(deserialize::<pallas::Base>(&p).is_err());
+        let mut q = (-pallas::Scalar::one()).to_repr();
+        q[0] += 1;
+        assert!(matches!(scalar_from_bytes(q), Err(Error::NonCanonicalFieldElement)));
+        assert!(deserialize::<pallas::Scalar>(&q).is_err());
+        assert!(base_from_bytes([0xff; 32]).is_err());
+        assert!(scalar_from_bytes([0xff; 32]).is_err());
+
+        // An x coordinate with no point on the curve
+        let off_curve = (1u64..)
+            .map(|x| value_to_base(x).to_repr())
+            .find(|bytes| bool::from(pallas::Point::from_bytes(bytes).is_none()))
+            .unwrap();
+        assert!(matches!(point_from_bytes(&off_curve), Err(Error::InvalidPallasPoint)));
+        assert!(deserialize::<pallas::Point>(&off_curve).is_err());
+
+        // A non-canonical x coordinate, and the identity with the sign bit set
+        assert!(point_from_bytes(&p).is_err());
+        let mut negative_identity = [0u8; 32];
+        negative_identity[31] = 0x80;
+        assert!(point_from_bytes(&negative_identity).is_err());
+
+        // Truncated input
+        assert!(deserialize::<pallas::Point>(&[0u8; 31]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pedersen_commitment_u64_vectors() {
+        // (value, blind, serialized commitment)
+        let vectors: [(u64, u64, [u8; 32]); 6] = [
+            (0, 0, [0; 32]),
+            (
+                0,
+                1,
+                [
+                    145, 90, 60, 136, 104, 198, 195, 14, 47, 128, 144, 238, 69, 215, 110, 64, 72,
+                    32, 141, 234, 91, 35, 102, 79, 187, 9, 164, 15, 85, 68, 244, 7,
+                ],
+            ),
+            (
+                1,
+                0,
+                [
+                    103, 67, 249, 58, 110, 189, 167, 42, 140, 124, 90, 43, 127, 163, 4, 254, 50,
+                    178, 155, 79, 112, 106, 168, 247, 66, 15, 61, 142, 122, 89, 112, 47,
+                ],
+            ),
+            (
+                42,
+                1337,
+                [
+                    96, 252, 23, 244, 85, 79, 12, 140, 176, 69, 52, 168, 108, 36, 240, 60, 198, 71,
+                    36, 199, 154, 100, 95, 8, 234, 212, 109, 150, 235, 187, 30, 128,
+                ],
+            ),
+            (
+                u64::MAX,
+                1,
+                [
+                    10, 56, 29, 136, 198, 89, 74, 4, 81, 237, 220, 209, 103, 147, 81, 42, 44, 226,
+                    50, 22, 182, 78, 197, 106, 183, 119, 151, 25, 205, 44, 223, 35,
+                ],
+            ),
+            (
+                u64::MAX,
+                u64::MAX,
+                [
+                    228, 56, 93, 203, 196, 110, 66, 46, 88, 4, 91, 86, 243, 7, 211, 108, 39, 66, 9,
+                    84, 126, 163, 120, 197, 238, 192, 63, 23, 247, 200, 3, 142,
+                ],
+            ),
+        ];
+
+        for (value, blind, expected) in vectors {
+            let commit = pedersen_commitment_u64(value, DrkValueBlind::from(blind));
+            assert_eq!(commit.to_bytes(), expected);
+        }
+    }
+
+    #[test]
+    fn test_multiscalar_mul() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for n in [0, 1, 2, 3, 64, 100] {
+            let scalars: Vec<_> = (0..n).map(|_| pallas::Scalar::random(&mut rng)).collect();
+            let mut points: Vec<_> = (0..n).map(|_| pallas::Point::random(&mut rng)).collect();
+            if n > 2 {
+                points[1] = pallas::Point::identity();
+            }
+
+            let naive = scalars
+                .iter()
+                .zip(points.iter())
+                .fold(pallas::Point::identity(), |acc, (s, p)| acc + p * s);
+            assert_eq!(multiscalar_mul(&scalars, &points), naive);
+        }
+    }
+
+    #[test]
+    fn test_pedersen_commitment_sum() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let values: Vec<_> = [0, 1, 42, u64::MAX].into_iter().map(value_to_scalar).collect();
+        let blinds: Vec<_> = (0..4).map(|_| random_value_blind(&mut rng)).collect();
+
+        let naive =
+            values.iter().zip(blinds.iter()).fold(DrkValueCommit::identity(), |acc, (v, b)| {
+                acc + pedersen_commitment_scalar(*v, *b)
+            });
+        assert_eq!(pedersen_commitment_sum(&values, &blinds), naive);
+        assert_eq!(pedersen_commitment_sum(&[], &[]), DrkValueCommit::identity());
+    }
+
+    #[test]
+    fn test_pedersen_commitment_asset() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let a = DrkTokenId::from(1);
+        let b = DrkTokenId::from(2);
+
+        // Each asset has its own generator, unrelated to the shared one
+        let shared =
+            hash_to_pallas_point(VALUE_COMMITMENT_PERSONALIZATION, &VALUE_COMMITMENT_V_BYTES);
+        let (ga, gb) = (asset_value_generator(a), asset_value_generator(b));
+        assert_eq!(ga, asset_value_generator(a));
+        assert_ne!(ga, gb);
+        assert_ne!(ga, shared);
+        assert_ne!(gb, shared);
+        assert_ne!(ga, -gb);
+        assert!(!bool::from(ga.is_identity()) && !bool::from(gb.is_identity()));
+
+        // Commitments to the same asset add up
+        let (b1, b2) = (random_value_blind(&mut rng), random_value_blind(&mut rng));
+        assert_eq!(
+            pedersen_commitment_asset(a, 3, b1) + pedersen_commitment_asset(a, 4, b2),
+            pedersen_commitment_asset(a, 7, b1 + b2)
+        );
+
+        // Commitments to different assets don't
+        let mixed = pedersen_commitment_asset(a, 3, b1) + pedersen_commitment_asset(b, 4, b2);
+        assert_ne!(mixed, pedersen_commitment_asset(a, 7, b1 + b2));
+        assert_ne!(mixed, pedersen_commitment_asset(b, 7, b1 + b2));
+        assert_ne!(pedersen_commitment_asset(a, 5, b1), pedersen_commitment_asset(b, 5, b1));
+    }
+
+    #[test]
+    fn test_random_value_blind() {
+        // The same seed gives the same blinds, so the same commitments
+        let commits = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..4)
+                .map(|value| pedersen_commitment_u64(value, random_value_blind(&mut rng)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(commits(42), commits(42));
+        assert_ne!(commits(42), commits(43));
+
+        // Successive blinds from one RNG differ
+        let mut rng = StdRng::seed_from_u64(42);
+        assert_ne!(random_value_blind(&mut rng), random_value_blind(&mut rng));
+        assert_ne!(os_random_value_blind(), os_random_value_blind());
+    }
+}
@@ -118,6 +118,9 @@ pub enum Error {
     #[error("Channel timed out")]
     ChannelTimeout,
 
+    #[error("Incompatible protocol version: {0}")]
+    IncompatibleVersion(String),
+
     #[error("Network service stopped")]
     NetworkServiceStopped,
 
@@ -136,6 +139,9 @@ pub enum Error {
     #[error("Malformed packet")]
     MalformedPacket,
 
+    #[error("Packet exceeds the size limit")]
+    OversizedPacket,
+
     #[error("Socks proxy error: {0}")]
     SocksError(String),
 
@@ -184,6 +190,15 @@ pub enum Error {
     #[error("Invalid DarkFi address")]
     InvalidAddress,
 
+    #[error("Field element doesn't fit in a u64 value")]
+    ValueOutOfRange,
+
+    #[error("Bytes aren't the canonical encoding of a field element")]
+    NonCanonicalFieldElement,
+
+    #[error("Bytes aren't the canonical encoding of a Pallas point")]
+    InvalidPallasPoint,
+
     // =======================
     // Protocol-related errors
     // =======================
@@ -199,6 +214,9 @@ pub enum Error {
     #[error("Raft error: {0}")]
     RaftError(String),
 
+    #[error("Raft failed to apply a commit: {0}")]
+    RaftApplyError(String),
+
     #[error("JSON-RPC error: {0}")]
     JsonRpcError(String),
 
@@ -370,6 +388,9 @@ pub enum ClientFailed {
 
     #[error("Verify error: {0}")]
     VerifyError(String),
+
+    #[error("Nothing to sweep, the wallet has {0} coins of this token")]
+    NothingToSweep(usize),
 }
 
 impl From<Error> for VerifyFailed {
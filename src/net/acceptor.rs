@@ -1,7 +1,11 @@
-use async_std::sync::Arc;
-use std::{env, fs};
+use async_std::{sync::Arc, task};
+use std::{
+    cmp::min,
+    env, fs,
+    time::{Duration, Instant},
+};
 
-use log::{error, info};
+use log::{error, info, warn};
 use smol::Executor;
 use url::Url;
 
@@ -17,16 +21,58 @@ use super::{
 /// Atomic pointer to Acceptor class.
 pub type AcceptorPtr = Arc<Acceptor>;
 
+/// Backoff bounds for the accept loop once the rate limit is hit.
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(100);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(5);
+
+/// Limits the number of accepted connections per second.
+struct RateLimiter {
+    limit: u32,
+    window_start: Instant,
+    count: u32,
+}
+
+impl RateLimiter {
+    fn new(limit: u32) -> Self {
+        Self { limit, window_start: Instant::now(), count: 0 }
+    }
+
+    /// Returns whether a new connection can be accepted at the given time.
+    fn allow(&mut self, now: Instant) -> bool {
+        if self.limit == 0 {
+            return true
+        }
+
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.count = 0;
+        }
+
+        if self.count >= self.limit {
+            return false
+        }
+
+        self.count += 1;
+        true
+    }
+}
+
 /// Create inbound socket connections.
 pub struct Acceptor {
     channel_subscriber: SubscriberPtr<Result<ChannelPtr>>,
     task: StoppableTaskPtr,
+    rate_limit: u32,
 }
 
 impl Acceptor {
-    /// Create new Acceptor object.
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self { channel_subscriber: Subscriber::new(), task: StoppableTask::new() })
+    /// Create new Acceptor object. Accepts at most `rate_limit` new
+    /// connections per second, or any number of them if it is 0.
+    pub fn new(rate_limit: u32) -> Arc<Self> {
+        Arc::new(Self {
+            channel_subscriber: Subscriber::new(),
+            task: StoppableTask::new(),
+            rate_limit,
+        })
     }
     /// Start accepting inbound socket connections. Creates a listener to start
     /// listening on a local socket address. Then runs an accept loop in a new
@@ -137,11 +183,24 @@ impl Acceptor {
         );
     }
 
-    /// Run the accept loop.
+    /// Run the accept loop. Connections over the rate limit are dropped
+    /// right away, and the loop backs off before accepting new ones.
     async fn run_accept_loop(self: Arc<Self>, listener: Box<dyn TransportListener>) -> Result<()> {
+        let mut limiter = RateLimiter::new(self.rate_limit);
+        let mut backoff = ACCEPT_BACKOFF_MIN;
+
         loop {
             match listener.next().await {
                 Ok((stream, url)) => {
+                    if !limiter.allow(Instant::now()) {
+                        warn!(target: "net", "Inbound rate limit reached, rejecting {}", url);
+                        drop(stream);
+                        task::sleep(backoff).await;
+                        backoff = min(backoff * 2, ACCEPT_BACKOFF_MAX);
+                        continue
+                    }
+                    backoff = ACCEPT_BACKOFF_MIN;
+
                     let channel = Channel::new(stream, url).await;
                     self.channel_subscriber.notify(Ok(channel)).await;
                 }
@@ -165,3 +224,26 @@ impl Acceptor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2);
+        let start = Instant::now();
+
+        assert!(limiter.allow(start));
+        assert!(limiter.allow(start + Duration::from_millis(100)));
+        assert!(!limiter.allow(start + Duration::from_millis(200)));
+        assert!(!limiter.allow(start + Duration::from_millis(999)));
+        // A new window starts after a second
+        assert!(limiter.allow(start + Duration::from_secs(1)));
+
+        let mut limiter = RateLimiter::new(0);
+        for _ in 0..100 {
+            assert!(limiter.allow(start));
+        }
+    }
+}
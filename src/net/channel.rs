@@ -4,7 +4,7 @@ use futures::{
     io::{ReadHalf, WriteHalf},
     AsyncReadExt,
 };
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use rand::Rng;
 use serde_json::json;
 use smol::Executor;
@@ -12,13 +12,16 @@ use url::Url;
 
 use crate::{
     system::{StoppableTask, StoppableTaskPtr, Subscriber, SubscriberPtr, Subscription},
-    util::NanoTimestamp,
+    util::{NanoTimestamp, Timestamp},
     Error, Result,
 };
 
 use super::{
+    compression::{compress_packet, decompress_packet, Compression},
     message,
     message_subscriber::{MessageSubscription, MessageSubsystem},
+    metrics::{NetMetrics, NetMetricsPtr},
+    reputation::{ReputationPtr, Violation},
     TransportStream,
 };
 
@@ -28,6 +31,8 @@ pub type ChannelPtr = Arc<Channel>;
 struct ChannelInfo {
     random_id: u32,
     remote_node_id: String,
+    // protocol version announced by the peer in its version message
+    remote_version: Option<u32>,
     last_msg: String,
     last_status: String,
     // Message log which is cleared on querying get_info
@@ -39,6 +44,7 @@ impl ChannelInfo {
         Self {
             random_id: rand::thread_rng().gen(),
             remote_node_id: String::new(),
+            remote_version: None,
             last_msg: String::new(),
             last_status: String::new(),
             log: Mutex::new(Vec::new()),
@@ -49,6 +55,7 @@ impl ChannelInfo {
         let result = json!({
             "random_id": self.random_id,
             "remote_node_id": self.remote_node_id,
+            "remote_version": self.remote_version,
             "last_msg": self.last_msg,
             "last_status": self.last_status,
             "log": self.log.lock().await.clone(),
@@ -68,6 +75,9 @@ pub struct Channel {
     receive_task: StoppableTaskPtr,
     stopped: Mutex<bool>,
     info: Mutex<ChannelInfo>,
+    metrics: NetMetricsPtr,
+    compression: Mutex<Option<Compression>>,
+    reputation: Mutex<Option<ReputationPtr>>,
 }
 
 impl Channel {
@@ -91,6 +101,9 @@ impl Channel {
             receive_task: StoppableTask::new(),
             stopped: Mutex::new(false),
             info: Mutex::new(ChannelInfo::new()),
+            metrics: NetMetrics::new(),
+            compression: Mutex::new(None),
+            reputation: Mutex::new(None),
         })
     }
 
@@ -98,6 +111,11 @@ impl Channel {
         self.info.lock().await.get_info().await
     }
 
+    /// Traffic counters for this channel.
+    pub fn metrics(&self) -> NetMetricsPtr {
+        self.metrics.clone()
+    }
+
     /// Starts the channel. Runs a receive loop to start receiving messages or
     /// handles a network failure.
     pub fn start(self: Arc<Self>, executor: Arc<Executor<'_>>) {
@@ -210,8 +228,17 @@ impl Channel {
             info.log.lock().await.push((time, "send".to_string(), packet.command.clone()));
         }
 
+        let command = packet.command.clone();
+        let packet = match *self.compression.lock().await {
+            Some(compression) => compress_packet(packet, compression)?,
+            None => packet,
+        };
+        let size = packet.size();
+
         let stream = &mut *self.writer.lock().await;
-        message::send_packet(stream, packet).await
+        message::send_packet(stream, packet).await?;
+        self.metrics.record_sent(&command, size);
+        Ok(())
     }
 
     /// Subscribe to a messages on the message subsystem.
@@ -242,6 +269,44 @@ impl Channel {
         self.info.lock().await.remote_node_id = remote_node_id;
     }
 
+    /// Protocol version of the peer, once it sent its version message.
+    pub async fn remote_version(&self) -> Option<u32> {
+        self.info.lock().await.remote_version
+    }
+
+    pub async fn set_remote_version(&self, version: u32) {
+        self.info.lock().await.remote_version = Some(version);
+    }
+
+    /// Compress the packets sent from now on, once the peer announced it
+    /// can decompress them.
+    pub async fn set_compression(&self, compression: Compression) {
+        *self.compression.lock().await = Some(compression);
+    }
+
+    /// Keep the score of the peer in `reputation` from now on.
+    pub async fn set_reputation(&self, reputation: ReputationPtr) {
+        *self.reputation.lock().await = Some(reputation);
+    }
+
+    /// Lower the score of the peer for a protocol violation, and stop the
+    /// channel once the peer gets banned. Returns whether it was stopped.
+    pub async fn report_violation(&self, violation: Violation) -> bool {
+        let reputation = self.reputation.lock().await.clone();
+        let banned = match reputation {
+            Some(reputation) => {
+                reputation.penalize(&self.address, violation, Timestamp::current_time().0)
+            }
+            None => false,
+        };
+
+        if banned {
+            warn!(target: "net", "Stopping channel of banned peer [{}]", self.address());
+            self.stop().await;
+        }
+        banned
+    }
+
     /// End of file error. Triggered when unexpected end of file occurs.
     fn is_eof_error(err: Error) -> bool {
         match err {
@@ -284,6 +349,15 @@ impl Channel {
                     } else {
                         error!("Read error on channel: {}", err);
                     }
+                    match err {
+                        Error::OversizedPacket => {
+                            self.report_violation(Violation::OversizedPacket).await;
+                        }
+                        Error::MalformedPacket | Error::Utf8Error(_) => {
+                            self.report_violation(Violation::MalformedMessage).await;
+                        }
+                        _ => {}
+                    }
                     debug!(target: "net",
                      "Channel::receive_loop() stopping channel {:?}",
                      self.address()
@@ -292,6 +366,17 @@ impl Channel {
                     return Err(Error::ChannelStopped)
                 }
             };
+            let size = packet.size();
+            let packet = match decompress_packet(packet) {
+                Ok(packet) => packet,
+                Err(err) => {
+                    error!("Malformed compressed packet on channel {}: {}", self.address(), err);
+                    self.report_violation(Violation::MalformedMessage).await;
+                    self.stop().await;
+                    return Err(Error::ChannelStopped)
+                }
+            };
+            self.metrics.record_received(&packet.command, size);
             {
                 let info = &mut *self.info.lock().await;
                 info.last_msg = packet.command.clone();
@@ -302,7 +387,11 @@ impl Channel {
             }
 
             // Send result to our subscribers
-            self.message_subsystem.notify(&packet.command, packet.payload).await;
+            if self.message_subsystem.notify(&packet.command, packet.payload).await.is_err() &&
+                self.report_violation(Violation::MalformedMessage).await
+            {
+                return Err(Error::ChannelStopped)
+            }
         }
     }
 
@@ -0,0 +1,161 @@
+use std::{
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+use serde::Deserialize;
+
+use crate::{Error, Result};
+
+use super::message::Packet;
+
+/// Payloads smaller than this are always sent as they are, compressing
+/// them saves too little to be worth the CPU time.
+pub const MIN_COMPRESSED_SIZE: usize = 256;
+
+/// Upper bound on the size of a decompressed payload, so a small packet
+/// can't make a node allocate an unbounded amount of memory.
+pub const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Separates the algorithm from the command of a compressed packet, e.g.
+/// `deflate:raft`. Message names never contain it.
+const SEPARATOR: char = ':';
+
+/// Algorithms compressing the packet payloads. Nodes announce the ones
+/// they can decompress in their version message, and only compress the
+/// packets sent to peers announcing the configured one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Deflate,
+}
+
+/// Every algorithm this node can decompress.
+pub const SUPPORTED_COMPRESSIONS: [Compression; 1] = [Compression::Deflate];
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Deflate => "deflate",
+        }
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Deflate => {
+                let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::fast());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressed = vec![];
+        match self {
+            Self::Deflate => {
+                DeflateDecoder::new(data)
+                    .take(MAX_DECOMPRESSED_SIZE + 1)
+                    .read_to_end(&mut decompressed)?;
+            }
+        }
+
+        if decompressed.len() as u64 > MAX_DECOMPRESSED_SIZE {
+            return Err(Error::MalformedPacket)
+        }
+        Ok(decompressed)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        SUPPORTED_COMPRESSIONS
+            .iter()
+            .find(|compression| compression.name() == s.to_lowercase())
+            .copied()
+            .ok_or_else(|| format!("unknown compression: {}", s))
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Compress the payload of an outbound packet, marking the algorithm in
+/// its command. Small payloads, and the ones that don't shrink like
+/// encrypted data, are left as they are.
+pub fn compress_packet(packet: Packet, compression: Compression) -> Result<Packet> {
+    if packet.payload.len() < MIN_COMPRESSED_SIZE {
+        return Ok(packet)
+    }
+
+    let payload = compression.compress(&packet.payload)?;
+    if payload.len() >= packet.payload.len() {
+        return Ok(packet)
+    }
+
+    let command = format!("{}{}{}", compression.name(), SEPARATOR, packet.command);
+    Ok(Packet { command, payload })
+}
+
+/// Decompress the payload of an inbound packet compressed by
+/// `compress_packet()`, restoring its command. Other packets are
+/// returned as they are.
+pub fn decompress_packet(packet: Packet) -> Result<Packet> {
+    let (name, command) = match packet.command.split_once(SEPARATOR) {
+        Some(split) => split,
+        None => return Ok(packet),
+    };
+
+    let compression = Compression::from_str(name).map_err(|_| Error::MalformedPacket)?;
+    let payload = compression.decompress(&packet.payload)?;
+    Ok(Packet { command: command.to_string(), payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::*;
+
+    fn packet(payload: Vec<u8>) -> Packet {
+        Packet { command: "raft".into(), payload }
+    }
+
+    #[test]
+    fn compress_and_decompress() -> Result<()> {
+        // Repetitive payloads shrink and come back unchanged
+        let payload: Vec<u8> = (0..4096).map(|i| (i % 16) as u8).collect();
+        let compressed = compress_packet(packet(payload.clone()), Compression::Deflate)?;
+        assert_eq!(compressed.command, "deflate:raft");
+        assert!(compressed.payload.len() < payload.len());
+        let decompressed = decompress_packet(compressed)?;
+        assert_eq!(decompressed.command, "raft");
+        assert_eq!(decompressed.payload, payload);
+
+        // Small and random payloads are sent as they are
+        let small = compress_packet(packet(vec![0; 16]), Compression::Deflate)?;
+        assert_eq!(small.command, "raft");
+        let mut random = vec![0; 4096];
+        rand::thread_rng().fill_bytes(&mut random);
+        let random = compress_packet(packet(random.clone()), Compression::Deflate)?;
+        assert_eq!(random.command, "raft");
+        assert_eq!(decompress_packet(random)?.command, "raft");
+
+        // Unknown algorithms and corrupted payloads are rejected
+        let unknown = Packet { command: "lz4:raft".into(), payload: vec![1, 2, 3] };
+        assert!(decompress_packet(unknown).is_err());
+        let corrupted = Packet { command: "deflate:raft".into(), payload: vec![0xff; 64] };
+        assert!(decompress_packet(corrupted).is_err());
+
+        assert_eq!("Deflate".parse(), Ok(Compression::Deflate));
+        assert!("zstd".parse::<Compression>().is_err());
+        Ok(())
+    }
+}
@@ -30,6 +30,11 @@ impl Hosts {
         }
     }
 
+    /// Remove a host from the host list.
+    pub async fn remove(&self, addr: &Url) {
+        self.addrs.lock().await.retain(|a| a != addr)
+    }
+
     /// Return the list of hosts.
     pub async fn load_all(&self) -> Vec<Url> {
         self.addrs.lock().await.clone()
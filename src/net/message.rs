@@ -11,6 +11,20 @@ use crate::{
 
 const MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x7d];
 
+/// Version of the messages sent over the network, net and raft ones
+/// included. Raise it when a change makes them undecodable by older nodes,
+/// along with `MIN_PROTOCOL_VERSION` if older nodes can't keep up anymore.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest protocol version of the peers we talk with. Nodes from before
+/// versions were announced count as version 0.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Longest command name accepted in a packet.
+const MAX_COMMAND_LEN: usize = 64;
+/// Largest payload accepted in a packet, so that a peer can't make us
+/// allocate arbitrary amounts of memory.
+const MAX_PAYLOAD_LEN: usize = 32 * 1024 * 1024;
+
 /// Generic message template.
 pub trait Message: 'static + Encodable + Decodable + Send + Sync {
     fn name() -> &'static str;
@@ -38,6 +52,12 @@ pub struct AddrsMessage {
 /// Requests version information of outbound connection.
 pub struct VersionMessage {
     pub node_id: String,
+    /// Compression algorithms the node can decompress
+    pub compression: Vec<String>,
+    /// `PROTOCOL_VERSION` of the node
+    pub version: u32,
+    /// `MIN_PROTOCOL_VERSION` of the node
+    pub min_version: u32,
 }
 
 /// Sends version information to inbound connection. Response to VersionMessage.
@@ -138,13 +158,21 @@ impl Encodable for VersionMessage {
     fn encode<S: io::Write>(&self, mut s: S) -> Result<usize> {
         let mut len = 0;
         len += self.node_id.encode(&mut s)?;
+        len += self.compression.encode(&mut s)?;
+        len += self.version.encode(&mut s)?;
+        len += self.min_version.encode(&mut s)?;
         Ok(len)
     }
 }
 
 impl Decodable for VersionMessage {
     fn decode<D: io::Read>(mut d: D) -> Result<Self> {
-        Ok(Self { node_id: Decodable::decode(&mut d)? })
+        let node_id = Decodable::decode(&mut d)?;
+        // Older nodes don't announce any compression, nor their versions
+        let compression = Decodable::decode(&mut d).unwrap_or_default();
+        let version = Decodable::decode(&mut d).unwrap_or(0);
+        let min_version = Decodable::decode(&mut d).unwrap_or(0);
+        Ok(Self { node_id, compression, version, min_version })
     }
 }
 
@@ -167,6 +195,17 @@ pub struct Packet {
     pub payload: Vec<u8>,
 }
 
+impl Packet {
+    /// Size of the packet on the wire, including the header.
+    pub fn size(&self) -> u64 {
+        let command_len = self.command.len() as u64;
+        let payload_len = self.payload.len() as u64;
+        (MAGIC_BYTES.len() + VarInt(command_len).length() + VarInt(payload_len).length()) as u64 +
+            command_len +
+            payload_len
+    }
+}
+
 /// Reads and decodes an inbound payload.
 pub async fn read_packet<R: AsyncRead + Unpin + Sized>(stream: &mut R) -> Result<Packet> {
     // Packets have a 4 byte header of magic digits
@@ -183,6 +222,9 @@ pub async fn read_packet<R: AsyncRead + Unpin + Sized>(stream: &mut R) -> Result
 
     // The type of the message
     let command_len = VarInt::decode_async(stream).await?.0 as usize;
+    if command_len > MAX_COMMAND_LEN {
+        return Err(Error::OversizedPacket)
+    }
     let mut cmd = vec![0u8; command_len];
     if command_len > 0 {
         stream.read_exact(&mut cmd).await?;
@@ -191,6 +233,9 @@ pub async fn read_packet<R: AsyncRead + Unpin + Sized>(stream: &mut R) -> Result
     debug!(target: "net", "read command: {}", cmd);
 
     let payload_len = VarInt::decode_async(stream).await?.0 as usize;
+    if payload_len > MAX_PAYLOAD_LEN {
+        return Err(Error::OversizedPacket)
+    }
 
     // The message-dependent data (see message types)
     let mut payload = vec![0u8; payload_len];
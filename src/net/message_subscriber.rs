@@ -45,7 +45,7 @@ impl<M: Message> MessageSubscription<M> {
 #[async_trait]
 /// Generic interface for message dispatcher.
 trait MessageDispatcherInterface: Send + Sync {
-    async fn trigger(&self, payload: Vec<u8>);
+    async fn trigger(&self, payload: Vec<u8>) -> Result<()>;
 
     async fn trigger_error(&self, err: Error);
 
@@ -133,18 +133,21 @@ impl<M: Message> MessageDispatcher<M> {
 #[async_trait]
 // Local implementation of the Message Dispatcher Interface.
 impl<M: Message> MessageDispatcherInterface for MessageDispatcher<M> {
-    /// Deserialize data into a message type.
-    async fn trigger(&self, payload: Vec<u8>) {
+    /// Deserialize data into a message type. Returns the error of a
+    /// message that doesn't decode.
+    async fn trigger(&self, payload: Vec<u8>) -> Result<()> {
         // deserialize data into type
         // send down the pipes
         let cursor = Cursor::new(payload);
         match M::decode(cursor) {
             Ok(message) => {
                 let message = Ok(Arc::new(message));
-                self.trigger_all(message).await
+                self.trigger_all(message).await;
+                Ok(())
             }
             Err(err) => {
                 error!("Unable to decode data. Dropping...: {}", err);
+                Err(err)
             }
         }
     }
@@ -202,19 +205,18 @@ impl MessageSubsystem {
     }
 
     /// Sends a message out to subscribers. Returns an error if the message
-    /// doesn't send.
-    pub async fn notify(&self, command: &str, payload: Vec<u8>) {
+    /// doesn't decode. Commands without a dispatcher are dropped.
+    pub async fn notify(&self, command: &str, payload: Vec<u8>) -> Result<()> {
         let dispatcher = self.dispatchers.lock().await.get(command).cloned();
 
         match dispatcher {
-            Some(dispatcher) => {
-                dispatcher.trigger(payload).await;
-            }
+            Some(dispatcher) => dispatcher.trigger(payload).await,
             None => {
                 warn!(
                     "MessageSubsystem::notify(\"{}\", payload) did not find a dispatcher",
                     command
                 );
+                Ok(())
             }
         }
     }
@@ -281,7 +283,10 @@ async fn _do_message_subscriber_test() {
     // receive message and publish
     //   1. based on string, lookup relevant dispatcher interface
     //   2. publish data there
-    subsystem.notify("verver", payload).await;
+    subsystem.notify("verver", payload).await.unwrap();
+
+    // an undecodable payload is reported, and not published
+    assert!(subsystem.notify("verver", vec![]).await.is_err());
 
     // receive
     //    1. do a get easy
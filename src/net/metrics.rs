@@ -0,0 +1,152 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, RwLock,
+};
+
+use fxhash::FxHashMap;
+use serde_json::json;
+
+/// Atomic pointer to network metrics.
+pub type NetMetricsPtr = Arc<NetMetrics>;
+
+/// Counters for a single message command.
+#[derive(Default)]
+struct MessageCounter {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+/// Traffic counters for a channel, or for the whole p2p network.
+///
+/// Byte counts include the packet framing (magic bytes and length
+/// prefixes). Counters are atomics, so updating them never blocks the
+/// read and write paths. The per-command map is only write-locked the
+/// first time a command is seen.
+#[derive(Default)]
+pub struct NetMetrics {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    commands: RwLock<FxHashMap<String, Arc<MessageCounter>>>,
+}
+
+impl NetMetrics {
+    pub fn new() -> NetMetricsPtr {
+        Arc::new(Self::default())
+    }
+
+    fn command(&self, command: &str) -> Arc<MessageCounter> {
+        if let Some(counter) = self.commands.read().unwrap().get(command) {
+            return counter.clone()
+        }
+
+        self.commands.write().unwrap().entry(command.to_string()).or_default().clone()
+    }
+
+    /// Record an outbound packet of `bytes` bytes.
+    pub fn record_sent(&self, command: &str, bytes: u64) {
+        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.command(command).sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record an inbound packet of `bytes` bytes.
+    pub fn record_received(&self, command: &str, bytes: u64) {
+        self.bytes_received.fetch_add(bytes, Ordering::Relaxed);
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.command(command).received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Add all the counters of `other` to this one.
+    pub fn merge(&self, other: &NetMetrics) {
+        self.bytes_sent.fetch_add(other.bytes_sent(), Ordering::Relaxed);
+        self.bytes_received.fetch_add(other.bytes_received(), Ordering::Relaxed);
+        self.messages_sent.fetch_add(other.messages_sent(), Ordering::Relaxed);
+        self.messages_received.fetch_add(other.messages_received(), Ordering::Relaxed);
+
+        for (command, (sent, received)) in other.commands() {
+            let counter = self.command(&command);
+            counter.sent.fetch_add(sent, Ordering::Relaxed);
+            counter.received.fetch_add(received, Ordering::Relaxed);
+        }
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    pub fn messages_sent(&self) -> u64 {
+        self.messages_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn messages_received(&self) -> u64 {
+        self.messages_received.load(Ordering::Relaxed)
+    }
+
+    /// Messages sent and received, by command.
+    pub fn commands(&self) -> FxHashMap<String, (u64, u64)> {
+        self.commands
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(command, counter)| {
+                let sent = counter.sent.load(Ordering::Relaxed);
+                let received = counter.received.load(Ordering::Relaxed);
+                (command.clone(), (sent, received))
+            })
+            .collect()
+    }
+
+    pub fn get_info(&self) -> serde_json::Value {
+        let commands: serde_json::Map<String, serde_json::Value> = self
+            .commands()
+            .into_iter()
+            .map(|(command, (sent, received))| {
+                (command, json!({ "sent": sent, "received": received }))
+            })
+            .collect();
+
+        json!({
+            "bytes_sent": self.bytes_sent(),
+            "bytes_received": self.bytes_received(),
+            "messages_sent": self.messages_sent(),
+            "messages_received": self.messages_received(),
+            "commands": commands,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_merge() {
+        let channel = NetMetrics::new();
+        channel.record_sent("ping", 10);
+        channel.record_sent("ping", 10);
+        channel.record_received("pong", 12);
+
+        assert_eq!(channel.bytes_sent(), 20);
+        assert_eq!(channel.bytes_received(), 12);
+        assert_eq!(channel.messages_sent(), 2);
+        assert_eq!(channel.messages_received(), 1);
+        assert_eq!(channel.commands()["ping"], (2, 0));
+        assert_eq!(channel.commands()["pong"], (0, 1));
+
+        let total = NetMetrics::new();
+        total.record_received("ping", 10);
+        total.merge(&channel);
+
+        assert_eq!(total.bytes_sent(), 20);
+        assert_eq!(total.bytes_received(), 22);
+        assert_eq!(total.messages_received(), 2);
+        assert_eq!(total.commands()["ping"], (2, 1));
+        assert_eq!(total.commands()["pong"], (0, 1));
+    }
+}
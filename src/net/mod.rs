@@ -10,6 +10,10 @@ pub mod acceptor;
 /// Implements message functionality and the message subscriber subsystem.
 pub mod channel;
 
+/// Optional compression of the packet payloads, negotiated with each peer
+/// during the version handshake.
+pub mod compression;
+
 /// Handles the creation of outbound connections. Used to establish an outbound
 /// connection.
 pub mod connector;
@@ -48,6 +52,8 @@ pub mod message_subscriber;
 /// converted into messages and passed to an event loop.
 pub mod message;
 
+pub mod metrics;
+
 /// P2P provides all core functionality to interact with the peer-to-peer
 /// network.
 ///
@@ -74,6 +80,11 @@ pub mod p2p;
 /// asynchronous execution of the protocols.
 pub mod protocol;
 
+/// Scores of the peers violating the protocol, e.g. by sending packets
+/// that don't decode. Peers scoring too low are banned for a while, their
+/// channels are stopped and no new connection is made with them.
+pub mod reputation;
+
 /// Defines the interaction between nodes during a connection. Consists of an
 /// inbound session, which describes how to set up an incoming connection, and
 /// an outbound session, which describes setting up an outbound connection. Also
@@ -90,12 +101,15 @@ pub mod transport;
 
 pub use acceptor::{Acceptor, AcceptorPtr};
 pub use channel::{Channel, ChannelPtr};
+pub use compression::Compression;
 pub use connector::Connector;
 pub use hosts::{Hosts, HostsPtr};
 pub use message::Message;
 pub use message_subscriber::MessageSubscription;
+pub use metrics::{NetMetrics, NetMetricsPtr};
 pub use p2p::{P2p, P2pPtr};
 pub use protocol::{ProtocolBase, ProtocolBasePtr, ProtocolJobsManager, ProtocolJobsManagerPtr};
+pub use reputation::{PeerScore, Reputation, ReputationPtr, Violation};
 pub use session::{SESSION_ALL, SESSION_INBOUND, SESSION_MANUAL, SESSION_OUTBOUND, SESSION_SEED};
 pub use settings::{Settings, SettingsPtr};
 pub use transport::{
@@ -9,12 +9,15 @@ use url::Url;
 
 use crate::{
     system::{Subscriber, SubscriberPtr, Subscription},
+    util::Timestamp,
     Error, Result,
 };
 
 use super::{
     message::Message,
+    metrics::{NetMetrics, NetMetricsPtr},
     protocol::{register_default_protocols, ProtocolRegistry},
+    reputation::{Reputation, ReputationPtr, Violation},
     session::{InboundSession, ManualSession, OutboundSession, SeedSession, Session},
     Channel, ChannelPtr, Hosts, HostsPtr, Settings, SettingsPtr,
 };
@@ -70,12 +73,18 @@ pub struct P2p {
     state: Mutex<P2pState>,
 
     settings: SettingsPtr,
+
+    // Traffic of the channels which were removed
+    closed_metrics: NetMetricsPtr,
+
+    reputation: ReputationPtr,
 }
 
 impl P2p {
     /// Create a new p2p network.
     pub async fn new(settings: Settings) -> Arc<Self> {
         let settings = Arc::new(settings);
+        let reputation = Reputation::new(settings.ban_threshold, settings.ban_duration_seconds);
 
         let self_ = Arc::new(Self {
             pending: Mutex::new(FxHashSet::default()),
@@ -89,6 +98,8 @@ impl P2p {
             session_outbound: Mutex::new(None),
             state: Mutex::new(P2pState::Open),
             settings,
+            closed_metrics: NetMetrics::new(),
+            reputation,
         });
 
         let parent = Arc::downgrade(&self_);
@@ -116,6 +127,8 @@ impl P2p {
             "session_inbound": self.session_inbound().await.get_info().await,
             "session_outbound": self.session_outbound().await.get_info().await,
             "state": self.state.lock().await.to_string(),
+            "metrics": self.metrics_info().await,
+            "reputation": self.reputation.get_info(Timestamp::current_time().0),
         })
     }
 
@@ -209,7 +222,9 @@ impl P2p {
 
     /// Remove a channel from the list of connected channels.
     pub async fn remove(&self, channel: ChannelPtr) {
-        self.channels.lock().await.remove(&channel.address());
+        if let Some(channel) = self.channels.lock().await.remove(&channel.address()) {
+            self.closed_metrics.merge(&channel.metrics());
+        }
     }
 
     /// Check whether a channel is stored in the list of connected channels.
@@ -227,6 +242,64 @@ impl P2p {
         self.pending.lock().await.remove(addr);
     }
 
+    /// Traffic counters summed over every channel that has been connected.
+    pub async fn total_metrics(&self) -> NetMetrics {
+        let total = NetMetrics::default();
+        total.merge(&self.closed_metrics);
+        for channel in self.channels.lock().await.values() {
+            total.merge(&channel.metrics());
+        }
+        total
+    }
+
+    /// Traffic counters of each connected channel.
+    pub async fn peer_metrics(&self) -> FxHashMap<Url, NetMetricsPtr> {
+        self.channels
+            .lock()
+            .await
+            .iter()
+            .map(|(addr, channel)| (addr.clone(), channel.metrics()))
+            .collect()
+    }
+
+    async fn metrics_info(&self) -> serde_json::Value {
+        let peers: serde_json::Map<String, serde_json::Value> = self
+            .peer_metrics()
+            .await
+            .into_iter()
+            .map(|(addr, metrics)| (addr.to_string(), metrics.get_info()))
+            .collect();
+
+        json!({
+            "total": self.total_metrics().await.get_info(),
+            "peers": peers,
+        })
+    }
+
+    /// Return an atomic pointer to the scores and bans of the peers.
+    pub fn reputation(&self) -> ReputationPtr {
+        self.reputation.clone()
+    }
+
+    /// Whether the peer at `addr` is currently banned.
+    pub fn is_banned(&self, addr: &Url) -> bool {
+        self.reputation.is_banned(addr, Timestamp::current_time().0)
+    }
+
+    /// Lower the score of the peer at `addr` for a protocol violation
+    /// detected by a protocol, stopping its channel once it gets banned.
+    pub async fn penalize(&self, addr: &Url, violation: Violation) {
+        let channel = self.channels.lock().await.get(addr).cloned();
+        match channel {
+            Some(channel) => {
+                channel.report_violation(violation).await;
+            }
+            None => {
+                self.reputation.penalize(addr, violation, Timestamp::current_time().0);
+            }
+        }
+    }
+
     /// Return the number of connected channels.
     pub async fn connections_count(&self) -> usize {
         self.channels.lock().await.len()
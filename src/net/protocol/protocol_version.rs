@@ -6,7 +6,31 @@ use smol::Executor;
 
 use crate::{Error, Result};
 
-use super::super::{message, message_subscriber::MessageSubscription, ChannelPtr, SettingsPtr};
+use super::super::{
+    compression::SUPPORTED_COMPRESSIONS,
+    message::{self, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION},
+    message_subscriber::MessageSubscription,
+    ChannelPtr, SettingsPtr,
+};
+
+/// Why we can't talk with a peer announcing `version` and accepting peers
+/// from `min_version` on, if we can't, and what to do about it.
+fn version_mismatch(version: u32, min_version: u32) -> Option<String> {
+    if version < MIN_PROTOCOL_VERSION {
+        return Some(format!(
+            "the peer speaks protocol version {}, older than version {} we need, it must be upgraded",
+            version, MIN_PROTOCOL_VERSION
+        ))
+    }
+    if PROTOCOL_VERSION < min_version {
+        return Some(format!(
+            "the peer needs protocol version {} or later and we speak version {}, \
+             upgrade this node",
+            min_version, PROTOCOL_VERSION
+        ))
+    }
+    None
+}
 
 /// Implements the protocol version handshake sent out by nodes at the beginning
 /// of a connection.
@@ -75,7 +99,12 @@ impl ProtocolVersion {
     /// Send version info and wait for version acknowledgement.
     async fn send_version(self: Arc<Self>) -> Result<()> {
         debug!(target: "net", "ProtocolVersion::send_version() [START]");
-        let version = message::VersionMessage { node_id: self.settings.node_id.clone() };
+        let version = message::VersionMessage {
+            node_id: self.settings.node_id.clone(),
+            compression: SUPPORTED_COMPRESSIONS.iter().map(|c| c.name().to_string()).collect(),
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+        };
         self.channel.clone().send(version).await?;
 
         // Wait for version acknowledgement
@@ -91,8 +120,23 @@ impl ProtocolVersion {
         // Receive version message
         let version = self.version_sub.receive().await?;
         self.channel.set_remote_node_id(version.node_id.clone()).await;
+        self.channel.set_remote_version(version.version).await;
+
+        // Refuse peers we can't decode the messages of, rather than failing
+        // on every message later. The peer gets no verack, so it gives up.
+        if let Some(reason) = version_mismatch(version.version, version.min_version) {
+            warn!(target: "net", "Refusing peer [{}]: {}", self.channel.address(), reason);
+            return Err(Error::IncompatibleVersion(reason))
+        }
 
-        // Check the message is OK
+        // Compress what we send if the peer can decompress it, peers
+        // not supporting it keep getting uncompressed packets
+        if let Some(compression) = self.settings.compression {
+            if version.compression.iter().any(|name| name == compression.name()) {
+                debug!(target: "net", "Compressing packets with {}", compression);
+                self.channel.set_compression(compression).await;
+            }
+        }
 
         // Send version acknowledgement
         let verack = message::VerackMessage {};
@@ -102,3 +146,22 @@ impl ProtocolVersion {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_mismatch() {
+        assert!(version_mismatch(PROTOCOL_VERSION, MIN_PROTOCOL_VERSION).is_none());
+        assert!(version_mismatch(PROTOCOL_VERSION + 1, PROTOCOL_VERSION).is_none());
+
+        // Nodes from before versions were announced
+        let reason = version_mismatch(0, 0).unwrap();
+        assert!(reason.contains("it must be upgraded"));
+
+        // A newer peer which dropped support for our version
+        let reason = version_mismatch(PROTOCOL_VERSION + 2, PROTOCOL_VERSION + 1).unwrap();
+        assert!(reason.contains("upgrade this node"));
+    }
+}
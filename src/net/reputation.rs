@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex};
+
+use fxhash::FxHashMap;
+use log::warn;
+use serde_json::json;
+use url::Url;
+
+/// Atomic pointer to the peer reputations.
+pub type ReputationPtr = Arc<Reputation>;
+
+/// Score of a peer never seen misbehaving, or whose ban expired.
+pub const INITIAL_SCORE: i32 = 100;
+
+/// Protocol violations lowering the score of a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// A packet or message that doesn't decode
+    MalformedMessage,
+    /// A packet over the size limit
+    OversizedPacket,
+    /// A message failing authentication
+    FailedAuth,
+}
+
+impl Violation {
+    fn penalty(&self) -> i32 {
+        match self {
+            Self::MalformedMessage => 20,
+            Self::OversizedPacket => 50,
+            Self::FailedAuth => 25,
+        }
+    }
+}
+
+/// Current score of a peer, and when its ban ends if it is banned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerScore {
+    pub score: i32,
+    pub banned_until: Option<i64>,
+}
+
+impl Default for PeerScore {
+    fn default() -> Self {
+        Self { score: INITIAL_SCORE, banned_until: None }
+    }
+}
+
+/// Scores of the peers which violated the protocol, banning the ones
+/// falling below a threshold for a while.
+///
+/// Peers are identified by host, as inbound connections come from a
+/// different port each time. Timestamps are in seconds.
+pub struct Reputation {
+    ban_threshold: i32,
+    ban_duration: i64,
+    peers: Mutex<FxHashMap<String, PeerScore>>,
+}
+
+impl Reputation {
+    /// Peers scoring below `ban_threshold` are banned for `ban_duration`
+    /// seconds. A zero duration disables the bans.
+    pub fn new(ban_threshold: i32, ban_duration: u32) -> ReputationPtr {
+        Arc::new(Self {
+            ban_threshold,
+            ban_duration: ban_duration as i64,
+            peers: Mutex::new(FxHashMap::default()),
+        })
+    }
+
+    /// Key of a peer address, its host when it has one.
+    fn peer_key(addr: &Url) -> String {
+        match addr.host_str() {
+            Some(host) => host.to_string(),
+            None => addr.to_string(),
+        }
+    }
+
+    /// Lower the score of the peer at `addr` for a violation. Returns
+    /// whether the peer is banned.
+    pub fn penalize(&self, addr: &Url, violation: Violation, now: i64) -> bool {
+        let key = Self::peer_key(addr);
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(key.clone()).or_default();
+        if peer.banned_until.is_some_and(|until| until <= now) {
+            *peer = PeerScore::default();
+        }
+
+        peer.score -= violation.penalty();
+        warn!(target: "net", "Peer {} violated the protocol ({:?}), score {}", key, violation, peer.score);
+
+        if peer.banned_until.is_none() && self.ban_duration > 0 && peer.score < self.ban_threshold {
+            warn!(target: "net", "Banning peer {} for {} seconds", key, self.ban_duration);
+            peer.banned_until = Some(now + self.ban_duration);
+        }
+        peer.banned_until.is_some()
+    }
+
+    /// Whether the peer at `addr` is banned at the time `now`.
+    pub fn is_banned(&self, addr: &Url, now: i64) -> bool {
+        let peers = self.peers.lock().unwrap();
+        match peers.get(&Self::peer_key(addr)) {
+            Some(peer) => peer.banned_until.is_some_and(|until| until > now),
+            None => false,
+        }
+    }
+
+    /// Scores of the peers which violated the protocol, by host.
+    pub fn scores(&self) -> FxHashMap<String, PeerScore> {
+        self.peers.lock().unwrap().clone()
+    }
+
+    /// Peers banned at the time `now`, with the end of their ban.
+    pub fn bans(&self, now: i64) -> FxHashMap<String, i64> {
+        self.peers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(host, peer)| match peer.banned_until {
+                Some(until) if until > now => Some((host.clone(), until)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_info(&self, now: i64) -> serde_json::Value {
+        let scores: serde_json::Map<String, serde_json::Value> =
+            self.scores().into_iter().map(|(host, peer)| (host, json!(peer.score))).collect();
+        let banned: serde_json::Map<String, serde_json::Value> =
+            self.bans(now).into_iter().map(|(host, until)| (host, json!(until))).collect();
+
+        json!({
+            "scores": scores,
+            "banned": banned,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ban_and_expire() {
+        let reputation = Reputation::new(0, 60);
+        let peer = Url::parse("tcp://10.0.0.1:11001").unwrap();
+        // Same host, another port
+        let same_peer = Url::parse("tcp://10.0.0.1:42000").unwrap();
+        let other = Url::parse("tcp://10.0.0.2:11001").unwrap();
+
+        assert!(!reputation.penalize(&peer, Violation::OversizedPacket, 100));
+        assert!(!reputation.penalize(&same_peer, Violation::FailedAuth, 100));
+        assert_eq!(reputation.scores()["10.0.0.1"].score, 25);
+        assert!(!reputation.is_banned(&peer, 100));
+
+        assert!(reputation.penalize(&same_peer, Violation::OversizedPacket, 100));
+        assert!(reputation.is_banned(&peer, 159));
+        assert!(!reputation.is_banned(&other, 159));
+        assert_eq!(reputation.bans(159)["10.0.0.1"], 160);
+
+        // The ban expires, and the score starts over
+        assert!(!reputation.is_banned(&peer, 160));
+        assert!(reputation.bans(160).is_empty());
+        assert!(!reputation.penalize(&peer, Violation::MalformedMessage, 200));
+        assert_eq!(reputation.scores()["10.0.0.1"].score, INITIAL_SCORE - 20);
+    }
+
+    #[test]
+    fn bans_disabled() {
+        let reputation = Reputation::new(0, 0);
+        let peer = Url::parse("tcp://10.0.0.1:11001").unwrap();
+        for _ in 0..5 {
+            assert!(!reputation.penalize(&peer, Violation::OversizedPacket, 100));
+        }
+        assert!(!reputation.is_banned(&peer, 100));
+        assert_eq!(reputation.scores()["10.0.0.1"].score, INITIAL_SCORE - 250);
+    }
+}
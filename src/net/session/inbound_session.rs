@@ -1,9 +1,10 @@
 use async_std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use async_executor::Executor;
 use async_trait::async_trait;
 use fxhash::FxHashMap;
-use log::{error, info};
+use log::{error, info, warn};
 use serde_json::json;
 use url::Url;
 
@@ -33,18 +34,23 @@ pub struct InboundSession {
     acceptor: AcceptorPtr,
     accept_task: StoppableTaskPtr,
     connect_infos: Mutex<FxHashMap<Url, InboundInfo>>,
+    /// Inbound channels accepted and not stopped yet, including the ones
+    /// still doing the handshake.
+    connections: AtomicUsize,
 }
 
 impl InboundSession {
     /// Create a new inbound session.
     pub fn new(p2p: Weak<P2p>) -> Arc<Self> {
-        let acceptor = Acceptor::new();
+        let rate_limit = p2p.upgrade().unwrap().settings().inbound_rate_limit;
+        let acceptor = Acceptor::new(rate_limit);
 
         Arc::new(Self {
             p2p,
             acceptor,
             accept_task: StoppableTask::new(),
             connect_infos: Mutex::new(FxHashMap::default()),
+            connections: AtomicUsize::new(0),
         })
     }
 
@@ -92,14 +98,44 @@ impl InboundSession {
     }
 
     /// Wait for all new channels created by the acceptor and call
-    /// setup_channel() on them.
+    /// setup_channel() on them. New channels over the configured
+    /// connections limit are stopped right away, leaving the established
+    /// ones untouched.
     async fn channel_sub_loop(self: Arc<Self>, executor: Arc<Executor<'_>>) -> Result<()> {
         let channel_sub = self.acceptor.clone().subscribe().await;
+        let max_connections = self.p2p().settings().inbound_connections as usize;
         loop {
             let channel = channel_sub.receive().await?;
+
+            if max_connections != 0 && self.connections.load(Ordering::SeqCst) >= max_connections {
+                warn!(
+                    target: "net",
+                    "Inbound connections limit reached, rejecting [{}]",
+                    channel.address()
+                );
+                channel.stop().await;
+                continue
+            }
+
+            if self.p2p().is_banned(&channel.address()) {
+                warn!(target: "net", "Rejecting banned peer [{}]", channel.address());
+                channel.stop().await;
+                continue
+            }
+
+            self.connections.fetch_add(1, Ordering::SeqCst);
+
             // Spawn a detached task to process the channel
             // This will just perform the channel setup then exit.
-            executor.spawn(self.clone().setup_channel(channel, executor.clone())).detach();
+            let self2 = self.clone();
+            let executor2 = executor.clone();
+            executor
+                .spawn(async move {
+                    let result = self2.clone().setup_channel(channel, executor2).await;
+                    self2.connections.fetch_sub(1, Ordering::SeqCst);
+                    result
+                })
+                .detach();
         }
     }
 
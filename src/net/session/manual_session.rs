@@ -70,6 +70,12 @@ impl ManualSession {
                 break
             }
 
+            if self.p2p().is_banned(&addr) {
+                info!(target: "net", "Manual outbound [{}] is banned, waiting", addr);
+                sleep(settings.connect_timeout_seconds.into()).await;
+                continue
+            }
+
             self.p2p().add_pending(addr.clone()).await;
 
             info!(target: "net", "Connecting to manual outbound [{}]", addr);
@@ -86,7 +92,14 @@ impl ManualSession {
                         continue
                     }
 
-                    self.clone().register_channel(channel.clone(), executor.clone()).await?;
+                    if let Err(err) =
+                        self.clone().register_channel(channel.clone(), executor.clone()).await
+                    {
+                        info!(target: "net", "Handshake with manual outbound [{}] failed: {}", addr, err);
+                        self.p2p().remove_pending(&addr).await;
+                        sleep(settings.connect_timeout_seconds.into()).await;
+                        continue
+                    }
 
                     // Channel is now connected but not yet setup
 
@@ -86,6 +86,7 @@ pub trait Session: Sync {
         // while the handshake protocol is ongoing.
         // They are currently in sleep mode.
         let p2p = self.p2p();
+        channel.set_reputation(p2p.reputation()).await;
         let protocols =
             p2p.protocol_registry().attach(self.selector_id(), channel.clone(), p2p.clone()).await;
 
@@ -95,10 +96,14 @@ pub trait Session: Sync {
             self.perform_handshake_protocols(protocol_version, channel.clone(), executor.clone());
 
         // Switch on the channel
-        channel.start(executor.clone());
+        channel.clone().start(executor.clone());
 
-        // Wait for handshake to finish.
-        handshake_task.await?;
+        // Wait for handshake to finish. The channel of a peer failing it,
+        // e.g. with an incompatible version, is of no use.
+        if let Err(e) = handshake_task.await {
+            channel.stop().await;
+            return Err(e)
+        }
 
         // Now the channel is ready
         debug!(target: "net", "Session handshake complete. Activating remaining protocols");
@@ -157,7 +157,21 @@ impl OutboundSession {
                         continue
                     }
 
-                    self.clone().register_channel(channel.clone(), executor.clone()).await?;
+                    if let Err(err) =
+                        self.clone().register_channel(channel.clone(), executor.clone()).await
+                    {
+                        info!(target: "net", "#{} handshake with [{}] failed: {}", slot_number, addr, err);
+                        self.p2p().remove_pending(&addr).await;
+                        // Don't keep connecting to a peer we can't talk with
+                        if let Error::IncompatibleVersion(_) = err {
+                            self.p2p().hosts().remove(&addr).await;
+                        }
+                        let info = &mut self.slot_info.lock().await[slot_number as usize];
+                        info.addr = None;
+                        info.channel = None;
+                        info.state = OutboundState::Open;
+                        continue
+                    }
 
                     // Channel is now connected but not yet setup
 
@@ -188,8 +202,8 @@ impl OutboundSession {
     /// Loops through host addresses to find a outbound address that we can
     /// connect to. Checks whether address is valid by making sure it isn't
     /// our own inbound address, then checks whether it is already connected
-    /// (exists) or connecting (pending). Keeps looping until address is
-    /// found that passes all checks.
+    /// (exists), banned or connecting (pending). Keeps looping until address
+    /// is found that passes all checks.
     async fn load_address(&self, slot_number: u32) -> Result<Url> {
         let p2p = self.p2p();
         let self_inbound_addr = p2p.settings().external_addr.clone();
@@ -204,7 +218,7 @@ impl OutboundSession {
         addrs.shuffle(&mut rand::thread_rng());
 
         for addr in addrs {
-            if p2p.exists(&addr).await {
+            if p2p.exists(&addr).await || p2p.is_banned(&addr) {
                 continue
             }
 
@@ -1,5 +1,6 @@
 use async_std::{
     future::timeout,
+    net::ToSocketAddrs,
     sync::{Arc, Weak},
 };
 use futures::future;
@@ -23,6 +24,51 @@ pub struct SeedSession {
     p2p: Weak<P2p>,
 }
 
+/// Resolve the hostname of a DNS seed to one seed URL per address, keeping
+/// the scheme and the port of `dns_seed`.
+pub async fn resolve_dns_seed(dns_seed: &Url) -> Result<Vec<Url>> {
+    let host = dns_seed.host_str().ok_or(Error::NoUrlFound)?;
+    let port = dns_seed.port().ok_or(Error::NoUrlFound)?;
+
+    let mut seeds: Vec<Url> = vec![];
+    for addr in (host, port).to_socket_addrs().await? {
+        let mut seed = dns_seed.clone();
+        if seed.set_ip_host(addr.ip()).is_err() {
+            continue
+        }
+        if !seeds.contains(&seed) {
+            seeds.push(seed);
+        }
+    }
+
+    Ok(seeds)
+}
+
+/// Seeds from the settings, followed by the addresses the DNS seeds
+/// resolve to. A DNS seed failing to resolve is skipped.
+async fn load_seeds(seeds: &[Url], dns_seeds: &[Url]) -> Vec<Url> {
+    let mut all_seeds = seeds.to_vec();
+
+    for dns_seed in dns_seeds {
+        match resolve_dns_seed(dns_seed).await {
+            Ok(resolved) if resolved.is_empty() => {
+                warn!("DNS seed {} resolved to no address", dns_seed)
+            }
+            Ok(resolved) => {
+                info!("DNS seed {} resolved to {} seeds", dns_seed, resolved.len());
+                for seed in resolved {
+                    if !all_seeds.contains(&seed) {
+                        all_seeds.push(seed);
+                    }
+                }
+            }
+            Err(err) => warn!("Failed resolving DNS seed {}: {}", dns_seed, err),
+        }
+    }
+
+    all_seeds
+}
+
 impl SeedSession {
     /// Create a new seed session instance.
     pub fn new(p2p: Weak<P2p>) -> Arc<Self> {
@@ -35,7 +81,14 @@ impl SeedSession {
         debug!(target: "net", "SeedSession::start() [START]");
         let settings = self.p2p().settings();
 
-        if settings.seeds.is_empty() {
+        let seeds = load_seeds(&settings.seeds, &settings.dns_seeds).await;
+
+        if seeds.is_empty() && !settings.dns_seeds.is_empty() {
+            error!("None of the DNS seeds resolved and no other seeds are configured");
+            return Err(Error::NetworkOperationFailed)
+        }
+
+        if seeds.is_empty() {
             warn!("Skipping seed sync process since no seeds are configured.");
             // Store external address in hosts explicitly
             match &settings.external_addr {
@@ -53,7 +106,7 @@ impl SeedSession {
         // This loops through all the seeds and tries to start them.
         // If the seed_query_timeout_seconds times out before they are finished,
         // it will return an error.
-        for (i, seed) in settings.seeds.iter().enumerate() {
+        for (i, seed) in seeds.iter().enumerate() {
             let ex2 = executor.clone();
             let self2 = self.clone();
             let sett2 = settings.clone();
@@ -167,3 +220,27 @@ impl Session for SeedSession {
         SESSION_SEED
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dns_seeds() {
+        async_std::task::block_on(async {
+            let dns_seed = Url::parse("tls://localhost:11001").unwrap();
+            let resolved = resolve_dns_seed(&dns_seed).await.unwrap();
+            assert!(resolved.contains(&Url::parse("tls://127.0.0.1:11001").unwrap()));
+
+            // A port is required
+            assert!(resolve_dns_seed(&Url::parse("tls://localhost").unwrap()).await.is_err());
+
+            // Static seeds come first, and duplicates are dropped
+            let seed = Url::parse("tls://127.0.0.1:11001").unwrap();
+            let no_port = Url::parse("tls://localhost").unwrap();
+            let seeds = load_seeds(&[seed.clone()], &[dns_seed, no_port]).await;
+            assert_eq!(seeds[0], seed);
+            assert_eq!(seeds.iter().filter(|s| **s == seed).count(), 1);
+        });
+    }
+}
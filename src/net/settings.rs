@@ -5,6 +5,8 @@ use structopt::StructOpt;
 use structopt_toml::StructOptToml;
 use url::Url;
 
+use super::compression::Compression;
+
 /// Atomic pointer to network settings.
 pub type SettingsPtr = Arc<Settings>;
 
@@ -12,6 +14,10 @@ pub type SettingsPtr = Arc<Settings>;
 #[derive(Clone, Debug)]
 pub struct Settings {
     pub inbound: Option<Url>,
+    /// Maximum number of concurrent inbound connections (0 for unlimited)
+    pub inbound_connections: u32,
+    /// Maximum number of new inbound connections per second (0 for unlimited)
+    pub inbound_rate_limit: u32,
     pub outbound_connections: u32,
     pub manual_attempt_limit: u32,
     pub seed_query_timeout_seconds: u32,
@@ -21,13 +27,24 @@ pub struct Settings {
     pub external_addr: Option<Url>,
     pub peers: Vec<Url>,
     pub seeds: Vec<Url>,
+    /// Seed URLs whose hostname resolves to several seed addresses, each
+    /// contacted with the URL's scheme and port
+    pub dns_seeds: Vec<Url>,
     pub node_id: String,
+    /// Compression of the packets sent to peers supporting it
+    pub compression: Option<Compression>,
+    /// Peers whose score falls below this are banned
+    pub ban_threshold: i32,
+    /// How long a peer stays banned (0 disables the bans)
+    pub ban_duration_seconds: u32,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
             inbound: None,
+            inbound_connections: 0,
+            inbound_rate_limit: 0,
             outbound_connections: 0,
             manual_attempt_limit: 0,
             seed_query_timeout_seconds: 8,
@@ -37,7 +54,11 @@ impl Default for Settings {
             external_addr: None,
             peers: Vec::new(),
             seeds: Vec::new(),
+            dns_seeds: Vec::new(),
             node_id: String::new(),
+            compression: None,
+            ban_threshold: 0,
+            ban_duration_seconds: 3600,
         }
     }
 }
@@ -68,6 +89,20 @@ pub struct SettingsOpt {
     #[structopt(long)]
     pub seeds: Vec<Url>,
 
+    /// DNS seeds, hostnames resolving to several seed nodes
+    /// (ex: tls://seeds.example.com:11001)
+    #[serde(default)]
+    #[structopt(long)]
+    pub dns_seeds: Vec<Url>,
+
+    /// Compress the packets sent to peers supporting it (deflate)
+    #[structopt(long)]
+    pub compression: Option<Compression>,
+
+    #[structopt(skip)]
+    pub inbound_connections: Option<u32>,
+    #[structopt(skip)]
+    pub inbound_rate_limit: Option<u32>,
     #[structopt(skip)]
     pub manual_attempt_limit: Option<u32>,
     #[structopt(skip)]
@@ -78,6 +113,10 @@ pub struct SettingsOpt {
     pub channel_handshake_seconds: Option<u32>,
     #[structopt(skip)]
     pub channel_heartbeat_seconds: Option<u32>,
+    #[structopt(skip)]
+    pub ban_threshold: Option<i32>,
+    #[structopt(skip)]
+    pub ban_duration_seconds: Option<u32>,
 
     #[serde(default)]
     #[structopt(skip)]
@@ -88,6 +127,8 @@ impl From<SettingsOpt> for Settings {
     fn from(settings_opt: SettingsOpt) -> Self {
         Self {
             inbound: settings_opt.inbound,
+            inbound_connections: settings_opt.inbound_connections.unwrap_or(0),
+            inbound_rate_limit: settings_opt.inbound_rate_limit.unwrap_or(0),
             outbound_connections: settings_opt.outbound_connections.unwrap_or(0),
             manual_attempt_limit: settings_opt.manual_attempt_limit.unwrap_or(0),
             seed_query_timeout_seconds: settings_opt.seed_query_timeout_seconds.unwrap_or(8),
@@ -97,7 +138,11 @@ impl From<SettingsOpt> for Settings {
             external_addr: settings_opt.external_addr,
             peers: settings_opt.peers,
             seeds: settings_opt.seeds,
+            dns_seeds: settings_opt.dns_seeds,
             node_id: settings_opt.node_id,
+            compression: settings_opt.compression,
+            ban_threshold: settings_opt.ban_threshold.unwrap_or(0),
+            ban_duration_seconds: settings_opt.ban_duration_seconds.unwrap_or(3600),
         }
     }
 }
@@ -71,16 +71,17 @@ impl Client {
         token_id: DrkTokenId,
         clear_input: bool,
         state: Arc<Mutex<State>>,
-    ) -> ClientResult<(Transaction, Vec<Coin>)> {
+    ) -> ClientResult<(Transaction, Vec<Coin>, u64)> {
         debug!("build_slab_from_tx(): Begin building slab from tx");
         let mut clear_inputs = vec![];
         let mut inputs = vec![];
         let mut outputs = vec![];
         let mut coins = vec![];
+        let mut change = 0;
 
         if clear_input {
             debug!("build_slab_from_tx(): Building clear input");
-            let signature_secret = self.main_keypair.lock().await.secret;
+            let signature_secret = self.main_keypair.lock().await.secret.clone();
             let input = TransactionBuilderClearInputInfo { value, token_id, signature_secret };
             clear_inputs.push(input);
         } else {
@@ -103,8 +104,8 @@ impl Client {
                 let input = TransactionBuilderInputInfo {
                     leaf_position,
                     merkle_path,
-                    secret: own_coin.secret,
-                    note: own_coin.note,
+                    secret: own_coin.secret.clone(),
+                    note: own_coin.note.clone(),
                 };
 
                 inputs.push(input);
@@ -120,6 +121,7 @@ impl Client {
 
             if inputs_value > value {
                 let return_value = inputs_value - value;
+                change = return_value;
                 outputs.push(TransactionBuilderOutputInfo {
                     value: return_value,
                     token_id,
@@ -132,6 +134,18 @@ impl Client {
 
         outputs.push(TransactionBuilderOutputInfo { value, token_id, public: pubkey });
         let builder = TransactionBuilder { clear_inputs, inputs, outputs };
+        let tx = self.build_and_check(builder, state).await?;
+
+        Ok((tx, coins, change))
+    }
+
+    /// Build the transaction and check that it is a valid state transition
+    /// before it gets broadcasted.
+    async fn build_and_check(
+        &self,
+        builder: TransactionBuilder,
+        state: Arc<Mutex<State>>,
+    ) -> ClientResult<Transaction> {
         let mut tx_data = vec![];
 
         let mint_pk = self.mint_pk.get_or_create(Client::build_mint_pk);
@@ -139,17 +153,18 @@ impl Client {
         let tx = builder.build(mint_pk, burn_pk)?;
         tx.encode(&mut tx_data)?;
 
-        // Check if state transition is valid before broadcasting
-        debug!("build_slab_from_tx(): Checking if state transition is valid");
+        debug!("build_and_check(): Checking if state transition is valid");
         let state = &*state.lock().await;
-        debug!("build_slab_from_tx(): Got state lock");
+        debug!("build_and_check(): Got state lock");
         state_transition(state, tx.clone())?;
-        debug!("build_slab_from_tx(): Successful state transition");
+        debug!("build_and_check(): Successful state transition");
 
-        Ok((tx, coins))
+        Ok(tx)
     }
 
     /// Build a transaction given the required parameters and state machine.
+    /// Returns the transaction along with the value of its change output,
+    /// which is `0` if the inputs matched the amount exactly.
     pub async fn build_transaction(
         &self,
         pubkey: PublicKey,
@@ -157,7 +172,7 @@ impl Client {
         token_id: DrkTokenId,
         clear_input: bool,
         state: Arc<Mutex<State>>,
-    ) -> ClientResult<Transaction> {
+    ) -> ClientResult<(Transaction, u64)> {
         // TODO: Token id debug
         debug!("send(): Sending {}", amount);
 
@@ -169,7 +184,7 @@ impl Client {
             return Err(ClientFailed::NotEnoughValue(amount))
         }
 
-        let (tx, coins) =
+        let (tx, coins, change) =
             self.build_slab_from_tx(pubkey, amount, token_id, clear_input, state).await?;
         for coin in coins.iter() {
             // TODO: This should be more robust. In case our transaction is denied,
@@ -178,7 +193,53 @@ impl Client {
         }
 
         debug!("send(): Sent {}", amount);
-        Ok(tx)
+        Ok((tx, change))
+    }
+
+    /// Build a transaction spending all the coins of the given token owned
+    /// by the wallet to a single coin of the main keypair. Returns the
+    /// transaction along with the number of coins spent and their value.
+    pub async fn build_sweep_transaction(
+        &self,
+        token_id: DrkTokenId,
+        state: Arc<Mutex<State>>,
+    ) -> ClientResult<(Transaction, usize, u64)> {
+        let own_coins = self.get_own_coins_of(token_id).await?;
+        if own_coins.len() < 2 {
+            return Err(ClientFailed::NothingToSweep(own_coins.len()))
+        }
+
+        let mut inputs = vec![];
+        let mut value: u64 = 0;
+        let state_m = state.lock().await;
+        let root = state_m.tree.root(0).unwrap();
+        for own_coin in own_coins.iter() {
+            let merkle_path =
+                state_m.tree.authentication_path(own_coin.leaf_position, &root).unwrap();
+            value = value
+                .checked_add(own_coin.note.value)
+                .ok_or_else(|| ClientFailed::InternalError("coin values overflow".into()))?;
+
+            inputs.push(TransactionBuilderInputInfo {
+                leaf_position: own_coin.leaf_position,
+                merkle_path,
+                secret: own_coin.secret.clone(),
+                note: own_coin.note.clone(),
+            });
+        }
+        drop(state_m);
+
+        let public = self.main_keypair.lock().await.public;
+        let outputs = vec![TransactionBuilderOutputInfo { value, token_id, public }];
+        let builder = TransactionBuilder { clear_inputs: vec![], inputs, outputs };
+        let tx = self.build_and_check(builder, state).await?;
+
+        for own_coin in own_coins.iter() {
+            self.wallet.confirm_spend_coin(&own_coin.coin).await?;
+        }
+
+        debug!("build_sweep_transaction(): Swept {} coins of value {}", own_coins.len(), value);
+        Ok((tx, own_coins.len(), value))
     }
 
     pub async fn init_db(&self) -> Result<()> {
@@ -189,6 +250,12 @@ impl Client {
         self.wallet.get_own_coins().await
     }
 
+    /// The unspent coins of the given token owned by the wallet.
+    pub async fn get_own_coins_of(&self, token_id: DrkTokenId) -> Result<Vec<OwnCoin>> {
+        let own_coins = self.wallet.get_own_coins().await?;
+        Ok(own_coins.into_iter().filter(|coin| coin.note.token_id == token_id).collect())
+    }
+
     pub async fn confirm_spend_coin(&self, coin: &Coin) -> Result<()> {
         self.wallet.confirm_spend_coin(coin).await
     }
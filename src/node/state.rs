@@ -17,6 +17,7 @@ use crate::{
         OwnCoin,
     },
     tx::Transaction,
+    util::serial::serialize,
     wallet::walletdb::WalletPtr,
     zk::circuit::{BurnContract, MintContract},
     Result, VerifyFailed, VerifyResult,
@@ -42,6 +43,8 @@ pub trait ProgramState {
 /// This gets applied on top of an existing state.
 #[derive(Clone)]
 pub struct StateUpdate {
+    /// Hash of the transaction, its ID in the wallet history
+    pub tx_hash: String,
     /// All nullifiers in a transaction
     pub nullifiers: Vec<Nullifier>,
     /// All coins in a transaction
@@ -52,6 +55,8 @@ pub struct StateUpdate {
 
 /// State transition function
 pub fn state_transition<S: ProgramState>(state: &S, tx: Transaction) -> VerifyResult<StateUpdate> {
+    let tx_hash = blake3::hash(&serialize(&tx)).to_hex().as_str().to_string();
+
     // Check the public keys in the clear inputs to see if they're coming
     // from a valid cashier or faucet.
     debug!(target: "state_transition", "Iterate clear_inputs");
@@ -111,7 +116,7 @@ pub fn state_transition<S: ProgramState>(state: &S, tx: Transaction) -> VerifyRe
         enc_notes.push(output.enc_note);
     }
 
-    Ok(StateUpdate { nullifiers, coins, enc_notes })
+    Ok(StateUpdate { tx_hash, nullifiers, coins, enc_notes })
 }
 
 /// Struct holding the state which we can apply a [`StateUpdate`] onto.
@@ -163,12 +168,13 @@ impl State {
             self.merkle_roots.insert(&[self.tree.root(0).unwrap()])?;
 
             for secret in secret_keys.iter() {
-                if let Some(note) = State::try_decrypt_note(enc_note, *secret) {
+                if let Some(note) = State::try_decrypt_note(enc_note, secret) {
                     debug!(target: "state_apply", "Received a coin: amount {}", note.value);
                     let leaf_position = self.tree.witness().unwrap();
-                    let nullifier = Nullifier::new(*secret, note.serial);
+                    let nullifier = Nullifier::new(secret.clone(), note.serial);
+                    let (value, token_id) = (note.value, note.token_id);
                     let own_coin =
-                        OwnCoin { coin, note, secret: *secret, nullifier, leaf_position };
+                        OwnCoin { coin, note, secret: secret.clone(), nullifier, leaf_position };
 
                     // FIXME: BUG check values inside the note are correct
                     // We need to hash them all and check them against the coin
@@ -176,11 +182,12 @@ impl State {
                     // Don't trust - verify.
 
                     wallet.put_own_coin(own_coin, tokenlist.clone()).await?;
+                    wallet.put_received_transaction(&update.tx_hash, value, token_id).await?;
 
                     if let Some(ch) = notify.clone() {
                         debug!(target: "state_apply", "Send a notification");
-                        let pubkey = PublicKey::from_secret(*secret);
-                        ch.send((pubkey, note.value)).await?;
+                        let pubkey = PublicKey::from_secret(secret.clone());
+                        ch.send((pubkey, value)).await?;
                     }
                 }
             }
@@ -193,8 +200,8 @@ impl State {
         Ok(())
     }
 
-    fn try_decrypt_note(ciphertext: &EncryptedNote, secret: SecretKey) -> Option<Note> {
-        match ciphertext.decrypt(&secret) {
+    fn try_decrypt_note(ciphertext: &EncryptedNote, secret: &SecretKey) -> Option<Note> {
+        match ciphertext.decrypt(secret) {
             Ok(note) => Some(note),
             Err(_) => None,
         }
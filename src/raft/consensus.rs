@@ -2,13 +2,20 @@ use async_std::{
     sync::{Arc, Mutex},
     task,
 };
-use std::{cmp::min, collections::HashMap, path::PathBuf, time::Duration};
+use std::{
+    cmp::{min, Reverse},
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
 
 use async_executor::Executor;
-use futures::{select, FutureExt};
+use futures::{channel::oneshot, select, FutureExt};
 use log::{debug, error, info, warn};
 use rand::{rngs::OsRng, Rng, RngCore};
 use url::Url;
+use zeroize::Zeroizing;
 
 use crate::{
     net,
@@ -17,19 +24,35 @@ use crate::{
 };
 
 use super::{
+    datastore::DataTree,
     primitives::{
-        Broadcast, BroadcastMsgRequest, Log, LogRequest, LogResponse, Logs, MapLength, NetMsg,
-        NetMsgMethod, NodeId, Role, Sender, SyncRequest, SyncResponse, VoteRequest, VoteResponse,
+        ApplyStatus, Broadcast, BroadcastMsgRequest, Log, LogRequest, LogResponse, Logs, MapLength,
+        NetMsg, NetMsgMethod, NodeId, PendingRead, Proposal, Proposer, Reader, Role, RoleChange,
+        Sender, SyncRequest, SyncResponse, VoteRequest, VoteResponse,
     },
-    DataStore,
+    settings::MAX_ELECTION_PRIORITY,
+    ChannelFullPolicy, DataStore, RaftSettings,
 };
 
 const HEARTBEATTIMEOUT: u64 = 300;
 const TIMEOUT: u64 = 900;
 const TIMEOUT_NODES: u64 = 900;
+// Random part of the election timeout, also the delay added per step of
+// election priority so a node never times out before a higher priority one
+const TIMEOUT_JITTER: u64 = 200;
+// Longest leader lease, leaving at least TIMEOUT_JITTER for clock drift
+// before followers would vote for another node
+const MAX_LEASE_DURATION: u64 = TIMEOUT - TIMEOUT_JITTER;
+// Heartbeat rounds remembered by the leader, older acks aren't counted
+const MAX_ROUNDS: usize = 32;
+// Two instances of a node id seen within this many milliseconds are two
+// nodes sharing the id, rather than a node that restarted
+const COLLISION_WINDOW: u64 = TIMEOUT * 2;
+const MAC_KEY_CONTEXT: &str = "darkfi raft 2022-07 message authentication";
 
 async fn load_node_ids_loop(
     nodes: Arc<Mutex<HashMap<NodeId, Url>>>,
+    nodes_store: DataTree<(NodeId, Url)>,
     p2p: net::P2pPtr,
     role: Role,
 ) -> Result<()> {
@@ -42,13 +65,36 @@ async fn load_node_ids_loop(
         let hosts = p2p.hosts().clone();
         let nodes_ip = hosts.load_all().await.clone();
         let mut nodes = nodes.lock().await;
+        let mut changed = false;
         for ip in nodes_ip.iter() {
-            nodes.insert(NodeId::from(ip.clone()), ip.clone());
+            if nodes.insert(NodeId::from(ip.clone()), ip.clone()).is_none() {
+                changed = true;
+            }
+        }
+
+        // Persist the membership so quorum math is right straight after a restart
+        if changed {
+            let entries: Vec<(NodeId, Url)> = nodes.clone().into_iter().collect();
+            if let Err(e) = nodes_store.wipe_insert_all(&entries) {
+                error!(target: "raft", "failed to persist the nodes: {}", e);
+            }
         }
         drop(nodes);
     }
 }
 
+/// Decode `bytes` from the payload of `msg`, logging a failure along
+/// with the message id and method.
+fn decode_payload<D: Decodable>(msg: &NetMsg, bytes: &[u8]) -> Option<D> {
+    match deserialize(bytes) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            warn!(target: "raft", "drop malformed msg id: {} method: {:?}: {}", msg.id, msg.method, e);
+            None
+        }
+    }
+}
+
 async fn p2p_send_loop(receiver: async_channel::Receiver<NetMsg>, p2p: net::P2pPtr) -> Result<()> {
     loop {
         let msg: NetMsg = match receiver.recv().await {
@@ -68,6 +114,38 @@ async fn p2p_send_loop(receiver: async_channel::Receiver<NetMsg>, p2p: net::P2pP
     }
 }
 
+/// Validate the state loaded from the datastore. A partially written
+/// datastore (e.g. after a power loss) can't be trusted, so we refuse
+/// to start with it instead of running with an inconsistent state.
+fn check_datastore(logs: &Logs, commit_length: u64) -> Result<()> {
+    if commit_length > logs.len() {
+        return Err(Error::RaftError(format!(
+            "datastore is corrupted: commit length {} is greater than logs length {}",
+            commit_length,
+            logs.len()
+        )))
+    }
+
+    for (i, pair) in logs.0.windows(2).enumerate() {
+        if pair[1].term < pair[0].term {
+            return Err(Error::RaftError(format!(
+                "datastore is corrupted: log {} has term {} lower than the previous term {}",
+                i + 1,
+                pair[1].term,
+                pair[0].term
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+/// Extra milliseconds a node with the given election priority waits
+/// before starting an election. Priorities above the maximum count as it.
+fn election_delay(priority: u8) -> u64 {
+    (MAX_ELECTION_PRIORITY - priority.min(MAX_ELECTION_PRIORITY)) as u64 * TIMEOUT_JITTER
+}
+
 pub struct Raft<T> {
     // this will be derived from the ip
     pub id: Option<NodeId>,
@@ -77,6 +155,8 @@ pub struct Raft<T> {
     voted_for: Option<NodeId>,
     logs: Logs,
     commit_length: u64,
+    // commit_length, shared with the ApplyStatus handles
+    shared_commit_length: Arc<AtomicU64>,
 
     role: Role,
 
@@ -89,39 +169,99 @@ pub struct Raft<T> {
 
     nodes: Arc<Mutex<HashMap<NodeId, Url>>>,
 
+    // nodes that joined after this node became the leader, replicating the
+    // logs without counting for the commits until they catch up
+    learners: HashSet<NodeId>,
+
     last_term: u64,
 
     sender: Sender,
 
     broadcast_msg: Broadcast<T>,
     broadcast_commits: Broadcast<T>,
+    broadcast_role_changes: Broadcast<RoleChange>,
+    broadcast_proposals: Broadcast<(T, oneshot::Sender<Result<u64>>)>,
+
+    // proposed entries waiting to be committed
+    proposals: Vec<Proposal>,
+
+    broadcast_reads: Broadcast<oneshot::Sender<Result<u64>>>,
+    // reads waiting for a quorum to ack a heartbeat round
+    reads: Vec<PendingRead>,
+
+    lease_duration: Duration,
+    // last heartbeat round sent as the leader, and when the recent ones started
+    round: u64,
+    round_starts: VecDeque<(u64, Instant)>,
+    // latest round each node acked in the current term, and when it started
+    round_acks: HashMap<NodeId, (u64, Instant)>,
+    // when we last heard from the leader of the current term
+    leader_seen_at: Option<Instant>,
+
+    // random number telling us apart from a node with the same id, e.g.
+    // behind the same NAT, and the instances recently seen for each id
+    instance: u64,
+    instances: HashMap<NodeId, Vec<(u64, Instant)>>,
 
     datastore: DataStore<T>,
+
+    mac_key: Option<Zeroizing<[u8; 32]>>,
+
+    channel_full_policy: ChannelFullPolicy,
+
+    // extra wait before starting an election, from the election priority
+    election_delay: u64,
+
+    apply_lag_warning: u64,
+    // whether the application is behind by more than apply_lag_warning
+    lagging: bool,
+
+    // maximum number of entries in a log request
+    log_batch_size: u64,
 }
 
 impl<T: Decodable + Encodable + Clone> Raft<T> {
-    pub fn new(addr: Option<Url>, db_path: PathBuf) -> Result<Self> {
+    pub fn new(addr: Option<Url>, db_path: PathBuf, settings: RaftSettings) -> Result<Self> {
         if db_path.to_str().is_none() {
             error!(target: "raft", "datastore path is incorrect");
             return Err(Error::ParseFailed("unable to parse pathbuf to str"))
         };
 
-        let datastore = DataStore::new(db_path.to_str().unwrap())?;
+        let datastore = DataStore::new(db_path.to_str().unwrap(), settings.sync_policy)?;
 
         // load from sled datastore
         let current_term = datastore.current_term.get_last()?.unwrap_or(0);
         let voted_for = datastore.voted_for.get_last()?.flatten();
         let logs = Logs(datastore.logs.get_all()?);
         let commit_length = datastore.commits.get_all()?.len() as u64;
+        let nodes: HashMap<NodeId, Url> = datastore.nodes.get_all()?.into_iter().collect();
+
+        if let Err(e) = check_datastore(&logs, commit_length) {
+            error!(target: "raft", "{}", e);
+            return Err(e)
+        }
 
         // broadcasting channels
-        let broadcast_msg = async_channel::unbounded::<T>();
-        let broadcast_commits = async_channel::unbounded::<T>();
+        let capacity = settings.channel_capacity.max(1);
+        let broadcast_msg = async_channel::bounded::<T>(capacity);
+        let broadcast_commits = async_channel::bounded::<T>(capacity);
+        let broadcast_role_changes = async_channel::bounded::<RoleChange>(capacity);
+        let broadcast_proposals = async_channel::bounded(capacity);
+        let broadcast_reads = async_channel::bounded(capacity);
+
+        let sender = async_channel::bounded::<NetMsg>(capacity);
 
-        let sender = async_channel::unbounded::<NetMsg>();
+        // The secret itself isn't kept, only the key derived from it
+        let mac_key = settings
+            .cluster_secret
+            .map(|secret| Zeroizing::new(blake3::derive_key(MAC_KEY_CONTEXT, &secret)));
 
         let id = addr.map(NodeId::from);
-        let role = if id.is_some() { Role::Follower } else { Role::Listener };
+        let role = match (&id, settings.join_as_learner) {
+            (None, _) => Role::Listener,
+            (Some(_), true) => Role::Learner,
+            (Some(_), false) => Role::Follower,
+        };
 
         Ok(Self {
             id,
@@ -129,17 +269,37 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             voted_for,
             logs,
             commit_length,
+            shared_commit_length: Arc::new(AtomicU64::new(commit_length)),
             role,
             current_leader: None,
             votes_received: vec![],
             sent_length: MapLength(HashMap::new()),
             acked_length: MapLength(HashMap::new()),
-            nodes: Arc::new(Mutex::new(HashMap::new())),
+            nodes: Arc::new(Mutex::new(nodes)),
+            learners: HashSet::new(),
             last_term: 0,
             sender,
             broadcast_msg,
             broadcast_commits,
+            broadcast_role_changes,
+            broadcast_proposals,
+            proposals: vec![],
+            broadcast_reads,
+            reads: vec![],
+            lease_duration: Duration::from_millis(settings.lease_duration.min(MAX_LEASE_DURATION)),
+            round: 0,
+            round_starts: VecDeque::new(),
+            round_acks: HashMap::new(),
+            leader_seen_at: None,
+            instance: OsRng.next_u64(),
+            instances: HashMap::new(),
             datastore,
+            mac_key,
+            channel_full_policy: settings.channel_full_policy,
+            election_delay: election_delay(settings.election_priority),
+            apply_lag_warning: settings.apply_lag_warning,
+            lagging: false,
+            log_batch_size: settings.log_batch_size.max(1) as u64,
         })
     }
 
@@ -153,8 +313,12 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         let receiver = self.sender.1.clone();
         let p2p_send_task = executor.spawn(p2p_send_loop(receiver.clone(), p2p.clone()));
 
-        let load_ips_task =
-            executor.spawn(load_node_ids_loop(self.nodes.clone(), p2p.clone(), self.role.clone()));
+        let load_ips_task = executor.spawn(load_node_ids_loop(
+            self.nodes.clone(),
+            self.datastore.nodes.clone(),
+            p2p.clone(),
+            self.role.clone(),
+        ));
 
         // Sync listener node
         if self.role == Role::Listener {
@@ -172,12 +336,16 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         let mut rng = rand::thread_rng();
 
         let broadcast_msg_rv = self.broadcast_msg.1.clone();
+        let proposals_rv = self.broadcast_proposals.1.clone();
+        let reads_rv = self.broadcast_reads.1.clone();
 
         loop {
             let timeout: Duration = if self.role == Role::Leader {
                 Duration::from_millis(HEARTBEATTIMEOUT)
             } else {
-                Duration::from_millis(rng.gen_range(0..200) + TIMEOUT)
+                Duration::from_millis(
+                    rng.gen_range(0..TIMEOUT_JITTER) + TIMEOUT + self.election_delay,
+                )
             };
 
             let result: Result<()>;
@@ -185,6 +353,11 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             select! {
                 m =  p2p_recv_channel.recv().fuse() => result = self.handle_method(m?).await,
                 m =  broadcast_msg_rv.recv().fuse() => result = self.broadcast_msg(&m?,None).await,
+                p = proposals_rv.recv().fuse() => {
+                    let (msg, reply) = p?;
+                    result = self.receive_proposal(&msg, reply).await;
+                },
+                r = reads_rv.recv().fuse() => result = self.receive_read(r?).await,
                 _ = task::sleep(timeout).fuse() => {
                     result = if self.role == Role::Leader {
                         self.send_heartbeat().await
@@ -197,6 +370,7 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
 
             match result {
                 Ok(_) => {}
+                Err(e @ Error::RaftApplyError(_)) => error!(target: "raft", "{}", e),
                 Err(e) => warn!(target: "raft", "warn: {}", e),
             }
         }
@@ -212,26 +386,113 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         self.broadcast_commits.1.clone()
     }
 
+    /// The current role, as sent to the receivers of `get_role_changes()`.
+    pub fn current_role(&self) -> RoleChange {
+        RoleChange {
+            role: self.role.clone(),
+            term: self.current_term,
+            leader: self.current_leader.clone(),
+        }
+    }
+
+    /// Receive a `RoleChange` each time the node takes a new role. Changes
+    /// are dropped while the channel is full, so a slow receiver misses
+    /// some of them rather than stalling the consensus.
+    pub fn get_role_changes(&self) -> async_channel::Receiver<RoleChange> {
+        self.broadcast_role_changes.1.clone()
+    }
+
+    /// Follow how far the application reading `get_commits()` is behind
+    /// the committed entries, e.g. because it's slow to save them.
+    pub fn apply_status(&self) -> ApplyStatus<T> {
+        ApplyStatus {
+            commit_length: self.shared_commit_length.clone(),
+            commits: self.broadcast_commits.0.clone(),
+        }
+    }
+
     pub fn get_broadcast(&self) -> async_channel::Sender<T> {
         self.broadcast_msg.0.clone()
     }
 
+    /// Propose `value` as a new log entry, like sending it to
+    /// `get_broadcast()`, and get its index in the commits once committed.
+    ///
+    /// The receiver resolves to an error if the entry gets overwritten by
+    /// a new leader, or if the leader changes before a follower sees the
+    /// entry forwarded to it in the logs. The entry may still be committed
+    /// in the latter case, so proposals are best kept idempotent. The
+    /// receiver is canceled if raft stops first.
+    ///
+    /// Like the broadcast channel, this waits while the queue of
+    /// proposals is full.
+    pub async fn propose(&self, value: T) -> Result<oneshot::Receiver<Result<u64>>> {
+        self.get_proposer().propose(value).await
+    }
+
+    /// Read the committed state without appending to the logs. The
+    /// receiver resolves to the commit length the read has to see: the
+    /// application answers it once it has applied that many commits.
+    ///
+    /// While the leader's lease holds, the commit length is given right
+    /// away without contacting the followers. Otherwise the leader sends a
+    /// heartbeat and answers once a quorum acked it (read-index). See
+    /// `RaftSettings::lease_duration` for what the lease assumes about the
+    /// clocks.
+    ///
+    /// The receiver resolves to an error on a node that isn't the leader,
+    /// on a leader that hasn't committed an entry of its term yet, or if
+    /// the leadership is lost before the quorum acks. It is canceled if
+    /// raft stops first.
+    pub fn leased_read(&self) -> oneshot::Receiver<Result<u64>> {
+        self.get_reader().leased_read()
+    }
+
+    /// Handle to make `leased_read()`s while raft runs.
+    pub fn get_reader(&self) -> Reader {
+        Reader { reads: self.broadcast_reads.0.clone() }
+    }
+
+    /// Key authenticating the messages of the cluster, for `ProtocolRaft`
+    /// to drop the unauthenticated ones before relaying them.
+    pub fn mac_key(&self) -> Option<Zeroizing<[u8; 32]>> {
+        self.mac_key.clone()
+    }
+
+    /// Handle to `propose()` entries while raft runs.
+    pub fn get_proposer(&self) -> Proposer<T> {
+        Proposer { proposals: self.broadcast_proposals.0.clone() }
+    }
+
+    /// Read the committed entries from the datastore, starting from the
+    /// given commit index, along with their index.
+    pub fn committed_entries(&self, from: u64) -> Result<Vec<(u64, T)>> {
+        let commits = self.datastore.commits.get_from(from)?;
+        Ok(commits.into_iter().enumerate().map(|(i, commit)| (from + i as u64, commit)).collect())
+    }
+
+    /// Feed every committed entry to `apply` with its index, in commit
+    /// order, and return how many there were. Meant for the application to
+    /// rebuild its state from the commits alone before calling `start()`,
+    /// as the commits channel only gets the entries committed afterwards.
+    ///
+    /// The commits are read one at a time rather than all loaded. Raft
+    /// keeps every commit and takes no snapshots, so the replay always
+    /// starts from the first one. An error from `apply` stops it.
+    pub fn replay<F: FnMut(u64, T) -> Result<()>>(&self, mut apply: F) -> Result<u64> {
+        let mut count = 0;
+        for commit in self.datastore.commits.iter() {
+            apply(count, commit?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
     async fn broadcast_msg(&mut self, msg: &T, msg_id: Option<u64>) -> Result<()> {
         if self.role == Role::Leader {
-            let msg = serialize(msg);
-            let log = Log { msg, term: self.current_term };
-            self.push_log(&log)?;
-
-            self.acked_length.insert(&self.id.clone().unwrap(), self.logs.len());
+            self.append_as_leader(msg)?;
         } else {
-            let b_msg = BroadcastMsgRequest(serialize(msg));
-            self.send(
-                self.current_leader.clone(),
-                &serialize(&b_msg),
-                NetMsgMethod::BroadcastRequest,
-                msg_id,
-            )
-            .await?;
+            self.forward_to_leader(msg, msg_id).await?;
         }
 
         info!(target: "raft", "Role: {:?}, broadcast a msg id: {:?} ", self.role, msg_id);
@@ -239,33 +500,217 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         Ok(())
     }
 
+    /// Broadcast the proposed msg, then wait for it to show up in the logs
+    /// to reply once it's committed.
+    async fn receive_proposal(
+        &mut self,
+        msg: &T,
+        reply: oneshot::Sender<Result<u64>>,
+    ) -> Result<()> {
+        self.proposals.push(Proposal {
+            msg: serialize(msg),
+            term: self.current_term,
+            index: None,
+            reply,
+        });
+
+        let index = self.proposals.len() - 1;
+        if let Err(e) = self.broadcast_msg(msg, None).await {
+            let proposal = self.proposals.remove(index);
+            let _ = proposal.reply.send(Err(Error::RaftError(e.to_string())));
+            return Err(e)
+        }
+        Ok(())
+    }
+
+    /// Reply to the proposals whose entry got committed, or overwritten
+    /// by another leader. Proposals forwarded to a leader and not seen in
+    /// the logs are given up once the term changes.
+    fn settle_proposals(&mut self) {
+        let mut pending = vec![];
+        for proposal in self.proposals.drain(..) {
+            let result = match proposal.index {
+                Some(index) if index < self.commit_length => Ok(index),
+                Some(index) if index >= self.logs.len() => {
+                    Err(Error::RaftError("the proposed entry was overwritten".into()))
+                }
+                None if proposal.term < self.current_term => {
+                    Err(Error::RaftError("leadership lost before the proposal was appended".into()))
+                }
+                _ => {
+                    pending.push(proposal);
+                    continue
+                }
+            };
+            let _ = proposal.reply.send(result);
+        }
+        self.proposals = pending;
+    }
+
+    /// Answer a read with the commit length, right away while the lease
+    /// holds, or once a quorum acks the next heartbeat round.
+    async fn receive_read(&mut self, reply: oneshot::Sender<Result<u64>>) -> Result<()> {
+        if self.role != Role::Leader {
+            let _ = reply.send(Err(Error::RaftError("not the leader".into())));
+            return Ok(())
+        }
+
+        // Entries of previous terms may be committed by a quorum without
+        // the leader knowing it until it commits one of its own
+        let committed_in_term = self.commit_length > 0 &&
+            self.logs.get(self.commit_length - 1)?.term == self.current_term;
+        if !committed_in_term {
+            let _ = reply
+                .send(Err(Error::RaftError("no entry committed in the current term yet".into())));
+            return Ok(())
+        }
+
+        if self.lease_valid().await {
+            let _ = reply.send(Ok(self.commit_length));
+            return Ok(())
+        }
+
+        self.reads.push(PendingRead {
+            round: self.round + 1,
+            term: self.current_term,
+            read_index: self.commit_length,
+            reply,
+        });
+        self.send_heartbeat().await
+    }
+
+    /// The latest heartbeat round acked by a quorum, and when it started.
+    /// A node alone in the cluster is its own quorum.
+    async fn quorum_round(&self) -> Option<(u64, Instant)> {
+        let nodes = self.nodes.lock().await;
+        // learners don't count for the quorum
+        let voters: Vec<&NodeId> = nodes.keys().filter(|id| !self.learners.contains(id)).collect();
+        let min_acks = (voters.len() + 1) / 2;
+        let mut acks: Vec<(u64, Instant)> = voters
+            .iter()
+            .filter(|id| !self.is_colliding(id))
+            .filter_map(|id| self.round_acks.get(*id).copied())
+            .collect();
+        drop(nodes);
+
+        if min_acks == 0 {
+            return Some((self.round, Instant::now()))
+        }
+        acks.sort_by_key(|(round, _)| Reverse(*round));
+        acks.get(min_acks - 1).copied()
+    }
+
+    /// Whether a quorum acked a heartbeat round started less than
+    /// `lease_duration` ago, during which no other node can be elected.
+    async fn lease_valid(&self) -> bool {
+        if self.lease_duration.is_zero() {
+            return false
+        }
+        match self.quorum_round().await {
+            Some((_, start)) => start.elapsed() < self.lease_duration,
+            None => false,
+        }
+    }
+
+    /// Answer the reads whose heartbeat round got acked by a quorum.
+    async fn settle_reads(&mut self) {
+        if self.reads.is_empty() {
+            return
+        }
+        let acked = self.quorum_round().await.map_or(0, |(round, _)| round);
+        let (done, pending): (Vec<_>, Vec<_>) =
+            self.reads.drain(..).partition(|read| read.round <= acked);
+        self.reads = pending;
+        for read in done {
+            let _ = read.reply.send(Ok(read.read_index));
+        }
+    }
+
+    /// Give up the reads made in an earlier term or since we stepped down.
+    fn drop_stale_reads(&mut self) {
+        let (leader, term) = (self.role == Role::Leader, self.current_term);
+        let (stale, pending): (Vec<_>, Vec<_>) =
+            self.reads.drain(..).partition(|read| !leader || read.term != term);
+        self.reads = pending;
+        for read in stale {
+            let _ = read.reply.send(Err(Error::RaftError(
+                "leadership lost before the read was confirmed".into(),
+            )));
+        }
+    }
+
+    /// Append the msg to the logs as an entry of the current term, and mark
+    /// it as acknowledged by the leader itself.
+    fn append_as_leader(&mut self, msg: &T) -> Result<()> {
+        let msg = serialize(msg);
+        let log = Log { msg, term: self.current_term };
+        self.push_log(&log)?;
+
+        self.acked_length.insert(&self.id.clone().unwrap(), self.logs.len());
+        Ok(())
+    }
+
+    /// Forward the msg to the current leader, which appends it to the logs.
+    async fn forward_to_leader(&self, msg: &T, msg_id: Option<u64>) -> Result<()> {
+        let b_msg = BroadcastMsgRequest(serialize(msg));
+        self.send(
+            self.current_leader.clone(),
+            &serialize(&b_msg),
+            NetMsgMethod::BroadcastRequest,
+            msg_id,
+        )
+        .await
+    }
+
+    /// Whether the msg carries a valid MAC, or authentication is disabled.
+    fn is_authentic(&self, msg: &NetMsg) -> bool {
+        match &self.mac_key {
+            Some(key) => msg.verify(key),
+            None => true,
+        }
+    }
+
     async fn handle_method(&mut self, msg: NetMsg) -> Result<()> {
+        if !self.is_authentic(&msg) {
+            warn!(target: "raft", "drop unauthenticated msg id: {} method: {:?}", msg.id, msg.method);
+            return Ok(())
+        }
+
+        // A payload that doesn't decode is dropped, the next messages are
+        // handled as usual
         match msg.method {
             NetMsgMethod::LogResponse => {
-                let lr: LogResponse = deserialize(&msg.payload)?;
-                self.receive_log_response(lr).await?;
+                if let Some(lr) = decode_payload::<LogResponse>(&msg, &msg.payload) {
+                    self.receive_log_response(lr).await?;
+                }
             }
             NetMsgMethod::LogRequest => {
-                let lr: LogRequest = deserialize(&msg.payload)?;
-                self.receive_log_request(lr).await?;
+                if let Some(lr) = decode_payload::<LogRequest>(&msg, &msg.payload) {
+                    self.receive_log_request(lr).await?;
+                }
             }
             NetMsgMethod::VoteResponse => {
-                let vr: VoteResponse = deserialize(&msg.payload)?;
-                self.receive_vote_response(vr).await?;
+                if let Some(vr) = decode_payload::<VoteResponse>(&msg, &msg.payload) {
+                    self.receive_vote_response(vr).await?;
+                }
             }
             NetMsgMethod::VoteRequest => {
-                let vr: VoteRequest = deserialize(&msg.payload)?;
-                self.receive_vote_request(vr).await?;
+                if let Some(vr) = decode_payload::<VoteRequest>(&msg, &msg.payload) {
+                    self.receive_vote_request(vr).await?;
+                }
             }
             NetMsgMethod::BroadcastRequest => {
-                let vr: BroadcastMsgRequest = deserialize(&msg.payload)?;
-                let d: T = deserialize(&vr.0)?;
-                self.broadcast_msg(&d, Some(msg.id)).await?;
+                let d = decode_payload::<BroadcastMsgRequest>(&msg, &msg.payload)
+                    .and_then(|vr| decode_payload::<T>(&msg, &vr.0));
+                if let Some(d) = d {
+                    self.broadcast_msg(&d, Some(msg.id)).await?;
+                }
             }
             NetMsgMethod::SyncRequest => {
                 info!("receive sync request");
-                let sr: SyncRequest = deserialize(&msg.payload)?;
-                self.receive_sync_request(&sr, msg.id).await?;
+                if let Some(sr) = decode_payload::<SyncRequest>(&msg, &msg.payload) {
+                    self.receive_sync_request(&sr, msg.id).await?;
+                }
             }
             NetMsgMethod::SyncResponse => {}
         }
@@ -323,7 +768,9 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
     async fn receive_sync_response(&mut self, sr: &SyncResponse) -> Result<()> {
         info!("receive sync response");
         if sr.wipe {
+            // The commits are applied again from the new logs
             self.set_commit_length(&0)?;
+            self.datastore.commits.wipe()?;
             self.push_logs(&sr.logs)?;
         } else {
             for log in sr.logs.0.iter() {
@@ -335,11 +782,7 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             self.set_current_term(&self.logs.0.last().unwrap().term.clone())?;
         }
 
-        for i in self.commit_length..sr.commit_length {
-            self.push_commit(&self.logs.get(i)?.msg).await?;
-        }
-
-        self.set_commit_length(&sr.commit_length)?;
+        self.apply_commits(sr.commit_length).await?;
 
         self.current_leader = Some(sr.leader_id.clone());
 
@@ -358,9 +801,34 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         debug!(target: "raft","Role: {:?}  send a msg id: {}  recipient_id: {:?} method: {:?} ",
         self.role, random_id, &recipient_id.is_some(), &method);
 
-        let net_msg = NetMsg { id: random_id, recipient_id, payload: payload.to_vec(), method };
-        self.sender.0.send(net_msg).await?;
+        let mut net_msg =
+            NetMsg { id: random_id, recipient_id, payload: payload.to_vec(), method, mac: None };
+        if let Some(key) = &self.mac_key {
+            net_msg.sign(key);
+        }
+        self.send_bounded(&self.sender.0, net_msg, "network").await
+    }
 
+    /// Send on one of the channels raft writes to, following the
+    /// `ChannelFullPolicy` when the channel is full.
+    async fn send_bounded<M>(
+        &self,
+        channel: &async_channel::Sender<M>,
+        msg: M,
+        name: &str,
+    ) -> Result<()> {
+        match self.channel_full_policy {
+            ChannelFullPolicy::Block => channel.send(msg).await?,
+            ChannelFullPolicy::Drop => match channel.try_send(msg) {
+                Ok(()) => {}
+                Err(async_channel::TrySendError::Full(_)) => {
+                    warn!(target: "raft", "the {} channel is full, dropping a message", name);
+                }
+                Err(async_channel::TrySendError::Closed(msg)) => {
+                    return Err(async_channel::SendError(msg).into())
+                }
+            },
+        }
         Ok(())
     }
 
@@ -373,10 +841,13 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             select! {
                 msg =  p2p_recv_channel.recv().fuse() => {
                     let msg = msg?;
-                    if msg.method == NetMsgMethod::SyncResponse {
-                        let sr: SyncResponse = deserialize(&msg.payload)?;
-                        self.receive_sync_response(&sr).await?;
-                        break
+                    if msg.method == NetMsgMethod::SyncResponse && self.is_authentic(&msg) {
+                        // A malformed response is dropped, the leader sends
+                        // the next one
+                        if let Some(sr) = decode_payload::<SyncResponse>(&msg, &msg.payload) {
+                            self.receive_sync_response(&sr).await?;
+                            break
+                        }
                     }},
                     _ = stop_signal.recv().fuse() => break,
             }
@@ -384,29 +855,84 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         Ok(())
     }
 
-    async fn send_heartbeat(&self) -> Result<()> {
+    async fn send_heartbeat(&mut self) -> Result<()> {
         if self.role == Role::Leader {
+            self.start_round();
             let nodes = self.nodes.lock().await;
             let nodes_cloned = nodes.clone();
             drop(nodes);
             for node in nodes_cloned.iter() {
+                if !self.sent_length.0.contains_key(node.0) {
+                    self.add_learner(node.0);
+                }
                 self.update_logs(node.0).await?;
             }
         }
         Ok(())
     }
 
+    /// Start a new heartbeat round, the acks of the nodes tell when they
+    /// last saw us as the leader.
+    fn start_round(&mut self) {
+        self.round += 1;
+        self.round_starts.push_back((self.round, Instant::now()));
+        if self.round_starts.len() > MAX_ROUNDS {
+            self.round_starts.pop_front();
+        }
+    }
+
+    /// Count the ack of a heartbeat round by a node of the current term.
+    fn ack_round(&mut self, node_id: &NodeId, round: u64) {
+        let start = match self.round_starts.iter().find(|(r, _)| *r == round) {
+            Some((_, start)) => *start,
+            None => return,
+        };
+        match self.round_acks.get(node_id) {
+            Some((acked, _)) if *acked >= round => {}
+            _ => {
+                self.round_acks.insert(node_id.clone(), (round, start));
+            }
+        }
+    }
+
+    /// Take the given role, notifying the receivers of `get_role_changes()`
+    /// if it differs from the current one.
+    fn set_role(&mut self, role: Role, leader: Option<NodeId>) {
+        if self.role == role {
+            return
+        }
+
+        info!(target: "raft", "role: {:?} -> {:?}, term: {}", self.role, role, self.current_term);
+        self.role = role.clone();
+        self.drop_stale_reads();
+
+        let change = RoleChange { role, term: self.current_term, leader };
+        if self.broadcast_role_changes.0.try_send(change).is_err() {
+            debug!(target: "raft", "role changes channel is full, drop the change");
+        }
+    }
+
+    /// Start replicating the logs to a node that joined after the election,
+    /// from the first entry since it may have none of them.
+    fn add_learner(&mut self, node_id: &NodeId) {
+        info!(target: "raft", "add learner: {:?}", node_id);
+        self.sent_length.insert(node_id, 0);
+        self.acked_length.insert(node_id, 0);
+        self.learners.insert(node_id.clone());
+    }
+
     async fn send_vote_request(&mut self) -> Result<()> {
-        if self.role == Role::Listener {
+        if self.role == Role::Listener || self.role == Role::Learner {
             return Ok(())
         }
 
         let self_id = self.id.clone().unwrap();
 
         self.set_current_term(&(self.current_term + 1))?;
-        self.role = Role::Candidate;
+        self.set_role(Role::Candidate, None);
         self.set_voted_for(&Some(self_id.clone()))?;
-        self.votes_received.push(self_id.clone());
+        // Votes from earlier elections don't count for this term
+        self.votes_received = vec![self_id.clone()];
 
         self.reset_last_term();
 
@@ -422,36 +948,36 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
     }
 
     async fn receive_vote_request(&mut self, vr: VoteRequest) -> Result<()> {
-        if self.role == Role::Listener {
+        if self.role == Role::Listener || self.role == Role::Learner {
+            return Ok(())
+        }
+
+        // The leader may still hold a lease, electing another node could
+        // commit entries its local reads would miss
+        if self.heard_from_leader() && self.current_leader.as_ref() != Some(&vr.node_id) {
+            debug!(target: "raft", "ignore vote request while following the leader");
             return Ok(())
         }
 
         if vr.current_term > self.current_term {
+            // The term is stored before clearing the vote: a crash in between
+            // leaves a stale vote that can only make the node refuse to vote
+            // in the new term, never vote twice in the old one.
             self.set_current_term(&vr.current_term)?;
             self.set_voted_for(&None)?;
-            self.role = Role::Follower;
+            self.set_role(Role::Follower, None);
         }
 
         self.reset_last_term();
 
-        // check the logs of the candidate
-        let vote_ok = (vr.last_term > self.last_term) ||
-            (vr.last_term == self.last_term && vr.log_length >= self.logs.len());
-
-        // slef.voted_for equal to vr.node_id or is None or voted to someone else
-        let vote = if let Some(voted_for) = self.voted_for.as_ref() {
-            *voted_for == vr.node_id
-        } else {
-            true
-        };
-
         let mut response = VoteResponse {
             node_id: self.id.clone().unwrap(),
             current_term: self.current_term,
             ok: false,
+            instance: self.instance,
         };
 
-        if vr.current_term == self.current_term && vote_ok && vote {
+        if self.can_vote_for(&vr) {
             self.set_voted_for(&Some(vr.node_id.clone()))?;
             response.set_ok(true);
         }
@@ -460,21 +986,102 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         self.send(Some(vr.node_id), &payload, NetMsgMethod::VoteResponse, None).await
     }
 
+    /// Whether leader leases are enabled and we heard from the leader less
+    /// than an election timeout ago.
+    fn heard_from_leader(&self) -> bool {
+        match self.leader_seen_at {
+            Some(seen) if !self.lease_duration.is_zero() => {
+                seen.elapsed() < Duration::from_millis(TIMEOUT)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the vote can be granted: the request is for the current term,
+    /// the candidate's logs are at least as up to date as ours, and we
+    /// haven't voted for another node in this term.
+    fn can_vote_for(&self, vr: &VoteRequest) -> bool {
+        if vr.current_term != self.current_term {
+            return false
+        }
+
+        let log_ok = (vr.last_term > self.last_term) ||
+            (vr.last_term == self.last_term && vr.log_length >= self.logs.len());
+
+        let not_voted_for_other = match self.voted_for.as_ref() {
+            Some(voted_for) => *voted_for == vr.node_id,
+            None => true,
+        };
+
+        log_ok && not_voted_for_other
+    }
+
+    /// Record that `instance` of the node is running. Returns false if
+    /// another node runs with the same id, in which case the messages of
+    /// both are ignored and their votes and acks aren't counted.
+    fn check_instance(&mut self, node_id: &NodeId, instance: u64) -> bool {
+        if self.id.as_ref() == Some(node_id) && instance != self.instance {
+            error!(
+                target: "raft",
+                "another node runs with our id {:?}, its messages are ignored", node_id
+            );
+            return false
+        }
+
+        let window = Duration::from_millis(COLLISION_WINDOW);
+        let seen = self.instances.entry(node_id.clone()).or_default();
+        seen.retain(|(_, at)| at.elapsed() < window);
+        match seen.iter_mut().find(|(i, _)| *i == instance) {
+            Some((_, at)) => *at = Instant::now(),
+            None => {
+                seen.push((instance, Instant::now()));
+                if seen.len() > 1 {
+                    error!(
+                        target: "raft",
+                        "{} nodes run with the same id {:?}, e.g. behind the same NAT, \
+                         none of them counts for the quorum",
+                        seen.len(), node_id
+                    );
+                }
+            }
+        }
+        seen.len() == 1
+    }
+
+    /// Whether several nodes were seen lately with this id.
+    fn is_colliding(&self, node_id: &NodeId) -> bool {
+        let window = Duration::from_millis(COLLISION_WINDOW);
+        self.instances
+            .get(node_id)
+            .is_some_and(|seen| seen.iter().filter(|(_, at)| at.elapsed() < window).count() > 1)
+    }
+
     async fn receive_vote_response(&mut self, vr: VoteResponse) -> Result<()> {
-        if self.role == Role::Listener {
+        if self.role == Role::Listener || self.role == Role::Learner {
+            return Ok(())
+        }
+
+        if !self.check_instance(&vr.node_id, vr.instance) {
             return Ok(())
         }
 
         if self.role == Role::Candidate && vr.current_term == self.current_term && vr.ok {
+            // A node may answer more than once, count its vote only once
+            if self.votes_received.contains(&vr.node_id) {
+                return Ok(())
+            }
             self.votes_received.push(vr.node_id);
 
             let nodes = self.nodes.lock().await;
             let nodes_cloned = nodes.clone();
             drop(nodes);
 
-            if self.votes_received.len() >= ((nodes_cloned.len() + 1) / 2) {
-                self.role = Role::Leader;
+            // Votes given before a collision showed up don't count either
+            let votes = self.votes_received.iter().filter(|id| !self.is_colliding(id)).count();
+            if votes >= ((nodes_cloned.len() + 1) / 2) {
                 self.current_leader = Some(self.id.clone().unwrap());
+                self.set_role(Role::Leader, self.current_leader.clone());
+                self.learners.clear();
                 for node in nodes_cloned.iter() {
                     self.sent_length.insert(node.0, self.logs.len());
                     self.acked_length.insert(node.0, 0);
@@ -482,7 +1089,7 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             }
         } else if vr.current_term > self.current_term {
             self.set_current_term(&vr.current_term)?;
-            self.role = Role::Follower;
+            self.set_role(Role::Follower, None);
             self.set_voted_for(&None)?;
         }
 
@@ -498,10 +1105,10 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             }
         };
 
-        let suffix: Logs = if self.logs.slice_from(prefix_len).is_some() {
-            self.logs.slice_from(prefix_len).unwrap()
-        } else {
-            return Ok(())
+        // The rest is sent once the node acks this batch
+        let suffix: Logs = match self.logs.slice(prefix_len, self.log_batch_size) {
+            Some(suffix) => suffix,
+            None => return Ok(()),
         };
 
         let mut prefix_term = 0;
@@ -517,6 +1124,7 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             prefix_term,
             commit_length: self.commit_length,
             suffix,
+            round: self.round,
         };
 
         let payload = serialize(&request);
@@ -530,10 +1138,11 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         }
 
         if lr.current_term == self.current_term {
-            if self.role != Role::Listener {
-                self.role = Role::Follower;
-            }
             self.current_leader = Some(lr.leader_id.clone());
+            self.leader_seen_at = Some(Instant::now());
+            if self.role != Role::Listener && self.role != Role::Learner {
+                self.set_role(Role::Follower, self.current_leader.clone());
+            }
         }
 
         let mut ok = (self.logs.len() >= lr.prefix_len) &&
@@ -544,6 +1153,12 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         if lr.current_term == self.current_term && ok {
             self.append_log(lr.prefix_len, lr.commit_length, &lr.suffix).await?;
             ack = lr.prefix_len + lr.suffix.len();
+
+            // The leader promotes us once our acks reach its commit length
+            if self.role == Role::Learner && ack >= lr.commit_length {
+                info!(target: "raft", "caught up with the leader, become a follower");
+                self.set_role(Role::Follower, self.current_leader.clone());
+            }
         } else {
             ok = false;
         }
@@ -557,6 +1172,8 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             current_term: self.current_term,
             ack,
             ok,
+            round: if lr.current_term == self.current_term { lr.round } else { 0 },
+            instance: self.instance,
         };
 
         let payload = serialize(&response);
@@ -564,18 +1181,34 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
     }
 
     async fn receive_log_response(&mut self, lr: LogResponse) -> Result<()> {
+        // Acks from nodes sharing an id would mix up their sent and acked
+        // lengths
+        if !self.check_instance(&lr.node_id, lr.instance) {
+            return Ok(())
+        }
+
         if lr.current_term == self.current_term && self.role == Role::Leader {
+            // Rejected requests still ack the leadership
+            self.ack_round(&lr.node_id, lr.round);
+            self.settle_reads().await;
             if lr.ok && lr.ack >= self.acked_length.get(&lr.node_id)? {
                 self.sent_length.insert(&lr.node_id, lr.ack);
                 self.acked_length.insert(&lr.node_id, lr.ack);
+                if lr.ack >= self.commit_length && self.learners.remove(&lr.node_id) {
+                    info!(target: "raft", "promote learner to voter: {:?}", lr.node_id);
+                }
                 self.commit_log().await?;
+                // Send the next batch to a node catching up right away
+                if lr.ack < self.logs.len() {
+                    self.update_logs(&lr.node_id).await?;
+                }
             } else if self.sent_length.get(&lr.node_id)? > 0 {
                 self.sent_length.insert(&lr.node_id, self.sent_length.get(&lr.node_id)? - 1);
             }
         } else if lr.current_term > self.current_term {
             self.set_current_term(&lr.current_term)?;
-            if self.role != Role::Listener {
-                self.role = Role::Follower;
+            if self.role != Role::Listener && self.role != Role::Learner {
+                self.set_role(Role::Follower, None);
             }
             self.set_voted_for(&None)?;
         }
@@ -595,6 +1228,9 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
         nodes
             .into_iter()
             .filter(|n| {
+                if self.is_colliding(&n.0) {
+                    return false
+                }
                 let len = self.acked_length.get(&n.0);
                 len.is_ok() && len.unwrap() >= length
             })
@@ -603,9 +1239,14 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
 
     async fn commit_log(&mut self) -> Result<()> {
         let nodes_ptr = self.nodes.lock().await;
-        let min_acks = ((nodes_ptr.len() + 1) / 2) as usize;
-        let nodes = nodes_ptr.clone();
+        // learners don't count for the quorum
+        let nodes: HashMap<NodeId, Url> = nodes_ptr
+            .iter()
+            .filter(|(id, _)| !self.learners.contains(id))
+            .map(|(id, url)| (id.clone(), url.clone()))
+            .collect();
         drop(nodes_ptr);
+        let min_acks = ((nodes.len() + 1) / 2) as usize;
 
         let mut ready: Vec<u64> = vec![];
 
@@ -621,13 +1262,13 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
 
         let max_ready = *ready.iter().max().unwrap();
 
+        // Only an entry from the current term is committed by counting
+        // acks. Entries from previous terms are committed along with it,
+        // never on their own, as another leader could still overwrite
+        // them (Raft §5.4.2).
         if max_ready > self.commit_length && self.logs.get(max_ready - 1)?.term == self.current_term
         {
-            for i in self.commit_length..max_ready {
-                self.push_commit(&self.logs.get(i)?.msg).await?;
-            }
-
-            self.set_commit_length(&max_ready)?;
+            self.apply_commits(max_ready).await?;
         }
 
         Ok(())
@@ -652,39 +1293,1096 @@ impl<T: Decodable + Encodable + Clone> Raft<T> {
             }
         }
 
-        if leader_commit > self.commit_length {
-            for i in self.commit_length..leader_commit {
-                self.push_commit(&self.logs.get(i)?.msg).await?;
-            }
-            self.set_commit_length(&leader_commit)?;
-        }
+        // The leader may have committed entries not sent to us yet
+        self.apply_commits(min(leader_commit, self.logs.len())).await?;
 
         Ok(())
     }
 
     fn set_commit_length(&mut self, i: &u64) -> Result<()> {
         self.commit_length = *i;
+        self.shared_commit_length.store(*i, Ordering::Relaxed);
         Ok(())
     }
     fn set_current_term(&mut self, i: &u64) -> Result<()> {
         self.current_term = *i;
+        self.round_acks.clear();
+        self.leader_seen_at = None;
+        self.settle_proposals();
+        self.drop_stale_reads();
         self.datastore.current_term.insert(i)
     }
     fn set_voted_for(&mut self, i: &Option<NodeId>) -> Result<()> {
         self.voted_for = i.clone();
         self.datastore.voted_for.insert(i)
     }
-    async fn push_commit(&mut self, commit: &[u8]) -> Result<()> {
-        let commit: T = deserialize(commit)?;
-        self.broadcast_commits.0.send(commit.clone()).await?;
-        self.datastore.commits.insert(&commit)
+    /// Apply the log entries up to `length`, in order. Each entry is
+    /// persisted to the commits store before the commit length moves past
+    /// it, and only then sent to the commits channel.
+    ///
+    /// An entry that can't be applied stops there with
+    /// `Error::RaftApplyError`, leaving the commit length on it so that no
+    /// later entry is committed ahead of it. Failing to send an entry
+    /// doesn't stop the others from being applied, the first send error is
+    /// returned at the end and the entries can be read back with
+    /// `committed_entries`.
+    async fn apply_commits(&mut self, length: u64) -> Result<()> {
+        let mut sent = Ok(());
+        for i in self.commit_length..length {
+            let commit = self.apply_commit(i)?;
+            self.set_commit_length(&(i + 1))?;
+            self.settle_proposals();
+            if sent.is_ok() {
+                sent = self.send_bounded(&self.broadcast_commits.0, commit, "commits").await;
+            }
+        }
+        self.check_apply_lag();
+        sent
+    }
+    /// Warn when the application falls more than `apply_lag_warning`
+    /// entries behind the commits, and tell when it has caught up, as
+    /// seen on the next commits.
+    fn check_apply_lag(&mut self) {
+        let lag = self.broadcast_commits.0.len() as u64;
+        if lag > self.apply_lag_warning && !self.lagging {
+            warn!(
+                target: "raft",
+                "the application is {} entries behind the commits (commit length {})",
+                lag,
+                self.commit_length
+            );
+            self.lagging = true;
+        } else if lag <= self.apply_lag_warning && self.lagging {
+            info!(target: "raft", "the application caught up with the commits");
+            self.lagging = false;
+        }
+    }
+    fn apply_commit(&mut self, index: u64) -> Result<T> {
+        let commit: T = deserialize(&self.logs.get(index)?.msg).map_err(|e| {
+            Error::RaftApplyError(format!("unable to decode log entry {}: {}", index, e))
+        })?;
+        self.datastore.commits.insert(&commit)?;
+        Ok(commit)
     }
     fn push_log(&mut self, log: &Log) -> Result<()> {
         self.logs.push(log);
+        let index = self.logs.len() - 1;
+        if let Some(proposal) =
+            self.proposals.iter_mut().find(|p| p.index.is_none() && p.msg == log.msg)
+        {
+            proposal.index = Some(index);
+        }
         self.datastore.logs.insert(log)
     }
     fn push_logs(&mut self, logs: &Logs) -> Result<()> {
         self.logs = logs.clone();
+        self.settle_proposals();
         self.datastore.logs.wipe_insert_all(&logs.to_vec())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::remove_dir_all;
+
+    use super::*;
+    use crate::raft::SyncPolicy;
+
+    fn create_datastore(path: &str, terms: &[u64], commits: u64) -> Result<PathBuf> {
+        remove_dir_all(path).ok();
+
+        let datastore = DataStore::<u64>::new(path, SyncPolicy::Always)?;
+        for term in terms {
+            datastore.logs.insert(&Log { term: *term, msg: serialize(term) })?;
+        }
+        for i in 0..commits {
+            datastore.commits.insert(&i)?;
+        }
+
+        Ok(PathBuf::from(path))
+    }
+
+    /// Open a Raft over the datastore at `path`. sled may hold the lock of
+    /// a datastore closed just before for a little while, so retry then.
+    fn new_raft(addr: Option<Url>, path: PathBuf, settings: RaftSettings) -> Result<Raft<u64>> {
+        let mut attempts = 0;
+        loop {
+            match Raft::new(addr.clone(), path.clone(), settings.clone()) {
+                Err(Error::SledError(_)) if attempts < 50 => {
+                    attempts += 1;
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                res => return res,
+            }
+        }
+    }
+
+    #[test]
+    fn load_valid_datastore() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_datastore_valid", &[1, 1, 2], 2)?;
+
+        let raft = new_raft(None, path.clone(), RaftSettings::default())?;
+        assert_eq!(raft.logs.len(), 3);
+        assert_eq!(raft.commit_length, 2);
+        drop(raft);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn restore_nodes() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_restore_nodes", &[1], 1)?;
+
+        let url_a = Url::parse("tcp://127.0.0.1:11001").unwrap();
+        let url_b = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let datastore = DataStore::<u64>::new(path.to_str().unwrap(), SyncPolicy::Always)?;
+        datastore.nodes.wipe_insert_all(&vec![
+            (NodeId::from(url_a.clone()), url_a.clone()),
+            (NodeId::from(url_b.clone()), url_b.clone()),
+        ])?;
+        drop(datastore);
+
+        let raft = new_raft(None, path.clone(), RaftSettings::default())?;
+        let nodes = task::block_on(raft.nodes.lock()).clone();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes.get(&NodeId::from(url_a.clone())), Some(&url_a));
+        assert_eq!(nodes.get(&NodeId::from(url_b.clone())), Some(&url_b));
+        drop(raft);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn committed_entries() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_committed_entries", &[1, 1, 2, 2], 3)?;
+
+        let raft = new_raft(None, path.clone(), RaftSettings::default())?;
+        assert_eq!(raft.committed_entries(0)?, vec![(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(raft.committed_entries(2)?, vec![(2, 2)]);
+        assert!(raft.committed_entries(3)?.is_empty());
+        assert!(raft.committed_entries(10)?.is_empty());
+        drop(raft);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn replay() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_replay", &[1, 1, 2], 3)?;
+
+        let raft = new_raft(None, path.clone(), RaftSettings::default())?;
+        let mut replayed = vec![];
+        let count = raft.replay(|index, commit| {
+            replayed.push((index, commit));
+            Ok(())
+        })?;
+        assert_eq!(count, 3);
+        assert_eq!(replayed, raft.committed_entries(0)?);
+
+        // The replay stops at the first error
+        let mut applied = 0;
+        let res = raft.replay(|index, _| {
+            if index == 1 {
+                return Err(Error::RaftError("cannot apply".into()))
+            }
+            applied += 1;
+            Ok(())
+        });
+        assert!(res.is_err());
+        assert_eq!(applied, 1);
+        drop(raft);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn apply_commits() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_apply_commits", &[], 0)?;
+
+        let mut raft = new_raft(None, path.clone(), RaftSettings::default())?;
+        raft.push_log(&Log { term: 1, msg: serialize(&7u64) })?;
+        raft.push_log(&Log { term: 1, msg: vec![1, 2] })?;
+        raft.push_log(&Log { term: 1, msg: serialize(&8u64) })?;
+
+        // The undecodable entry blocks the ones after it
+        let res = task::block_on(raft.apply_commits(3));
+        assert!(matches!(res, Err(Error::RaftApplyError(_))));
+        assert_eq!(raft.commit_length, 1);
+        assert_eq!(raft.committed_entries(0)?, vec![(0, 7)]);
+        assert_eq!(raft.broadcast_commits.1.try_recv().unwrap(), 7);
+        assert!(raft.broadcast_commits.1.is_empty());
+
+        // Entries are applied even when they can't be sent
+        raft.push_logs(&Logs(vec![
+            Log { term: 1, msg: serialize(&7u64) },
+            Log { term: 1, msg: serialize(&8u64) },
+            Log { term: 1, msg: serialize(&9u64) },
+        ]))?;
+        raft.broadcast_commits.0.close();
+        assert!(task::block_on(raft.apply_commits(3)).is_err());
+        assert_eq!(raft.commit_length, 3);
+        assert_eq!(raft.committed_entries(0)?, vec![(0, 7), (1, 8), (2, 9)]);
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn wipe_sync_then_restart() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_wipe_sync", &[1, 1, 2], 3)?;
+
+        let mut raft = new_raft(None, path.clone(), RaftSettings::default())?;
+        let leader = NodeId::from(Url::parse("tcp://127.0.0.1:11004").unwrap());
+        let sr = SyncResponse {
+            logs: Logs(vec![
+                Log { term: 3, msg: serialize(&7u64) },
+                Log { term: 3, msg: serialize(&8u64) },
+            ]),
+            commit_length: 2,
+            leader_id: leader,
+            wipe: true,
+        };
+        task::block_on(raft.receive_sync_response(&sr))?;
+        assert_eq!(raft.committed_entries(0)?, vec![(0, 7), (1, 8)]);
+        drop(raft);
+
+        // The node comes back up with the synced state
+        let raft = new_raft(None, path.clone(), RaftSettings::default())?;
+        assert_eq!(raft.commit_length, 2);
+        assert_eq!(raft.datastore.commits.get_all()?, vec![7, 8]);
+        drop(raft);
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn skip_malformed_sync_response() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_malformed_sync", &[], 0)?;
+
+        let mut raft = new_raft(None, path.clone(), RaftSettings::default())?;
+        let leader = NodeId::from(Url::parse("tcp://127.0.0.1:11005").unwrap());
+        let sr = SyncResponse {
+            logs: Logs(vec![Log { term: 1, msg: serialize(&7u64) }]),
+            commit_length: 1,
+            leader_id: leader.clone(),
+            wipe: true,
+        };
+        let sync_msg = |payload| NetMsg {
+            id: 1,
+            recipient_id: None,
+            method: NetMsgMethod::SyncResponse,
+            payload,
+            mac: None,
+        };
+
+        let (sender, receiver) = async_channel::unbounded();
+        let (_stop_sender, stop_receiver) = async_channel::unbounded();
+        task::block_on(sender.send(sync_msg(vec![1, 2])))?;
+        task::block_on(sender.send(sync_msg(serialize(&sr))))?;
+
+        // The malformed response is skipped, the next one is applied
+        task::block_on(raft.waiting_for_sync(receiver, stop_receiver))?;
+        assert_eq!(raft.current_leader, Some(leader));
+        assert_eq!(raft.committed_entries(0)?, vec![(0, 7)]);
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn apply_lag() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_apply_lag", &[1, 1], 1)?;
+
+        let settings = RaftSettings { apply_lag_warning: 1, ..RaftSettings::default() };
+        let mut raft = new_raft(None, path.clone(), settings)?;
+        let status = raft.apply_status();
+        assert_eq!(status.commit_length(), 1);
+        assert_eq!(status.lag(), 0);
+
+        for i in 2..4u64 {
+            raft.push_log(&Log { term: 1, msg: serialize(&i) })?;
+        }
+        task::block_on(raft.apply_commits(4))?;
+        assert_eq!(status.commit_length(), 4);
+        assert_eq!(status.lag(), 3);
+        assert!(raft.lagging);
+
+        // The lag shrinks as the application receives the commits
+        raft.get_commits().try_recv().unwrap();
+        raft.get_commits().try_recv().unwrap();
+        assert_eq!(status.lag(), 1);
+        raft.check_apply_lag();
+        assert!(!raft.lagging);
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn detect_commit_length_corruption() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_datastore_commits", &[1], 3)?;
+
+        assert!(new_raft(None, path.clone(), RaftSettings::default()).is_err());
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn detect_terms_corruption() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_datastore_terms", &[1, 3, 2], 1)?;
+
+        assert!(new_raft(None, path.clone(), RaftSettings::default()).is_err());
+
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn election_delay_by_priority() {
+        assert_eq!(election_delay(MAX_ELECTION_PRIORITY), 0);
+        assert_eq!(election_delay(MAX_ELECTION_PRIORITY + 1), 0);
+        assert_eq!(election_delay(MAX_ELECTION_PRIORITY - 1), TIMEOUT_JITTER);
+        assert_eq!(election_delay(0), MAX_ELECTION_PRIORITY as u64 * TIMEOUT_JITTER);
+    }
+
+    #[test]
+    fn append_as_leader() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_append_as_leader", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.role = Role::Leader;
+        raft.current_term = 2;
+
+        raft.append_as_leader(&7)?;
+        raft.append_as_leader(&8)?;
+
+        assert_eq!(raft.logs.len(), 2);
+        let log = raft.logs.get(1)?;
+        assert_eq!(log.term, 2);
+        assert_eq!(deserialize::<u64>(&log.msg)?, 8);
+        assert_eq!(raft.acked_length.get(&raft.id.clone().unwrap())?, 2);
+        assert_eq!(raft.datastore.logs.get_all()?.len(), 2);
+
+        // Nothing is sent to the network until the next heartbeat
+        assert!(raft.sender.1.is_empty());
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn forward_to_leader() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_forward_to_leader", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let leader = NodeId::from(Url::parse("tcp://127.0.0.1:11003").unwrap());
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.current_leader = Some(leader.clone());
+
+        task::block_on(raft.forward_to_leader(&7, Some(42)))?;
+
+        let net_msg = raft.sender.1.try_recv().unwrap();
+        assert_eq!(net_msg.id, 42);
+        assert_eq!(net_msg.method, NetMsgMethod::BroadcastRequest);
+        assert_eq!(net_msg.recipient_id, Some(leader));
+        let b_msg: BroadcastMsgRequest = deserialize(&net_msg.payload)?;
+        assert_eq!(deserialize::<u64>(&b_msg.0)?, 7);
+
+        // Followers don't touch their own logs
+        assert_eq!(raft.logs.len(), 0);
+        assert!(raft.datastore.logs.get_all()?.is_empty());
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn propose_as_leader() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_propose_as_leader", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.role = Role::Leader;
+        raft.current_term = 2;
+
+        let mut first = task::block_on(raft.propose(7))?;
+        let mut second = task::block_on(raft.propose(8))?;
+        while let Ok((msg, reply)) = raft.broadcast_proposals.1.try_recv() {
+            task::block_on(raft.receive_proposal(&msg, reply))?;
+        }
+        assert_eq!(raft.logs.len(), 2);
+
+        task::block_on(raft.apply_commits(1))?;
+        assert!(matches!(first.try_recv(), Ok(Some(Ok(0)))));
+        assert!(matches!(second.try_recv(), Ok(None)));
+
+        // A new leader overwrites the uncommitted entry
+        raft.push_logs(&Logs(raft.logs.0[..1].to_vec()))?;
+        assert!(matches!(second.try_recv(), Ok(Some(Err(Error::RaftError(_))))));
+        assert!(raft.proposals.is_empty());
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn propose_as_follower() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_propose_as_follower", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let leader = NodeId::from(Url::parse("tcp://127.0.0.1:11003").unwrap());
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.current_term = 1;
+        raft.current_leader = Some(leader);
+
+        let (reply, mut committed) = oneshot::channel();
+        task::block_on(raft.receive_proposal(&7, reply))?;
+        let (reply, mut dropped) = oneshot::channel();
+        task::block_on(raft.receive_proposal(&8, reply))?;
+        assert_eq!(raft.sender.1.len(), 2);
+
+        // The leader appended the first entry only
+        task::block_on(raft.append_log(0, 1, &Logs(vec![Log { term: 1, msg: serialize(&7u64) }])))?;
+        assert!(matches!(committed.try_recv(), Ok(Some(Ok(0)))));
+        assert!(matches!(dropped.try_recv(), Ok(None)));
+
+        raft.set_current_term(&2)?;
+        assert!(matches!(dropped.try_recv(), Ok(Some(Err(Error::RaftError(_))))));
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    fn vote_request(node_id: &NodeId, term: u64) -> VoteRequest {
+        VoteRequest { node_id: node_id.clone(), current_term: term, log_length: 0, last_term: 0 }
+    }
+
+    fn vote_granted(raft: &mut Raft<u64>, vr: VoteRequest) -> Result<bool> {
+        task::block_on(raft.receive_vote_request(vr))?;
+        let response: VoteResponse = deserialize(&raft.sender.1.try_recv().unwrap().payload)?;
+        Ok(response.ok)
+    }
+
+    #[test]
+    fn single_vote_per_term() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_single_vote_per_term", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let node_a = NodeId::from(Url::parse("tcp://127.0.0.1:11003").unwrap());
+        let node_b = NodeId::from(Url::parse("tcp://127.0.0.1:11004").unwrap());
+        let mut raft = new_raft(Some(addr.clone()), path.clone(), RaftSettings::default())?;
+
+        // Two competing candidates in the same term, only the first gets the vote
+        assert!(vote_granted(&mut raft, vote_request(&node_a, 3))?);
+        assert!(!vote_granted(&mut raft, vote_request(&node_b, 3))?);
+        // A retried request from the same candidate is granted again
+        assert!(vote_granted(&mut raft, vote_request(&node_a, 3))?);
+
+        // The vote survives a restart
+        drop(raft);
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        assert_eq!(raft.current_term, 3);
+        assert!(!vote_granted(&mut raft, vote_request(&node_b, 3))?);
+
+        // Requests for an older term are refused, a new term allows a new vote
+        assert!(!vote_granted(&mut raft, vote_request(&node_b, 2))?);
+        assert!(vote_granted(&mut raft, vote_request(&node_b, 4))?);
+        assert!(!vote_granted(&mut raft, vote_request(&node_a, 4))?);
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn role_changes() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_role_changes", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let other = NodeId::from(Url::parse("tcp://127.0.0.1:11003").unwrap());
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        let self_id = raft.id.clone();
+        let role_changes = raft.get_role_changes();
+
+        // Elected with the vote of the other node
+        task::block_on(raft.send_vote_request())?;
+        let response =
+            VoteResponse { node_id: other.clone(), current_term: 1, ok: true, instance: 0 };
+        task::block_on(raft.receive_vote_response(response))?;
+        assert_eq!(
+            role_changes.try_recv().unwrap(),
+            RoleChange { role: Role::Candidate, term: 1, leader: None }
+        );
+        assert_eq!(
+            role_changes.try_recv().unwrap(),
+            RoleChange { role: Role::Leader, term: 1, leader: self_id }
+        );
+
+        // The other node leads a higher term
+        let request = LogRequest {
+            leader_id: other.clone(),
+            current_term: 2,
+            prefix_len: 0,
+            prefix_term: 0,
+            commit_length: 0,
+            suffix: Logs(vec![]),
+            round: 0,
+        };
+        task::block_on(raft.receive_log_request(request.clone()))?;
+        assert_eq!(
+            role_changes.try_recv().unwrap(),
+            RoleChange { role: Role::Follower, term: 2, leader: Some(other) }
+        );
+
+        // Staying a follower isn't a change
+        task::block_on(raft.receive_log_request(request))?;
+        assert!(role_changes.try_recv().is_err());
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn no_prior_term_commit() -> Result<()> {
+        // Figure 8 of the Raft paper: the leader of term 2 replicated its
+        // entry to one node and crashed. As the leader of term 4 it gets
+        // the entry on a majority, which must not commit it.
+        let path = create_datastore("/tmp/test_raft_no_prior_term_commit", &[1, 2], 1)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.role = Role::Leader;
+        raft.current_term = 4;
+
+        let mut followers = vec![];
+        for (port, acked) in [(11003, 2), (11004, 1), (11005, 1), (11006, 1)] {
+            let url = Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+            let node_id = NodeId::from(url.clone());
+            raft.sent_length.insert(&node_id, acked);
+            raft.acked_length.insert(&node_id, acked);
+            task::block_on(raft.nodes.lock()).insert(node_id.clone(), url);
+            followers.push(node_id);
+        }
+
+        let response = |node_id: &NodeId, ack| LogResponse {
+            node_id: node_id.clone(),
+            current_term: 4,
+            ack,
+            ok: true,
+            round: 0,
+            instance: 0,
+        };
+
+        // Three of five nodes have the term 2 entry, it stays uncommitted
+        task::block_on(raft.receive_log_response(response(&followers[1], 2)))?;
+        assert_eq!(raft.commit_length, 1);
+
+        // An entry from the current term on a majority commits both
+        raft.append_as_leader(&4)?;
+        task::block_on(raft.receive_log_response(response(&followers[0], 3)))?;
+        assert_eq!(raft.commit_length, 1);
+        task::block_on(raft.receive_log_response(response(&followers[1], 3)))?;
+        assert_eq!(raft.commit_length, 3);
+        assert_eq!(raft.datastore.commits.get_all()?.len(), 3);
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn node_id_collision() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_node_id_collision", &[1], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.role = Role::Leader;
+        raft.current_term = 1;
+
+        let mut followers = vec![];
+        for port in [11003, 11004, 11005] {
+            let url = Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+            let node_id = NodeId::from(url.clone());
+            raft.sent_length.insert(&node_id, 0);
+            raft.acked_length.insert(&node_id, 0);
+            task::block_on(raft.nodes.lock()).insert(node_id.clone(), url);
+            followers.push(node_id);
+        }
+
+        let response = |node_id: &NodeId, instance| LogResponse {
+            node_id: node_id.clone(),
+            current_term: 1,
+            ack: 1,
+            ok: true,
+            round: 0,
+            instance,
+        };
+
+        // Two nodes behind the same NAT answer as the first follower, so
+        // neither of them counts for the commit
+        task::block_on(raft.receive_log_response(response(&followers[0], 1)))?;
+        task::block_on(raft.receive_log_response(response(&followers[0], 2)))?;
+        assert!(raft.is_colliding(&followers[0]));
+        task::block_on(raft.receive_log_response(response(&followers[1], 1)))?;
+        assert_eq!(raft.commit_length, 0);
+        task::block_on(raft.receive_log_response(response(&followers[2], 1)))?;
+        assert_eq!(raft.commit_length, 1);
+
+        // Once one of them is gone, the other counts again like a node
+        // that restarted
+        let past = Instant::now().checked_sub(Duration::from_millis(COLLISION_WINDOW)).unwrap();
+        for (_, at) in raft.instances.get_mut(&followers[0]).unwrap().iter_mut() {
+            *at = past;
+        }
+        assert!(!raft.is_colliding(&followers[0]));
+        assert!(raft.check_instance(&followers[0], 2));
+        assert!(!raft.is_colliding(&followers[0]));
+
+        // Nor does a node running with our own id
+        let own_id = raft.id.clone().unwrap();
+        assert!(!raft.check_instance(&own_id, raft.instance.wrapping_add(1)));
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn promote_learner() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_promote_learner", &[1, 1], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let voter_addr = Url::parse("tcp://127.0.0.1:11003").unwrap();
+        let learner_addr = Url::parse("tcp://127.0.0.1:11004").unwrap();
+        let (voter, learner) =
+            (NodeId::from(voter_addr.clone()), NodeId::from(learner_addr.clone()));
+
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.role = Role::Leader;
+        raft.current_term = 1;
+        raft.sent_length.insert(&voter, 2);
+        raft.acked_length.insert(&voter, 0);
+        task::block_on(raft.nodes.lock()).insert(voter.clone(), voter_addr);
+        task::block_on(raft.nodes.lock()).insert(learner.clone(), learner_addr);
+
+        // The new node gets the logs from the first entry
+        task::block_on(raft.send_heartbeat())?;
+        assert!(raft.learners.contains(&learner));
+        let requests: Vec<LogRequest> = std::iter::from_fn(|| raft.sender.1.try_recv().ok())
+            .map(|msg| deserialize(&msg.payload).unwrap())
+            .collect();
+        assert!(requests.iter().any(|lr| lr.prefix_len == 0 && lr.suffix.len() == 2));
+
+        // The voter's ack is enough to commit, the learner doesn't count
+        let response = |node_id: &NodeId, ack| LogResponse {
+            node_id: node_id.clone(),
+            current_term: 1,
+            ack,
+            ok: true,
+            round: 0,
+            instance: 0,
+        };
+        task::block_on(raft.receive_log_response(response(&voter, 2)))?;
+        assert_eq!(raft.commit_length, 2);
+        assert!(raft.learners.contains(&learner));
+
+        // Promoted once its acks reach the commit length
+        task::block_on(raft.receive_log_response(response(&learner, 1)))?;
+        assert!(raft.learners.contains(&learner));
+        task::block_on(raft.receive_log_response(response(&learner, 2)))?;
+        assert!(!raft.learners.contains(&learner));
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn learner_catches_up() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_learner_catches_up", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11004").unwrap();
+        let leader = NodeId::from(Url::parse("tcp://127.0.0.1:11002").unwrap());
+        let settings = RaftSettings { join_as_learner: true, ..Default::default() };
+        let mut raft = new_raft(Some(addr), path.clone(), settings)?;
+        assert_eq!(raft.role, Role::Learner);
+
+        // Learners neither vote nor start elections
+        task::block_on(raft.receive_vote_request(vote_request(&leader, 1)))?;
+        task::block_on(raft.send_vote_request())?;
+        assert!(raft.sender.1.is_empty());
+        assert_eq!(raft.current_term, 0);
+
+        let log_request = |prefix_len, terms: &[u64]| LogRequest {
+            leader_id: leader.clone(),
+            current_term: 1,
+            prefix_len,
+            prefix_term: 1,
+            commit_length: 2,
+            suffix: Logs(terms.iter().map(|t| Log { term: *t, msg: serialize(t) }).collect()),
+            round: 0,
+        };
+
+        // Missing the logs before the suffix
+        task::block_on(raft.receive_log_request(log_request(1, &[1])))?;
+        assert_eq!(raft.role, Role::Learner);
+        task::block_on(raft.receive_log_request(log_request(0, &[1, 1])))?;
+        assert_eq!(raft.role, Role::Follower);
+        assert_eq!(raft.commit_length, 2);
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn batch_log_requests() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_batch_log_requests", &[1, 1, 1, 1, 1], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let follower_addr = Url::parse("tcp://127.0.0.1:11003").unwrap();
+        let follower = NodeId::from(follower_addr.clone());
+
+        let settings = RaftSettings { log_batch_size: 2, ..RaftSettings::default() };
+        let mut raft = new_raft(Some(addr), path.clone(), settings)?;
+        raft.role = Role::Leader;
+        raft.current_term = 1;
+        raft.sent_length.insert(&follower, 0);
+        raft.acked_length.insert(&follower, 0);
+        task::block_on(raft.nodes.lock()).insert(follower.clone(), follower_addr);
+
+        let next_request = |raft: &Raft<u64>| -> LogRequest {
+            deserialize(&raft.sender.1.try_recv().unwrap().payload).unwrap()
+        };
+        let response = |ack| LogResponse {
+            node_id: follower.clone(),
+            current_term: 1,
+            ack,
+            ok: true,
+            round: 0,
+            instance: 0,
+        };
+
+        task::block_on(raft.send_heartbeat())?;
+        let request = next_request(&raft);
+        assert_eq!((request.prefix_len, request.suffix.len()), (0, 2));
+
+        // Each ack brings the next batch, up to the last entry
+        task::block_on(raft.receive_log_response(response(2)))?;
+        let request = next_request(&raft);
+        assert_eq!((request.prefix_len, request.suffix.len()), (2, 2));
+        task::block_on(raft.receive_log_response(response(4)))?;
+        let request = next_request(&raft);
+        assert_eq!((request.prefix_len, request.suffix.len()), (4, 1));
+        task::block_on(raft.receive_log_response(response(5)))?;
+        assert!(raft.sender.1.is_empty());
+        assert_eq!(raft.commit_length, 5);
+        drop(raft);
+
+        // A follower only commits the entries it has received so far
+        let follower_path = create_datastore("/tmp/test_raft_batch_log_requests_follower", &[], 0)?;
+        let leader = NodeId::from(Url::parse("tcp://127.0.0.1:11002").unwrap());
+        let mut raft = new_raft(
+            Some(Url::parse("tcp://127.0.0.1:11003").unwrap()),
+            follower_path.clone(),
+            RaftSettings::default(),
+        )?;
+        let request = LogRequest {
+            leader_id: leader,
+            current_term: 1,
+            prefix_len: 0,
+            prefix_term: 0,
+            commit_length: 5,
+            suffix: Logs(vec![Log { term: 1, msg: serialize(&1u64) }; 2]),
+            round: 0,
+        };
+        task::block_on(raft.receive_log_request(request))?;
+        assert_eq!(raft.logs.len(), 2);
+        assert_eq!(raft.commit_length, 2);
+        drop(raft);
+
+        remove_dir_all(path).ok();
+        remove_dir_all(follower_path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn leased_read() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_leased_read", &[1], 1)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.current_term = 1;
+
+        let mut followers = vec![];
+        for port in [11003, 11004] {
+            let url = Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+            let node_id = NodeId::from(url.clone());
+            raft.sent_length.insert(&node_id, 1);
+            raft.acked_length.insert(&node_id, 1);
+            task::block_on(raft.nodes.lock()).insert(node_id.clone(), url);
+            followers.push(node_id);
+        }
+
+        let read = |raft: &mut Raft<u64>| {
+            let (reply, read) = oneshot::channel();
+            task::block_on(raft.receive_read(reply)).unwrap();
+            read
+        };
+        let response = |node_id: &NodeId, round| LogResponse {
+            node_id: node_id.clone(),
+            current_term: 1,
+            ack: 1,
+            ok: true,
+            round,
+            instance: 0,
+        };
+
+        // Only a leader that committed an entry of its term answers reads
+        assert!(matches!(read(&mut raft).try_recv(), Ok(Some(Err(Error::RaftError(_))))));
+        raft.role = Role::Leader;
+        raft.current_term = 2;
+        assert!(matches!(read(&mut raft).try_recv(), Ok(Some(Err(Error::RaftError(_))))));
+        raft.current_term = 1;
+
+        // Without a lease, the read waits for a quorum to ack a new round
+        let mut first = read(&mut raft);
+        assert!(matches!(first.try_recv(), Ok(None)));
+        let request: LogRequest = deserialize(&raft.sender.1.try_recv().unwrap().payload)?;
+        assert_eq!(request.round, 1);
+        while raft.sender.1.try_recv().is_ok() {}
+        task::block_on(raft.receive_log_response(response(&followers[0], 1)))?;
+        assert!(matches!(first.try_recv(), Ok(Some(Ok(1)))));
+
+        // Within the lease, reads are answered without contacting anyone
+        assert!(matches!(read(&mut raft).try_recv(), Ok(Some(Ok(1)))));
+        assert!(raft.sender.1.is_empty());
+
+        // Once it expired, acks of the rounds sent before the read don't count
+        let expired = Instant::now().checked_sub(Duration::from_secs(1)).unwrap();
+        raft.round_acks.insert(followers[0].clone(), (1, expired));
+        let mut second = read(&mut raft);
+        assert_eq!(raft.sender.1.len(), 2);
+        task::block_on(raft.receive_log_response(response(&followers[1], 1)))?;
+        assert!(matches!(second.try_recv(), Ok(None)));
+        task::block_on(raft.receive_log_response(response(&followers[1], 2)))?;
+        assert!(matches!(second.try_recv(), Ok(Some(Ok(1)))));
+
+        // Reads waiting when the leadership is lost fail
+        raft.round_acks.clear();
+        let mut third = read(&mut raft);
+        raft.set_current_term(&2)?;
+        assert!(matches!(third.try_recv(), Ok(Some(Err(Error::RaftError(_))))));
+        assert!(raft.reads.is_empty());
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn no_vote_while_following_leader() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_no_vote_while_following", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let leader = NodeId::from(Url::parse("tcp://127.0.0.1:11003").unwrap());
+        let candidate = NodeId::from(Url::parse("tcp://127.0.0.1:11004").unwrap());
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+
+        let request = LogRequest {
+            leader_id: leader,
+            current_term: 1,
+            prefix_len: 0,
+            prefix_term: 0,
+            commit_length: 0,
+            suffix: Logs(vec![]),
+            round: 7,
+        };
+        task::block_on(raft.receive_log_request(request))?;
+        let response: LogResponse = deserialize(&raft.sender.1.try_recv().unwrap().payload)?;
+        assert_eq!(response.round, 7);
+
+        // The leader's lease may still hold
+        task::block_on(raft.receive_vote_request(vote_request(&candidate, 2)))?;
+        assert!(raft.sender.1.is_empty());
+        assert_eq!(raft.current_term, 1);
+
+        // Unless leases are disabled
+        raft.lease_duration = Duration::ZERO;
+        assert!(vote_granted(&mut raft, vote_request(&candidate, 2))?);
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn drop_when_channel_full() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_drop_when_channel_full", &[], 0)?;
+
+        let settings = RaftSettings {
+            channel_capacity: 1,
+            channel_full_policy: ChannelFullPolicy::Drop,
+            ..RaftSettings::default()
+        };
+        let raft = new_raft(None, path.clone(), settings)?;
+
+        task::block_on(raft.send(None, &[1], NetMsgMethod::LogRequest, Some(1)))?;
+        task::block_on(raft.send(None, &[2], NetMsgMethod::LogRequest, Some(2)))?;
+        assert_eq!(raft.sender.1.len(), 1);
+        assert_eq!(raft.sender.1.try_recv().unwrap().id, 1);
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn redact_cluster_secret() {
+        let settings = RaftSettings {
+            cluster_secret: Some(Zeroizing::new(b"secret".to_vec())),
+            ..Default::default()
+        };
+        let debug = format!("{:?}", settings);
+        assert!(debug.contains("cluster_secret: Some(\"<redacted>\")"));
+        assert!(!debug.contains("115, 101, 99"));
+    }
+
+    #[test]
+    fn drop_unauthenticated_msg() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_unauthenticated_msg", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let candidate = NodeId::from(Url::parse("tcp://127.0.0.1:11003").unwrap());
+        let settings = RaftSettings {
+            cluster_secret: Some(Zeroizing::new(b"secret".to_vec())),
+            ..Default::default()
+        };
+        let mut raft = new_raft(Some(addr), path.clone(), settings)?;
+
+        let vr = VoteRequest { node_id: candidate, current_term: 5, log_length: 0, last_term: 0 };
+        let mut msg = NetMsg {
+            id: 1,
+            recipient_id: None,
+            method: NetMsgMethod::VoteRequest,
+            payload: serialize(&vr),
+            mac: None,
+        };
+
+        // Missing MAC
+        task::block_on(raft.handle_method(msg.clone()))?;
+        assert_eq!(raft.current_term, 0);
+
+        // MAC keyed by another secret
+        msg.sign(&blake3::derive_key(MAC_KEY_CONTEXT, b"other"));
+        task::block_on(raft.handle_method(msg.clone()))?;
+        assert_eq!(raft.current_term, 0);
+
+        // Tampered payload
+        msg.sign(raft.mac_key.as_ref().unwrap());
+        let mut tampered = msg.clone();
+        tampered.payload = serialize(&VoteRequest { current_term: 6, ..vr });
+        task::block_on(raft.handle_method(tampered))?;
+        assert_eq!(raft.current_term, 0);
+        assert!(raft.sender.1.is_empty());
+
+        task::block_on(raft.handle_method(msg))?;
+        assert_eq!(raft.current_term, 5);
+
+        // The vote response is signed as well
+        let response = raft.sender.1.try_recv().unwrap();
+        assert!(raft.is_authentic(&response));
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn drop_malformed_payloads() -> Result<()> {
+        let path = create_datastore("/tmp/test_raft_malformed_payloads", &[], 0)?;
+
+        let addr = Url::parse("tcp://127.0.0.1:11002").unwrap();
+        let mut raft = new_raft(Some(addr), path.clone(), RaftSettings::default())?;
+        raft.role = Role::Leader;
+
+        let methods = [
+            NetMsgMethod::LogResponse,
+            NetMsgMethod::LogRequest,
+            NetMsgMethod::VoteResponse,
+            NetMsgMethod::VoteRequest,
+            NetMsgMethod::BroadcastRequest,
+            NetMsgMethod::SyncRequest,
+            NetMsgMethod::SyncResponse,
+        ];
+        // A well formed broadcast request wrapping a malformed T
+        let bad_broadcast = serialize(&BroadcastMsgRequest(vec![0xff; 3]));
+        let payloads = [vec![], vec![0xff; 3], vec![0xff; 64], bad_broadcast];
+
+        for method in methods {
+            for payload in payloads.iter() {
+                let msg = NetMsg {
+                    id: 1,
+                    recipient_id: None,
+                    method: method.clone(),
+                    payload: payload.clone(),
+                    mac: None,
+                };
+                task::block_on(raft.handle_method(msg))?;
+            }
+        }
+        assert!(raft.logs.is_empty());
+        assert!(raft.sender.1.is_empty());
+
+        // Still handling valid messages
+        raft.role = Role::Follower;
+        let candidate = NodeId::from(Url::parse("tcp://127.0.0.1:11003").unwrap());
+        let vr = VoteRequest { node_id: candidate, current_term: 5, log_length: 0, last_term: 0 };
+        let msg = NetMsg {
+            id: 2,
+            recipient_id: None,
+            method: NetMsgMethod::VoteRequest,
+            payload: serialize(&vr),
+            mac: None,
+        };
+        task::block_on(raft.handle_method(msg))?;
+        assert_eq!(raft.current_term, 5);
+        assert!(raft.sender.1.try_recv().is_ok());
+
+        drop(raft);
+        remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unknown_method() {
+        let msg = NetMsg {
+            id: 1,
+            recipient_id: None,
+            method: NetMsgMethod::SyncResponse,
+            payload: vec![],
+            mac: None,
+        };
+        let mut bytes = serialize(&msg);
+        assert!(deserialize::<NetMsg>(&bytes).is_ok());
+
+        // The method byte follows the id and the absent recipient
+        bytes[9] = 7;
+        assert!(deserialize::<NetMsg>(&bytes).is_err());
+    }
+}
@@ -1,20 +1,53 @@
-use std::marker::PhantomData;
+use std::{
+    collections::{HashMap, VecDeque},
+    marker::PhantomData,
+};
 
 use log::debug;
-use sled::Batch;
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Batch, Transactional,
+};
+use url::Url;
 
 use crate::{
     util::serial::{deserialize, serialize, Decodable, Encodable},
-    Result,
+    Error, Result,
 };
 
-use super::primitives::{Log, NodeId};
+use super::{
+    primitives::{Log, NodeId},
+    SyncPolicy,
+};
 
 const SLED_LOGS_TREE: &[u8] = b"_logs";
 const SLED_COMMITS_TREE: &[u8] = b"_commits";
-const _SLED_COMMITS_LENGTH_TREE: &[u8] = b"_commit_length";
 const SLED_VOTED_FOR_TREE: &[u8] = b"_voted_for";
 const SLED_CURRENT_TERM_TREE: &[u8] = b"_current_term";
+const SLED_NODES_TREE: &[u8] = b"_nodes";
+
+/// Key set in the default tree once every entry carries a checksum.
+/// Datastores written before checksums existed get them added on open,
+/// with a key per tree made of this one and the tree name marking each
+/// tree done.
+const SLED_CHECKSUMS_KEY: &[u8] = b"_checksums";
+
+/// Length of the checksum appended to every entry: the first bytes of
+/// the blake3 hash of the serialized entry.
+const CHECKSUM_LEN: usize = 4;
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let mut ret = [0; CHECKSUM_LEN];
+    ret.copy_from_slice(&blake3::hash(data).as_bytes()[..CHECKSUM_LEN]);
+    ret
+}
+
+/// Append its checksum to a serialized entry.
+fn with_checksum(mut data: Vec<u8>) -> Vec<u8> {
+    let sum = checksum(&data);
+    data.extend_from_slice(&sum);
+    data
+}
 
 pub struct DataStore<T> {
     _db: sled::Db,
@@ -22,17 +55,54 @@ pub struct DataStore<T> {
     pub commits: DataTree<T>,
     pub voted_for: DataTree<Option<NodeId>>,
     pub current_term: DataTree<u64>,
+    /// Last known cluster membership, restored on startup
+    pub nodes: DataTree<(NodeId, Url)>,
 }
 
 impl<T: Encodable + Decodable> DataStore<T> {
-    pub fn new(db_path: &str) -> Result<Self> {
-        let _db = sled::open(db_path)?;
-        let logs = DataTree::new(&_db, SLED_LOGS_TREE)?;
-        let commits = DataTree::new(&_db, SLED_COMMITS_TREE)?;
-        let voted_for = DataTree::new(&_db, SLED_VOTED_FOR_TREE)?;
-        let current_term = DataTree::new(&_db, SLED_CURRENT_TERM_TREE)?;
+    pub fn new(db_path: &str, sync_policy: SyncPolicy) -> Result<Self> {
+        let flush_every_ms = match sync_policy {
+            SyncPolicy::Batched(ms) => Some(ms),
+            // Always flushes on every write instead, Buffered never does
+            SyncPolicy::Always | SyncPolicy::Buffered => None,
+        };
+        let _db = sled::Config::new().path(db_path).flush_every_ms(flush_every_ms).open()?;
+
+        let sync = sync_policy == SyncPolicy::Always;
+        let logs = DataTree::new(&_db, SLED_LOGS_TREE, sync)?;
+        let commits = DataTree::new(&_db, SLED_COMMITS_TREE, sync)?;
+        let voted_for = DataTree::new(&_db, SLED_VOTED_FOR_TREE, sync)?;
+        let current_term = DataTree::new(&_db, SLED_CURRENT_TERM_TREE, sync)?;
+        let nodes = DataTree::new(&_db, SLED_NODES_TREE, sync)?;
+
+        if _db.get(SLED_CHECKSUMS_KEY)?.is_none() {
+            logs.add_checksums()?;
+            commits.add_checksums()?;
+            voted_for.add_checksums()?;
+            current_term.add_checksums()?;
+            nodes.add_checksums()?;
+            _db.insert(SLED_CHECKSUMS_KEY, &[1])?;
+            _db.flush()?;
+        }
+
+        // The order of the entries of a term was lost with the hash keys,
+        // they keep the hash order older versions read them in.
+        logs.rekey_hashed(|logs: &mut Vec<Log>| {
+            logs.sort_by_key(|log| log.term);
+            Ok(())
+        })?;
+        commits.rekey_hashed(|commits| {
+            let msgs: Vec<Vec<u8>> = logs.get_all()?.into_iter().map(|log| log.msg).collect();
+            sort_as_logs(commits, &msgs);
+            Ok(())
+        })?;
+        current_term.rekey_hashed(|terms: &mut Vec<u64>| {
+            terms.sort();
+            Ok(())
+        })?;
+        voted_for.rekey_hashed(|_| Ok(()))?;
 
-        Ok(Self { _db, logs, commits, voted_for, current_term })
+        Ok(Self { _db, logs, commits, voted_for, current_term, nodes })
     }
     pub async fn flush(&self) -> Result<()> {
         debug!(target: "raft", "DataStore flush");
@@ -41,56 +111,300 @@ impl<T: Encodable + Decodable> DataStore<T> {
     }
 }
 
+/// Order commits like the log entries they were applied from, given the
+/// serialized commits of the log in order. Commits missing from the log
+/// go last.
+fn sort_as_logs<T: Encodable>(commits: &mut Vec<T>, log_msgs: &[Vec<u8>]) {
+    let mut positions: HashMap<&[u8], VecDeque<usize>> = HashMap::new();
+    for (i, msg) in log_msgs.iter().enumerate() {
+        positions.entry(msg).or_default().push_back(i);
+    }
+
+    let mut keyed: Vec<(usize, T)> = commits
+        .drain(..)
+        .map(|commit| {
+            let position = positions
+                .get_mut(serialize(&commit).as_slice())
+                .and_then(|positions| positions.pop_front());
+            (position.unwrap_or(usize::MAX), commit)
+        })
+        .collect();
+    keyed.sort_by_key(|(position, _)| *position);
+    commits.extend(keyed.into_iter().map(|(_, commit)| commit));
+}
+
+/// Entries are keyed by a monotonically increasing id generated by sled,
+/// so iterating over the tree returns them in insertion order.
+/// If `sync` is set, every write is flushed to disk before returning.
+///
+/// Every entry is stored with a checksum, verified when it's read back so
+/// that an entry corrupted on disk is reported instead of being used.
 pub struct DataTree<T> {
+    db: sled::Db,
     tree: sled::Tree,
+    sync: bool,
     phantom: PhantomData<T>,
 }
 
+impl<T> Clone for DataTree<T> {
+    fn clone(&self) -> Self {
+        Self { db: self.db.clone(), tree: self.tree.clone(), sync: self.sync, phantom: PhantomData }
+    }
+}
+
 impl<T: Decodable + Encodable> DataTree<T> {
-    pub fn new(db: &sled::Db, tree_name: &[u8]) -> Result<Self> {
+    pub fn new(db: &sled::Db, tree_name: &[u8], sync: bool) -> Result<Self> {
         let tree = db.open_tree(tree_name)?;
-        Ok(Self { tree, phantom: PhantomData })
+        Ok(Self { db: db.clone(), tree, sync, phantom: PhantomData })
     }
 
-    pub fn insert(&self, data: &T) -> Result<()> {
-        let serialized = serialize(data);
-        let datahash = blake3::hash(&serialized);
-        self.tree.insert(datahash.as_bytes(), serialized)?;
+    fn sync(&self) -> Result<()> {
+        if self.sync {
+            self.tree.flush()?;
+        }
         Ok(())
     }
 
+    fn next_key(&self) -> Result<[u8; 8]> {
+        Ok(self.db.generate_id()?.to_be_bytes())
+    }
+
+    pub fn insert(&self, data: &T) -> Result<()> {
+        let serialized = with_checksum(serialize(data));
+        self.tree.insert(self.next_key()?, serialized)?;
+        self.sync()
+    }
+
+    pub fn wipe(&self) -> Result<()> {
+        self.tree.clear()?;
+        self.sync()
+    }
+
     pub fn wipe_insert_all(&self, data: &Vec<T>) -> Result<()> {
         self.tree.clear()?;
 
         let mut batch = Batch::default();
 
         for i in data {
-            let serialized = serialize(i);
-            let hash = blake3::hash(&serialized);
-            batch.insert(hash.as_bytes(), serialized);
+            let serialized = with_checksum(serialize(i));
+            batch.insert(&self.next_key()?, serialized);
         }
 
         self.tree.apply_batch(batch)?;
+        self.sync()
+    }
 
-        Ok(())
+    /// Append a checksum to every entry, for trees written before the
+    /// entries had one. The entries are written in one transaction along
+    /// with the marker of the tree, so a crash leaves the tree either as
+    /// it was or done, and it is never given a second checksum.
+    fn add_checksums(&self) -> Result<()> {
+        let marker = [SLED_CHECKSUMS_KEY, &self.tree.name()].concat();
+        if self.db.get(&marker)?.is_some() {
+            return Ok(())
+        }
+
+        let mut batch = Batch::default();
+
+        for i in self.tree.iter() {
+            let (key, value) = i?;
+            batch.insert(key, with_checksum(value.to_vec()));
+        }
+
+        let default_tree: &sled::Tree = &self.db;
+        (&self.tree, default_tree)
+            .transaction(|(tree, default_tree)| {
+                tree.apply_batch(&batch)?;
+                default_tree.insert(marker.as_slice(), &[1])?;
+                Ok::<_, ConflictableTransactionError<sled::Error>>(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) | TransactionError::Storage(e) => Error::from(e),
+            })?;
+        self.sync()
+    }
+
+    /// Re-key a tree written when entries were keyed by the blake3 hash of
+    /// their content, which sorts them in hash order and among the entries
+    /// added since. `sort` puts the entries back in insertion order as far
+    /// as it can be told. The tree is replaced in a single batch, so a crash
+    /// leaves it either as it was or re-keyed.
+    fn rekey_hashed(&self, sort: impl FnOnce(&mut Vec<T>) -> Result<()>) -> Result<()> {
+        let mut keys = vec![];
+        for key in self.tree.iter().keys() {
+            keys.push(key?);
+        }
+        if keys.iter().all(|key| key.len() == 8) {
+            return Ok(())
+        }
+
+        debug!(
+            target: "raft",
+            "Re-keying the hash-keyed entries of {}",
+            String::from_utf8_lossy(&self.tree.name())
+        );
+        let mut entries = self.get_all()?;
+        sort(&mut entries)?;
+
+        let mut batch = Batch::default();
+        for key in keys {
+            batch.remove(key);
+        }
+        for entry in entries.iter() {
+            batch.insert(&self.next_key()?, with_checksum(serialize(entry)));
+        }
+
+        self.tree.apply_batch(batch)?;
+        self.sync()
+    }
+
+    /// Verify the checksum of an entry and deserialize it.
+    fn decode(&self, key: &[u8], value: &[u8]) -> Result<T> {
+        let corrupted = || {
+            Error::RaftError(format!(
+                "datastore is corrupted: entry {} of {} fails its checksum",
+                hex::encode(key),
+                String::from_utf8_lossy(&self.tree.name())
+            ))
+        };
+
+        if value.len() < CHECKSUM_LEN {
+            return Err(corrupted())
+        }
+        let (data, sum) = value.split_at(value.len() - CHECKSUM_LEN);
+        if checksum(data) != sum {
+            return Err(corrupted())
+        }
+
+        deserialize(data)
     }
 
     pub fn get_all(&self) -> Result<Vec<T>> {
         let mut ret: Vec<T> = Vec::new();
 
         for i in self.tree.iter() {
-            let da = deserialize(&i?.1)?;
-            ret.push(da)
+            let (key, value) = i?;
+            ret.push(self.decode(&key, &value)?)
+        }
+
+        Ok(ret)
+    }
+
+    /// Iterate over the entries in insertion order, decoding them one at a
+    /// time.
+    pub fn iter(&self) -> impl Iterator<Item = Result<T>> + '_ {
+        self.tree.iter().map(move |i| {
+            let (key, value) = i?;
+            self.decode(&key, &value)
+        })
+    }
+
+    /// Get the entries starting from the given index, in insertion order.
+    pub fn get_from(&self, index: u64) -> Result<Vec<T>> {
+        let mut ret: Vec<T> = Vec::new();
+
+        for i in self.tree.iter().skip(index as usize) {
+            let (key, value) = i?;
+            ret.push(self.decode(&key, &value)?)
         }
 
         Ok(ret)
     }
 
     pub fn get_last(&self) -> Result<Option<T>> {
-        if let Some(found) = self.tree.last()? {
-            let da = deserialize(&found.1)?;
-            return Ok(Some(da))
+        if let Some((key, value)) = self.tree.last()? {
+            return Ok(Some(self.decode(&key, &value)?))
         }
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_db() -> Result<sled::Db> {
+        Ok(sled::Config::new().temporary(true).open()?)
+    }
+
+    #[test]
+    fn detect_corrupted_entry() -> Result<()> {
+        let db = temporary_db()?;
+        let logs = DataTree::<Log>::new(&db, SLED_LOGS_TREE, false)?;
+        logs.insert(&Log { term: 1, msg: vec![1, 2, 3] })?;
+        logs.insert(&Log { term: 2, msg: vec![4, 5, 6] })?;
+        assert_eq!(logs.get_all()?.len(), 2);
+
+        // Flip a bit of the term of the last entry
+        let (key, value) = logs.tree.last()?.unwrap();
+        let mut value = value.to_vec();
+        value[0] ^= 1;
+        logs.tree.insert(key, value)?;
+
+        assert!(matches!(logs.get_all(), Err(Error::RaftError(_))));
+        assert!(logs.get_last().is_err());
+        assert!(logs.get_from(1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rekey_hash_keyed_entries() -> Result<()> {
+        let db = temporary_db()?;
+        let logs = DataTree::<Log>::new(&db, SLED_LOGS_TREE, false)?;
+        let commits = DataTree::<u64>::new(&db, SLED_COMMITS_TREE, false)?;
+
+        // Entries keyed by their hash, as older versions wrote them
+        let entries: Vec<u64> = (10..30).collect();
+        for (i, entry) in entries.iter().enumerate() {
+            let log = Log { term: 1 + i as u64 / 4, msg: serialize(entry) };
+            let serialized = serialize(&log);
+            logs.tree.insert(blake3::hash(&serialized).as_bytes(), with_checksum(serialized))?;
+            let serialized = serialize(entry);
+            commits.tree.insert(blake3::hash(&serialized).as_bytes(), with_checksum(serialized))?;
+        }
+
+        logs.rekey_hashed(|logs| {
+            logs.sort_by_key(|log| log.term);
+            Ok(())
+        })?;
+        let msgs: Vec<Vec<u8>> = logs.get_all()?.into_iter().map(|log| log.msg).collect();
+        commits.rekey_hashed(|commits| {
+            sort_as_logs(commits, &msgs);
+            Ok(())
+        })?;
+
+        let terms: Vec<u64> = logs.get_all()?.iter().map(|log| log.term).collect();
+        assert!(terms.windows(2).all(|w| w[0] <= w[1]));
+        let restored = commits.get_all()?;
+        assert_eq!(restored.len(), entries.len());
+        for (commit, msg) in restored.iter().zip(msgs.iter()) {
+            assert_eq!(&serialize(commit), msg);
+        }
+
+        // New entries go after the re-keyed ones
+        commits.insert(&99)?;
+        assert_eq!(commits.get_last()?, Some(99));
+        Ok(())
+    }
+
+    #[test]
+    fn add_checksums_to_old_entries() -> Result<()> {
+        let db = temporary_db()?;
+        let logs = DataTree::<Log>::new(&db, SLED_LOGS_TREE, false)?;
+        let log = Log { term: 3, msg: vec![7, 8] };
+        // An entry written without a checksum
+        logs.tree.insert(logs.next_key()?, serialize(&log))?;
+        assert!(logs.get_all().is_err());
+
+        logs.add_checksums()?;
+        let restored = logs.get_all()?;
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].term, log.term);
+        assert_eq!(restored[0].msg, log.msg);
+
+        // Done once, even if the datastore wasn't marked as a whole
+        logs.add_checksums()?;
+        assert_eq!(logs.get_all()?.len(), 1);
+        Ok(())
+    }
+}
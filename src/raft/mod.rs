@@ -2,8 +2,17 @@ mod consensus;
 mod datastore;
 mod primitives;
 mod protocol_raft;
+mod settings;
 
 pub use consensus::Raft;
 pub use datastore::DataStore;
-pub use primitives::NetMsg;
+pub use primitives::{
+    ApplyStatus, BroadcastMsgRequest, LogRequest, LogResponse, NetMsg, NetMsgMethod, NodeId,
+    Proposer, Reader, Role, RoleChange, SyncRequest, SyncResponse, VoteRequest, VoteResponse,
+};
 pub use protocol_raft::ProtocolRaft;
+pub use settings::{
+    ChannelFullPolicy, RaftSettings, SyncPolicy, DEFAULT_APPLY_LAG_WARNING,
+    DEFAULT_CHANNEL_CAPACITY, DEFAULT_LEASE_DURATION, DEFAULT_LOG_BATCH_SIZE,
+    MAX_ELECTION_PRIORITY,
+};
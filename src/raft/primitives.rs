@@ -1,5 +1,13 @@
-use std::{collections::HashMap, io};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
+use futures::channel::oneshot;
 use url::Url;
 
 use crate::{
@@ -13,12 +21,107 @@ pub type Sender = (async_channel::Sender<NetMsg>, async_channel::Receiver<NetMsg
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Role {
+    /// Only syncs the committed logs, never part of the cluster
     Listener,
+    /// Replicates the logs without voting until it has caught up with
+    /// the leader's commits, then becomes a follower
+    Learner,
     Follower,
     Candidate,
     Leader,
 }
 
+/// Sent to the receivers of `Raft::get_role_changes()` each time the
+/// node takes a new role.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RoleChange {
+    pub role: Role,
+    pub term: u64,
+    /// Leader known when taking the role, if any
+    pub leader: Option<NodeId>,
+}
+
+/// How far the application is behind the committed entries, returned by
+/// `Raft::apply_status()` and kept up to date while raft runs.
+#[derive(Clone, Debug)]
+pub struct ApplyStatus<T> {
+    pub(super) commit_length: Arc<AtomicU64>,
+    pub(super) commits: async_channel::Sender<T>,
+}
+
+impl<T> ApplyStatus<T> {
+    /// Number of log entries committed.
+    pub fn commit_length(&self) -> u64 {
+        self.commit_length.load(Ordering::Relaxed)
+    }
+
+    /// Number of committed entries waiting in the commits channel, not
+    /// received by the application yet.
+    pub fn lag(&self) -> u64 {
+        self.commits.len() as u64
+    }
+}
+
+/// Sends entries to propose to raft while it runs, returned by
+/// `Raft::get_proposer()`.
+#[derive(Clone, Debug)]
+pub struct Proposer<T> {
+    pub(super) proposals: async_channel::Sender<(T, oneshot::Sender<Result<u64>>)>,
+}
+
+impl<T> Proposer<T> {
+    /// Propose `value` as a new log entry, waiting for room in the queue
+    /// when it's full. The receiver resolves to the index of the entry
+    /// once committed, see `Raft::propose()`.
+    pub async fn propose(&self, value: T) -> Result<oneshot::Receiver<Result<u64>>> {
+        let (reply, committed) = oneshot::channel();
+        self.proposals.send((value, reply)).await?;
+        Ok(committed)
+    }
+}
+
+/// Asks raft for reads of the committed state while it runs, returned by
+/// `Raft::get_reader()`.
+#[derive(Clone, Debug)]
+pub struct Reader {
+    pub(super) reads: async_channel::Sender<oneshot::Sender<Result<u64>>>,
+}
+
+impl Reader {
+    /// Get the commit length a read of the committed state has to see,
+    /// see `Raft::leased_read()`.
+    pub fn leased_read(&self) -> oneshot::Receiver<Result<u64>> {
+        let (reply, read) = oneshot::channel();
+        if let Err(e) = self.reads.try_send(reply) {
+            let reply = e.into_inner();
+            let _ = reply.send(Err(Error::RaftError("unable to queue the read".into())));
+        }
+        read
+    }
+}
+
+/// A read waiting for a quorum to confirm the leadership.
+pub(super) struct PendingRead {
+    /// Heartbeat round a quorum has to ack
+    pub round: u64,
+    /// Term the read was made in
+    pub term: u64,
+    /// Commit length when the read was made
+    pub read_index: u64,
+    pub reply: oneshot::Sender<Result<u64>>,
+}
+
+/// A proposed entry waiting to be committed.
+pub(super) struct Proposal {
+    /// The serialized entry, to find it once appended to the logs
+    pub msg: Vec<u8>,
+    /// Term the entry was proposed in
+    pub term: u64,
+    /// Index of the entry in the logs, once appended
+    pub index: Option<u64>,
+    pub reply: oneshot::Sender<Result<u64>>,
+}
+
 #[derive(SerialDecodable, SerialEncodable, Clone, Debug)]
 pub struct SyncRequest {
     pub logs_len: u64,
@@ -46,6 +149,9 @@ pub struct VoteResponse {
     pub node_id: NodeId,
     pub current_term: u64,
     pub ok: bool,
+    /// Random number drawn by the node when it starts, telling apart two
+    /// nodes with the same id
+    pub instance: u64,
 }
 
 #[derive(SerialDecodable, SerialEncodable, Clone, Debug)]
@@ -56,6 +162,8 @@ pub struct LogRequest {
     pub prefix_term: u64,
     pub commit_length: u64,
     pub suffix: Logs,
+    /// Heartbeat round of the leader, echoed in the response
+    pub round: u64,
 }
 
 #[derive(SerialDecodable, SerialEncodable, Clone, Debug)]
@@ -64,6 +172,11 @@ pub struct LogResponse {
     pub current_term: u64,
     pub ack: u64,
     pub ok: bool,
+    /// Round of the request, or 0 if it wasn't from the leader of the
+    /// current term
+    pub round: u64,
+    /// See [`VoteResponse::instance`]
+    pub instance: u64,
 }
 
 impl VoteResponse {
@@ -113,6 +226,15 @@ impl Logs {
         None
     }
 
+    /// At most `max_len` entries starting from `start`.
+    pub fn slice(&self, start: u64, max_len: u64) -> Option<Self> {
+        if self.len() >= start {
+            let end = self.len().min(start.saturating_add(max_len));
+            return Some(Self(self.0[start as usize..end as usize].to_vec()))
+        }
+        None
+    }
+
     pub fn slice_to(&self, end: u64) -> Self {
         for i in (0..end).rev() {
             if self.len() >= i {
@@ -156,6 +278,37 @@ pub struct NetMsg {
     pub recipient_id: Option<NodeId>,
     pub method: NetMsgMethod,
     pub payload: Vec<u8>,
+    /// Keyed blake3 hash of the fields above, see [`NetMsg::sign`]
+    pub mac: Option<[u8; 32]>,
+}
+
+impl NetMsg {
+    fn authenticated_data(&self) -> Vec<u8> {
+        [
+            serialize(&self.id),
+            serialize(&self.recipient_id),
+            serialize(&self.method),
+            serialize(&self.payload),
+        ]
+        .concat()
+    }
+
+    /// Set the MAC of the msg using the cluster key.
+    pub fn sign(&mut self, key: &[u8; 32]) {
+        let mac = blake3::keyed_hash(key, &self.authenticated_data());
+        self.mac = Some(*mac.as_bytes());
+    }
+
+    /// Check the MAC of the msg against the cluster key.
+    pub fn verify(&self, key: &[u8; 32]) -> bool {
+        match self.mac {
+            // blake3::Hash equality is constant-time
+            Some(mac) => {
+                blake3::Hash::from(mac) == blake3::keyed_hash(key, &self.authenticated_data())
+            }
+            None => false,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -196,7 +349,8 @@ impl Decodable for NetMsgMethod {
             3 => Self::VoteRequest,
             4 => Self::BroadcastRequest,
             5 => Self::SyncRequest,
-            _ => Self::SyncResponse,
+            6 => Self::SyncResponse,
+            _ => return Err(Error::ParseFailed("couldn't decode NetMsgMethod")),
         })
     }
 }
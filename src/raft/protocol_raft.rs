@@ -2,8 +2,9 @@ use async_std::sync::{Arc, Mutex};
 
 use async_executor::Executor;
 use async_trait::async_trait;
-use log::debug;
+use log::{debug, warn};
 use url::Url;
+use zeroize::Zeroizing;
 
 use crate::{net, Result};
 
@@ -17,6 +18,7 @@ pub struct ProtocolRaft {
     p2p: net::P2pPtr,
     msgs: Arc<Mutex<Vec<u64>>>,
     channel_address: Url,
+    mac_key: Option<Zeroizing<[u8; 32]>>,
 }
 
 impl ProtocolRaft {
@@ -26,6 +28,7 @@ impl ProtocolRaft {
         notify_queue_sender: async_channel::Sender<NetMsg>,
         p2p: net::P2pPtr,
         msgs: Arc<Mutex<Vec<u64>>>,
+        mac_key: Option<Zeroizing<[u8; 32]>>,
     ) -> net::ProtocolBasePtr {
         let message_subsytem = channel.get_message_subsystem();
         message_subsytem.add_dispatch::<NetMsg>().await;
@@ -41,6 +44,7 @@ impl ProtocolRaft {
             p2p,
             msgs,
             channel_address,
+            mac_key,
         })
     }
 
@@ -60,6 +64,16 @@ impl ProtocolRaft {
                 continue
             }
 
+            // Unauthenticated messages aren't relayed, so the peer sending
+            // one is the one at fault
+            if let Some(key) = &self.mac_key {
+                if !msg.verify(key) {
+                    warn!(target: "raft", "drop unauthenticated msg from [{}]", self.channel_address);
+                    self.p2p.penalize(&self.channel_address, net::Violation::FailedAuth).await;
+                    continue
+                }
+            }
+
             self.msgs.lock().await.push(msg.id);
 
             let msg = (*msg).clone();
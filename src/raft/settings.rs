@@ -0,0 +1,138 @@
+use std::fmt;
+
+use zeroize::Zeroizing;
+
+/// Durability policy for writes to the raft datastore.
+///
+/// Raft relies on `current_term`, `voted_for` and the logs surviving a
+/// restart, so the policy trades write throughput against how much of
+/// the recent state can be lost if the node crashes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Flush the datastore to disk after every write.
+    /// Nothing that has been written is lost on a crash, but every
+    /// `set_*`/`push_log` call pays for an fsync.
+    Always,
+    /// Flush the datastore in the background every given number of
+    /// milliseconds. A crash can lose the writes made during the last
+    /// interval, which may make the node vote twice in the same term.
+    Batched(u64),
+    /// Never flush: sled keeps the writes in memory and only writes them
+    /// out on a clean shutdown. Fastest, but a crash loses everything
+    /// written since the last clean shutdown.
+    Buffered,
+}
+
+impl Default for SyncPolicy {
+    /// Matches sled's default flush interval.
+    fn default() -> Self {
+        Self::Batched(500)
+    }
+}
+
+/// What raft does when one of the channels it writes to is full.
+///
+/// Applies to the commits channel read by the application and to the
+/// channel of outgoing network messages. The broadcast and proposal
+/// channels written by the application always block the application when
+/// full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChannelFullPolicy {
+    /// Wait for the consumer to catch up. Nothing is lost, but a stalled
+    /// consumer stalls raft too, including its heartbeats and elections.
+    #[default]
+    Block,
+    /// Drop the message with a warning and carry on. Dropped network
+    /// messages are resent with the next heartbeat, and dropped commits
+    /// can be read back with `Raft::committed_entries()`.
+    Drop,
+}
+
+/// Highest election priority, and the default one.
+pub const MAX_ELECTION_PRIORITY: u8 = 10;
+
+/// Default capacity of the raft channels.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Default number of commits the application can fall behind before
+/// raft warns about it.
+pub const DEFAULT_APPLY_LAG_WARNING: u64 = 128;
+
+/// Default maximum number of log entries sent in a single log request.
+pub const DEFAULT_LOG_BATCH_SIZE: usize = 256;
+
+/// Default leader lease in milliseconds, a third below the election
+/// timeout.
+pub const DEFAULT_LEASE_DURATION: u64 = 600;
+
+#[derive(Clone)]
+pub struct RaftSettings {
+    /// Durability policy for the datastore
+    pub sync_policy: SyncPolicy,
+    /// Secret shared by the nodes of the cluster, used to authenticate
+    /// the messages between them. Messages without a valid MAC are
+    /// dropped. `None` disables authentication.
+    pub cluster_secret: Option<Zeroizing<Vec<u8>>>,
+    /// Join the cluster as a learner, replicating the logs without voting
+    /// or starting elections until caught up with the leader's commits.
+    /// Use it when adding a node to a cluster with existing logs.
+    pub join_as_learner: bool,
+    /// Capacity of the broadcast, commits and network channels (at least 1)
+    pub channel_capacity: usize,
+    /// What to do when a channel written by raft is full
+    pub channel_full_policy: ChannelFullPolicy,
+    /// From 0 to `MAX_ELECTION_PRIORITY`. Each step below the maximum
+    /// delays the elections started by this node, so the nodes with a
+    /// higher priority tend to become the leader. Nodes with the same
+    /// priority compete evenly.
+    pub election_priority: u8,
+    /// Warn when more committed entries than this are waiting for the
+    /// application in the commits channel, see `Raft::apply_status()`
+    pub apply_lag_warning: u64,
+    /// Maximum number of log entries sent to a node in a single log request
+    /// (at least 1). A node further behind catches up over several
+    /// requests, the next one sent as soon as the node acks the previous.
+    pub log_batch_size: usize,
+    /// Milliseconds the leader answers `Raft::leased_read()` locally after
+    /// a quorum acked one of its heartbeats, counted from when the
+    /// heartbeat was sent. Followers don't vote for another node until an
+    /// election timeout (900ms) has passed since they last heard from the
+    /// leader, so the lease is safe as long as it expires on the leader
+    /// before that. Clocks don't need to be synchronized, but they must
+    /// run at about the same rate: the gap between the lease and the
+    /// election timeout is the drift tolerated. Capped at 700ms, and 0
+    /// disables the lease, every read then waits for a heartbeat round.
+    pub lease_duration: u64,
+}
+
+impl fmt::Debug for RaftSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RaftSettings")
+            .field("sync_policy", &self.sync_policy)
+            .field("cluster_secret", &self.cluster_secret.as_ref().map(|_| "<redacted>"))
+            .field("join_as_learner", &self.join_as_learner)
+            .field("channel_capacity", &self.channel_capacity)
+            .field("channel_full_policy", &self.channel_full_policy)
+            .field("election_priority", &self.election_priority)
+            .field("apply_lag_warning", &self.apply_lag_warning)
+            .field("log_batch_size", &self.log_batch_size)
+            .field("lease_duration", &self.lease_duration)
+            .finish()
+    }
+}
+
+impl Default for RaftSettings {
+    fn default() -> Self {
+        Self {
+            sync_policy: SyncPolicy::default(),
+            cluster_secret: None,
+            join_as_learner: false,
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            channel_full_policy: ChannelFullPolicy::default(),
+            election_priority: MAX_ELECTION_PRIORITY,
+            apply_lag_warning: DEFAULT_APPLY_LAG_WARNING,
+            log_batch_size: DEFAULT_LOG_BATCH_SIZE,
+            lease_duration: DEFAULT_LEASE_DURATION,
+        }
+    }
+}
@@ -3,9 +3,10 @@ use std::time::Duration;
 
 use async_std::io::timeout;
 use futures::{select, AsyncReadExt, AsyncWriteExt, FutureExt};
-use log::{debug, error};
-use serde_json::{json, Value};
+use log::{debug, error, warn};
+use serde_json::Value;
 use url::Url;
+use zeroize::Zeroizing;
 
 use super::jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResult};
 use crate::{
@@ -16,10 +17,13 @@ use crate::{
     Error, Result,
 };
 
+/// If we don't get a reply within 30 seconds, we'll fail.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// JSON-RPC client implementation using asynchronous channels.
 pub struct RpcClient {
-    send: async_channel::Sender<Value>,
-    recv: async_channel::Receiver<JsonResult>,
+    send: async_channel::Sender<Zeroizing<Vec<u8>>>,
+    recv: async_channel::Receiver<Result<JsonResult>>,
     stop_signal: async_channel::Sender<()>,
     url: Url,
 }
@@ -27,10 +31,41 @@ pub struct RpcClient {
 impl RpcClient {
     /// Instantiate a new JSON-RPC client that will connect to the given URL.
     pub async fn new(url: Url) -> Result<Self> {
-        let (send, recv, stop_signal) = Self::open_channels(&url).await?;
+        Self::new_with_timeout(url, None).await
+    }
+
+    /// Instantiate a new JSON-RPC client that will connect to the given URL.
+    /// The timeout bounds connecting and waiting for each reply, and
+    /// defaults to 30 seconds.
+    pub async fn new_with_timeout(url: Url, timeout: Option<Duration>) -> Result<Self> {
+        let timeout = timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let (send, recv, stop_signal) = Self::open_channels(&url, timeout).await?;
         Ok(Self { send, recv, stop_signal, url })
     }
 
+    /// Instantiate a new JSON-RPC client connected to the first reachable
+    /// URL of the given list. URLs are tried in order, failing over to the
+    /// next one on connection error.
+    pub async fn new_with_failover(urls: &[Url], timeout: Option<Duration>) -> Result<Self> {
+        for url in urls {
+            match Self::new_with_timeout(url.clone(), timeout).await {
+                Ok(client) => {
+                    debug!(target: "jsonrpc-client", "Connected to {}", url);
+                    return Ok(client)
+                }
+                Err(e) => warn!("JSON-RPC client unable to use {}: {}", url, e),
+            }
+        }
+
+        error!("JSON-RPC client unable to connect to any of the given endpoints");
+        Err(Error::ConnectFailed)
+    }
+
+    /// The URL this client is connected to.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
     /// Close the channels of an instantiated [`RpcClient`].
     pub async fn close(&self) -> Result<()> {
         self.stop_signal.send(()).await?;
@@ -39,26 +74,53 @@ impl RpcClient {
 
     /// Send a given JSON-RPC request over the instantiated client.
     pub async fn request(&self, value: JsonRequest) -> Result<Value> {
-        let req_id = value.id.clone().as_u64().unwrap();
-
         debug!(target: "jsonrpc-client", "--> {}", serde_json::to_string(&value)?);
+        self.send_request(&value).await
+    }
+
+    /// Send a given JSON-RPC request carrying a secret, left to the caller
+    /// to wipe once sent. Only the method is logged, and the request is
+    /// wiped from the buffer it's serialized into.
+    pub async fn secret_request(&self, value: &JsonRequest) -> Result<Value> {
+        debug!(target: "jsonrpc-client", "--> {} (params hidden)", value.method);
+        self.send_request(value).await
+    }
+
+    async fn send_request(&self, value: &JsonRequest) -> Result<Value> {
+        let req_id = value.id.clone().as_u64().unwrap();
+        let data = Zeroizing::new(serde_json::to_vec(value)?);
 
         // If the connection is closed, the sender will get an error for
         // sending to a closed channel.
-        if let Err(e) = self.send.send(json!(value)).await {
+        if let Err(e) = self.send.send(data).await {
             error!("JSON-RPC client unable to send to {} (channels closed): {}", self.url, e);
             return Err(Error::NetworkOperationFailed)
         }
 
         // If the connection is closed, the receiver will get an error for
         // waiting on a closed channel.
-        let reply = self.recv.recv().await;
-        if reply.is_err() {
-            error!("JSON-RPC client unable to recv from {} (channels closed)", self.url);
-            return Err(Error::NetworkOperationFailed)
-        }
+        let reply = match self.recv.recv().await {
+            Ok(reply) => reply,
+            Err(_) => {
+                error!("JSON-RPC client unable to recv from {} (channels closed)", self.url);
+                return Err(Error::NetworkOperationFailed)
+            }
+        };
 
-        match reply? {
+        // The connection is closed after a read error.
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(Error::Io(std::io::ErrorKind::TimedOut)) => {
+                error!("JSON-RPC client timed out waiting for a reply from {}", self.url);
+                return Err(Error::TimeoutError)
+            }
+            Err(e) => {
+                error!("JSON-RPC client unable to read from {}: {}", self.url, e);
+                return Err(e)
+            }
+        };
+
+        match reply {
             JsonResult::Response(r) => {
                 // Check if the IDs match
                 let resp_id = r.id.as_u64();
@@ -103,9 +165,10 @@ impl RpcClient {
     /// Instantiate channels for a new [`RpcClient`].
     async fn open_channels(
         uri: &Url,
+        timeout: Duration,
     ) -> Result<(
-        async_channel::Sender<Value>,
-        async_channel::Receiver<JsonResult>,
+        async_channel::Sender<Zeroizing<Vec<u8>>>,
+        async_channel::Receiver<Result<JsonResult>>,
         async_channel::Sender<()>,
     )> {
         let (data_send, data_recv) = async_channel::unbounded();
@@ -130,13 +193,25 @@ impl RpcClient {
                 let stream = stream?;
                 match $upgrade {
                     None => {
-                        smol::spawn(Self::reqrep_loop(stream, result_send, data_recv, stop_recv))
-                            .detach();
+                        smol::spawn(Self::reqrep_loop(
+                            stream,
+                            timeout,
+                            result_send,
+                            data_recv,
+                            stop_recv,
+                        ))
+                        .detach();
                     }
                     Some(u) if u == "tls" => {
                         let stream = $transport.upgrade_dialer(stream)?.await?;
-                        smol::spawn(Self::reqrep_loop(stream, result_send, data_recv, stop_recv))
-                            .detach();
+                        smol::spawn(Self::reqrep_loop(
+                            stream,
+                            timeout,
+                            result_send,
+                            data_recv,
+                            stop_recv,
+                        ))
+                        .detach();
                     }
                     Some(u) => return Err(Error::UnsupportedTransportUpgrade(u)),
                 }
@@ -146,13 +221,13 @@ impl RpcClient {
         match transport_name {
             TransportName::Tcp(upgrade) => {
                 let transport = TcpTransport::new(None, 1024);
-                let stream = transport.dial(uri.clone(), None);
+                let stream = transport.dial(uri.clone(), Some(timeout));
                 reqrep!(stream, transport, upgrade);
             }
             TransportName::Tor(upgrade) => {
                 let socks5_url = TorTransport::get_dialer_env()?;
                 let transport = TorTransport::new(socks5_url, None)?;
-                let stream = transport.clone().dial(uri.clone(), None);
+                let stream = transport.clone().dial(uri.clone(), Some(timeout));
                 reqrep!(stream, transport, upgrade);
             }
             TransportName::Unix => {
@@ -163,7 +238,8 @@ impl RpcClient {
                     return Err(Error::ConnectFailed)
                 }
 
-                smol::spawn(Self::reqrep_loop(stream?, result_send, data_recv, stop_recv)).detach();
+                smol::spawn(Self::reqrep_loop(stream?, timeout, result_send, data_recv, stop_recv))
+                    .detach();
             }
             _ => unimplemented!(),
         }
@@ -174,23 +250,31 @@ impl RpcClient {
     /// Internal function that loops on a given stream and multiplexes the data.
     async fn reqrep_loop<T: TransportStream>(
         mut stream: T,
-        result_send: async_channel::Sender<JsonResult>,
-        data_recv: async_channel::Receiver<Value>,
+        read_timeout: Duration,
+        result_send: async_channel::Sender<Result<JsonResult>>,
+        data_recv: async_channel::Receiver<Zeroizing<Vec<u8>>>,
         stop_recv: async_channel::Receiver<()>,
     ) -> Result<()> {
-        // If we don't get a reply within 30 seconds, we'll fail.
-        let read_timeout = Duration::from_secs(30);
-
         loop {
             // Nasty size
             let mut buf = vec![0; 2048 * 10];
 
             select! {
                 data = data_recv.recv().fuse() => {
-                    let data_bytes = serde_json::to_vec(&data?)?;
-                    stream.write_all(&data_bytes).await?;
-                    let n = timeout(read_timeout, async { stream.read(&mut buf[..]).await }).await?;
-                    let reply: JsonResult = serde_json::from_slice(&buf[0..n])?;
+                    let data_bytes = data?;
+                    let reply: Result<JsonResult> = async {
+                        stream.write_all(&data_bytes).await?;
+                        let n = timeout(read_timeout, async { stream.read(&mut buf[..]).await }).await?;
+                        let reply: JsonResult = serde_json::from_slice(&buf[0..n])?;
+                        Ok(reply)
+                    }
+                    .await;
+
+                    // Let the request know why the connection is closed
+                    if let Err(e) = reply {
+                        result_send.send(Err(e.clone())).await?;
+                        return Err(e)
+                    }
                     result_send.send(reply).await?;
                 }
 
@@ -15,6 +15,7 @@ use crate::{
         proof::ProvingKey,
         schnorr::SchnorrSecret,
         types::{DrkCoinBlind, DrkSerial, DrkTokenId, DrkValueBlind},
+        util::os_random_value_blind,
     },
     util::serial::Encodable,
     Result,
@@ -70,10 +71,10 @@ impl TransactionBuilder {
 
     pub fn build(self, mint_pk: &ProvingKey, burn_pk: &ProvingKey) -> Result<Transaction> {
         let mut clear_inputs = vec![];
-        let token_blind = DrkValueBlind::random(&mut OsRng);
+        let token_blind = os_random_value_blind();
         for input in &self.clear_inputs {
-            let signature_public = PublicKey::from_secret(input.signature_secret);
-            let value_blind = DrkValueBlind::random(&mut OsRng);
+            let signature_public = PublicKey::from_secret(input.signature_secret.clone());
+            let value_blind = os_random_value_blind();
 
             let clear_input = PartialTransactionClearInput {
                 value: input.value,
@@ -106,7 +107,7 @@ impl TransactionBuilder {
                 input.secret,
                 input.leaf_position,
                 input.merkle_path,
-                signature_secret,
+                signature_secret.clone(),
             )?;
 
             // First we make the tx then sign after
@@ -123,7 +124,7 @@ impl TransactionBuilder {
             let value_blind = if i == self.outputs.len() - 1 {
                 Self::compute_remainder_blind(&clear_inputs, &input_blinds, &output_blinds)
             } else {
-                DrkValueBlind::random(&mut OsRng)
+                os_random_value_blind()
             };
             output_blinds.push(value_blind);
 
@@ -13,7 +13,7 @@ use crate::{
         schnorr,
         schnorr::SchnorrPublic,
         types::{DrkTokenId, DrkValueBlind, DrkValueCommit},
-        util::{mod_r_p, pedersen_commitment_scalar, pedersen_commitment_u64},
+        util::{mod_r_p, pedersen_commitment_scalar, pedersen_commitment_sum, value_to_scalar},
         BurnRevealedValues, MintRevealedValues, Proof,
     },
     impl_vec,
@@ -77,13 +77,11 @@ pub struct TransactionOutput {
 impl Transaction {
     /// Verify the transaction
     pub fn verify(&self, mint_vk: &VerifyingKey, burn_vk: &VerifyingKey) -> VerifyResult<()> {
-        // Accumulator for the value commitments
-        let mut valcom_total = DrkValueCommit::identity();
-
-        // Add values from the clear inputs
-        for input in &self.clear_inputs {
-            valcom_total += pedersen_commitment_u64(input.value, input.value_blind);
-        }
+        // Accumulator for the value commitments, starting from the sum of
+        // the clear inputs
+        let values: Vec<_> = self.clear_inputs.iter().map(|i| value_to_scalar(i.value)).collect();
+        let blinds: Vec<_> = self.clear_inputs.iter().map(|i| i.value_blind).collect();
+        let mut valcom_total = pedersen_commitment_sum(&values, &blinds);
 
         // Add values from the inputs
         for (i, input) in self.inputs.iter().enumerate() {
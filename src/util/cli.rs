@@ -1,5 +1,5 @@
 use std::{
-    env, fs,
+    env, fs, io,
     io::Write,
     marker::PhantomData,
     path::{Path, PathBuf},
@@ -7,7 +7,9 @@ use std::{
 };
 
 use serde::{de::DeserializeOwned, Serialize};
-use simplelog::ConfigBuilder;
+use simplelog::{
+    ColorChoice, CombinedLogger, ConfigBuilder, SharedLogger, TermLogger, TerminalMode, WriteLogger,
+};
 
 use crate::{Error, Result};
 
@@ -79,6 +81,95 @@ pub fn get_log_config() -> simplelog::Config {
     }
 }
 
+/// Size in bytes after which a log file gets rotated
+pub const LOG_FILE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// Number of rotated log files kept next to the current one
+const LOG_FILE_ROTATIONS: usize = 3;
+
+/// Log file writer that rotates the file once it grows past `max_size`.
+/// Rotated files are renamed to `<path>.1`, `<path>.2`, ..., where the
+/// highest number is the oldest one.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    size: u64,
+    file: fs::File,
+}
+
+impl RotatingFile {
+    pub fn new(path: &Path, max_size: u64) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self { path: path.to_path_buf(), max_size, size, file })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        for n in (1..LOG_FILE_ROTATIONS).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(n + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = fs::File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Initialize logging to the terminal and, if a path is given, to a log
+/// file as well, which is rotated once it reaches `LOG_FILE_MAX_SIZE`.
+pub fn init_logger(
+    log_level: simplelog::LevelFilter,
+    log_config: simplelog::Config,
+    log_file: Option<&Path>,
+) -> Result<()> {
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
+        log_level,
+        log_config.clone(),
+        TerminalMode::Mixed,
+        ColorChoice::Auto,
+    )];
+
+    if let Some(path) = log_file {
+        let file = RotatingFile::new(path, LOG_FILE_MAX_SIZE)?;
+        loggers.push(WriteLogger::new(log_level, log_config, file));
+    }
+
+    CombinedLogger::init(loggers)?;
+    Ok(())
+}
+
 pub const ANSI_LOGO: &str = include_str!("../../contrib/darkfi.ansi");
 
 #[macro_export]
@@ -142,6 +233,10 @@ macro_rules! cli_desc {
 ///     #[structopt(short, parse(from_occurrences))]
 ///     /// Increase verbosity (-vvv supported)
 ///     verbose: u8,
+///
+///     #[structopt(long)]
+///     /// Log to the given file as well (default is $DARKFI_LOG or /tmp/darkfi.log)
+///     log_file: Option<String>,
 /// }
 ///
 /// async_daemonize!(realmain);
@@ -162,20 +257,15 @@ macro_rules! async_daemonize {
             let log_level = get_log_level(args.verbose.into());
             let log_config = get_log_config();
 
-            let env_log_file_path = match std::env::var("DARKFI_LOG") {
-                Ok(p) => std::fs::File::create(p).unwrap(),
-                Err(_) => std::fs::File::create("/tmp/darkfi.log").unwrap(),
+            let log_file = match args.log_file.clone() {
+                Some(p) => darkfi::util::path::expand_path(&p)?,
+                None => match std::env::var("DARKFI_LOG") {
+                    Ok(p) => std::path::PathBuf::from(p),
+                    Err(_) => std::path::PathBuf::from("/tmp/darkfi.log"),
+                },
             };
 
-            simplelog::CombinedLogger::init(vec![
-                simplelog::TermLogger::new(
-                    log_level,
-                    log_config.clone(),
-                    simplelog::TerminalMode::Mixed,
-                    simplelog::ColorChoice::Auto,
-                ),
-                simplelog::WriteLogger::new(log_level, log_config, env_log_file_path),
-            ])?;
+            darkfi::util::cli::init_logger(log_level, log_config, Some(&log_file))?;
 
             // https://docs.rs/smol/latest/smol/struct.Executor.html#examples
             let ex = Arc::new(async_executor::Executor::new());
@@ -196,3 +286,30 @@ macro_rules! async_daemonize {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotating_file() -> Result<()> {
+        let dir = Path::new("/tmp/test_darkfi_rotating_log");
+        fs::remove_dir_all(dir).ok();
+        let path = dir.join("test.log");
+
+        let mut file = RotatingFile::new(&path, 10)?;
+        for _ in 0..5 {
+            file.write_all(b"12345678")?;
+        }
+        file.flush()?;
+
+        assert_eq!(fs::read(&path)?, b"12345678");
+        for n in 1..=LOG_FILE_ROTATIONS {
+            assert_eq!(fs::read(file.rotated_path(n))?, b"12345678");
+        }
+        assert!(!file.rotated_path(LOG_FILE_ROTATIONS + 1).exists());
+
+        fs::remove_dir_all(dir).ok();
+        Ok(())
+    }
+}
@@ -16,6 +16,11 @@ pub use darkfi_derive::{SerialDecodable, SerialEncodable};
 use super::endian;
 use crate::{Error, Result};
 
+/// Upper bound on the number of elements preallocated when decoding a
+/// vector. The length prefix comes from untrusted data, so larger vectors
+/// grow as their elements are actually read.
+pub const MAX_PREALLOC: u64 = 1024;
+
 /// Encode an object into a vector
 pub fn serialize<T: Encodable + ?Sized>(data: &T) -> Vec<u8> {
     let mut encoder = Vec::new();
@@ -29,6 +34,12 @@ pub fn serialize_hex<T: Encodable + ?Sized>(data: &T) -> String {
     hex::encode(serialize(data))
 }
 
+/// Decode an object from a hex-encoded string, will error if said
+/// deserialization doesn't consume all of the data.
+pub fn deserialize_hex<T: Decodable>(data: &str) -> Result<T> {
+    deserialize(&hex::decode(data.trim())?)
+}
+
 /// Deserialize an object from a vector, will error if said deserialization
 /// doesn't consume the entire vector.
 pub fn deserialize<T: Decodable>(data: &[u8]) -> Result<T> {
@@ -463,7 +474,7 @@ impl<T: Encodable> Encodable for Vec<Option<T>> {
 impl<T: Decodable> Decodable for Vec<Option<T>> {
     fn decode<D: io::Read>(mut d: D) -> Result<Self> {
         let len = VarInt::decode(&mut d)?.0;
-        let mut ret = Vec::with_capacity(len as usize);
+        let mut ret = Vec::with_capacity(len.min(MAX_PREALLOC) as usize);
         for _ in 0..len {
             ret.push(Decodable::decode(&mut d)?);
         }
@@ -490,7 +501,8 @@ macro_rules! impl_vec {
             #[inline]
             fn decode<D: io::Read>(mut d: D) -> Result<Self> {
                 let len = VarInt::decode(&mut d)?.0;
-                let mut ret = Vec::with_capacity(len as usize);
+                let mut ret =
+                    Vec::with_capacity(len.min($crate::util::serial::MAX_PREALLOC) as usize);
                 for _ in 0..len {
                     ret.push(Decodable::decode(&mut d)?);
                 }
@@ -500,6 +512,7 @@ macro_rules! impl_vec {
     };
 }
 
+impl_vec!(String);
 impl_vec!(SocketAddr);
 impl_vec!(Url);
 impl_vec!([u8; 32]);
@@ -609,9 +622,13 @@ impl Encodable for Vec<u8> {
 impl Decodable for Vec<u8> {
     #[inline]
     fn decode<D: io::Read>(mut d: D) -> Result<Self> {
-        let len = VarInt::decode(&mut d)?.0 as usize;
-        let mut ret = vec![0u8; len];
-        d.read_slice(&mut ret)?;
+        let len = VarInt::decode(&mut d)?.0;
+        // Read up to `len` bytes rather than allocating them upfront
+        let mut ret = Vec::with_capacity(len.min(MAX_PREALLOC) as usize);
+        d.take(len).read_to_end(&mut ret).map_err(|e| Error::Io(e.kind()))?;
+        if ret.len() as u64 != len {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof))
+        }
         Ok(ret)
     }
 }
@@ -688,11 +705,13 @@ macro_rules! encode_payload {
 #[cfg(test)]
 mod tests {
     use super::{
-        deserialize, deserialize_partial,
+        deserialize, deserialize_hex, deserialize_partial,
         endian::{u16_to_array_le, u32_to_array_le, u64_to_array_le},
-        serialize, Encodable, Error, Result, SerialDecodable, SerialEncodable, VarInt,
+        serialize, serialize_hex, Encodable, Error, Result, SerialDecodable, SerialEncodable,
+        VarInt,
     };
     use std::{io, mem::discriminant};
+    use url::Url;
 
     #[test]
     fn serialize_int_test() {
@@ -811,6 +830,16 @@ mod tests {
         test_varint_len(VarInt(u64::max_value()), 9);
     }
 
+    #[test]
+    fn hex_roundtrip() {
+        let data = vec!["dark".to_string(), "fi".to_string()];
+        let hex = serialize_hex(&data);
+        assert_eq!(deserialize_hex::<Vec<String>>(&hex).unwrap(), data);
+        assert_eq!(deserialize_hex::<Vec<String>>(&format!(" {}\n", hex)).unwrap(), data);
+        assert!(deserialize_hex::<Vec<String>>("zz").is_err());
+        assert!(deserialize_hex::<Vec<String>>(&format!("{}00", hex)).is_err());
+    }
+
     fn test_varint_len(varint: VarInt, expected: usize) {
         let mut encoder = io::Cursor::new(vec![]);
         assert_eq!(varint.encode(&mut encoder).unwrap(), expected);
@@ -951,6 +980,11 @@ mod tests {
     fn deserialize_vec_test() {
         assert_eq!(deserialize(&[3u8, 2, 3, 4]).ok(), Some(vec![2u8, 3, 4]));
         assert!((deserialize(&[4u8, 2, 3, 4, 5, 6]) as Result<Vec<u8>>).is_err());
+
+        // A huge length prefix fails instead of allocating it
+        let huge = [0xffu8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 1, 2];
+        assert!((deserialize(&huge) as Result<Vec<u8>>).is_err());
+        assert!((deserialize(&huge) as Result<Vec<Url>>).is_err());
     }
 
     #[test]
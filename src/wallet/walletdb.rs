@@ -26,7 +26,7 @@ use crate::{
     util::{
         expand_path,
         serial::{deserialize, serialize},
-        NetworkName,
+        NetworkName, Timestamp,
     },
     Error::{WalletEmptyPassword, WalletTreeExists},
     Result,
@@ -46,6 +46,35 @@ pub struct Balances {
     pub list: Vec<Balance>,
 }
 
+/// Whether a transaction of the wallet history spent or received value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxDirection {
+    Sent,
+    Received,
+}
+
+impl TxDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::Received => "received",
+        }
+    }
+}
+
+/// A transaction of the wallet history.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WalletTransaction {
+    pub tx_id: String,
+    pub direction: TxDirection,
+    /// Address the value was sent to. The notes don't reveal who sent a
+    /// coin, so received transactions have none.
+    pub counterparty: Option<String>,
+    pub value: u64,
+    pub token_id: DrkTokenId,
+    pub timestamp: Timestamp,
+}
+
 pub struct WalletDb {
     pub conn: SqlitePool,
 }
@@ -92,6 +121,9 @@ impl WalletDb {
         let tree = include_str!("../../script/sql/tree.sql");
         let keys = include_str!("../../script/sql/keys.sql");
         let coins = include_str!("../../script/sql/coins.sql");
+        let transactions = include_str!("../../script/sql/transactions.sql");
+        let transfer_keys = include_str!("../../script/sql/transfer_keys.sql");
+        let wallet_lock = include_str!("../../script/sql/wallet_lock.sql");
 
         let mut conn = self.conn.acquire().await?;
 
@@ -103,6 +135,15 @@ impl WalletDb {
 
         debug!("Initializing coins table");
         sqlx::query(coins).execute(&mut conn).await?;
+
+        debug!("Initializing transactions table");
+        sqlx::query(transactions).execute(&mut conn).await?;
+
+        debug!("Initializing transfer keys table");
+        sqlx::query(transfer_keys).execute(&mut conn).await?;
+
+        debug!("Initializing wallet lock table");
+        sqlx::query(wallet_lock).execute(&mut conn).await?;
         Ok(())
     }
 
@@ -181,7 +222,7 @@ impl WalletDb {
 
         let keypair = if default_keypair.is_err() {
             let keypairs = self.get_keypairs().await?;
-            let kp = if keypairs.is_empty() { self.keygen().await? } else { keypairs[0] };
+            let kp = if keypairs.is_empty() { self.keygen().await? } else { keypairs[0].clone() };
             self.set_default_keypair(&kp.public).await?;
             kp
         } else {
@@ -427,6 +468,190 @@ impl WalletDb {
         Ok(id_check.is_some())
     }
 
+    /// Record a transaction sending `value` of `token_id` to `recipient`
+    /// in the wallet history.
+    pub async fn put_sent_transaction(
+        &self,
+        tx_id: &str,
+        recipient: &str,
+        value: u64,
+        token_id: DrkTokenId,
+    ) -> Result<()> {
+        debug!("Putting sent transaction {} into wallet database", tx_id);
+        let tx = WalletTransaction {
+            tx_id: tx_id.to_string(),
+            direction: TxDirection::Sent,
+            counterparty: Some(recipient.to_string()),
+            value,
+            token_id,
+            timestamp: Timestamp::current_time(),
+        };
+        self.put_transaction(&tx).await
+    }
+
+    /// Record a coin of `value` received in the transaction `tx_id` in the
+    /// wallet history, adding it to the other coins of the transaction.
+    /// The change of the transactions sent by the wallet isn't recorded.
+    pub async fn put_received_transaction(
+        &self,
+        tx_id: &str,
+        value: u64,
+        token_id: DrkTokenId,
+    ) -> Result<()> {
+        debug!("Putting received transaction {} into wallet database", tx_id);
+        let mut conn = self.conn.acquire().await?;
+        let rows = sqlx::query("SELECT direction, value FROM transactions WHERE tx_id = ?1;")
+            .bind(tx_id)
+            .fetch_all(&mut conn)
+            .await?;
+        drop(conn);
+
+        let mut tx = WalletTransaction {
+            tx_id: tx_id.to_string(),
+            direction: TxDirection::Received,
+            counterparty: None,
+            value,
+            token_id,
+            timestamp: Timestamp::current_time(),
+        };
+
+        for row in rows {
+            let direction: String = row.get("direction");
+            if direction == TxDirection::Sent.as_str() {
+                debug!("Transaction {} was sent by the wallet, skipping its change", tx_id);
+                return Ok(())
+            }
+
+            let previous: u64 = deserialize(row.get("value"))?;
+            tx.value = tx.value.saturating_add(previous);
+        }
+
+        self.put_transaction(&tx).await
+    }
+
+    async fn put_transaction(&self, tx: &WalletTransaction) -> Result<()> {
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO transactions
+            (tx_id, direction, counterparty, value, token_id, timestamp)
+            VALUES
+             (?1, ?2, ?3, ?4, ?5, ?6);",
+        )
+        .bind(&tx.tx_id)
+        .bind(tx.direction.as_str())
+        .bind(&tx.counterparty)
+        .bind(serialize(&tx.value))
+        .bind(serialize(&tx.token_id))
+        .bind(tx.timestamp.0)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The `limit` latest transactions of the wallet history, newest first,
+    /// only those of `token_id` if given.
+    pub async fn get_transactions(
+        &self,
+        limit: u32,
+        token_id: Option<DrkTokenId>,
+    ) -> Result<Vec<WalletTransaction>> {
+        debug!("Getting the {} latest transactions", limit);
+
+        let mut conn = self.conn.acquire().await?;
+        let rows = sqlx::query(
+            "SELECT * FROM transactions WHERE ?2 IS NULL OR token_id = ?2
+            ORDER BY timestamp DESC, rowid DESC LIMIT ?1;",
+        )
+        .bind(limit as i64)
+        .bind(token_id.map(|token_id| serialize(&token_id)))
+        .fetch_all(&mut conn)
+        .await?;
+
+        let mut transactions = vec![];
+        for row in rows {
+            let direction: String = row.get("direction");
+            let direction = if direction == TxDirection::Sent.as_str() {
+                TxDirection::Sent
+            } else {
+                TxDirection::Received
+            };
+
+            transactions.push(WalletTransaction {
+                tx_id: row.get("tx_id"),
+                direction,
+                counterparty: row.get("counterparty"),
+                value: deserialize(row.get("value"))?,
+                token_id: deserialize(row.get("token_id"))?,
+                timestamp: Timestamp(row.get("timestamp")),
+            });
+        }
+
+        Ok(transactions)
+    }
+
+    /// Keep the reply to a transfer sent with the idempotency key `key`,
+    /// along with a hash of its params, so that retrying it returns the
+    /// same reply instead of sending another transaction.
+    pub async fn put_transfer_reply(
+        &self,
+        key: &str,
+        params_hash: &str,
+        reply: &str,
+    ) -> Result<()> {
+        debug!("Putting the reply to transfer {} into wallet database", key);
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query(
+            "INSERT INTO transfer_keys (key, params_hash, reply, timestamp)
+            VALUES (?1, ?2, ?3, ?4);",
+        )
+        .bind(key)
+        .bind(params_hash)
+        .bind(reply)
+        .bind(Timestamp::current_time().0)
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The params hash and the reply of the transfer sent with the
+    /// idempotency key `key`, if there was one.
+    pub async fn get_transfer_reply(&self, key: &str) -> Result<Option<(String, String)>> {
+        debug!("Getting the reply to transfer {}", key);
+        let mut conn = self.conn.acquire().await?;
+        let row = sqlx::query("SELECT params_hash, reply FROM transfer_keys WHERE key = ?1;")
+            .bind(key)
+            .fetch_optional(&mut conn)
+            .await?;
+
+        Ok(row.map(|row| (row.get("params_hash"), row.get("reply"))))
+    }
+
+    /// Keep whether the wallet is locked, so it stays so across restarts.
+    pub async fn put_locked(&self, locked: bool) -> Result<()> {
+        debug!("Putting the wallet lock state into wallet database");
+        let mut conn = self.conn.acquire().await?;
+        sqlx::query("INSERT OR REPLACE INTO wallet_lock (id, locked) VALUES (0, ?1);")
+            .bind(locked)
+            .execute(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Whether the wallet was left locked, if it was ever locked or
+    /// unlocked.
+    pub async fn get_locked(&self) -> Result<Option<bool>> {
+        debug!("Getting the wallet lock state");
+        let mut conn = self.conn.acquire().await?;
+        let row = sqlx::query("SELECT locked FROM wallet_lock WHERE id = 0;")
+            .fetch_optional(&mut conn)
+            .await?;
+
+        Ok(row.map(|row| row.get("locked")))
+    }
+
     pub async fn test_wallet(&self) -> Result<()> {
         debug!("Testing wallet");
         let mut conn = self.conn.acquire().await?;
@@ -461,10 +686,10 @@ mod tests {
         };
 
         let coin = Coin(pallas::Base::random(&mut OsRng));
-        let nullifier = Nullifier::new(*s, serial);
+        let nullifier = Nullifier::new(s.clone(), serial);
         let leaf_position: incrementalmerkletree::Position = 0.into();
 
-        OwnCoin { coin, note, secret: *s, nullifier, leaf_position }
+        OwnCoin { coin, note, secret: s.clone(), nullifier, leaf_position }
     }
 
     #[async_std::test]
@@ -496,19 +721,19 @@ mod tests {
         let c3 = dummy_coin(&keypair.secret, 11, &token_id);
 
         // put_own_coin()
-        wallet.put_own_coin(c0, tokenlist.clone()).await?;
+        wallet.put_own_coin(c0.clone(), tokenlist.clone()).await?;
         tree1.append(&MerkleNode::from_coin(&c0.coin));
         tree1.witness();
 
-        wallet.put_own_coin(c1, tokenlist.clone()).await?;
+        wallet.put_own_coin(c1.clone(), tokenlist.clone()).await?;
         tree1.append(&MerkleNode::from_coin(&c1.coin));
         tree1.witness();
 
-        wallet.put_own_coin(c2, tokenlist.clone()).await?;
+        wallet.put_own_coin(c2.clone(), tokenlist.clone()).await?;
         tree1.append(&MerkleNode::from_coin(&c2.coin));
         tree1.witness();
 
-        wallet.put_own_coin(c3, tokenlist).await?;
+        wallet.put_own_coin(c3.clone(), tokenlist).await?;
         tree1.append(&MerkleNode::from_coin(&c3.coin));
         tree1.witness();
 
@@ -570,4 +795,70 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn test_transactions() -> Result<()> {
+        let wallet = WalletDb::new("sqlite::memory:", WPASS).await?;
+        wallet.init_db().await?;
+        let token_id = DrkTokenId::random(&mut OsRng);
+
+        // The coins of a transaction add up, the change of sent ones is skipped
+        wallet.put_received_transaction("tx0", 69, token_id).await?;
+        wallet.put_received_transaction("tx0", 1, token_id).await?;
+        wallet.put_sent_transaction("tx1", "1DarkFi", 42, token_id).await?;
+        wallet.put_received_transaction("tx1", 28, token_id).await?;
+
+        let txs = wallet.get_transactions(10, None).await?;
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].tx_id, "tx1");
+        assert_eq!(txs[0].direction, TxDirection::Sent);
+        assert_eq!(txs[0].counterparty.as_deref(), Some("1DarkFi"));
+        assert_eq!(txs[0].value, 42);
+        assert_eq!(txs[1].tx_id, "tx0");
+        assert_eq!(txs[1].direction, TxDirection::Received);
+        assert_eq!(txs[1].counterparty, None);
+        assert_eq!(txs[1].value, 70);
+        assert_eq!(txs[1].token_id, token_id);
+
+        assert_eq!(wallet.get_transactions(1, None).await?, txs[..1]);
+
+        // Filtered by token
+        let other_token_id = DrkTokenId::random(&mut OsRng);
+        wallet.put_received_transaction("tx2", 5, other_token_id).await?;
+        assert_eq!(wallet.get_transactions(10, Some(token_id)).await?, txs);
+        let other_txs = wallet.get_transactions(10, Some(other_token_id)).await?;
+        assert_eq!(other_txs.len(), 1);
+        assert_eq!(other_txs[0].tx_id, "tx2");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_transfer_keys() -> Result<()> {
+        let wallet = WalletDb::new("sqlite::memory:", WPASS).await?;
+        wallet.init_db().await?;
+
+        assert_eq!(wallet.get_transfer_reply("key0").await?, None);
+        wallet.put_transfer_reply("key0", "hash0", "{\"tx_id\":\"tx0\"}").await?;
+        assert_eq!(
+            wallet.get_transfer_reply("key0").await?,
+            Some(("hash0".to_string(), "{\"tx_id\":\"tx0\"}".to_string()))
+        );
+
+        // A key is only used once
+        assert!(wallet.put_transfer_reply("key0", "hash1", "{}").await.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_wallet_lock() -> Result<()> {
+        let wallet = WalletDb::new("sqlite::memory:", WPASS).await?;
+        wallet.init_db().await?;
+
+        assert_eq!(wallet.get_locked().await?, None);
+        wallet.put_locked(true).await?;
+        assert_eq!(wallet.get_locked().await?, Some(true));
+        wallet.put_locked(false).await?;
+        assert_eq!(wallet.get_locked().await?, Some(false));
+        Ok(())
+    }
 }
@@ -516,7 +516,8 @@ mod tests {
         let sig_secret = SecretKey::random(&mut OsRng);
 
         let coin2 = {
-            let coords = PublicKey::from_secret(secret).0.to_affine().coordinates().unwrap();
+            let coords =
+                PublicKey::from_secret(secret.clone()).0.to_affine().coordinates().unwrap();
             let msg = [*coords.x(), *coords.y(), value, token_id, serial, coin_blind];
             poseidon::Hash::<_, P128Pow5T3, ConstantLength<6>, 3, 2>::init().hash(msg)
         };
@@ -548,7 +549,7 @@ mod tests {
         let token_commit = pedersen_commitment_scalar(mod_r_p(token_id), token_blind);
         let token_coords = token_commit.to_affine().coordinates().unwrap();
 
-        let sig_pubkey = PublicKey::from_secret(sig_secret);
+        let sig_pubkey = PublicKey::from_secret(sig_secret.clone());
         let sig_coords = sig_pubkey.0.to_affine().coordinates().unwrap();
 
         let public_inputs = vec![
@@ -45,7 +45,7 @@ fn burn_proof() -> Result<()> {
 
     // Build the coin
     let coin2 = {
-        let coords = PublicKey::from_secret(secret).0.to_affine().coordinates().unwrap();
+        let coords = PublicKey::from_secret(secret.clone()).0.to_affine().coordinates().unwrap();
         let messages =
             [*coords.x(), *coords.y(), pallas::Base::from(value), token_id, serial, coin_blind];
 
@@ -97,7 +97,7 @@ fn burn_proof() -> Result<()> {
     let token_commit = pedersen_commitment_scalar(mod_r_p(token_id), token_blind);
     let token_coords = token_commit.to_affine().coordinates().unwrap();
 
-    let sig_pubkey = PublicKey::from_secret(sig_secret);
+    let sig_pubkey = PublicKey::from_secret(sig_secret.clone());
     let sig_coords = sig_pubkey.0.to_affine().coordinates().unwrap();
 
     let merkle_root = tree.root(0).unwrap();